@@ -0,0 +1,254 @@
+use std::fmt;
+use std::io;
+use std::process::{Child, Command, Stdio};
+use std::str::FromStr;
+
+use ggez::graphics::Color;
+use itsjustchess::chess_core::{GameState, Move, PieceColor, PieceType};
+
+/// How each move is announced for players who can't easily follow the board.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum Announce {
+    #[default]
+    Off,
+    Text,   // Printed to stdout, for a screen reader watching the terminal
+    Speech, // Printed and read out by the system's text-to-speech command
+}
+
+impl Announce {
+    /// The next choice along, for cycling through them from a menu.
+    pub fn next(self) -> Self {
+        match self {
+            Announce::Off => Announce::Text,
+            Announce::Text => Announce::Speech,
+            Announce::Speech => Announce::Off,
+        }
+    }
+}
+
+impl fmt::Display for Announce {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Announce::Off => "off",
+            Announce::Text => "text",
+            Announce::Speech => "speech",
+        })
+    }
+}
+
+impl FromStr for Announce {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "off" => Ok(Announce::Off),
+            "text" => Ok(Announce::Text),
+            "speech" => Ok(Announce::Speech),
+            _ => Err(format!(
+                "Unknown announcement mode '{s}', expected off, text or speech"
+            )),
+        }
+    }
+}
+
+/// Announces moves and results in the chosen way.
+pub struct Announcer {
+    mode: Announce,
+    speaking: Option<Child>, // The last announcement still being read out
+    speech_failed: bool,     // No text-to-speech command could be run
+}
+
+impl Announcer {
+    pub fn new(mode: Announce) -> Self {
+        Announcer {
+            mode,
+            speaking: None,
+            speech_failed: false,
+        }
+    }
+
+    pub fn mode(&self) -> Announce {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: Announce) {
+        self.mode = mode;
+        self.speech_failed = false;
+    }
+
+    /// Prints `text` and, in speech mode, reads it out, cutting off anything
+    /// still being read so the announcements keep up with fast games.
+    pub fn announce(&mut self, text: &str) {
+        if self.mode == Announce::Off {
+            return;
+        }
+        println!("{text}");
+        if self.mode != Announce::Speech || self.speech_failed {
+            return;
+        }
+        if let Some(mut previous) = self.speaking.take() {
+            let _ = previous.kill();
+            let _ = previous.wait();
+        }
+        match speak(text) {
+            Ok(child) => self.speaking = Some(child),
+            Err(e) => {
+                log::warn!("Text-to-speech is unavailable ({e}); announcing moves as text only");
+                self.speech_failed = true;
+            }
+        }
+    }
+}
+
+// Starts reading `text` aloud with the platform's speech command: say on
+// macOS, the System.Speech synthesiser on Windows, and speech-dispatcher or
+// espeak elsewhere.
+fn speak(text: &str) -> io::Result<Child> {
+    let run = |program: &str, args: &[&str]| {
+        Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+    };
+    if cfg!(target_os = "macos") {
+        run("say", &[text])
+    } else if cfg!(windows) {
+        let script = format!(
+            "Add-Type -AssemblyName System.Speech; \
+             (New-Object System.Speech.Synthesis.SpeechSynthesizer).Speak('{}')",
+            text.replace('\'', "''")
+        );
+        run("powershell", &["-NoProfile", "-Command", &script])
+    } else {
+        run("spd-say", &[text]).or_else(|_| run("espeak", &[text]))
+    }
+}
+
+/// `mv` in words as it would be read out, e.g. "White knight from g1 to f3,
+/// check" or "Black pawn from d4 takes pawn on e3 en passant". Call before
+/// the move is made.
+pub fn spoken_move(before: &GameState, mv: Move) -> String {
+    let Some(piece) = before.board.squares[mv.from.0][mv.from.1].occupant else {
+        return String::new();
+    };
+    let side = match piece.color {
+        PieceColor::White => "White",
+        PieceColor::Black => "Black",
+    };
+    let from = before.board.square_name(mv.from);
+    let to = before.board.square_name(mv.to);
+
+    let mut spoken = if mv.is_castling(before) {
+        if mv.to.1 > mv.from.1 {
+            format!("{side} castles kingside")
+        } else {
+            format!("{side} castles queenside")
+        }
+    } else {
+        let name = piece_name(piece.piece_type);
+        match before.board.squares[mv.to.0][mv.to.1].occupant {
+            Some(captured) => format!(
+                "{side} {name} from {from} takes {} on {to}",
+                piece_name(captured.piece_type)
+            ),
+            None if piece.piece_type == PieceType::Pawn && mv.from.1 != mv.to.1 => {
+                format!("{side} pawn from {from} takes pawn on {to} en passant")
+            }
+            None => format!("{side} {name} from {from} to {to}"),
+        }
+    };
+
+    if piece.piece_type == PieceType::Pawn && before.board.is_back_rank(mv.to.0) {
+        let promotion = mv.promotion.unwrap_or(PieceType::Queen);
+        spoken.push_str(&format!(", promotes to {}", piece_name(promotion)));
+    }
+
+    let mut after = before.clone();
+    after.make_move(mv);
+    if after.is_king_in_check(after.turn) {
+        if after.generate_valid_moves(after.turn).is_empty() {
+            spoken.push_str(", checkmate");
+        } else {
+            spoken.push_str(", check");
+        }
+    }
+    spoken
+}
+
+fn piece_name(piece_type: PieceType) -> &'static str {
+    match piece_type {
+        PieceType::Pawn => "pawn",
+        PieceType::Knight => "knight",
+        PieceType::Bishop => "bishop",
+        PieceType::Rook => "rook",
+        PieceType::Queen => "queen",
+        PieceType::King => "king",
+        PieceType::Archbishop => "archbishop",
+        PieceType::Chancellor => "chancellor",
+    }
+}
+
+/// The letter drawn over a piece when piece letters are on, as in SAN.
+pub fn piece_letter(piece_type: PieceType) -> &'static str {
+    match piece_type {
+        PieceType::Pawn => "P",
+        PieceType::Knight => "N",
+        PieceType::Bishop => "B",
+        PieceType::Rook => "R",
+        PieceType::Queen => "Q",
+        PieceType::King => "K",
+        PieceType::Archbishop => "A",
+        PieceType::Chancellor => "C",
+    }
+}
+
+/// Colours for the board squares, as (light, dark) pairs, and for the marks
+/// drawn over them, with their opacity.
+pub struct BoardTheme {
+    pub square: ((u8, u8, u8), (u8, u8, u8)),
+    pub last_move: ((u8, u8, u8), (u8, u8, u8)),
+    pub selected: (u8, u8, u8),
+    pub possible_move: (u8, u8, u8, u8),
+    pub hover: (u8, u8, u8, u8),
+    pub threatened: (u8, u8, u8, u8), // Outlines pieces the other side attacks
+    pub hanging: (u8, u8, u8, u8),    // and those it could win
+}
+
+const STANDARD: BoardTheme = BoardTheme {
+    square: ((161, 159, 151), (118, 150, 86)),
+    last_move: ((205, 210, 106), (170, 162, 58)),
+    selected: (237, 202, 142),
+    possible_move: (20, 85, 30, 110),
+    hover: (255, 255, 255, 60),
+    threatened: (240, 150, 30, 220),
+    hanging: (215, 30, 30, 235),
+};
+
+// Yellow and blue squares stay apart under the common kinds of colour
+// blindness, and the highlights differ in brightness as well as hue.
+const HIGH_CONTRAST: BoardTheme = BoardTheme {
+    square: ((250, 224, 110), (40, 90, 190)),
+    last_move: ((150, 240, 240), (0, 165, 175)),
+    selected: (255, 255, 255),
+    possible_move: (225, 105, 0, 255),
+    hover: (255, 255, 255, 110),
+    threatened: (230, 0, 230, 255),
+    hanging: (255, 0, 0, 255),
+};
+
+impl BoardTheme {
+    pub fn get(high_contrast: bool) -> &'static BoardTheme {
+        if high_contrast {
+            &HIGH_CONTRAST
+        } else {
+            &STANDARD
+        }
+    }
+
+    /// Picks the light or dark colour of `pair`.
+    pub fn shade(pair: ((u8, u8, u8), (u8, u8, u8)), is_light: bool) -> Color {
+        Color::from(if is_light { pair.0 } else { pair.1 })
+    }
+}
@@ -0,0 +1,190 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use itsjustchess::chess_core::{Analysis, GameState};
+
+use crate::evals::EvalCache;
+
+/// How deep the background search goes before it stops to wait for the next
+/// position, unless another depth is chosen
+pub const DEFAULT_ANALYSIS_DEPTH: u32 = 7;
+
+/// The depths that can be chosen
+pub const ANALYSIS_DEPTHS: std::ops::RangeInclusive<u32> = 1..=12;
+
+/// How many of the best moves can be shown at once
+pub const MAX_ANALYSIS_LINES: usize = 5;
+
+// A position to analyse, with the flag that abandons it and a number
+// identifying its results
+struct Job {
+    generation: u64,
+    state: GameState,
+    depth: u32,
+    lines: usize,
+    stop: Arc<AtomicBool>,
+}
+
+/// Runs the built-in search on a background thread, deepening until the
+/// position is changed with [`Analyser::set_position`], and finding the best
+/// few moves if asked for more than one line. Each depth is kept in the
+/// session's evaluations, so a position analysed before shows its deepest
+/// result straight away.
+pub struct Analyser {
+    jobs: Sender<Job>,
+    results: Receiver<(u64, Vec<Analysis>)>,
+    generation: u64,
+    stop: Arc<AtomicBool>,
+    position: Option<(String, GameState)>, // By its FEN, to tell it has changed
+    latest: Vec<Analysis>,                 // Best first
+    evals: Arc<Mutex<EvalCache>>,
+    depth: u32,
+    lines: usize,
+}
+
+impl Analyser {
+    pub fn new(evals: Arc<Mutex<EvalCache>>, depth: u32, lines: usize) -> Self {
+        let (jobs, jobs_rx) = mpsc::channel::<Job>();
+        let (results_tx, results) = mpsc::channel();
+        let cache = Arc::clone(&evals);
+
+        thread::spawn(move || {
+            while let Ok(mut job) = jobs_rx.recv() {
+                // Skip straight to the newest position
+                while let Ok(newer) = jobs_rx.try_recv() {
+                    job = newer;
+                }
+                job.state
+                    .analyse_lines(job.depth, job.lines, &job.stop, |analyses| {
+                        if let Ok(mut cache) = cache.lock() {
+                            cache.store_analysis(&job.state, &analyses[0]);
+                        }
+                        let _ = results_tx.send((job.generation, analyses));
+                    });
+            }
+        });
+
+        Analyser {
+            jobs,
+            results,
+            generation: 0,
+            stop: Arc::new(AtomicBool::new(false)),
+            position: None,
+            latest: Vec::new(),
+            evals,
+            depth: depth.clamp(*ANALYSIS_DEPTHS.start(), *ANALYSIS_DEPTHS.end()),
+            lines: lines.clamp(1, MAX_ANALYSIS_LINES),
+        }
+    }
+
+    /// Starts analysing `state` unless it is already the position being
+    /// analysed, abandoning any search still running.
+    pub fn set_position(&mut self, state: &GameState) {
+        let fen = state.to_fen();
+        if self
+            .position
+            .as_ref()
+            .is_some_and(|(known, _)| *known == fen)
+        {
+            return;
+        }
+        self.position = Some((fen, state.clone()));
+        self.restart();
+    }
+
+    /// How deep the search goes.
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Searches `depth` plies deep from now on, within ANALYSIS_DEPTHS.
+    pub fn set_depth(&mut self, depth: u32) {
+        let depth = depth.clamp(*ANALYSIS_DEPTHS.start(), *ANALYSIS_DEPTHS.end());
+        if depth != self.depth {
+            self.depth = depth;
+            self.restart();
+        }
+    }
+
+    /// How many of the best moves are found.
+    pub fn line_count(&self) -> usize {
+        self.lines
+    }
+
+    /// Finds the best `lines` moves from now on, up to MAX_ANALYSIS_LINES.
+    pub fn set_line_count(&mut self, lines: usize) {
+        let lines = lines.clamp(1, MAX_ANALYSIS_LINES);
+        if lines != self.lines {
+            self.lines = lines;
+            self.restart();
+        }
+    }
+
+    // Searches the position again from the start, showing what the
+    // evaluations already have for it meanwhile.
+    fn restart(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.stop = Arc::new(AtomicBool::new(false));
+        self.generation += 1;
+        let Some((_, state)) = &self.position else {
+            self.latest.clear();
+            return;
+        };
+        self.latest = self
+            .evals
+            .lock()
+            .ok()
+            .and_then(|cache| cache.analysis(state).cloned())
+            .into_iter()
+            .collect();
+        let known = self.latest.first().is_some_and(|a| a.depth >= self.depth);
+        if known && self.lines == 1 {
+            return;
+        }
+        let _ = self.jobs.send(Job {
+            generation: self.generation,
+            state: state.clone(),
+            depth: self.depth,
+            lines: self.lines,
+            stop: Arc::clone(&self.stop),
+        });
+    }
+
+    /// Collects finished depths. Returns whether there is anything new.
+    pub fn poll(&mut self) -> bool {
+        let mut updated = false;
+        while let Ok((generation, analyses)) = self.results.try_recv() {
+            // The search starts again from depth 1, below what may have been
+            // shown from the evaluations already, which only have the best
+            // line
+            let depth = |analyses: &[Analysis]| analyses.first().map(|a| a.depth);
+            let better =
+                (analyses.len(), depth(&analyses)) > (self.latest.len(), depth(&self.latest));
+            if generation == self.generation && better {
+                self.latest = analyses;
+                updated = true;
+            }
+        }
+        updated
+    }
+
+    /// The deepest result so far for the current position.
+    pub fn latest(&self) -> Option<&Analysis> {
+        self.latest.first()
+    }
+
+    /// The best moves found so far for the current position, best first,
+    /// each with its score and line.
+    pub fn lines(&self) -> &[Analysis] {
+        &self.latest
+    }
+}
+
+impl Drop for Analyser {
+    fn drop(&mut self) {
+        // Dropping the job sender ends the thread once its search stops
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
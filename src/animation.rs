@@ -0,0 +1,106 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use ggez::event::EventHandler;
+use ggez::graphics::ImageFormat;
+use ggez::{Context, GameError, GameResult};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, RgbaImage};
+
+use crate::ChessGame;
+
+// How long each position of the replay is shown, and the final one before
+// the GIF starts again
+const MOVE_DELAY_MS: u32 = 1000;
+const FINAL_DELAY_MS: u32 = 3000;
+
+// Trades the GIF's colour quantisation quality for speed, from 1 (best) to 30
+const GIF_SPEED: i32 = 10;
+
+/// Replays a game into an animated GIF for --export-gif, or into a
+/// directory of numbered PNG frames if the output doesn't end in ".gif".
+/// ggez only renders within a frame, so this runs as the event handler of a
+/// hidden window, drawing the board after each move offscreen the way
+/// Ctrl+P does and quitting once the output is written.
+pub struct ReplayExport {
+    game: ChessGame,
+    out: PathBuf,
+}
+
+impl ReplayExport {
+    /// `game` should already have the moves to replay played on it.
+    pub fn new(game: ChessGame, out: PathBuf) -> Self {
+        ReplayExport { game, out }
+    }
+
+    // The board from the starting position through to after the last move
+    fn render_frames(&mut self, ctx: &mut Context) -> GameResult<Vec<RgbaImage>> {
+        let plies = self.game.history.len();
+        let mut frames = Vec::new();
+        for ply in 0..=plies {
+            // The last frame shows the game as it stands, result and all
+            self.game.viewed_ply = (ply < plies).then_some(ply);
+            let image = self
+                .game
+                .render_board(ctx, ImageFormat::Rgba8UnormSrgb, false)?;
+            let pixels = image.to_pixels(ctx)?;
+            let frame =
+                RgbaImage::from_raw(image.width(), image.height(), pixels).ok_or_else(|| {
+                    GameError::CustomError("Rendered frame has the wrong size".into())
+                })?;
+            frames.push(frame);
+        }
+        self.game.viewed_ply = None;
+        Ok(frames)
+    }
+
+    fn write(&self, frames: Vec<RgbaImage>) -> GameResult {
+        let failed = |e: &dyn std::fmt::Display| {
+            GameError::CustomError(format!("Failed to write {}: {e}", self.out.display()))
+        };
+        let is_gif = self
+            .out
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("gif"));
+        if !is_gif {
+            std::fs::create_dir_all(&self.out).map_err(|e| failed(&e))?;
+            for (i, frame) in frames.iter().enumerate() {
+                let path = self.out.join(format!("frame_{i:03}.png"));
+                frame.save(path).map_err(|e| failed(&e))?;
+            }
+            return Ok(());
+        }
+
+        let file = File::create(&self.out).map_err(|e| failed(&e))?;
+        let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), GIF_SPEED);
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(|e| failed(&e))?;
+        let last = frames.len() - 1;
+        let frames = frames.into_iter().enumerate().map(|(i, frame)| {
+            let delay = if i == last {
+                FINAL_DELAY_MS
+            } else {
+                MOVE_DELAY_MS
+            };
+            Frame::from_parts(frame, 0, 0, Delay::from_numer_denom_ms(delay, 1))
+        });
+        encoder.encode_frames(frames).map_err(|e| failed(&e))
+    }
+}
+
+impl EventHandler<GameError> for ReplayExport {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let frames = self.render_frames(ctx)?;
+        let count = frames.len();
+        self.write(frames)?;
+        println!("Wrote {count} frames to {}", self.out.display());
+        ctx.request_quit();
+        Ok(())
+    }
+}
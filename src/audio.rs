@@ -0,0 +1,148 @@
+use ggez::audio::{SoundData, SoundSource, Source};
+use ggez::Context;
+
+const SAMPLE_RATE: u32 = 22_050;
+
+/// The game events that make a sound.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Sound {
+    // Ordered by priority: when one move triggers several, the highest wins
+    Move,
+    Capture,
+    Castle,
+    Promotion,
+    Check,
+    GameEnd,
+}
+
+impl Sound {
+    // The notes for each sound as (frequency in Hz, length in seconds)
+    fn notes(self) -> &'static [(f32, f32)] {
+        match self {
+            Sound::Move => &[(660.0, 0.06)],
+            Sound::Capture => &[(520.0, 0.05), (330.0, 0.09)],
+            Sound::Castle => &[(660.0, 0.05), (0.0, 0.04), (660.0, 0.05)],
+            Sound::Promotion => &[(523.0, 0.07), (659.0, 0.07), (784.0, 0.12)],
+            Sound::Check => &[(880.0, 0.07), (1100.0, 0.12)],
+            Sound::GameEnd => &[(784.0, 0.15), (659.0, 0.15), (523.0, 0.35)],
+        }
+    }
+}
+
+const ALL_SOUNDS: [Sound; 6] = [
+    Sound::Move,
+    Sound::Capture,
+    Sound::Castle,
+    Sound::Promotion,
+    Sound::Check,
+    Sound::GameEnd,
+];
+
+/// Short generated tones for moves and game events. A game queues at most one
+/// sound per frame, which is played from `update` where a context is at hand.
+pub struct SoundEffects {
+    sources: Vec<(Sound, Source)>,
+    muted: bool,
+    queued: Option<Sound>,
+}
+
+impl SoundEffects {
+    /// Builds the sounds. If audio is unavailable the game carries on silently.
+    pub fn new(ctx: &Context, muted: bool) -> Self {
+        let mut sources = Vec::new();
+        for sound in ALL_SOUNDS {
+            let data = SoundData::from_bytes(&tone_wav(sound.notes()));
+            match Source::from_data(ctx, data) {
+                Ok(source) => sources.push((sound, source)),
+                Err(e) => {
+                    log::warn!("Sound disabled: {e}");
+                    sources.clear();
+                    break;
+                }
+            }
+        }
+        SoundEffects {
+            sources,
+            muted,
+            queued: None,
+        }
+    }
+
+    /// No sounds at all, for games built without a context in tests.
+    #[cfg(test)]
+    pub fn silent() -> Self {
+        SoundEffects {
+            sources: Vec::new(),
+            muted: true,
+            queued: None,
+        }
+    }
+
+    /// Sets the volume of every sound, from 0.0 (silent) to 1.0 (full).
+    pub fn set_volume(&mut self, volume: f32) {
+        for (_, source) in &mut self.sources {
+            source.set_volume(volume.clamp(0.0, 1.0));
+        }
+    }
+
+    /// Toggles mute and returns whether sound is now muted.
+    pub fn toggle_mute(&mut self) -> bool {
+        self.muted = !self.muted;
+        self.muted
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    /// Queues `sound` unless a more important one is already waiting.
+    pub fn queue(&mut self, sound: Sound) {
+        self.queued = self.queued.max(Some(sound));
+    }
+
+    pub fn play_queued(&mut self, ctx: &Context) {
+        let sound = match self.queued.take() {
+            Some(sound) if !self.muted => sound,
+            _ => return,
+        };
+        if let Some((_, source)) = self.sources.iter_mut().find(|(s, _)| *s == sound) {
+            if let Err(e) = source.play_detached(ctx) {
+                log::warn!("Failed to play sound: {e}");
+            }
+        }
+    }
+}
+
+// Renders a sequence of notes as a 16-bit mono WAV file. A frequency of zero
+// is a rest; each note fades out so it ends without a click.
+fn tone_wav(notes: &[(f32, f32)]) -> Vec<u8> {
+    let mut samples: Vec<i16> = Vec::new();
+    for &(frequency, seconds) in notes {
+        let count = (seconds * SAMPLE_RATE as f32) as usize;
+        for i in 0..count {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            let envelope = 1.0 - i as f32 / count as f32;
+            let value = (t * frequency * std::f32::consts::TAU).sin() * envelope * 0.3;
+            samples.push((value * i16::MAX as f32) as i16);
+        }
+    }
+
+    let data_len = (samples.len() * 2) as u32;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes()); // Format chunk size
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // Mono
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes()); // Byte rate
+    wav.extend_from_slice(&2u16.to_le_bytes()); // Block align
+    wav.extend_from_slice(&16u16.to_le_bytes()); // Bits per sample
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
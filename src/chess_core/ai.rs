@@ -0,0 +1,403 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+// std's clock panics in the browser
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+use rand::Rng;
+
+use super::board::{PieceColor, PieceType};
+use super::book::OpeningBook;
+use super::eval::{piece_value, square_bonus};
+use super::game_state::{GameState, Move};
+use super::variant::Variant;
+
+const INFINITY: i32 = 1_000_000;
+const MATE_SCORE: i32 = 100_000;
+
+// Scores this close to MATE_SCORE mean a forced mate
+const MATE_THRESHOLD: i32 = MATE_SCORE - 1_000;
+
+/// The result of searching a position to some depth.
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    pub depth: u32,
+    /// In centipawns from White's point of view
+    pub score: i32,
+    /// The best line found, starting with the best move
+    pub line: Vec<Move>,
+    /// The work done to get this far
+    pub stats: SearchStats,
+}
+
+/// How much searching went into an analysis, counting every depth up to it.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct SearchStats {
+    /// Positions visited, including the captures searched past the depth limit
+    pub nodes: u64,
+    /// Positions searched to full width...
+    pub full_width: u64,
+    /// ...and how many of those were positions the search had already
+    /// reached by way of another move order
+    pub repeated: u64,
+    pub elapsed: Duration,
+}
+
+impl SearchStats {
+    pub fn nodes_per_second(&self) -> u64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            (self.nodes as f64 / seconds) as u64
+        } else {
+            0
+        }
+    }
+
+    /// The share of full-width positions that had been reached before, in
+    /// percent.
+    pub fn repeated_rate(&self) -> f32 {
+        if self.full_width == 0 {
+            return 0.0;
+        }
+        100.0 * self.repeated as f32 / self.full_width as f32
+    }
+
+    fn add(&mut self, search: &Search) {
+        self.nodes += search.nodes;
+        self.full_width += search.full_width;
+        self.repeated += search.repeated;
+    }
+}
+
+// What a search carries from one position to the next: the flag that
+// abandons it and the counts for SearchStats
+struct Search<'a> {
+    stop: &'a AtomicBool,
+    nodes: u64,
+    full_width: u64,
+    repeated: u64,
+    seen: HashSet<u64>,
+}
+
+impl<'a> Search<'a> {
+    fn new(stop: &'a AtomicBool) -> Self {
+        Search {
+            stop,
+            nodes: 0,
+            full_width: 0,
+            repeated: 0,
+            seen: HashSet::new(),
+        }
+    }
+}
+
+impl Analysis {
+    /// Moves until mate if the score is a forced mate: positive when White
+    /// mates, negative when Black does.
+    pub fn mate_in(&self) -> Option<i32> {
+        if self.score.abs() < MATE_THRESHOLD {
+            return None;
+        }
+        let plies = MATE_SCORE - self.score.abs();
+        Some((plies + 1) / 2 * self.score.signum())
+    }
+
+    /// The score as an engine would print it, e.g. "+0.35" or "#-2".
+    pub fn score_text(&self) -> String {
+        match self.mate_in() {
+            Some(moves) => format!("#{moves}"),
+            None => format!("{:+.2}", self.score as f32 / 100.0),
+        }
+    }
+}
+
+impl GameState {
+    /// Picks a move for the side to move: from `book` while still in the
+    /// opening, otherwise by searching `depth` plies ahead. `rng` picks
+    /// between book moves, so the same seed picks the same ones.
+    pub fn choose_ai_move(
+        &self,
+        depth: u32,
+        book: Option<&OpeningBook>,
+        rng: &mut impl Rng,
+    ) -> Option<Move> {
+        if let Some(mv) = book.and_then(|book| book.choose_move(self, rng)) {
+            log::info!("AI chose {} from the book", mv.to_uci_on(&self.board));
+            return Some(mv);
+        }
+
+        let never = AtomicBool::new(false);
+        let (score, line) = self.search_root(depth, &never)?;
+        log::info!(
+            "AI chose {} at depth {depth}, scoring {score:+} for the side to move",
+            line[0].to_uci_on(&self.board)
+        );
+        Some(line[0])
+    }
+
+    /// Searches the position one ply deeper at a time, up to `max_depth`,
+    /// calling `report` with the result of each completed depth. Returns as
+    /// soon as `stop` is set, so it can run on a background thread that
+    /// gives up when the position changes.
+    pub fn analyse(&self, max_depth: u32, stop: &AtomicBool, mut report: impl FnMut(Analysis)) {
+        self.analyse_lines(max_depth, 1, stop, |mut lines| {
+            report(lines.remove(0));
+        });
+    }
+
+    /// As [`GameState::analyse`], but finding the best `lines` moves, each
+    /// with its own score and line (MultiPV), reported best first. There
+    /// are fewer when there aren't as many legal moves.
+    pub fn analyse_lines(
+        &self,
+        max_depth: u32,
+        lines: usize,
+        stop: &AtomicBool,
+        mut report: impl FnMut(Vec<Analysis>),
+    ) {
+        let started = Instant::now();
+        let mut stats = SearchStats::default();
+        for depth in 1..=max_depth {
+            let mut search = Search::new(stop);
+            let best = self.search_lines_from_root(depth, lines.max(1), &mut search);
+            if best.is_empty() {
+                return; // No legal moves
+            }
+            if stop.load(Ordering::Relaxed) {
+                return;
+            }
+            stats.add(&search);
+            stats.elapsed = started.elapsed();
+            let analyses = best
+                .into_iter()
+                .map(|(score, line)| Analysis {
+                    depth,
+                    score: match self.turn {
+                        PieceColor::White => score,
+                        PieceColor::Black => -score,
+                    },
+                    line,
+                    stats,
+                })
+                .collect();
+            report(analyses);
+        }
+    }
+
+    // Searches every move from the root and returns the best score with the
+    // line that leads to it, or None if there are no legal moves.
+    pub(super) fn search_root(&self, depth: u32, stop: &AtomicBool) -> Option<(i32, Vec<Move>)> {
+        self.search_from_root(depth, &mut Search::new(stop))
+    }
+
+    fn search_from_root(&self, depth: u32, search: &mut Search) -> Option<(i32, Vec<Move>)> {
+        self.search_lines_from_root(depth, 1, search)
+            .into_iter()
+            .next()
+    }
+
+    // Searches every move from the root and returns the best `lines` of
+    // them, best first, each with its score and the line it leads to. Only
+    // moves that might make the list are searched with an open window, so
+    // each score in it is exact.
+    fn search_lines_from_root(
+        &self,
+        depth: u32,
+        lines: usize,
+        search: &mut Search,
+    ) -> Vec<(i32, Vec<Move>)> {
+        let mut position = self.clone();
+        position.refresh_accumulator();
+        let mut moves = position.generate_valid_moves(position.turn);
+        position.order_moves(&mut moves);
+
+        let mut best: Vec<(i32, Vec<Move>)> = Vec::new();
+        for mv in moves {
+            // The score a move has to beat to make the list
+            let alpha = match best.get(lines - 1) {
+                Some(&(score, _)) => score,
+                None => -INFINITY,
+            };
+            let mut line = Vec::new();
+            let undo = position.make_move(mv);
+            let score = -position.alpha_beta(
+                depth.saturating_sub(1),
+                -INFINITY,
+                -alpha,
+                1,
+                &mut line,
+                search,
+            );
+            position.unmake_move(mv, undo);
+
+            if best.len() < lines || score > alpha {
+                line.insert(0, mv);
+                let index = best.partition_point(|&(other, _)| other >= score);
+                best.insert(index, (score, line));
+                best.truncate(lines);
+            }
+        }
+
+        best
+    }
+
+    // Searches every move from the root with a full window, so that each gets
+    // its exact score rather than just the best one.
+    pub(super) fn score_root_moves(&self, depth: u32) -> Vec<(Move, i32)> {
+        self.generate_valid_moves(self.turn)
+            .into_iter()
+            .map(|mv| (mv, self.score_move(mv, depth)))
+            .collect()
+    }
+
+    // The exact score of playing `mv` here, searched to the same depth as
+    // search_root would, from the point of view of the side to move.
+    pub(super) fn score_move(&self, mv: Move, depth: u32) -> i32 {
+        let never = AtomicBool::new(false);
+        let mut position = self.clone();
+        position.refresh_accumulator();
+        let mut line = Vec::new();
+        position.make_move(mv);
+        -position.alpha_beta(
+            depth.saturating_sub(1),
+            -INFINITY,
+            INFINITY,
+            1,
+            &mut line,
+            &mut Search::new(&never),
+        )
+    }
+
+    // Negamax search with alpha-beta pruning; scores are from the point of
+    // view of the side to move. The best line found is left in `pv`. Once
+    // `stop` is set the scores are meaningless and the caller must discard
+    // them.
+    fn alpha_beta(
+        &mut self,
+        depth: u32,
+        mut alpha: i32,
+        beta: i32,
+        ply: i32,
+        pv: &mut Vec<Move>,
+        search: &mut Search,
+    ) -> i32 {
+        pv.clear();
+        if search.stop.load(Ordering::Relaxed) {
+            return 0;
+        }
+        // A variant win usually goes to the side that just moved, but in
+        // Antichess it can be the side that has just lost its last piece
+        if let Some(winner) = self.variant_winner() {
+            return if winner == self.turn {
+                MATE_SCORE - ply
+            } else {
+                -MATE_SCORE + ply
+            };
+        }
+        if depth == 0 {
+            return self.quiescence(alpha, beta, search);
+        }
+        search.nodes += 1;
+        search.full_width += 1;
+        if !search.seen.insert(self.zobrist) {
+            search.repeated += 1;
+        }
+
+        let mut moves = self.generate_valid_moves(self.turn);
+        if moves.is_empty() {
+            return if self.variant == Variant::Antichess {
+                MATE_SCORE - ply // Having no move wins
+            } else if self.is_king_in_check(self.turn) {
+                -MATE_SCORE + ply // Prefer the quickest mate
+            } else {
+                0 // Stalemate
+            };
+        }
+        self.order_moves(&mut moves);
+
+        let mut line = Vec::new();
+        for mv in moves {
+            let undo = self.make_move(mv);
+            let score = -self.alpha_beta(depth - 1, -beta, -alpha, ply + 1, &mut line, search);
+            self.unmake_move(mv, undo);
+
+            if score >= beta {
+                return beta;
+            }
+            if score > alpha {
+                alpha = score;
+                pv.clear();
+                pv.push(mv);
+                pv.append(&mut line);
+            }
+        }
+
+        alpha
+    }
+
+    // Keeps searching captures past the depth limit so the evaluation isn't
+    // taken in the middle of an exchange.
+    fn quiescence(&mut self, mut alpha: i32, beta: i32, search: &mut Search) -> i32 {
+        search.nodes += 1;
+        if let Some(winner) = self.variant_winner() {
+            return if winner == self.turn {
+                MATE_SCORE
+            } else {
+                -MATE_SCORE
+            };
+        }
+        let moves = self.generate_valid_moves(self.turn);
+        if moves.is_empty() && self.variant == Variant::Antichess {
+            return MATE_SCORE;
+        }
+        let mut captures: Vec<Move> = moves.into_iter().filter(|mv| mv.is_capture(self)).collect();
+
+        // Standing pat means declining every capture, which isn't allowed
+        // where captures are compulsory
+        if !self.variant.forces_captures() || captures.is_empty() {
+            let stand_pat = self.evaluate();
+            if stand_pat >= beta {
+                return beta;
+            }
+            alpha = alpha.max(stand_pat);
+        }
+        self.order_moves(&mut captures);
+
+        for mv in captures {
+            let undo = self.make_move(mv);
+            let score = -self.quiescence(-beta, -alpha, search);
+            self.unmake_move(mv, undo);
+
+            if score >= beta {
+                return beta;
+            }
+            alpha = alpha.max(score);
+        }
+
+        alpha
+    }
+
+    // Tries the most promising moves first so alpha-beta can cut more:
+    // captures by most valuable victim / least valuable attacker, then quiet
+    // moves by how much they improve the piece's square.
+    fn order_moves(&self, moves: &mut [Move]) {
+        moves.sort_by_cached_key(|mv| {
+            let moving_piece = self.board.squares[mv.from.0][mv.from.1].occupant.unwrap();
+            let key = if mv.is_capture(self) {
+                let victim = self.board.squares[mv.to.0][mv.to.1]
+                    .occupant
+                    .map_or(PieceType::Pawn, |p| p.piece_type);
+                10_000 + 10 * piece_value(victim) - piece_value(moving_piece.piece_type)
+            } else {
+                let board = &self.board;
+                square_bonus(board, moving_piece.piece_type, moving_piece.color, mv.to)
+                    - square_bonus(board, moving_piece.piece_type, moving_piece.color, mv.from)
+            };
+            -key
+        });
+    }
+}
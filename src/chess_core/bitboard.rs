@@ -0,0 +1,722 @@
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+use super::board::{ChessBoard, PieceColor, PieceType, MAX_FILES, MAX_RANKS};
+use super::castling::CastlingRights;
+use super::game_state::{GameState, Move};
+
+/// A set of squares, one bit per square: bit `row * files + col`, where
+/// `files` is the width of the board.
+pub(super) type Bitboard = u128;
+
+// Ray directions as (row, col) steps. The first four head towards higher
+// square indices, the last four towards lower ones.
+const DIRECTIONS: [(isize, isize); 8] = [
+    (1, 0),
+    (0, 1),
+    (1, 1),
+    (1, -1),
+    (-1, 0),
+    (0, -1),
+    (-1, -1),
+    (-1, 1),
+];
+const ROOK_DIRECTIONS: [usize; 4] = [0, 1, 4, 5];
+const BISHOP_DIRECTIONS: [usize; 4] = [2, 3, 6, 7];
+
+// The number of kinds of piece, the length of per-type tables
+const PIECE_TYPES: usize = PieceType::ALL.len();
+
+/// The attack tables for one size of board, worked out the first time a
+/// board of that size is seen and kept from then on.
+pub(super) struct Geometry {
+    files: usize,
+    ranks: usize,
+    // Squares each kind of piece leaps to from each square, by type index
+    leaps: [Vec<Bitboard>; PIECE_TYPES],
+    // Squares a pawn of each colour attacks from each square (white moves
+    // up the board, towards row 0)
+    pawn_attacks: [Vec<Bitboard>; 2],
+    // Every square from each square to the edge of the board in each
+    // direction
+    rays: [Vec<Bitboard>; 8],
+    file_masks: Vec<Bitboard>, // Every square of each file
+}
+
+impl Geometry {
+    /// The tables for a board `files` wide and `ranks` high.
+    pub(super) fn of(files: usize, ranks: usize) -> &'static Geometry {
+        // The standard board is looked up on every move, so skips the lock
+        static STANDARD: OnceLock<Geometry> = OnceLock::new();
+        static OTHERS: Mutex<Vec<&'static Geometry>> = Mutex::new(Vec::new());
+        let standard = STANDARD.get_or_init(|| Geometry::new(8, 8));
+        if (files, ranks) == (standard.files, standard.ranks) {
+            return standard;
+        }
+        let mut others = OTHERS.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(geometry) = others.iter().find(|g| (g.files, g.ranks) == (files, ranks)) {
+            return geometry;
+        }
+        let geometry: &'static Geometry = Box::leak(Box::new(Geometry::new(files, ranks)));
+        others.push(geometry);
+        geometry
+    }
+
+    /// The tables for the standard board.
+    pub(super) fn standard() -> &'static Geometry {
+        Geometry::of(8, 8)
+    }
+
+    fn new(files: usize, ranks: usize) -> Self {
+        debug_assert!(files <= MAX_FILES && ranks <= MAX_RANKS);
+        let mut geometry = Geometry {
+            files,
+            ranks,
+            leaps: Default::default(),
+            pawn_attacks: Default::default(),
+            rays: Default::default(),
+            file_masks: Vec::new(),
+        };
+        geometry.leaps =
+            PieceType::ALL.map(|piece_type| geometry.leaper_table(piece_type.movement().leaps));
+        geometry.pawn_attacks = [
+            geometry.leaper_table(&[(-1, -1), (-1, 1)]),
+            geometry.leaper_table(&[(1, -1), (1, 1)]),
+        ];
+        geometry.rays = DIRECTIONS.map(|(dr, dc)| {
+            (0..geometry.len())
+                .map(|square| {
+                    let (row, col) = geometry.square(square);
+                    let (mut r, mut c) = (row as isize + dr, col as isize + dc);
+                    let mut ray = 0;
+                    while geometry.on_board(r, c) {
+                        ray |= geometry.bit((r as usize, c as usize));
+                        (r, c) = (r + dr, c + dc);
+                    }
+                    ray
+                })
+                .collect()
+        });
+        geometry.file_masks = (0..files)
+            .map(|col| (0..ranks).fold(0, |mask, row| mask | geometry.bit((row, col))))
+            .collect();
+        geometry
+    }
+
+    // The number of squares
+    fn len(&self) -> usize {
+        self.files * self.ranks
+    }
+
+    pub(super) fn files(&self) -> usize {
+        self.files
+    }
+
+    pub(super) fn ranks(&self) -> usize {
+        self.ranks
+    }
+
+    fn on_board(&self, row: isize, col: isize) -> bool {
+        row >= 0 && row < self.ranks as isize && col >= 0 && col < self.files as isize
+    }
+
+    // Attack sets for pieces that jump a fixed set of offsets.
+    fn leaper_table(&self, offsets: &[(isize, isize)]) -> Vec<Bitboard> {
+        (0..self.len())
+            .map(|square| {
+                let (row, col) = self.square(square);
+                offsets
+                    .iter()
+                    .map(|&(dr, dc)| (row as isize + dr, col as isize + dc))
+                    .filter(|&(r, c)| self.on_board(r, c))
+                    .fold(0, |table, (r, c)| {
+                        table | self.bit((r as usize, c as usize))
+                    })
+            })
+            .collect()
+    }
+
+    /// The index of `square`, its bit in a bitboard.
+    pub(super) fn index(&self, (row, col): (usize, usize)) -> usize {
+        row * self.files + col
+    }
+
+    /// The square with index `index`.
+    pub(super) fn square(&self, index: usize) -> (usize, usize) {
+        (index / self.files, index % self.files)
+    }
+
+    /// The bitboard of just `square`.
+    pub(super) fn bit(&self, square: (usize, usize)) -> Bitboard {
+        1 << self.index(square)
+    }
+
+    /// Every square on file `col`.
+    pub(super) fn file(&self, col: usize) -> Bitboard {
+        self.file_masks[col]
+    }
+
+    // Squares a slider reaches in one direction, up to and including the
+    // first occupied square.
+    fn ray_attacks(&self, dir: usize, square: usize, occupied: Bitboard) -> Bitboard {
+        let ray = self.rays[dir][square];
+        let blockers = ray & occupied;
+        if blockers == 0 {
+            return ray;
+        }
+        let first_blocker = if dir < 4 {
+            blockers.trailing_zeros()
+        } else {
+            Bitboard::BITS - 1 - blockers.leading_zeros()
+        };
+        ray ^ self.rays[dir][first_blocker as usize]
+    }
+
+    fn slider_attacks(
+        &self,
+        directions: [usize; 4],
+        square: usize,
+        occupied: Bitboard,
+    ) -> Bitboard {
+        directions.iter().fold(0, |attacks, &dir| {
+            attacks | self.ray_attacks(dir, square, occupied)
+        })
+    }
+}
+
+pub(super) fn color_index(color: PieceColor) -> usize {
+    match color {
+        PieceColor::White => 0,
+        PieceColor::Black => 1,
+    }
+}
+
+/// The index of `piece_type` into per-type tables, its place in
+/// [`PieceType::ALL`].
+pub(super) const fn type_index(piece_type: PieceType) -> usize {
+    piece_type as usize
+}
+
+// Iterates over the squares in a bitboard, lowest index first.
+pub(super) fn squares(mut bitboard: Bitboard) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if bitboard == 0 {
+            return None;
+        }
+        let square = bitboard.trailing_zeros() as usize;
+        bitboard &= bitboard - 1;
+        Some(square)
+    })
+}
+
+/// The board as one bitboard per colour and piece type.
+#[derive(Copy, Clone)]
+pub(super) struct Bitboards {
+    pieces: [[Bitboard; PIECE_TYPES]; 2], // Indexed by colour, then piece type
+    colors: [Bitboard; 2],
+    geometry: &'static Geometry, // The size of the board
+}
+
+impl Bitboards {
+    pub(super) fn from_board(board: &ChessBoard) -> Self {
+        let mut bitboards = Bitboards {
+            pieces: [[0; PIECE_TYPES]; 2],
+            colors: [0; 2],
+            geometry: Geometry::of(board.files, board.ranks),
+        };
+        for row in 0..board.ranks {
+            for col in 0..board.files {
+                if let Some(piece) = board.squares[row][col].occupant {
+                    bitboards.put(
+                        bitboards.geometry.index((row, col)),
+                        color_index(piece.color),
+                        type_index(piece.piece_type),
+                    );
+                }
+            }
+        }
+        bitboards
+    }
+
+    /// A standard board with just `pieces` on it, as (square index, colour
+    /// index, type) triples.
+    pub(super) fn with_pieces(pieces: &[(usize, usize, PieceType)]) -> Self {
+        let mut bitboards = Bitboards {
+            pieces: [[0; PIECE_TYPES]; 2],
+            colors: [0; 2],
+            geometry: Geometry::standard(),
+        };
+        for &(square, color, piece_type) in pieces {
+            bitboards.put(square, color, type_index(piece_type));
+        }
+        bitboards
+    }
+
+    pub(super) fn geometry(&self) -> &'static Geometry {
+        self.geometry
+    }
+
+    pub(super) fn occupied(&self) -> Bitboard {
+        self.colors[0] | self.colors[1]
+    }
+
+    /// The pieces of colour index `color`.
+    pub(super) fn color(&self, color: usize) -> Bitboard {
+        self.colors[color]
+    }
+
+    /// The pieces of colour index `color` and type index `kind`.
+    pub(super) fn pieces(&self, color: usize, kind: usize) -> Bitboard {
+        self.pieces[color][kind]
+    }
+
+    /// Squares a piece of colour index `color` on `square` attacks, whoever
+    /// stands on them. Pawns attack only diagonally and kings don't castle.
+    pub(super) fn attacks(&self, piece_type: PieceType, color: usize, square: usize) -> Bitboard {
+        let geometry = self.geometry;
+        if piece_type == PieceType::Pawn {
+            return geometry.pawn_attacks[color][square];
+        }
+        let movement = piece_type.movement();
+        let occupied = self.occupied();
+        let mut attacks = geometry.leaps[type_index(piece_type)][square];
+        if movement.straight {
+            attacks |= geometry.slider_attacks(ROOK_DIRECTIONS, square, occupied);
+        }
+        if movement.diagonal {
+            attacks |= geometry.slider_attacks(BISHOP_DIRECTIONS, square, occupied);
+        }
+        attacks
+    }
+
+    fn put(&mut self, square: usize, color: usize, kind: usize) {
+        self.pieces[color][kind] |= 1 << square;
+        self.colors[color] |= 1 << square;
+    }
+
+    fn remove(&mut self, square: usize, color: usize, kind: usize) {
+        self.pieces[color][kind] &= !(1 << square);
+        self.colors[color] &= !(1 << square);
+    }
+
+    /// Whether any piece of colour index `by` attacks `square`.
+    pub(super) fn is_attacked(&self, square: usize, by: usize) -> bool {
+        let pieces = &self.pieces[by];
+        let geometry = self.geometry;
+        // A pawn of `by` attacks `square` from where the other side's pawn
+        // on `square` would attack
+        if geometry.pawn_attacks[1 - by][square] & pieces[type_index(PieceType::Pawn)] != 0 {
+            return true;
+        }
+
+        // Every other piece moves alike both ways, so attacks `square` from
+        // the squares it would reach from there
+        let mut straight = 0;
+        let mut diagonal = 0;
+        for piece_type in PieceType::ALL {
+            let kind = type_index(piece_type);
+            if geometry.leaps[kind][square] & pieces[kind] != 0 {
+                return true;
+            }
+            let movement = piece_type.movement();
+            if movement.straight {
+                straight |= pieces[kind];
+            }
+            if movement.diagonal {
+                diagonal |= pieces[kind];
+            }
+        }
+        let occupied = self.occupied();
+        (straight != 0
+            && geometry.slider_attacks(ROOK_DIRECTIONS, square, occupied) & straight != 0)
+            || (diagonal != 0
+                && geometry.slider_attacks(BISHOP_DIRECTIONS, square, occupied) & diagonal != 0)
+    }
+}
+
+impl GameState {
+    /// Whether `square` is attacked by the opponent of `defender`.
+    pub fn is_square_attacked(&self, square: (usize, usize), defender: PieceColor) -> bool {
+        let bitboards = Bitboards::from_board(&self.board);
+        bitboards.is_attacked(
+            bitboards.geometry.index(square),
+            color_index(defender.opposite()),
+        )
+    }
+
+    /// The pieces of `color` the other side attacks, each with whether it
+    /// hangs: nothing defends it, or a cheaper piece attacks it. The king is
+    /// left out, being in check instead.
+    pub fn threatened_pieces(&self, color: PieceColor) -> Vec<((usize, usize), bool)> {
+        let mut cheapest_first = PieceType::ALL;
+        cheapest_first.sort_by_key(|&piece_type| match piece_type {
+            PieceType::King => u32::MAX,
+            piece_type => piece_type.points(),
+        });
+        let bitboards = Bitboards::from_board(&self.board);
+        let us = color_index(color);
+        let mut threatened = Vec::new();
+        for square in squares(bitboards.colors[us]) {
+            let (row, col) = bitboards.geometry.square(square);
+            let Some(piece) = self.board.squares[row][col].occupant else {
+                continue;
+            };
+            if piece.piece_type == PieceType::King {
+                continue;
+            }
+            // A piece of ours on `square` would attack the squares the same
+            // kind of piece attacks it from
+            let cheapest = cheapest_first.into_iter().find(|&attacker| {
+                bitboards.attacks(attacker, us, square)
+                    & bitboards.pieces(1 - us, type_index(attacker))
+                    != 0
+            });
+            let Some(attacker) = cheapest else {
+                continue;
+            };
+            let hangs = !bitboards.is_attacked(square, us)
+                || (attacker != PieceType::King && attacker.points() < piece.piece_type.points());
+            threatened.push(((row, col), hangs));
+        }
+        threatened
+    }
+
+    /// How many pieces of `color` attack each square, indexed by row and
+    /// column: a square's attackers or, if one of `color`'s own pieces stands
+    /// there, its defenders. Pieces behind another on the same line don't
+    /// count until it moves.
+    pub fn square_control(&self, color: PieceColor) -> [[u8; MAX_FILES]; MAX_RANKS] {
+        let bitboards = Bitboards::from_board(&self.board);
+        let geometry = bitboards.geometry;
+        let us = color_index(color);
+        let mut control = [[0; MAX_FILES]; MAX_RANKS];
+        for from in squares(bitboards.colors[us]) {
+            let (row, col) = geometry.square(from);
+            let Some(piece) = self.board.squares[row][col].occupant else {
+                continue;
+            };
+            for target in squares(bitboards.attacks(piece.piece_type, us, from)) {
+                let (row, col) = geometry.square(target);
+                control[row][col] += 1;
+            }
+        }
+        control
+    }
+
+    /// All legal moves for `color`, which must be the side to move. Pawn
+    /// moves to the last rank are listed once and promote to a queen unless
+    /// the move is given another piece.
+    pub fn generate_valid_moves(&self, color: PieceColor) -> Vec<Move> {
+        // Only the side to move has legal moves
+        if color != self.turn {
+            return Vec::new();
+        }
+        let bitboards = Bitboards::from_board(&self.board);
+        self.legal_moves_from(&bitboards, bitboards.colors[color_index(color)])
+    }
+
+    // Legal moves for the side to move of the pieces on `origins`, ordered by
+    // origin square and then destination square. Where captures are
+    // compulsory and any piece of the side to move can make one, only
+    // captures are legal, so that looks at every piece, not just `origins`.
+    pub(super) fn legal_moves_from(&self, bitboards: &Bitboards, origins: Bitboard) -> Vec<Move> {
+        // Nothing more to play once the variant's win condition has been met
+        if self.variant_winner().is_some() {
+            return Vec::new();
+        }
+
+        let geometry = bitboards.geometry;
+        let us = color_index(self.turn);
+        let them = 1 - us;
+        let own = bitboards.colors[us];
+        let enemy = bitboards.colors[them];
+        let occupied = own | enemy;
+        let en_passant = self
+            .en_passant_target
+            .map_or(0, |target| geometry.bit(target));
+        let king_square = squares(bitboards.pieces[us][type_index(PieceType::King)]).next();
+
+        // Each piece's destinations, and the ones among them that capture
+        let targets = |from: usize| {
+            let (row, col) = geometry.square(from);
+            let piece = self.board.squares[row][col].occupant?;
+            let targets = match piece.piece_type {
+                PieceType::Pawn => self.pawn_targets(geometry, from, occupied, enemy | en_passant),
+                PieceType::King => {
+                    geometry.leaps[type_index(PieceType::King)][from] & !own
+                        | self.castling_targets(bitboards, from)
+                }
+                piece_type => bitboards.attacks(piece_type, us, from) & !own,
+            };
+            let captures = if piece.piece_type == PieceType::Pawn {
+                enemy | en_passant
+            } else {
+                enemy
+            };
+            Some((piece, targets, targets & captures))
+        };
+        let must_capture = self.variant.forces_captures()
+            && squares(own).any(|from| targets(from).is_some_and(|(_, _, captures)| captures != 0));
+
+        let mut moves = Vec::new();
+        for from in squares(origins & own) {
+            let Some((piece, targets, captures)) = targets(from) else {
+                continue;
+            };
+            let targets = if must_capture { captures } else { targets };
+
+            for to in squares(targets) {
+                // Without check there is no king to keep safe
+                if !self.variant.has_check()
+                    || self.leaves_king_safe(bitboards, piece.piece_type, from, to, king_square)
+                {
+                    moves.push(Move::new(geometry.square(from), geometry.square(to)));
+                }
+            }
+        }
+        moves
+    }
+
+    fn pawn_targets(
+        &self,
+        geometry: &Geometry,
+        from: usize,
+        occupied: Bitboard,
+        capturable: Bitboard,
+    ) -> Bitboard {
+        let (row, col) = geometry.square(from);
+        let forward: isize = if self.turn == PieceColor::White {
+            -1
+        } else {
+            1
+        };
+
+        let mut targets = geometry.pawn_attacks[color_index(self.turn)][from] & capturable;
+        let one_step = row as isize + forward;
+        if geometry.on_board(one_step, col as isize) {
+            let one_step = geometry.bit((one_step as usize, col));
+            if occupied & one_step == 0 {
+                targets |= one_step;
+                // Pawns still on their starting rank may advance two squares
+                let two_step = row as isize + 2 * forward;
+                if row == self.board.pawn_row(self.turn)
+                    && geometry.on_board(two_step, col as isize)
+                {
+                    let two_step = geometry.bit((two_step as usize, col));
+                    if occupied & two_step == 0 {
+                        targets |= two_step;
+                    }
+                }
+            }
+        }
+        targets
+    }
+
+    // Castling destinations for a king on its home square: the right must
+    // still be held, the squares between king and rook empty, the rook in
+    // its corner, and the king may not castle out of, through or into check.
+    // The king goes to the second file from its corner, as to the g- and
+    // c-files on the standard board, and the rook to the square it crosses.
+    fn castling_targets(&self, bitboards: &Bitboards, from: usize) -> Bitboard {
+        let geometry = bitboards.geometry;
+        let home_row = self.board.home_row(self.turn);
+        let king_col = self.board.king_file();
+        if from != geometry.index((home_row, king_col)) {
+            return 0;
+        }
+
+        let them = 1 - color_index(self.turn);
+        let occupied = bitboards.occupied();
+        let mut targets = 0;
+        for (king_side, right) in [
+            (true, CastlingRights::kingside(self.turn)),
+            (false, CastlingRights::queenside(self.turn)),
+        ] {
+            if !self.castling_rights.contains(right) {
+                continue;
+            }
+
+            let Some(king_to) = self.board.castled_king_file(king_side) else {
+                continue;
+            };
+            let rook_col = if king_side { self.board.files - 1 } else { 0 };
+            let between = (rook_col.min(king_col) + 1..rook_col.max(king_col))
+                .fold(0, |mask, col| mask | geometry.bit((home_row, col)));
+            let rook_in_place = self.board.squares[home_row][rook_col]
+                .occupant
+                .is_some_and(|p| p.piece_type == PieceType::Rook && p.color == self.turn);
+            if occupied & between != 0 || !rook_in_place {
+                continue;
+            }
+
+            let king_path_safe = (king_to.min(king_col)..=king_to.max(king_col))
+                .all(|col| !bitboards.is_attacked(geometry.index((home_row, col)), them));
+            if king_path_safe {
+                targets |= geometry.bit((home_row, king_to));
+            }
+        }
+        targets
+    }
+
+    // Plays the move on a copy of the bitboards and checks that the mover's
+    // king is not then attacked. Positions without a king never count as
+    // check.
+    fn leaves_king_safe(
+        &self,
+        bitboards: &Bitboards,
+        piece_type: PieceType,
+        from: usize,
+        to: usize,
+        king_square: Option<usize>,
+    ) -> bool {
+        let geometry = bitboards.geometry;
+        let us = color_index(self.turn);
+        let them = 1 - us;
+        let kind = type_index(piece_type);
+        let mut after = *bitboards;
+
+        let (from_row, from_col) = geometry.square(from);
+        let (to_row, to_col) = geometry.square(to);
+        if let Some(captured) = self.board.squares[to_row][to_col].occupant {
+            after.remove(to, them, type_index(captured.piece_type));
+        } else if piece_type == PieceType::Pawn && from_col != to_col {
+            // En passant: the captured pawn is beside the moving one
+            let captured = geometry.index((from_row, to_col));
+            after.remove(captured, them, type_index(PieceType::Pawn));
+        }
+        after.remove(from, us, kind);
+        after.put(to, us, kind);
+
+        // Castling is the only king move of more than one file
+        if piece_type == PieceType::King && from_col.abs_diff(to_col) > 1 {
+            let (rook_from, rook_to) = if to > from {
+                (self.board.files - 1, to_col - 1)
+            } else {
+                (0, to_col + 1)
+            };
+            let rook = type_index(PieceType::Rook);
+            after.remove(geometry.index((from_row, rook_from)), us, rook);
+            after.put(geometry.index((from_row, rook_to)), us, rook);
+        }
+
+        let king_square = if piece_type == PieceType::King {
+            Some(to)
+        } else {
+            king_square
+        };
+        king_square.is_none_or(|square| !after.is_attacked(square, them))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess_core::FenError;
+
+    fn position(fen: &str) -> GameState {
+        let mut state = GameState::new();
+        state.from_fen(fen).unwrap();
+        state
+    }
+
+    fn uci_moves(state: &GameState) -> Vec<String> {
+        state
+            .generate_valid_moves(state.turn)
+            .iter()
+            .map(|mv| mv.to_uci_on(&state.board))
+            .collect()
+    }
+
+    fn perft(state: &mut GameState, depth: u32) -> u64 {
+        let moves = state.generate_valid_moves(state.turn);
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        moves
+            .into_iter()
+            .map(|mv| {
+                let undo = state.make_move(mv);
+                let nodes = perft(state, depth - 1);
+                state.unmake_move(mv, undo);
+                nodes
+            })
+            .sum()
+    }
+
+    fn perft_counts(fen: &str, depth: u32) -> Vec<u64> {
+        let mut state = position(fen);
+        (1..=depth).map(|depth| perft(&mut state, depth)).collect()
+    }
+
+    #[test]
+    fn perft_standard_positions() {
+        assert_eq!(
+            perft_counts(
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                3
+            ),
+            [20, 400, 8902]
+        );
+        // Castling both ways, en passant and pins
+        assert_eq!(
+            perft_counts(
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                2
+            ),
+            [48, 2039]
+        );
+        // En passant that would expose the king along the rank
+        assert_eq!(
+            perft_counts("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 3),
+            [14, 191, 2812]
+        );
+        // Promotions with captures, and castling out of check. Each promotion
+        // is one move here (the piece is chosen when it is played), so these
+        // are lower than the usual 6/264/9467.
+        assert_eq!(
+            perft_counts(
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                3
+            ),
+            [6, 228, 8087]
+        );
+    }
+
+    #[test]
+    fn perft_capablanca() {
+        assert_eq!(
+            perft_counts(
+                "rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR w KQkq - 0 1",
+                3
+            ),
+            [28, 784, 25228]
+        );
+    }
+
+    #[test]
+    fn no_castling_without_room_for_the_rook() {
+        let fen = "r2k1r/6/6/6/6/R2K1R w KQkq - 0 1";
+        let state = position(fen);
+        assert_eq!(state.to_fen(), "r2k1r/6/6/6/6/R2K1R w - - 0 1");
+        assert!(!state
+            .generate_valid_moves(state.turn)
+            .iter()
+            .any(|mv| mv.is_castling(&state)));
+        assert!(matches!(
+            GameState::new().from_fen_strict(fen),
+            Err(FenError::ImpossibleCastlingRight(_))
+        ));
+    }
+
+    #[test]
+    fn castling_on_seven_files() {
+        let mut state = position("r2k2r/7/7/7/7/7/R2K2R w Kk - 0 1");
+        let moves = uci_moves(&state);
+        assert!(moves.contains(&"d1f1".to_string()));
+        assert!(!moves.contains(&"d1b1".to_string()));
+
+        let mv = Move::from_uci_on("d1f1", &state.board).unwrap();
+        assert!(mv.is_castling(&state));
+        state.make_move(mv);
+        assert_eq!(state.to_fen(), "r2k2r/7/7/7/7/7/R3RK1 b k - 1 1");
+    }
+}
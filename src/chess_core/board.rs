@@ -0,0 +1,412 @@
+/// The most files and ranks a board can have. Every square then has a
+/// one-letter file and a one-digit rank, and a bitboard has a bit for each.
+pub const MAX_FILES: usize = 12;
+pub const MAX_RANKS: usize = 9;
+/// The fewest files or ranks a board can have.
+pub const MIN_SIZE: usize = 3;
+
+// The size of the board chess is played on
+const STANDARD_SIZE: usize = 8;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum PieceColor {
+    White,
+    Black,
+}
+
+impl PieceColor {
+    pub fn opposite(self) -> Self {
+        match self {
+            PieceColor::White => PieceColor::Black,
+            PieceColor::Black => PieceColor::White,
+        }
+    }
+}
+
+/// A kind of piece. Besides the six of chess there are the two fairy pieces
+/// of Capablanca chess, which combine a knight's leap with a bishop's or a
+/// rook's slide; they can be set up in the editor and from a FEN. A new kind
+/// of piece needs a variant here, its [`Movement`], letter and value, and a
+/// drawing.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum PieceType {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+    /// Moves as a bishop or a knight
+    Archbishop,
+    /// Moves as a rook or a knight
+    Chancellor,
+}
+
+/// How a kind of piece moves and attacks: the squares it leaps to and the
+/// lines it slides along. A pawn's moves depend on its colour and whether it
+/// captures, so it has none here and the move generator handles it, as it
+/// does castling.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Movement {
+    pub leaps: &'static [(isize, isize)], // (row, col) offsets jumped to
+    pub straight: bool,                   // Slides along ranks and files
+    pub diagonal: bool,                   // Slides along diagonals
+}
+
+const KNIGHT_LEAPS: [(isize, isize); 8] = [
+    (-2, -1),
+    (-2, 1),
+    (-1, -2),
+    (-1, 2),
+    (1, -2),
+    (1, 2),
+    (2, -1),
+    (2, 1),
+];
+
+const KING_LEAPS: [(isize, isize); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+
+impl PieceType {
+    /// Every kind of piece, in the order of their indices into per-type
+    /// tables.
+    pub const ALL: [PieceType; 8] = [
+        PieceType::Pawn,
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+        PieceType::King,
+        PieceType::Archbishop,
+        PieceType::Chancellor,
+    ];
+
+    /// The pieces of orthodox chess.
+    pub const STANDARD: [PieceType; 6] = [
+        PieceType::Pawn,
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+        PieceType::King,
+    ];
+
+    /// The usual material count: 1 for a pawn, 3 for a knight or bishop, 5
+    /// for a rook and 9 for a queen, with 7 for an archbishop and 8 for a
+    /// chancellor. Kings don't count.
+    pub fn points(self) -> u32 {
+        match self {
+            PieceType::Pawn => 1,
+            PieceType::Knight | PieceType::Bishop => 3,
+            PieceType::Rook => 5,
+            PieceType::Queen => 9,
+            PieceType::King => 0,
+            PieceType::Archbishop => 7,
+            PieceType::Chancellor => 8,
+        }
+    }
+
+    /// The piece's letter in FEN and SAN, in upper case, e.g. 'N' for a
+    /// knight.
+    pub fn letter(self) -> char {
+        match self {
+            PieceType::Pawn => 'P',
+            PieceType::Knight => 'N',
+            PieceType::Bishop => 'B',
+            PieceType::Rook => 'R',
+            PieceType::Queen => 'Q',
+            PieceType::King => 'K',
+            PieceType::Archbishop => 'A',
+            PieceType::Chancellor => 'C',
+        }
+    }
+
+    /// The piece with `letter`, in either case.
+    pub fn from_letter(letter: char) -> Option<Self> {
+        let letter = letter.to_ascii_uppercase();
+        PieceType::ALL
+            .into_iter()
+            .find(|piece_type| piece_type.letter() == letter)
+    }
+
+    /// How the piece moves; see [`Movement`].
+    pub const fn movement(self) -> Movement {
+        let (leaps, straight, diagonal): (&[(isize, isize)], bool, bool) = match self {
+            PieceType::Pawn => (&[], false, false),
+            PieceType::Knight => (&KNIGHT_LEAPS, false, false),
+            PieceType::Bishop => (&[], false, true),
+            PieceType::Rook => (&[], true, false),
+            PieceType::Queen => (&[], true, true),
+            PieceType::King => (&KING_LEAPS, false, false),
+            PieceType::Archbishop => (&KNIGHT_LEAPS, false, true),
+            PieceType::Chancellor => (&KNIGHT_LEAPS, true, false),
+        };
+        Movement {
+            leaps,
+            straight,
+            diagonal,
+        }
+    }
+
+    /// The fairy pieces, which orthodox chess doesn't have.
+    pub fn is_fairy(self) -> bool {
+        !PieceType::STANDARD.contains(&self)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Piece {
+    pub piece_type: PieceType,
+    pub color: PieceColor,
+    pub has_moved: bool,
+}
+
+impl Piece {
+    /// The piece's Unicode chess symbol, e.g. '♘' for a white knight. The
+    /// fairy pieces have none, and are shown by their letter, in upper case
+    /// for White and lower case for Black as in FEN.
+    pub fn symbol(self) -> char {
+        let symbols = match self.color {
+            PieceColor::White => ['♙', '♘', '♗', '♖', '♕', '♔'],
+            PieceColor::Black => ['♟', '♞', '♝', '♜', '♛', '♚'],
+        };
+        match self.piece_type {
+            PieceType::Pawn => symbols[0],
+            PieceType::Knight => symbols[1],
+            PieceType::Bishop => symbols[2],
+            PieceType::Rook => symbols[3],
+            PieceType::Queen => symbols[4],
+            PieceType::King => symbols[5],
+            PieceType::Archbishop | PieceType::Chancellor => match self.color {
+                PieceColor::White => self.piece_type.letter(),
+                PieceColor::Black => self.piece_type.letter().to_ascii_lowercase(),
+            },
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Square {
+    pub occupant: Option<Piece>,
+}
+
+/// The grid of squares, `files` wide and `ranks` high: 8x8, or another size
+/// set up from a FEN, such as 10x8 for Capablanca chess or 5x6 for
+/// minichess. Row 0 is the top rank (Black's back rank) and column 0 is the
+/// a-file. Squares past the last file or rank are always empty.
+#[derive(Copy, Clone)]
+pub struct ChessBoard {
+    pub files: usize,
+    pub ranks: usize,
+    pub squares: [[Square; MAX_FILES]; MAX_RANKS],
+}
+
+impl ChessBoard {
+    /// An empty standard board.
+    pub fn empty() -> Self {
+        ChessBoard {
+            files: STANDARD_SIZE,
+            ranks: STANDARD_SIZE,
+            squares: [[Square { occupant: None }; MAX_FILES]; MAX_RANKS],
+        }
+    }
+
+    /// An empty board `files` wide and `ranks` high, if a board can be that
+    /// size.
+    pub fn with_size(files: usize, ranks: usize) -> Option<Self> {
+        let fits =
+            (MIN_SIZE..=MAX_FILES).contains(&files) && (MIN_SIZE..=MAX_RANKS).contains(&ranks);
+        fits.then(|| ChessBoard {
+            files,
+            ranks,
+            ..ChessBoard::empty()
+        })
+    }
+
+    /// Whether this is the 8x8 board of orthodox chess, the only size the
+    /// opening book, the endgame tablebase and the evaluation network know.
+    pub fn is_standard_size(&self) -> bool {
+        (self.files, self.ranks) == (STANDARD_SIZE, STANDARD_SIZE)
+    }
+
+    /// Whether `square` is on the board.
+    pub fn contains(&self, (row, col): (usize, usize)) -> bool {
+        row < self.ranks && col < self.files
+    }
+
+    /// The row of `color`'s back rank, where its king starts.
+    pub fn home_row(&self, color: PieceColor) -> usize {
+        match color {
+            PieceColor::White => self.ranks - 1,
+            PieceColor::Black => 0,
+        }
+    }
+
+    /// The row `color`'s pawns start on, from where they may move two
+    /// squares.
+    pub fn pawn_row(&self, color: PieceColor) -> usize {
+        match color {
+            PieceColor::White => self.ranks - 2,
+            PieceColor::Black => 1,
+        }
+    }
+
+    /// Whether `row` is the first or last rank, where pawns promote.
+    pub fn is_back_rank(&self, row: usize) -> bool {
+        row == 0 || row == self.ranks - 1
+    }
+
+    /// The file kings castle from: the e-file, or the middle one (the one to
+    /// the right of the middle on an even number of files).
+    pub fn king_file(&self) -> usize {
+        self.files / 2
+    }
+
+    /// The file the king lands on when castling to that side: next to the
+    /// corner, as the g- and c-files are. `None` on boards too narrow for
+    /// the king to move the two files that leave room for the rook beside it.
+    pub fn castled_king_file(&self, king_side: bool) -> Option<usize> {
+        let king_to = if king_side { self.files - 2 } else { 2 };
+        let king_col = self.king_file();
+        (king_to.abs_diff(king_col) >= 2 && (king_to > king_col) == king_side).then_some(king_to)
+    }
+
+    /// Whether `square` is a light square; the bottom right corner always is.
+    pub fn is_light(&self, (row, col): (usize, usize)) -> bool {
+        (self.ranks - 1 - row + self.files - 1 - col).is_multiple_of(2)
+    }
+
+    /// The name of `square` on this board, e.g. "e4", with the ranks counted
+    /// up from White's side.
+    pub fn square_name(&self, (row, col): (usize, usize)) -> String {
+        let file = (b'a' + col as u8) as char;
+        format!("{file}{}", self.ranks - row)
+    }
+
+    /// The square called `name` on this board, if there is one.
+    pub fn parse_square(&self, name: &str) -> Option<(usize, usize)> {
+        let mut chars = name.chars();
+        let (file, rank) = (chars.next()?, chars.next()?.to_digit(10)? as usize);
+        if chars.next().is_some() || !file.is_ascii_lowercase() {
+            return None;
+        }
+        let col = (file as u8 - b'a') as usize;
+        let row = self.ranks.checked_sub(rank)?;
+        Some((row, col)).filter(|&square| self.contains(square))
+    }
+
+    /// The total points of `color`'s pieces on the board.
+    pub fn material(&self, color: PieceColor) -> u32 {
+        self.squares
+            .iter()
+            .flatten()
+            .filter_map(|square| square.occupant)
+            .filter(|piece| piece.color == color)
+            .map(|piece| piece.piece_type.points())
+            .sum()
+    }
+
+    /// The board drawn in text for a terminal: a line per rank with its
+    /// number, the pieces as Unicode chess symbols and a dot for an empty
+    /// square, and the files lettered underneath. White is at the bottom
+    /// unless `black_at_bottom`.
+    pub fn to_text(&self, black_at_bottom: bool) -> String {
+        // Rows and columns both run the other way round with Black at the bottom
+        let mut rows: Vec<usize> = (0..self.ranks).collect();
+        let mut cols: Vec<usize> = (0..self.files).collect();
+        if black_at_bottom {
+            rows.reverse();
+            cols.reverse();
+        }
+        let mut text = String::new();
+        for &row in &rows {
+            text.push_str(&(self.ranks - row).to_string());
+            for &col in &cols {
+                text.push(' ');
+                text.push(self.squares[row][col].occupant.map_or('·', Piece::symbol));
+            }
+            text.push('\n');
+        }
+        text.push(' ');
+        for &col in &cols {
+            text.push(' ');
+            text.push((b'a' + col as u8) as char);
+        }
+        text
+    }
+
+    pub fn new_standard() -> Self {
+        let mut board = Self::empty();
+
+        // Place pawns
+        for file in 0..board.files {
+            board.squares[6][file].occupant = Some(Piece {
+                piece_type: PieceType::Pawn,
+                color: PieceColor::White,
+                has_moved: false,
+            });
+            board.squares[1][file].occupant = Some(Piece {
+                piece_type: PieceType::Pawn,
+                color: PieceColor::Black,
+                has_moved: false,
+            });
+        }
+
+        // Place back ranks with `has_moved` set to false
+        fn place_back_rank(row: usize, color: PieceColor, board: &mut ChessBoard) {
+            board.squares[row][0].occupant = Some(Piece {
+                piece_type: PieceType::Rook,
+                color,
+                has_moved: false,
+            });
+            board.squares[row][7].occupant = Some(Piece {
+                piece_type: PieceType::Rook,
+                color,
+                has_moved: false,
+            });
+            board.squares[row][1].occupant = Some(Piece {
+                piece_type: PieceType::Knight,
+                color,
+                has_moved: false,
+            });
+            board.squares[row][6].occupant = Some(Piece {
+                piece_type: PieceType::Knight,
+                color,
+                has_moved: false,
+            });
+            board.squares[row][2].occupant = Some(Piece {
+                piece_type: PieceType::Bishop,
+                color,
+                has_moved: false,
+            });
+            board.squares[row][5].occupant = Some(Piece {
+                piece_type: PieceType::Bishop,
+                color,
+                has_moved: false,
+            });
+            board.squares[row][3].occupant = Some(Piece {
+                piece_type: PieceType::Queen,
+                color,
+                has_moved: false,
+            });
+            board.squares[row][4].occupant = Some(Piece {
+                piece_type: PieceType::King,
+                color,
+                has_moved: false,
+            });
+        }
+
+        place_back_rank(0, PieceColor::Black, &mut board);
+        place_back_rank(7, PieceColor::White, &mut board);
+
+        board
+    }
+}
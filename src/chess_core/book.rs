@@ -0,0 +1,221 @@
+use rand::Rng;
+
+use super::board::PieceType;
+use super::game_state::{GameState, Move};
+use super::variant::Variant;
+
+// The book is only consulted up to this move number
+const BOOK_MOVE_LIMIT: u32 = 10;
+
+// Each Polyglot entry is a 64-bit key, a 16-bit move, a 16-bit weight and a
+// 32-bit learn value, all big-endian
+const ENTRY_LEN: usize = 16;
+
+// A handful of main lines, enough to vary the AI's first few moves
+const BUILTIN_LINES: [&str; 12] = [
+    "e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5a4 g8f6 e1g1 f8e7",
+    "e2e4 e7e5 g1f3 b8c6 f1c4 f8c5 c2c3 g8f6 d2d3 d7d6",
+    "e2e4 e7e5 g1f3 b8c6 d2d4 e5d4 f3d4 g8f6 d4c6 b7c6",
+    "e2e4 c7c5 g1f3 d7d6 d2d4 c5d4 f3d4 g8f6 b1c3 a7a6",
+    "e2e4 c7c5 g1f3 b8c6 d2d4 c5d4 f3d4 g8f6 b1c3 e7e5",
+    "e2e4 e7e6 d2d4 d7d5 b1c3 g8f6 c1g5 f8e7 e4e5 f6d7",
+    "e2e4 c7c6 d2d4 d7d5 b1c3 d5e4 c3e4 c8f5 e4g3 f5g6",
+    "d2d4 d7d5 c2c4 e7e6 b1c3 g8f6 c1g5 f8e7 e2e3 e8g8",
+    "d2d4 d7d5 c2c4 c7c6 g1f3 g8f6 b1c3 d5c4 a2a4 c8f5",
+    "d2d4 g8f6 c2c4 e7e6 b1c3 f8b4 e2e3 e8g8 f1d3 d7d5",
+    "d2d4 g8f6 c2c4 g7g6 b1c3 f8g7 e2e4 d7d6 g1f3 e8g8",
+    "c2c4 e7e5 b1c3 g8f6 g1f3 b8c6 g2g3 d7d5 c4d5 f6d5",
+];
+
+#[derive(Copy, Clone, Debug)]
+struct BookEntry {
+    key: u64,
+    raw_move: u16,
+    weight: u16,
+}
+
+/// An opening book in Polyglot format: a list of (position key, move,
+/// weight) entries sorted by key.
+pub struct OpeningBook {
+    entries: Vec<BookEntry>,
+}
+
+impl OpeningBook {
+    /// The small book that ships with the game.
+    pub fn builtin() -> Self {
+        let mut entries: Vec<BookEntry> = Vec::new();
+        for line in BUILTIN_LINES {
+            let mut state = GameState::new();
+            for uci in line.split_whitespace() {
+                let mv = Move::from_uci(uci).expect("built-in book move is valid UCI");
+                let key = state.zobrist_key();
+                let raw_move = state.encode_book_move(mv);
+                match entries
+                    .iter_mut()
+                    .find(|e| e.key == key && e.raw_move == raw_move)
+                {
+                    Some(entry) => entry.weight += 1,
+                    None => entries.push(BookEntry {
+                        key,
+                        raw_move,
+                        weight: 1,
+                    }),
+                }
+                state.make_move(mv);
+            }
+        }
+        entries.sort_by_key(|e| e.key);
+        OpeningBook { entries }
+    }
+
+    /// Reads a Polyglot `.bin` book.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("Failed to read book {path}: {e}"))?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if !bytes.len().is_multiple_of(ENTRY_LEN) {
+            return Err("Invalid book: length is not a whole number of entries".to_string());
+        }
+        let mut entries: Vec<BookEntry> = bytes
+            .chunks_exact(ENTRY_LEN)
+            .map(|chunk| BookEntry {
+                key: u64::from_be_bytes(chunk[0..8].try_into().unwrap()),
+                raw_move: u16::from_be_bytes([chunk[8], chunk[9]]),
+                weight: u16::from_be_bytes([chunk[10], chunk[11]]),
+            })
+            .collect();
+        // Books are written sorted, but a stable sort is cheap insurance
+        entries.sort_by_key(|e| e.key);
+        Ok(OpeningBook { entries })
+    }
+
+    /// Writes the book in Polyglot format (with the learn field zeroed).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.entries.len() * ENTRY_LEN);
+        for entry in &self.entries {
+            bytes.extend_from_slice(&entry.key.to_be_bytes());
+            bytes.extend_from_slice(&entry.raw_move.to_be_bytes());
+            bytes.extend_from_slice(&entry.weight.to_be_bytes());
+            bytes.extend_from_slice(&0u32.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Picks a book move for `state`, at random from `rng` in proportion to
+    /// the entry weights. Returns `None` once past the opening or when the
+    /// position isn't in the book.
+    pub fn choose_move(&self, state: &GameState, rng: &mut impl Rng) -> Option<Move> {
+        // Polyglot books only know the standard board, and openings meant to
+        // win material are no use in Antichess
+        if state.fullmove_number > BOOK_MOVE_LIMIT
+            || !state.board.is_standard_size()
+            || state.variant == Variant::Antichess
+        {
+            return None;
+        }
+
+        let key = state.zobrist_key();
+        let start = self.entries.partition_point(|e| e.key < key);
+        let candidates: Vec<(Move, u32)> = self.entries[start..]
+            .iter()
+            .take_while(|e| e.key == key)
+            .filter_map(|e| {
+                let mv = state.decode_book_move(e.raw_move)?;
+                state
+                    .validate_move(mv.from, mv.to)
+                    .then_some((mv, e.weight as u32))
+            })
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let total: u32 = candidates.iter().map(|(_, weight)| weight).sum();
+        if total == 0 {
+            let index = rng.random_range(0..candidates.len());
+            return Some(candidates[index].0);
+        }
+        let mut pick = rng.random_range(0..total);
+        for (mv, weight) in candidates {
+            if pick < weight {
+                return Some(mv);
+            }
+            pick -= weight;
+        }
+        None
+    }
+}
+
+impl GameState {
+    // Polyglot moves pack the to file, to rank, from file, from rank and
+    // promotion piece into three bits each. Castling is written as the king
+    // taking its own rook.
+    fn decode_book_move(&self, raw: u16) -> Option<Move> {
+        let field = |shift: u16| ((raw >> shift) & 7) as usize;
+        let from = (7 - field(9), field(6));
+        let mut to = (7 - field(3), field(0));
+        let promotion = match field(12) {
+            0 => None,
+            1 => Some(PieceType::Knight),
+            2 => Some(PieceType::Bishop),
+            3 => Some(PieceType::Rook),
+            4 => Some(PieceType::Queen),
+            _ => return None,
+        };
+
+        let piece = self.board.squares[from.0][from.1].occupant?;
+        let target = self.board.squares[to.0][to.1].occupant;
+        if piece.piece_type == PieceType::King
+            && target.is_some_and(|t| t.piece_type == PieceType::Rook && t.color == piece.color)
+        {
+            to.1 = if to.1 > from.1 {
+                from.1 + 2
+            } else {
+                from.1 - 2
+            };
+        }
+
+        Some(Move {
+            from,
+            to,
+            promotion,
+        })
+    }
+
+    fn encode_book_move(&self, mv: Move) -> u16 {
+        let mut to = mv.to;
+        if mv.is_castling(self) {
+            to.1 = if mv.to.1 > mv.from.1 { 7 } else { 0 };
+        }
+        let promotion = match mv.promotion {
+            Some(PieceType::Knight) => 1,
+            Some(PieceType::Bishop) => 2,
+            Some(PieceType::Rook) => 3,
+            Some(PieceType::Queen) => 4,
+            _ => 0,
+        };
+        (to.1 | (7 - to.0) << 3 | mv.from.1 << 6 | (7 - mv.from.0) << 9) as u16 | promotion << 12
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    // An entry as another tool would write it: 1. e4 from the start position
+    #[test]
+    fn reads_books_made_elsewhere() {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&0x463b96181691fc9cu64.to_be_bytes());
+        bytes.extend_from_slice(&0x031cu16.to_be_bytes());
+        bytes.extend_from_slice(&1u16.to_be_bytes());
+        bytes.extend_from_slice(&0u32.to_be_bytes());
+        let book = OpeningBook::from_bytes(&bytes).unwrap();
+        let mv = book.choose_move(&GameState::new(), &mut StdRng::seed_from_u64(0));
+        assert_eq!(mv, Move::from_uci("e2e4"));
+    }
+}
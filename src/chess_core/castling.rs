@@ -0,0 +1,153 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::board::{ChessBoard, PieceColor};
+
+/// The castling moves still allowed, as a set of flags. Written in FEN as
+/// some of "KQkq" in that order, or "-" for none.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct CastlingRights(u8);
+
+// Each single right with its FEN letter, in FEN order (also the order of
+// their Zobrist keys)
+const LETTERS: [(CastlingRights, char); 4] = [
+    (CastlingRights::WHITE_KINGSIDE, 'K'),
+    (CastlingRights::WHITE_QUEENSIDE, 'Q'),
+    (CastlingRights::BLACK_KINGSIDE, 'k'),
+    (CastlingRights::BLACK_QUEENSIDE, 'q'),
+];
+
+impl CastlingRights {
+    pub const NONE: Self = CastlingRights(0);
+    pub const WHITE_KINGSIDE: Self = CastlingRights(1);
+    pub const WHITE_QUEENSIDE: Self = CastlingRights(2);
+    pub const BLACK_KINGSIDE: Self = CastlingRights(4);
+    pub const BLACK_QUEENSIDE: Self = CastlingRights(8);
+    pub const ALL: Self = CastlingRights(15);
+
+    pub fn kingside(color: PieceColor) -> Self {
+        match color {
+            PieceColor::White => Self::WHITE_KINGSIDE,
+            PieceColor::Black => Self::BLACK_KINGSIDE,
+        }
+    }
+
+    pub fn queenside(color: PieceColor) -> Self {
+        match color {
+            PieceColor::White => Self::WHITE_QUEENSIDE,
+            PieceColor::Black => Self::BLACK_QUEENSIDE,
+        }
+    }
+
+    /// Both of `color`'s rights.
+    pub fn both(color: PieceColor) -> Self {
+        Self::kingside(color).union(Self::queenside(color))
+    }
+
+    /// The right that depends on a rook standing on `square` of `board`,
+    /// i.e. the one lost when anything moves from or to that corner.
+    pub fn for_corner(board: &ChessBoard, (row, col): (usize, usize)) -> Self {
+        let (last_row, last_col) = (board.ranks - 1, board.files - 1);
+        match (row, col) {
+            (r, 0) if r == last_row => Self::WHITE_QUEENSIDE,
+            (r, c) if r == last_row && c == last_col => Self::WHITE_KINGSIDE,
+            (0, 0) => Self::BLACK_QUEENSIDE,
+            (0, c) if c == last_col => Self::BLACK_KINGSIDE,
+            _ => Self::NONE,
+        }
+    }
+
+    /// Whether every right in `rights` is held.
+    pub fn contains(self, rights: Self) -> bool {
+        self.0 & rights.0 == rights.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn union(self, rights: Self) -> Self {
+        CastlingRights(self.0 | rights.0)
+    }
+
+    pub fn insert(&mut self, rights: Self) {
+        self.0 |= rights.0;
+    }
+
+    pub fn remove(&mut self, rights: Self) {
+        self.0 &= !rights.0;
+    }
+
+    pub fn toggle(&mut self, rights: Self) {
+        self.0 ^= rights.0;
+    }
+
+    /// The single rights held, in FEN order.
+    pub fn iter(self) -> impl Iterator<Item = Self> {
+        LETTERS
+            .into_iter()
+            .map(|(right, _)| right)
+            .filter(move |&right| self.contains(right))
+    }
+
+    /// The FEN letter of a single right.
+    pub fn letter(self) -> Option<char> {
+        LETTERS
+            .iter()
+            .find(|&&(right, _)| right == self)
+            .map(|&(_, letter)| letter)
+    }
+
+    /// The single right written as `letter` in FEN.
+    pub fn from_letter(letter: char) -> Option<Self> {
+        LETTERS
+            .iter()
+            .find(|&&(_, l)| l == letter)
+            .map(|&(right, _)| right)
+    }
+
+    // Which of the four Zobrist castling keys go with these rights
+    pub(super) fn key_indices(self) -> impl Iterator<Item = usize> {
+        LETTERS
+            .into_iter()
+            .enumerate()
+            .filter(move |&(_, (right, _))| self.contains(right))
+            .map(|(i, _)| i)
+    }
+}
+
+impl fmt::Display for CastlingRights {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("-");
+        }
+        for right in self.iter() {
+            write!(f, "{}", right.letter().unwrap_or('?'))?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the castling field of a FEN. Unknown or repeated letters are
+/// rejected; the letters may come in any order.
+impl FromStr for CastlingRights {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "-" {
+            return Ok(CastlingRights::NONE);
+        }
+        if s.is_empty() {
+            return Err(());
+        }
+        let mut rights = CastlingRights::NONE;
+        for letter in s.chars() {
+            let right = CastlingRights::from_letter(letter).ok_or(())?;
+            if rights.contains(right) {
+                return Err(());
+            }
+            rights.insert(right);
+        }
+        Ok(rights)
+    }
+}
@@ -0,0 +1,215 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::board::PieceColor;
+
+/// How a time control gives time back on each move.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ClockMode {
+    /// Fischer increment: the time is added after every move
+    Increment,
+    /// Simple (US) delay: the clock waits that long each move before running
+    Delay,
+    /// Bronstein delay: the time used on a move is given back, up to that much
+    Bronstein,
+}
+
+impl ClockMode {
+    /// The character between the base time and the per-move time, e.g. the
+    /// 'd' in "5d3".
+    pub fn separator(self) -> char {
+        match self {
+            ClockMode::Increment => '+',
+            ClockMode::Delay => 'd',
+            ClockMode::Bronstein => 'b',
+        }
+    }
+
+    /// Splits a time control such as "5d3" into its base time, mode and
+    /// per-move time. Without a separator it is all base time.
+    pub fn split(s: &str) -> (&str, ClockMode, &str) {
+        let modes = [ClockMode::Increment, ClockMode::Delay, ClockMode::Bronstein];
+        for (i, c) in s.char_indices() {
+            let c = c.to_ascii_lowercase();
+            if let Some(&mode) = modes.iter().find(|mode| mode.separator() == c) {
+                return (&s[..i], mode, &s[i + 1..]);
+            }
+        }
+        (s, ClockMode::Increment, "0")
+    }
+}
+
+/// A time control such as "5+3": base time in minutes plus three seconds
+/// per move, given as an increment ("5+3"), a simple delay ("5d3") or a
+/// Bronstein delay ("5b3").
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub increment: Duration, // The per-move time, whichever the mode
+    pub mode: ClockMode,
+}
+
+impl TimeControl {
+    /// How the per-move time is given, to show by the clock: "+3s",
+    /// "delay 3s" or "Bronstein 3s". None if there is none.
+    pub fn mode_label(&self) -> Option<String> {
+        if self.increment.is_zero() {
+            return None;
+        }
+        let seconds = self.increment.as_secs_f64();
+        Some(match self.mode {
+            ClockMode::Increment => format!("+{seconds}s"),
+            ClockMode::Delay => format!("delay {seconds}s"),
+            ClockMode::Bronstein => format!("Bronstein {seconds}s"),
+        })
+    }
+}
+
+impl FromStr for TimeControl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (base, mode, increment) = ClockMode::split(s);
+
+        let base_minutes: f64 = base
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid time control '{s}': bad base time"))?;
+        let increment_seconds: f64 = increment
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid time control '{s}': bad increment or delay"))?;
+        if base_minutes <= 0.0 || increment_seconds < 0.0 {
+            return Err(format!(
+                "Invalid time control '{s}': base must be positive and increment or delay not negative"
+            ));
+        }
+
+        Ok(TimeControl {
+            base: Duration::from_secs_f64(base_minutes * 60.0),
+            increment: Duration::from_secs_f64(increment_seconds),
+            mode,
+        })
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let minutes = self.base.as_secs_f64() / 60.0;
+        write!(
+            f,
+            "{}{}{}",
+            minutes,
+            self.mode.separator(),
+            self.increment.as_secs()
+        )
+    }
+}
+
+/// Remaining time for both players, each on their own time control: the
+/// same one unless the game gives one side less time, as in Armageddon.
+#[derive(Clone, Debug)]
+pub struct ChessClock {
+    white_control: TimeControl,
+    black_control: TimeControl,
+    white_remaining: Duration,
+    black_remaining: Duration,
+    move_elapsed: Duration, // Time the side to move has spent on this move
+}
+
+impl ChessClock {
+    pub fn new(time_control: TimeControl) -> Self {
+        ChessClock::asymmetric(time_control, time_control)
+    }
+
+    /// A clock with White on `white` and Black on `black`.
+    pub fn asymmetric(white: TimeControl, black: TimeControl) -> Self {
+        ChessClock {
+            white_control: white,
+            black_control: black,
+            white_remaining: white.base,
+            black_remaining: black.base,
+            move_elapsed: Duration::ZERO,
+        }
+    }
+
+    pub fn time_control(&self, color: PieceColor) -> TimeControl {
+        match color {
+            PieceColor::White => self.white_control,
+            PieceColor::Black => self.black_control,
+        }
+    }
+
+    /// Whether the sides have different time controls.
+    pub fn is_asymmetric(&self) -> bool {
+        self.white_control != self.black_control
+    }
+
+    pub fn reset(&mut self) {
+        *self = ChessClock::asymmetric(self.white_control, self.black_control);
+    }
+
+    pub fn remaining(&self, color: PieceColor) -> Duration {
+        match color {
+            PieceColor::White => self.white_remaining,
+            PieceColor::Black => self.black_remaining,
+        }
+    }
+
+    /// Sets `color`'s remaining time, e.g. to carry on an adjourned game.
+    pub fn set_remaining(&mut self, color: PieceColor, remaining: Duration) {
+        *self.remaining_mut(color) = remaining;
+    }
+
+    fn remaining_mut(&mut self, color: PieceColor) -> &mut Duration {
+        match color {
+            PieceColor::White => &mut self.white_remaining,
+            PieceColor::Black => &mut self.black_remaining,
+        }
+    }
+
+    /// Runs `color`'s clock down by `elapsed`, less whatever is left of a
+    /// simple delay. Returns true if their flag fell.
+    pub fn tick(&mut self, color: PieceColor, elapsed: Duration) -> bool {
+        let control = self.time_control(color);
+        let charged = match control.mode {
+            ClockMode::Delay => {
+                elapsed.saturating_sub(control.increment.saturating_sub(self.move_elapsed))
+            }
+            ClockMode::Increment | ClockMode::Bronstein => elapsed,
+        };
+        self.move_elapsed += elapsed;
+        let remaining = self.remaining_mut(color);
+        *remaining = remaining.saturating_sub(charged);
+        remaining.is_zero()
+    }
+
+    /// Gives `color` back time as their time control says once they complete
+    /// a move: the whole increment, or for Bronstein delay as much of it as
+    /// the move took.
+    pub fn end_move(&mut self, color: PieceColor) {
+        let control = self.time_control(color);
+        let bonus = match control.mode {
+            ClockMode::Increment => control.increment,
+            ClockMode::Delay => Duration::ZERO,
+            ClockMode::Bronstein => control.increment.min(self.move_elapsed),
+        };
+        *self.remaining_mut(color) += bonus;
+        self.move_elapsed = Duration::ZERO;
+    }
+}
+
+/// Formats a clock reading as "m:ss", or "s.t" in the last ten seconds.
+pub fn format_clock(remaining: Duration) -> String {
+    if remaining < Duration::from_secs(10) {
+        format!(
+            "{}.{}",
+            remaining.as_secs(),
+            remaining.subsec_millis() / 100
+        )
+    } else {
+        let secs = remaining.as_secs();
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
@@ -0,0 +1,196 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
+
+// std's clock panics in the browser
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+use rand::Rng;
+
+use super::book::OpeningBook;
+use super::game_state::{GameState, Move};
+
+/// How strongly the AI plays, from 1 (a beginner can beat it) to 8 (the
+/// full-strength search).
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Difficulty(u8);
+
+// What a difficulty level changes about the AI's play
+struct Level {
+    depth: u32,           // Deepest search
+    think_time: Duration, // No deeper searches are started after this long
+    blunder_percent: u32, // Chance of playing a random legal move instead
+    noise: i32,           // Centipawns added or taken off each move's score at random
+}
+
+const LEVELS: [Level; 8] = [
+    Level {
+        depth: 1,
+        think_time: Duration::from_millis(100),
+        blunder_percent: 30,
+        noise: 300,
+    },
+    Level {
+        depth: 1,
+        think_time: Duration::from_millis(100),
+        blunder_percent: 20,
+        noise: 200,
+    },
+    Level {
+        depth: 2,
+        think_time: Duration::from_millis(250),
+        blunder_percent: 12,
+        noise: 120,
+    },
+    Level {
+        depth: 2,
+        think_time: Duration::from_millis(250),
+        blunder_percent: 6,
+        noise: 70,
+    },
+    Level {
+        depth: 3,
+        think_time: Duration::from_millis(500),
+        blunder_percent: 2,
+        noise: 35,
+    },
+    Level {
+        depth: 3,
+        think_time: Duration::from_millis(1000),
+        blunder_percent: 0,
+        noise: 15,
+    },
+    Level {
+        depth: 4,
+        think_time: Duration::from_millis(2000),
+        blunder_percent: 0,
+        noise: 0,
+    },
+    Level {
+        depth: 5,
+        think_time: Duration::from_millis(4000),
+        blunder_percent: 0,
+        noise: 0,
+    },
+];
+
+impl Difficulty {
+    pub const MIN: u8 = 1;
+    pub const MAX: u8 = LEVELS.len() as u8;
+
+    pub fn new(level: u8) -> Option<Self> {
+        (Self::MIN..=Self::MAX)
+            .contains(&level)
+            .then_some(Difficulty(level))
+    }
+
+    pub fn level(self) -> u8 {
+        self.0
+    }
+
+    /// The deepest the AI searches at this level.
+    pub fn depth(self) -> u32 {
+        self.settings().depth
+    }
+
+    fn settings(self) -> &'static Level {
+        &LEVELS[self.0 as usize - 1]
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim()
+            .parse()
+            .ok()
+            .and_then(Difficulty::new)
+            .ok_or_else(|| {
+                format!(
+                    "Invalid difficulty '{s}': expected a level from {} to {}",
+                    Self::MIN,
+                    Self::MAX
+                )
+            })
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl GameState {
+    /// Picks a move for the side to move the way the AI plays at
+    /// `difficulty`: from `book` while still in the opening, otherwise by a
+    /// search limited in depth, and in time if `timed`, with the occasional
+    /// random blunder and some noise in the scores at the lower levels. All
+    /// of the chance comes from `rng`, so an untimed search with a seeded one
+    /// plays the same way every time, however fast the machine.
+    pub fn choose_move_at(
+        &self,
+        difficulty: Difficulty,
+        book: Option<&OpeningBook>,
+        timed: bool,
+        rng: &mut impl Rng,
+    ) -> Option<Move> {
+        if let Some(mv) = book.and_then(|book| book.choose_move(self, rng)) {
+            log::info!("AI chose {} from the book", mv.to_uci_on(&self.board));
+            return Some(mv);
+        }
+
+        let level = difficulty.settings();
+        if level.blunder_percent > 0 && rng.random_range(0..100u32) < level.blunder_percent {
+            let moves = self.generate_valid_moves(self.turn);
+            if moves.is_empty() {
+                return None;
+            }
+            let mv = moves[rng.random_range(0..moves.len())];
+            log::info!("AI blundered on purpose with {}", mv.to_uci_on(&self.board));
+            return Some(mv);
+        }
+
+        let started = Instant::now();
+        let never = AtomicBool::new(false);
+        let mut best = None;
+        let mut searched = 0;
+        for depth in 1..=level.depth {
+            best = if level.noise > 0 {
+                // Every move needs an exact score for the noise to reorder them
+                self.score_root_moves(depth)
+                    .into_iter()
+                    .map(|(mv, score)| {
+                        let noise = rng.random_range(0..2 * level.noise + 1) - level.noise;
+                        (mv, score + noise)
+                    })
+                    .max_by_key(|&(_, score)| score)
+            } else {
+                self.search_root(depth, &never)
+                    .map(|(score, line)| (line[0], score))
+            };
+            let Some((mv, score)) = best else {
+                break;
+            };
+            log::debug!(
+                "Depth {depth}: {} scoring {score:+}",
+                mv.to_uci_on(&self.board)
+            );
+            searched = depth;
+            if timed && started.elapsed() >= level.think_time {
+                break;
+            }
+        }
+        let (mv, score) = best?;
+        log::info!(
+            "AI at level {difficulty} chose {} at depth {searched}, scoring {score:+} for the side to move",
+            mv.to_uci_on(&self.board)
+        );
+        Some(mv)
+    }
+}
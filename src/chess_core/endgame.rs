@@ -0,0 +1,416 @@
+use std::fmt;
+use std::sync::OnceLock;
+
+use rand::Rng;
+
+use super::bitboard::{color_index, squares, Bitboards};
+use super::board::{ChessBoard, Piece, PieceColor, PieceType};
+use super::castling::CastlingRights;
+use super::game_state::{GameState, Move};
+use super::variant::Variant;
+
+// Colour indices in the tables. The side with the extra piece is counted as
+// White, moving up the board, and the other side's positions are mirrored.
+// The tables are for the standard board only
+const SIZE: usize = 8;
+
+const STRONG: usize = 0;
+const LONE: usize = 1;
+
+// A table entry for every side to move, square for the strong king, square
+// for its piece and square for the lone king
+const TABLE_SIZE: usize = 2 * 64 * 64 * 64;
+
+// An entry for a position that is drawn, or can't come about
+const DRAWN: u8 = u8::MAX;
+
+/// An endgame the built-in tablebase knows: a king and a queen, rook or
+/// pawn against a lone king.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Endgame {
+    Queen,
+    Rook,
+    Pawn,
+}
+
+/// The result of an endgame position with best play, for the side to move.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum EndgameResult {
+    /// Mates in this many moves
+    Win(u32),
+    /// Is mated after this many of the other side's moves, 0 if it already is
+    Loss(u32),
+    Draw,
+}
+
+// Where play can go from a position in a table
+#[derive(Copy, Clone)]
+enum Successor {
+    Same(usize),              // Another position in the same table, by its index
+    Promoted(Endgame, usize), // A position in the table the pawn promoted into
+    Drawn,                    // The lone king took the piece
+}
+
+/// The number of plies to mate from each position of an endgame, worked out
+/// backwards from the mates the first time the endgame is needed.
+struct Table {
+    plies: Vec<u8>,
+}
+
+impl Endgame {
+    pub const ALL: [Endgame; 3] = [Endgame::Queen, Endgame::Rook, Endgame::Pawn];
+
+    fn piece_type(self) -> PieceType {
+        match self {
+            Endgame::Queen => PieceType::Queen,
+            Endgame::Rook => PieceType::Rook,
+            Endgame::Pawn => PieceType::Pawn,
+        }
+    }
+
+    // The fewest moves to mate in a position set for training, so there is
+    // something to work out
+    fn min_training_moves(self) -> u32 {
+        match self {
+            Endgame::Queen => 7,
+            Endgame::Rook => 12,
+            Endgame::Pawn => 12,
+        }
+    }
+
+    /// A random position of this endgame with White, the side with the
+    /// extra piece, to move and win, taking at least a few moves to mate.
+    pub fn random_position(self, rng: &mut impl Rng) -> GameState {
+        let table = self.table();
+        loop {
+            let (king, piece, lone_king) = (
+                rng.random_range(0..64),
+                rng.random_range(0..64),
+                rng.random_range(0..64),
+            );
+            let plies = table.plies[index(STRONG, king, piece, lone_king)];
+            if plies != DRAWN && u32::from(plies).div_ceil(2) >= self.min_training_moves() {
+                return self.position(king, piece, lone_king);
+            }
+        }
+    }
+
+    // The position with White's king, White's piece and Black's king on the
+    // given squares and White to move.
+    fn position(self, king: usize, piece: usize, lone_king: usize) -> GameState {
+        let mut state = GameState::new();
+        state.board = ChessBoard::empty();
+        let placed = [
+            (king, PieceColor::White, PieceType::King),
+            (piece, PieceColor::White, self.piece_type()),
+            (lone_king, PieceColor::Black, PieceType::King),
+        ];
+        for (square, color, piece_type) in placed {
+            let (row, col) = (square / SIZE, square % SIZE);
+            state.board.squares[row][col].occupant = Some(Piece {
+                piece_type,
+                color,
+                // Only a pawn on its starting rank may still move two squares
+                has_moved: piece_type != PieceType::Pawn || row != SIZE - 2,
+            });
+        }
+        state.castling_rights = CastlingRights::NONE;
+        state.refresh_zobrist();
+        state
+    }
+
+    fn table(self) -> &'static Table {
+        static QUEEN: OnceLock<Table> = OnceLock::new();
+        static ROOK: OnceLock<Table> = OnceLock::new();
+        static PAWN: OnceLock<Table> = OnceLock::new();
+        let cell = match self {
+            Endgame::Queen => &QUEEN,
+            Endgame::Rook => &ROOK,
+            Endgame::Pawn => &PAWN,
+        };
+        cell.get_or_init(|| Table::solve(self))
+    }
+}
+
+impl fmt::Display for Endgame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Endgame::Queen => "KQvK",
+            Endgame::Rook => "KRvK",
+            Endgame::Pawn => "KPvK",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Table {
+    // Starts from the lone king being mated, then finds the positions one
+    // ply further from mate at a time until there are no more. Whatever is
+    // left over is drawn.
+    fn solve(endgame: Endgame) -> Table {
+        let started = std::time::Instant::now();
+        let mut table = Table {
+            plies: vec![DRAWN; TABLE_SIZE],
+        };
+        let mut successors = Vec::new();
+        for (king, piece, lone_king) in placements() {
+            if !is_legal(endgame, LONE, king, piece, lone_king) {
+                continue;
+            }
+            successors.clear();
+            push_successors(endgame, LONE, king, piece, lone_king, &mut successors);
+            let bitboards = placement_bitboards(endgame, king, piece, Some(lone_king));
+            if successors.is_empty() && bitboards.is_attacked(lone_king, STRONG) {
+                table.plies[index(LONE, king, piece, lone_king)] = 0;
+            }
+        }
+
+        // A pawn's table leads into the queen's and rook's, which may already
+        // be further from mate than anything found in it so far
+        let promoted_plies = match endgame {
+            Endgame::Pawn => [Endgame::Queen, Endgame::Rook]
+                .iter()
+                .flat_map(|promoted| promoted.table().plies.iter())
+                .filter(|&&plies| plies != DRAWN)
+                .max()
+                .map_or(0, |&plies| plies),
+            _ => 0,
+        };
+
+        for ply in 1..DRAWN {
+            let turn = if ply % 2 == 1 { STRONG } else { LONE };
+            let mut found = false;
+            for (king, piece, lone_king) in placements() {
+                let position = index(turn, king, piece, lone_king);
+                if table.plies[position] != DRAWN
+                    || !is_legal(endgame, turn, king, piece, lone_king)
+                {
+                    continue;
+                }
+                successors.clear();
+                push_successors(endgame, turn, king, piece, lone_king, &mut successors);
+                let mut after = successors.iter().map(|&next| table.plies_after(next));
+                let reached = if turn == STRONG {
+                    // The strong side mates as quickly as it can...
+                    after.any(|plies| plies == ply - 1)
+                } else {
+                    // ...and the lone king holds out as long as it can, but
+                    // is lost once every move is
+                    !successors.is_empty() && after.all(|plies| plies != DRAWN)
+                };
+                if reached {
+                    table.plies[position] = ply;
+                    found = true;
+                }
+            }
+            if !found && ply > promoted_plies {
+                break;
+            }
+        }
+        log::debug!("Solved {endgame} in {:.1?}", started.elapsed());
+        table
+    }
+
+    fn plies_after(&self, successor: Successor) -> u8 {
+        match successor {
+            Successor::Same(index) => self.plies[index],
+            Successor::Promoted(endgame, index) => endgame.table().plies[index],
+            Successor::Drawn => DRAWN,
+        }
+    }
+}
+
+impl GameState {
+    /// The result with best play, if this is a position the endgame
+    /// tablebase knows: standard chess, no castling rights and a king and a
+    /// queen, rook or pawn against a lone king.
+    pub fn endgame_result(&self) -> Option<EndgameResult> {
+        let (endgame, turn, king, piece, lone_king) = self.endgame_placement()?;
+        let plies = u32::from(endgame.table().plies[index(turn, king, piece, lone_king)]);
+        Some(match turn {
+            _ if plies == u32::from(DRAWN) => EndgameResult::Draw,
+            STRONG => EndgameResult::Win(plies.div_ceil(2)),
+            _ => EndgameResult::Loss(plies / 2),
+        })
+    }
+
+    /// The tablebase's move in a position it knows: the quickest mate when
+    /// winning, holding the draw when drawn, and the longest defence when
+    /// lost.
+    pub fn endgame_move(&self) -> Option<Move> {
+        self.endgame_result()?;
+        self.generate_valid_moves(self.turn)
+            .into_iter()
+            .max_by_key(|&mv| {
+                let mut next = self.clone();
+                next.make_move(mv);
+                // Anything the tablebase doesn't know, such as a lone king
+                // and knight after an underpromotion, can't be won
+                match next.endgame_result().unwrap_or(EndgameResult::Draw) {
+                    EndgameResult::Loss(moves) => (2, -(moves as i32)),
+                    EndgameResult::Draw => (1, 0),
+                    EndgameResult::Win(moves) => (0, moves as i32),
+                }
+            })
+    }
+
+    // The endgame, the side to move as a table colour index and the squares
+    // of the strong king, its piece and the lone king, turned round if Black
+    // is the side with the piece.
+    fn endgame_placement(&self) -> Option<(Endgame, usize, usize, usize, usize)> {
+        if self.variant != Variant::Standard
+            || self.castling_rights != CastlingRights::NONE
+            || !self.board.is_standard_size()
+        {
+            return None;
+        }
+        let mut kings = [None; 2];
+        let mut extra = None;
+        for row in 0..SIZE {
+            for col in 0..SIZE {
+                let Some(piece) = self.board.squares[row][col].occupant else {
+                    continue;
+                };
+                match piece.piece_type {
+                    PieceType::King => kings[color_index(piece.color)] = Some((row, col)),
+                    _ if extra.is_some() => return None,
+                    _ => extra = Some((piece, (row, col))),
+                }
+            }
+        }
+        let (piece, square) = extra?;
+        let endgame = match piece.piece_type {
+            PieceType::Queen => Endgame::Queen,
+            PieceType::Rook => Endgame::Rook,
+            PieceType::Pawn => Endgame::Pawn,
+            _ => return None,
+        };
+        let (white_king, black_king) = (kings[0]?, kings[1]?);
+        let (king, lone_king) = match piece.color {
+            PieceColor::White => (white_king, black_king),
+            PieceColor::Black => (black_king, white_king),
+        };
+        let flip = |(row, col): (usize, usize)| match piece.color {
+            PieceColor::White => row * SIZE + col,
+            PieceColor::Black => (SIZE - 1 - row) * SIZE + col,
+        };
+        let turn = if self.turn == piece.color {
+            STRONG
+        } else {
+            LONE
+        };
+        Some((endgame, turn, flip(king), flip(square), flip(lone_king)))
+    }
+}
+
+fn index(turn: usize, king: usize, piece: usize, lone_king: usize) -> usize {
+    ((turn * 64 + king) * 64 + piece) * 64 + lone_king
+}
+
+// Every way of putting the strong king, its piece and the lone king on the
+// board, overlapping or not.
+fn placements() -> impl Iterator<Item = (usize, usize, usize)> {
+    (0..64).flat_map(|king| {
+        (0..64).flat_map(move |piece| (0..64).map(move |lone| (king, piece, lone)))
+    })
+}
+
+fn placement_bitboards(
+    endgame: Endgame,
+    king: usize,
+    piece: usize,
+    lone_king: Option<usize>,
+) -> Bitboards {
+    let strong = [
+        (king, STRONG, PieceType::King),
+        (piece, STRONG, endgame.piece_type()),
+    ];
+    match lone_king {
+        Some(square) => {
+            Bitboards::with_pieces(&[strong[0], strong[1], (square, LONE, PieceType::King)])
+        }
+        None => Bitboards::with_pieces(&strong),
+    }
+}
+
+// Whether the position can come about with `turn` to move: three different
+// squares, the kings apart, no pawn on the first or last rank and the lone
+// king not left in check by its own move.
+fn is_legal(endgame: Endgame, turn: usize, king: usize, piece: usize, lone_king: usize) -> bool {
+    if king == piece || king == lone_king || piece == lone_king {
+        return false;
+    }
+    let bitboards = placement_bitboards(endgame, king, piece, Some(lone_king));
+    if bitboards.attacks(PieceType::King, STRONG, king) & 1 << lone_king != 0 {
+        return false;
+    }
+    let row = piece / SIZE;
+    if endgame == Endgame::Pawn && (row == 0 || row == SIZE - 1) {
+        return false;
+    }
+    turn == LONE || !bitboards.is_attacked(lone_king, STRONG)
+}
+
+// Adds where each legal move of the side to move leads.
+fn push_successors(
+    endgame: Endgame,
+    turn: usize,
+    king: usize,
+    piece: usize,
+    lone_king: usize,
+    successors: &mut Vec<Successor>,
+) {
+    let next = 1 - turn;
+    if turn == LONE {
+        // The lone king may take the piece unless the king guards it. It
+        // can't step back along a line the piece attacks it down.
+        let without = placement_bitboards(endgame, king, piece, None);
+        let targets = without.attacks(PieceType::King, LONE, lone_king) & !(1 << king);
+        for to in squares(targets) {
+            if to == piece {
+                if without.attacks(PieceType::King, STRONG, king) & 1 << to == 0 {
+                    successors.push(Successor::Drawn);
+                }
+            } else if !without.is_attacked(to, STRONG) {
+                successors.push(Successor::Same(index(next, king, piece, to)));
+            }
+        }
+        return;
+    }
+
+    let bitboards = placement_bitboards(endgame, king, piece, Some(lone_king));
+    let occupied = bitboards.occupied();
+    let guarded = bitboards.attacks(PieceType::King, LONE, lone_king);
+    for to in squares(bitboards.attacks(PieceType::King, STRONG, king) & !occupied & !guarded) {
+        successors.push(Successor::Same(index(next, to, piece, lone_king)));
+    }
+
+    if endgame != Endgame::Pawn {
+        for to in squares(bitboards.attacks(endgame.piece_type(), STRONG, piece) & !occupied) {
+            successors.push(Successor::Same(index(next, king, to, lone_king)));
+        }
+        return;
+    }
+
+    // The pawn has nothing to take, so only moves straight up the board
+    let one = piece - SIZE;
+    if occupied & 1 << one != 0 {
+        return;
+    }
+    if one < SIZE {
+        for promoted in [Endgame::Queen, Endgame::Rook] {
+            successors.push(Successor::Promoted(
+                promoted,
+                index(next, king, one, lone_king),
+            ));
+        }
+        return;
+    }
+    successors.push(Successor::Same(index(next, king, one, lone_king)));
+    if piece / SIZE == SIZE - 2 {
+        let two = one - SIZE;
+        if occupied & 1 << two == 0 {
+            successors.push(Successor::Same(index(next, king, two, lone_king)));
+        }
+    }
+}
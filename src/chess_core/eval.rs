@@ -0,0 +1,416 @@
+use super::bitboard::{color_index, squares, type_index, Bitboard, Bitboards, Geometry};
+use super::board::{ChessBoard, PieceColor, PieceType};
+use super::game_state::GameState;
+use super::variant::Variant;
+
+// The game phase runs from this (all pieces on the board) down to 0 (only
+// kings and pawns left)
+const OPENING_PHASE: i32 = 24;
+
+const DOUBLED_PAWN_PENALTY: i32 = 15;
+const ISOLATED_PAWN_PENALTY: i32 = 12;
+const BACKWARD_PAWN_PENALTY: i32 = 8;
+// Passed pawn bonus by the number of ranks the pawn has advanced, on the
+// standard board; other boards scale their ranks to these
+const PASSED_PAWN_BONUS: [i32; 8] = [0, 10, 15, 25, 40, 65, 100, 0];
+
+// Bonus for each pawn sheltering the king, one and two ranks in front of it
+const PAWN_SHIELD_BONUS: [i32; 2] = [10, 5];
+
+// What each piece still on the board costs its owner in Antichess, where the
+// aim is to lose them all
+const ANTICHESS_PIECE_PENALTY: i32 = 100;
+
+// Piece-square tables from White's point of view, laid out like the board
+// with rank 8 first. Black looks them up with the ranks mirrored, and boards
+// of other sizes stretch or squeeze theirs onto them.
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+    50,  50,  50,  50,  50,  50,  50,  50,
+    10,  10,  20,  30,  30,  20,  10,  10,
+     5,   5,  10,  25,  25,  10,   5,   5,
+     0,   0,   0,  20,  20,   0,   0,   0,
+     5,  -5, -10,   0,   0, -10,  -5,   5,
+     5,  10,  10, -20, -20,  10,  10,   5,
+     0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+   -50, -40, -30, -30, -30, -30, -40, -50,
+   -40, -20,   0,   0,   0,   0, -20, -40,
+   -30,   0,  10,  15,  15,  10,   0, -30,
+   -30,   5,  15,  20,  20,  15,   5, -30,
+   -30,   0,  15,  20,  20,  15,   0, -30,
+   -30,   5,  10,  15,  15,  10,   5, -30,
+   -40, -20,   0,   5,   5,   0, -20, -40,
+   -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+   -20, -10, -10, -10, -10, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,  10,  10,   5,   0, -10,
+   -10,   5,   5,  10,  10,   5,   5, -10,
+   -10,   0,  10,  10,  10,  10,   0, -10,
+   -10,  10,  10,  10,  10,  10,  10, -10,
+   -10,   5,   0,   0,   0,   0,   5, -10,
+   -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+     5,  10,  10,  10,  10,  10,  10,   5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+     0,   0,   0,   5,   5,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,   5,   5,   5,   0, -10,
+    -5,   0,   5,   5,   5,   5,   0,  -5,
+     0,   0,   5,   5,   5,   5,   0,  -5,
+   -10,   5,   5,   5,   5,   5,   0, -10,
+   -10,   0,   5,   0,   0,   0,   0, -10,
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+];
+
+// The king hides behind its pawns while there are pieces about...
+#[rustfmt::skip]
+const KING_MIDDLEGAME_TABLE: [i32; 64] = [
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -20, -30, -30, -40, -40, -30, -30, -20,
+   -10, -20, -20, -20, -20, -20, -20, -10,
+    20,  20,   0,   0,   0,   0,  20,  20,
+    20,  30,  10,   0,   0,  10,  30,  20,
+];
+
+// ...and heads for the centre once they are gone
+#[rustfmt::skip]
+const KING_ENDGAME_TABLE: [i32; 64] = [
+   -50, -40, -30, -20, -20, -30, -40, -50,
+   -30, -20, -10,   0,   0, -10, -20, -30,
+   -30, -10,  20,  30,  30,  20, -10, -30,
+   -30, -10,  30,  40,  40,  30, -10, -30,
+   -30, -10,  30,  40,  40,  30, -10, -30,
+   -30, -10,  20,  30,  30,  20, -10, -30,
+   -30, -30,   0,   0,   0,   0, -30, -30,
+   -50, -30, -30, -30, -30, -30, -30, -50,
+];
+
+/// A pawn and what stands out about its place in the pawn structure.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PawnInfo {
+    pub square: (usize, usize),
+    pub isolated: bool, // No pawn of its own on a file beside it
+    pub doubled: bool,  // Another of its own pawns shares the file
+    pub backward: bool, // Left behind its neighbours, and can't advance safely
+    pub passed: bool,   // No enemy pawn ahead of it on its file or those beside it
+}
+
+impl GameState {
+    /// `color`'s pawns, each with whether it is isolated, doubled, backward
+    /// or passed. The evaluation scores the same findings.
+    pub fn pawn_structure(&self, color: PieceColor) -> Vec<PawnInfo> {
+        pawn_info(&Bitboards::from_board(&self.board), color_index(color))
+    }
+
+    /// Static evaluation in centipawns from the side to move's point of view,
+    /// by the installed network if there is one.
+    pub(super) fn evaluate(&self) -> i32 {
+        if self.variant == Variant::Antichess {
+            return self.antichess_evaluate();
+        }
+        if let Some(score) = self.network_evaluate() {
+            return score;
+        }
+        let bitboards = Bitboards::from_board(&self.board);
+        let phase = game_phase(&bitboards);
+        let score = |color: usize| {
+            material(&bitboards, color)
+                + piece_squares(&bitboards, color, phase)
+                + king_safety(&bitboards, color, phase)
+                + mobility(&bitboards, color)
+                + pawn_structure(&bitboards, color)
+        };
+        let us = color_index(self.turn);
+        score(us) - score(1 - us)
+    }
+
+    // Antichess turns material upside down, so neither the network nor the
+    // usual terms apply: the fewer pieces left the better, and the more
+    // squares they reach the more choice there is when a capture is forced.
+    fn antichess_evaluate(&self) -> i32 {
+        let bitboards = Bitboards::from_board(&self.board);
+        let score = |color: usize| {
+            mobility(&bitboards, color)
+                - ANTICHESS_PIECE_PENALTY * bitboards.color(color).count_ones() as i32
+        };
+        let us = color_index(self.turn);
+        score(us) - score(1 - us)
+    }
+}
+
+/// Material value in centipawns; the king is never traded so it counts zero.
+pub(super) fn piece_value(piece_type: PieceType) -> i32 {
+    match piece_type {
+        PieceType::Pawn => 100,
+        PieceType::Knight => 320,
+        PieceType::Bishop => 330,
+        PieceType::Rook => 500,
+        PieceType::Queen => 900,
+        PieceType::King => 0,
+        PieceType::Archbishop => 750,
+        PieceType::Chancellor => 825,
+    }
+}
+
+/// The piece-square bonus for a piece of `color` on `square` of `board`,
+/// using the middlegame table for the king.
+pub(super) fn square_bonus(
+    board: &ChessBoard,
+    piece_type: PieceType,
+    color: PieceColor,
+    square: (usize, usize),
+) -> i32 {
+    let index = table_index(board.files, board.ranks, color_index(color), square);
+    match piece_type {
+        PieceType::King => KING_MIDDLEGAME_TABLE[index],
+        _ => piece_table(piece_type)[index],
+    }
+}
+
+/// How much is left on the board, from `OPENING_PHASE` down to 0.
+fn game_phase(bitboards: &Bitboards) -> i32 {
+    let weights = [
+        (PieceType::Knight, 1),
+        (PieceType::Bishop, 1),
+        (PieceType::Rook, 2),
+        (PieceType::Queen, 4),
+        (PieceType::Archbishop, 3),
+        (PieceType::Chancellor, 3),
+    ];
+    let phase: i32 = weights
+        .iter()
+        .map(|&(piece_type, weight)| {
+            weight * count(bitboards, 0, piece_type) + weight * count(bitboards, 1, piece_type)
+        })
+        .sum();
+    phase.min(OPENING_PHASE)
+}
+
+/// The value of `color`'s pieces.
+fn material(bitboards: &Bitboards, color: usize) -> i32 {
+    PieceType::ALL
+        .iter()
+        .map(|&piece_type| piece_value(piece_type) * count(bitboards, color, piece_type))
+        .sum()
+}
+
+/// Bonuses for where `color`'s pieces stand, blending the king's tables by
+/// the game phase.
+fn piece_squares(bitboards: &Bitboards, color: usize, phase: i32) -> i32 {
+    let geometry = bitboards.geometry();
+    let mut score = 0;
+    for piece_type in PieceType::ALL {
+        for square in squares(bitboards.pieces(color, type_index(piece_type))) {
+            let square = geometry.square(square);
+            let index = table_index(geometry.files(), geometry.ranks(), color, square);
+            score += match piece_type {
+                PieceType::King => {
+                    (KING_MIDDLEGAME_TABLE[index] * phase
+                        + KING_ENDGAME_TABLE[index] * (OPENING_PHASE - phase))
+                        / OPENING_PHASE
+                }
+                _ => piece_table(piece_type)[index],
+            };
+        }
+    }
+    score
+}
+
+/// Bonus for pawns in front of `color`'s king, which matters less as pieces
+/// come off.
+fn king_safety(bitboards: &Bitboards, color: usize, phase: i32) -> i32 {
+    let Some(king) = squares(bitboards.pieces(color, type_index(PieceType::King))).next() else {
+        return 0;
+    };
+    let geometry = bitboards.geometry();
+    let pawns = bitboards.pieces(color, type_index(PieceType::Pawn));
+    let (row, col) = geometry.square(king);
+    let forward: isize = if color == color_index(PieceColor::White) {
+        -1
+    } else {
+        1
+    };
+
+    let mut shield = 0;
+    for (distance, bonus) in PAWN_SHIELD_BONUS.iter().enumerate() {
+        let shield_row = row as isize + forward * (distance as isize + 1);
+        if !(0..geometry.ranks() as isize).contains(&shield_row) {
+            continue;
+        }
+        for shield_col in col.saturating_sub(1)..=(col + 1).min(geometry.files() - 1) {
+            if pawns & geometry.bit((shield_row as usize, shield_col)) != 0 {
+                shield += bonus;
+            }
+        }
+    }
+    shield * phase / OPENING_PHASE
+}
+
+/// Bonus for the squares `color`'s pieces other than pawns and the king
+/// can reach.
+fn mobility(bitboards: &Bitboards, color: usize) -> i32 {
+    let weights = [
+        (PieceType::Knight, 4),
+        (PieceType::Bishop, 5),
+        (PieceType::Rook, 2),
+        (PieceType::Queen, 1),
+        (PieceType::Archbishop, 2),
+        (PieceType::Chancellor, 1),
+    ];
+    let own = bitboards.color(color);
+    let mut score = 0;
+    for (piece_type, weight) in weights {
+        for square in squares(bitboards.pieces(color, type_index(piece_type))) {
+            let reachable = bitboards.attacks(piece_type, color, square) & !own;
+            score += weight * reachable.count_ones() as i32;
+        }
+    }
+    score
+}
+
+/// Penalties for doubled, isolated and backward pawns and bonuses for
+/// passed ones.
+fn pawn_structure(bitboards: &Bitboards, color: usize) -> i32 {
+    let geometry = bitboards.geometry();
+    let pawns = bitboards.pieces(color, type_index(PieceType::Pawn));
+    let is_white = color == color_index(PieceColor::White);
+
+    let mut score = 0;
+    for col in 0..geometry.files() {
+        let on_file = (pawns & geometry.file(col)).count_ones() as i32;
+        if on_file > 1 {
+            score -= DOUBLED_PAWN_PENALTY * (on_file - 1);
+        }
+    }
+
+    for pawn in pawn_info(bitboards, color) {
+        if pawn.isolated {
+            score -= ISOLATED_PAWN_PENALTY;
+        }
+        if pawn.backward {
+            score -= BACKWARD_PAWN_PENALTY;
+        }
+        if pawn.passed {
+            let (row, _) = pawn.square;
+            let last_row = geometry.ranks() - 1;
+            let advanced = if is_white { last_row - row } else { row };
+            score += PASSED_PAWN_BONUS[advanced * 7 / last_row];
+        }
+    }
+    score
+}
+
+/// What stands out about each of `color`'s pawns.
+fn pawn_info(bitboards: &Bitboards, color: usize) -> Vec<PawnInfo> {
+    let pawns = bitboards.pieces(color, type_index(PieceType::Pawn));
+    let enemy_pawns = bitboards.pieces(1 - color, type_index(PieceType::Pawn));
+    let is_white = color == color_index(PieceColor::White);
+    let geometry = bitboards.geometry();
+
+    squares(pawns)
+        .map(|square| {
+            let (row, col) = geometry.square(square);
+            let file = geometry.file(col);
+            let neighbours = adjacent_files(geometry, col);
+            let isolated = pawns & neighbours == 0;
+
+            // Backward: no pawn beside or behind it on the files next to it
+            // to come up in support, and an enemy pawn guarding the square in
+            // front of it
+            let stop = if is_white {
+                row.checked_sub(1)
+            } else {
+                Some(row + 1).filter(|&row| row < geometry.ranks())
+            };
+            let backward = !isolated
+                && pawns & neighbours & !ranks_ahead(geometry, row, is_white) == 0
+                && stop.is_some_and(|stop| {
+                    let stop = geometry.index((stop, col));
+                    bitboards.attacks(PieceType::Pawn, color, stop) & enemy_pawns != 0
+                });
+
+            PawnInfo {
+                square: (row, col),
+                isolated,
+                doubled: (pawns & file).count_ones() > 1,
+                backward,
+                passed: enemy_pawns & ranks_ahead(geometry, row, is_white) & (neighbours | file)
+                    == 0,
+            }
+        })
+        .collect()
+}
+
+/// The squares on the ranks in front of `row` for a pawn moving up the board
+/// if `is_white`, or down it otherwise.
+fn ranks_ahead(geometry: &Geometry, row: usize, is_white: bool) -> Bitboard {
+    if is_white {
+        (1 << (row * geometry.files())) - 1
+    } else {
+        Bitboard::MAX
+            .checked_shl(((row + 1) * geometry.files()) as u32)
+            .unwrap_or(0)
+    }
+}
+
+fn adjacent_files(geometry: &Geometry, col: usize) -> Bitboard {
+    let left = if col > 0 { geometry.file(col - 1) } else { 0 };
+    let right = if col + 1 < geometry.files() {
+        geometry.file(col + 1)
+    } else {
+        0
+    };
+    left | right
+}
+
+fn count(bitboards: &Bitboards, color: usize, piece_type: PieceType) -> i32 {
+    bitboards.pieces(color, type_index(piece_type)).count_ones() as i32
+}
+
+fn piece_table(piece_type: PieceType) -> &'static [i32; 64] {
+    match piece_type {
+        PieceType::Pawn => &PAWN_TABLE,
+        // The fairy pieces' knight leaps want the centre as much
+        PieceType::Knight | PieceType::Archbishop | PieceType::Chancellor => &KNIGHT_TABLE,
+        PieceType::Bishop => &BISHOP_TABLE,
+        PieceType::Rook => &ROOK_TABLE,
+        PieceType::Queen => &QUEEN_TABLE,
+        PieceType::King => &KING_MIDDLEGAME_TABLE,
+    }
+}
+
+// The tables are written for White on the standard board; Black's ranks run
+// the other way, and other boards map their squares onto the nearest ones
+fn table_index(files: usize, ranks: usize, color: usize, (row, col): (usize, usize)) -> usize {
+    let row = if color == color_index(PieceColor::White) {
+        row
+    } else {
+        ranks - 1 - row
+    };
+    row * 8 / ranks * 8 + col * 8 / files
+}
@@ -0,0 +1,505 @@
+use std::fmt;
+
+use super::board::{ChessBoard, Piece, PieceColor, PieceType, MAX_FILES, MAX_RANKS, MIN_SIZE};
+use super::castling::CastlingRights;
+use super::game_state::GameState;
+
+impl GameState {
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+
+        // Convert board to FEN, starting from the top rank (row 0)
+        for row in 0..self.board.ranks {
+            let mut empty_count = 0;
+
+            for col in 0..self.board.files {
+                if let Some(piece) = self.board.squares[row][col].occupant {
+                    if empty_count > 0 {
+                        fen.push_str(&empty_count.to_string());
+                        empty_count = 0;
+                    }
+                    fen.push(piece_to_fen_char(piece));
+                } else {
+                    empty_count += 1;
+                }
+            }
+
+            if empty_count > 0 {
+                fen.push_str(&empty_count.to_string());
+            }
+
+            if row < self.board.ranks - 1 {
+                fen.push('/');
+            }
+        }
+
+        // Add active color
+        fen.push(' ');
+        fen.push(if self.turn == PieceColor::White {
+            'w'
+        } else {
+            'b'
+        });
+
+        // Add castling rights
+        fen.push(' ');
+        fen.push_str(&self.castling_rights.to_string());
+
+        // Add en passant target square
+        fen.push(' ');
+        if let Some(target) = self.en_passant_target {
+            fen.push_str(&self.board.square_name(target));
+        } else {
+            fen.push('-');
+        }
+
+        // Add halfmove clock and fullmove number
+        fen.push(' ');
+        fen.push_str(&self.halfmove_clock.to_string());
+        fen.push(' ');
+        fen.push_str(&self.fullmove_number.to_string());
+
+        fen
+    }
+
+    /// Loads a position from a FEN. Malformed FENs and impossible positions
+    /// (a side without exactly one king, pawns on the first or last rank,
+    /// the side not to move in check) are rejected. Castling rights without
+    /// the king and rook in place, and en passant squares no pawn can have
+    /// just passed, are dropped with a warning; use
+    /// [`GameState::from_fen_strict`] to reject those too. On error the
+    /// position is left unchanged.
+    pub fn from_fen(&mut self, fen: &str) -> Result<(), FenError> {
+        self.load_fen(fen, false)
+    }
+
+    /// Like [`GameState::from_fen`], but rejects every inconsistency rather
+    /// than fixing the ones it can.
+    pub fn from_fen_strict(&mut self, fen: &str) -> Result<(), FenError> {
+        self.load_fen(fen, true)
+    }
+
+    fn load_fen(&mut self, fen: &str, strict: bool) -> Result<(), FenError> {
+        let parts: Vec<&str> = fen.split_whitespace().collect();
+        if parts.len() < 6 {
+            return Err(FenError::MissingFields);
+        }
+        let mut state = self.clone();
+
+        // Parse board layout; the first row in a FEN is the top rank. The
+        // board is as many ranks high as the FEN has, and as wide as the top
+        // one, which the rest have to match.
+        let rows: Vec<&str> = parts[0].split('/').collect();
+        let ranks = rows.len();
+        if !(MIN_SIZE..=MAX_RANKS).contains(&ranks) {
+            return Err(FenError::WrongRankCount(ranks));
+        }
+
+        let mut placed = Vec::new();
+        let mut files = None;
+        for (row, row_data) in rows.iter().enumerate() {
+            let rank = ranks - row;
+            let mut col = 0;
+            let mut empty_count = 0;
+            for ch in row_data.chars() {
+                // Runs of more than nine empty squares take several digits
+                if let Some(digit) = ch.to_digit(10) {
+                    empty_count = empty_count * 10 + digit as usize;
+                    // Give up on a run too long for any board before it can
+                    // overflow
+                    if col + empty_count > MAX_FILES {
+                        return Err(FenError::WrongFileCount(col + empty_count));
+                    }
+                    continue;
+                }
+                col += std::mem::take(&mut empty_count);
+                let piece = char_to_piece(ch).ok_or(FenError::UnknownPiece(ch))?;
+                placed.push(((row, col), piece));
+                col += 1;
+            }
+            col += empty_count;
+
+            match files {
+                None if !(MIN_SIZE..=MAX_FILES).contains(&col) => {
+                    return Err(FenError::WrongFileCount(col));
+                }
+                None => files = Some(col),
+                Some(files) if col != files => return Err(FenError::WrongRankLength(rank)),
+                Some(_) => {}
+            }
+        }
+
+        let files = files.unwrap_or(0);
+        state.board = ChessBoard::with_size(files, ranks).ok_or(FenError::WrongFileCount(files))?;
+        for ((row, col), piece) in placed {
+            state.board.squares[row][col].occupant = Some(piece);
+        }
+
+        // Parse active color
+        state.turn = match parts[1] {
+            "w" => PieceColor::White,
+            "b" => PieceColor::Black,
+            other => return Err(FenError::BadActiveColor(other.to_string())),
+        };
+
+        // Parse castling rights
+        state.castling_rights = parts[2]
+            .parse()
+            .map_err(|_| FenError::BadCastlingRights(parts[2].to_string()))?;
+
+        // Parse en passant target square
+        state.en_passant_target = if parts[3] == "-" {
+            None
+        } else {
+            Some(
+                state
+                    .board
+                    .parse_square(parts[3])
+                    .ok_or_else(|| FenError::BadEnPassant(parts[3].to_string()))?,
+            )
+        };
+
+        // Parse halfmove clock
+        state.halfmove_clock = parts[4]
+            .parse()
+            .map_err(|_| FenError::BadHalfmoveClock(parts[4].to_string()))?;
+
+        // Parse fullmove number
+        state.fullmove_number = parts[5]
+            .parse()
+            .map_err(|_| FenError::BadFullmoveNumber(parts[5].to_string()))?;
+
+        state.check_position(strict)?;
+        state.infer_has_moved();
+
+        // FEN doesn't record checks, so Three-Check counts start again
+        state.checks_given = [0; 2];
+
+        state.refresh_zobrist();
+        *self = state;
+        Ok(())
+    }
+
+    // Rejects positions that can't arise in a game, and drops (or, when
+    // strict, rejects) castling rights and en passant squares that don't fit
+    // the board.
+    fn check_position(&mut self, strict: bool) -> Result<(), FenError> {
+        for color in [PieceColor::White, PieceColor::Black] {
+            let kings = self
+                .board
+                .squares
+                .iter()
+                .flatten()
+                .filter_map(|square| square.occupant)
+                .filter(|p| p.piece_type == PieceType::King && p.color == color)
+                .count();
+            // Without check a side can have any number of kings
+            if kings != 1 && self.variant.has_check() {
+                return Err(FenError::KingCount {
+                    color,
+                    count: kings,
+                });
+            }
+        }
+
+        for row in [0, self.board.ranks - 1] {
+            for col in 0..self.board.files {
+                let occupant = self.board.squares[row][col].occupant;
+                if occupant.is_some_and(|p| p.piece_type == PieceType::Pawn) {
+                    return Err(FenError::PawnOnBackRank(self.board.square_name((row, col))));
+                }
+            }
+        }
+
+        if self.is_king_in_check(self.turn.opposite()) {
+            return Err(FenError::OpponentInCheck);
+        }
+
+        // Nor can it castle
+        if !self.variant.has_check() {
+            self.castling_rights = CastlingRights::NONE;
+        }
+        for right in self.castling_rights.iter() {
+            if !self.castling_right_possible(right) {
+                if strict {
+                    return Err(FenError::ImpossibleCastlingRight(right));
+                }
+                log::warn!("Ignoring castling right '{right}': the king or rook has moved, or there is no room to castle");
+                self.castling_rights.remove(right);
+            }
+        }
+
+        if let Some(target) = self.en_passant_target {
+            if !self.en_passant_possible(target) {
+                if strict {
+                    return Err(FenError::ImpossibleEnPassant(
+                        self.board.square_name(target),
+                    ));
+                }
+                log::warn!(
+                    "Ignoring en passant square {}: no pawn has just passed it",
+                    self.board.square_name(target)
+                );
+                self.en_passant_target = None;
+            }
+        }
+
+        Ok(())
+    }
+
+    // A castling right needs the king and that rook on their starting squares,
+    // and a board wide enough to castle on.
+    fn castling_right_possible(&self, right: CastlingRights) -> bool {
+        let color = if CastlingRights::both(PieceColor::White).contains(right) {
+            PieceColor::White
+        } else {
+            PieceColor::Black
+        };
+        let row = self.board.home_row(color);
+        let king_side = right == CastlingRights::kingside(color);
+        let rook_col = if king_side { self.board.files - 1 } else { 0 };
+        let is = |col: usize, piece_type: PieceType| {
+            self.board.squares[row][col]
+                .occupant
+                .is_some_and(|p| p.piece_type == piece_type && p.color == color)
+        };
+        self.board.castled_king_file(king_side).is_some()
+            && is(self.board.king_file(), PieceType::King)
+            && is(rook_col, PieceType::Rook)
+    }
+
+    // The en passant square must be the one an enemy pawn just skipped: on
+    // the right rank, empty, with the pawn in front of it and its starting
+    // square empty behind it.
+    fn en_passant_possible(&self, (row, col): (usize, usize)) -> bool {
+        let mover = self.turn.opposite();
+        let start_row = self.board.pawn_row(mover);
+        let (target_row, pawn_row) = match mover {
+            PieceColor::White => (start_row - 1, start_row.wrapping_sub(2)),
+            PieceColor::Black => (start_row + 1, start_row + 2),
+        };
+        row == target_row
+            && self.board.contains((pawn_row, col))
+            && self.board.squares[row][col].occupant.is_none()
+            && self.board.squares[start_row][col].occupant.is_none()
+            && self.board.squares[pawn_row][col]
+                .occupant
+                .is_some_and(|p| p.piece_type == PieceType::Pawn && p.color == mover)
+    }
+
+    // FEN doesn't say which pieces have moved, but castling rights show that
+    // a king or rook hasn't, and a pawn off its starting rank must have.
+    fn infer_has_moved(&mut self) {
+        let board = self.board;
+        for row in 0..board.ranks {
+            for col in 0..board.files {
+                let Some(piece) = &mut self.board.squares[row][col].occupant else {
+                    continue;
+                };
+                let home_row = board.home_row(piece.color);
+                piece.has_moved = match piece.piece_type {
+                    PieceType::King => {
+                        let rights = CastlingRights::both(piece.color);
+                        (row, col) != (home_row, board.king_file())
+                            || rights.iter().all(|r| !self.castling_rights.contains(r))
+                    }
+                    PieceType::Rook => {
+                        let right = CastlingRights::for_corner(&board, (row, col));
+                        right.is_empty()
+                            || !CastlingRights::both(piece.color).contains(right)
+                            || !self.castling_rights.contains(right)
+                    }
+                    PieceType::Pawn => row != board.pawn_row(piece.color),
+                    _ => false,
+                };
+            }
+        }
+    }
+}
+
+/// Why a FEN was rejected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FenError {
+    /// Fewer than the six space-separated fields
+    MissingFields,
+    /// The board has this many ranks, more or fewer than a board can have
+    WrongRankCount(usize),
+    /// The top rank is this many squares long, more or fewer than a board
+    /// can have
+    WrongFileCount(usize),
+    /// This rank (counting up from 1) isn't as long as the top one
+    WrongRankLength(usize),
+    UnknownPiece(char),
+    BadActiveColor(String),
+    BadCastlingRights(String),
+    BadEnPassant(String),
+    BadHalfmoveClock(String),
+    BadFullmoveNumber(String),
+    /// A side has no king, or more than one
+    KingCount {
+        color: PieceColor,
+        count: usize,
+    },
+    /// A pawn on the first or last rank, by the name of its square
+    PawnOnBackRank(String),
+    /// The side that just moved has left its king in check
+    OpponentInCheck,
+    /// A castling right whose king or rook isn't on its starting square, or
+    /// on a board too narrow to castle on
+    ImpossibleCastlingRight(CastlingRights),
+    /// An en passant square no pawn can have just passed, by its name
+    ImpossibleEnPassant(String),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Invalid FEN: ")?;
+        match self {
+            FenError::MissingFields => write!(f, "Missing fields"),
+            FenError::WrongRankCount(count) => {
+                write!(f, "{count} ranks, not between {MIN_SIZE} and {MAX_RANKS}")
+            }
+            FenError::WrongFileCount(count) => {
+                write!(f, "{count} files, not between {MIN_SIZE} and {MAX_FILES}")
+            }
+            FenError::WrongRankLength(rank) => {
+                write!(f, "Rank {rank} is not as long as the top rank")
+            }
+            FenError::UnknownPiece(ch) => write!(f, "Unknown piece '{ch}'"),
+            FenError::BadActiveColor(s) => write!(f, "Invalid active color '{s}'"),
+            FenError::BadCastlingRights(s) => write!(f, "Invalid castling rights '{s}'"),
+            FenError::BadEnPassant(s) => write!(f, "Invalid en passant square '{s}'"),
+            FenError::BadHalfmoveClock(s) => write!(f, "Invalid halfmove clock '{s}'"),
+            FenError::BadFullmoveNumber(s) => write!(f, "Invalid fullmove number '{s}'"),
+            FenError::KingCount { color, count } => {
+                write!(f, "{color:?} has {count} kings instead of 1")
+            }
+            FenError::PawnOnBackRank(square) => write!(f, "Pawn on {square}"),
+            FenError::OpponentInCheck => write!(f, "The side not to move is in check"),
+            FenError::ImpossibleCastlingRight(right) => {
+                write!(
+                    f,
+                    "Castling right '{right}' without the king and rook in place or room to castle"
+                )
+            }
+            FenError::ImpossibleEnPassant(square) => {
+                write!(f, "No pawn can have just passed en passant square {square}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+/// The name of a square of the standard board, e.g. "e4"; see
+/// [`ChessBoard::square_name`] for boards of other sizes.
+pub fn square_to_algebraic(row: usize, col: usize) -> String {
+    ChessBoard::empty().square_name((row, col))
+}
+
+/// The square of the standard board called `pos`; see
+/// [`ChessBoard::parse_square`] for boards of other sizes.
+pub fn algebraic_to_square(pos: &str) -> Option<(usize, usize)> {
+    ChessBoard::empty().parse_square(pos)
+}
+
+pub(super) fn char_to_piece(ch: char) -> Option<Piece> {
+    let color = if ch.is_uppercase() {
+        PieceColor::White
+    } else {
+        PieceColor::Black
+    };
+
+    let piece_type = PieceType::from_letter(ch)?;
+
+    Some(Piece {
+        piece_type,
+        color,
+        has_moved: false, // Set from the castling rights once the whole FEN is read
+    })
+}
+
+pub(super) fn piece_to_fen_char(piece: Piece) -> char {
+    let ch = piece.piece_type.letter();
+    if piece.color == PieceColor::White {
+        ch
+    } else {
+        ch.to_ascii_lowercase()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess_core::Move;
+
+    #[test]
+    fn errors_name_squares_of_the_board_played_on() {
+        let mut state = GameState::new();
+        let err = state
+            .from_fen("k8P/10/10/10/10/10/10/10/K9 w - - 0 1")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Invalid FEN: Pawn on j9");
+
+        let err = state
+            .from_fen_strict("k9/10/10/10/10/10/10/10/K9 w - j7 0 1")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid FEN: No pawn can have just passed en passant square j7"
+        );
+    }
+
+    #[test]
+    fn runs_of_empty_squares_too_long_for_any_board_are_refused() {
+        let err = GameState::new()
+            .from_fen("k99999999999999999999/8/8/8/8/8/8/K7 w - - 0 1")
+            .unwrap_err();
+        assert_eq!(err, FenError::WrongFileCount(100));
+    }
+
+    #[test]
+    fn positions_round_trip() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "rnbqkbnr/pppp1ppp/8/8/3Pp3/5N2/PPP1PPPP/RNBQKB1R b KQkq d3 0 3",
+            "r3k3/8/8/8/8/8/8/4K2R w Kq - 12 40",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR w KQkq - 0 1",
+        ];
+        let mut state = GameState::new();
+        for fen in fens {
+            state.from_fen_strict(fen).unwrap();
+            assert_eq!(state.to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn moves_update_clocks_en_passant_and_castling_fields() {
+        let mv = |from, to| {
+            Move::new(
+                algebraic_to_square(from).unwrap(),
+                algebraic_to_square(to).unwrap(),
+            )
+        };
+        let mut state = GameState::new();
+        state
+            .from_fen("r3k2r/8/8/8/1p6/8/P7/R3K2R w KQkq - 5 20")
+            .unwrap();
+
+        state.make_move(mv("a2", "a4"));
+        assert_eq!(state.to_fen(), "r3k2r/8/8/8/Pp6/8/8/R3K2R b KQkq a3 0 20");
+        state.make_move(mv("b4", "a3"));
+        assert_eq!(state.to_fen(), "r3k2r/8/8/8/8/p7/8/R3K2R w KQkq - 0 21");
+        state.make_move(mv("e1", "g1"));
+        assert_eq!(state.to_fen(), "r3k2r/8/8/8/8/p7/8/R4RK1 b kq - 1 21");
+        state.make_move(mv("h8", "h7"));
+        assert_eq!(state.to_fen(), "r3k3/7r/8/8/8/p7/8/R4RK1 w q - 2 22");
+        state.make_move(mv("a1", "a3"));
+        assert_eq!(state.to_fen(), "r3k3/7r/8/8/8/R7/8/5RK1 b q - 0 22");
+        state.make_move(mv("e8", "c8"));
+        assert_eq!(state.to_fen(), "2kr4/7r/8/8/8/R7/8/5RK1 w - - 1 23");
+    }
+}
@@ -0,0 +1,215 @@
+use std::fmt;
+
+use super::board::{ChessBoard, PieceColor, PieceType};
+use super::fen::FenError;
+use super::game_state::{GameState, Move, UndoInfo};
+use super::status::GameStatus;
+use super::variant::Variant;
+
+/// A whole game: the position it started from, the moves played since and
+/// everything needed to tell when it is over, including draws by
+/// repetition. This is the easiest way to embed the engine; [`GameState`]
+/// holds a single position for anything lower level.
+#[derive(Clone)]
+pub struct Game {
+    start: GameState,
+    position: GameState,
+    moves: Vec<Move>,
+    undo: Vec<UndoInfo>,           // One for each move, to take it back
+    position_history: Vec<String>, // Position keys for repetition, starting position first
+}
+
+/// Why a move was not played.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MoveError {
+    /// The game has already finished
+    GameOver(GameStatus),
+    /// The move is not legal in the current position
+    Illegal(Move),
+    /// The text is neither SAN nor UCI for a legal move
+    Unrecognised(String),
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveError::GameOver(status) => write!(f, "The game is over: {}", status.description()),
+            MoveError::Illegal(mv) => write!(f, "Illegal move {}", mv.to_uci()),
+            MoveError::Unrecognised(text) => write!(f, "No legal move '{text}'"),
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Game {
+    /// A standard game from the usual starting position.
+    pub fn new() -> Self {
+        Self::from_position(GameState::new())
+    }
+
+    /// A game of `variant` from the usual starting position.
+    pub fn with_variant(variant: Variant) -> Self {
+        Self::from_position(GameState::with_variant(variant))
+    }
+
+    /// A standard game starting from `fen`. See [`GameState::from_fen`] for
+    /// what is accepted.
+    pub fn from_fen(fen: &str) -> Result<Self, FenError> {
+        let mut start = GameState::new();
+        start.from_fen(fen)?;
+        Ok(Self::from_position(start))
+    }
+
+    /// A game starting from `start`.
+    pub fn from_position(start: GameState) -> Self {
+        Game {
+            position_history: vec![start.position_key()],
+            position: start.clone(),
+            start,
+            moves: Vec::new(),
+            undo: Vec::new(),
+        }
+    }
+
+    /// The current position.
+    pub fn position(&self) -> &GameState {
+        &self.position
+    }
+
+    /// The position the game started from.
+    pub fn start_position(&self) -> &GameState {
+        &self.start
+    }
+
+    pub fn board(&self) -> &ChessBoard {
+        &self.position.board
+    }
+
+    /// The side to move.
+    pub fn turn(&self) -> PieceColor {
+        self.position.turn
+    }
+
+    /// The moves played so far, first move first.
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    /// The moves played so far in standard algebraic notation.
+    pub fn san_moves(&self) -> Vec<String> {
+        let mut position = self.start.clone();
+        self.moves
+            .iter()
+            .map(|&mv| {
+                let san = mv.to_san(&position);
+                position.make_move(mv);
+                san
+            })
+            .collect()
+    }
+
+    /// Every legal move for the side to move, with each promotion listed
+    /// once for every piece it can promote to. None once the game is over.
+    pub fn legal_moves(&self) -> Vec<Move> {
+        if self.status().is_over() {
+            return Vec::new();
+        }
+        self.moves_in_position()
+    }
+
+    /// Whether `mv` can be played now. A promotion without a piece counts as
+    /// promoting to a queen.
+    pub fn is_legal(&self, mv: Move) -> bool {
+        !self.status().is_over()
+            && self
+                .moves_in_position()
+                .contains(&self.with_default_promotion(mv))
+    }
+
+    /// Plays `mv` for the side to move. A promotion without a piece promotes
+    /// to a queen.
+    pub fn make_move(&mut self, mv: Move) -> Result<(), MoveError> {
+        let status = self.status();
+        if status.is_over() {
+            return Err(MoveError::GameOver(status));
+        }
+        let mv = self.with_default_promotion(mv);
+        if !self.moves_in_position().contains(&mv) {
+            return Err(MoveError::Illegal(mv));
+        }
+        self.undo.push(self.position.make_move(mv));
+        self.moves.push(mv);
+        self.position_history.push(self.position.position_key());
+        Ok(())
+    }
+
+    /// Plays a move written in SAN (e.g. "Nf3", "exd8=Q+") or UCI (e.g.
+    /// "g1f3"), returning the move.
+    pub fn play(&mut self, notation: &str) -> Result<Move, MoveError> {
+        let mv = Move::from_san(notation, &self.position)
+            .or_else(|| Move::from_uci_on(notation, &self.position.board))
+            .ok_or_else(|| MoveError::Unrecognised(notation.to_string()))?;
+        self.make_move(mv)?;
+        Ok(mv)
+    }
+
+    /// Takes back the last move, returning it, or None at the start.
+    pub fn undo(&mut self) -> Option<Move> {
+        let mv = self.moves.pop()?;
+        let undo = self.undo.pop()?;
+        self.position.unmake_move(mv, undo);
+        self.position_history.pop();
+        Some(mv)
+    }
+
+    /// Whether the game is still going on, and if not how it ended. Draws by
+    /// threefold repetition and the fifty-move rule are included.
+    pub fn status(&self) -> GameStatus {
+        self.position.status_with_history(&self.position_history)
+    }
+
+    /// The current position as a FEN.
+    pub fn fen(&self) -> String {
+        self.position.to_fen()
+    }
+
+    // The legal moves in the position, whether or not the game is over
+    fn moves_in_position(&self) -> Vec<Move> {
+        self.position
+            .generate_valid_moves(self.position.turn)
+            .into_iter()
+            .flat_map(|mv| {
+                let promotions = if mv.is_promotion(&self.position) {
+                    self.position
+                        .promotion_pieces()
+                        .into_iter()
+                        .map(Some)
+                        .collect()
+                } else {
+                    vec![None]
+                };
+                promotions
+                    .into_iter()
+                    .map(move |promotion| Move { promotion, ..mv })
+            })
+            .collect()
+    }
+
+    fn with_default_promotion(&self, mv: Move) -> Move {
+        if mv.promotion.is_none() && mv.is_promotion(&self.position) {
+            Move {
+                promotion: Some(PieceType::Queen),
+                ..mv
+            }
+        } else {
+            mv
+        }
+    }
+}
@@ -0,0 +1,583 @@
+use std::cmp::Reverse;
+
+use super::bitboard::{color_index, Bitboards};
+use super::board::{ChessBoard, Piece, PieceColor, PieceType};
+use super::castling::CastlingRights;
+use super::nnue::Accumulator;
+use super::variant::Variant;
+use super::zobrist::{castling_key, piece_key, turn_key};
+
+/// A move from one square to another, in (row, col) board coordinates.
+///
+/// Whether a move captures, is made by a pawn or castles is asked of the
+/// position it is played from (`is_capture`, `is_pawn_move`, `is_castling`)
+/// rather than stored here. A move read from UCI, SAN or a mouse drag then
+/// compares equal to the generated one, and `make_move` reads the flags
+/// before it touches the board.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Move {
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+    pub promotion: Option<PieceType>,
+}
+
+impl Move {
+    pub fn new(from: (usize, usize), to: (usize, usize)) -> Self {
+        Move {
+            from,
+            to,
+            promotion: None,
+        }
+    }
+
+    /// Whether the move takes a piece in `state`, the position it is played
+    /// from, counting en passant.
+    pub fn is_capture(&self, state: &GameState) -> bool {
+        state.board.squares[self.to.0][self.to.1].occupant.is_some()
+            || (self.is_pawn_move(state) && self.from.1 != self.to.1)
+    }
+
+    /// Whether the move is made by a pawn in `state`, the position it is
+    /// played from.
+    pub fn is_pawn_move(&self, state: &GameState) -> bool {
+        state.board.squares[self.from.0][self.from.1]
+            .occupant
+            .is_some_and(|p| p.piece_type == PieceType::Pawn)
+    }
+
+    /// Whether the move castles in `state`, the position it is played from:
+    /// a king move of more than one file.
+    pub fn is_castling(&self, state: &GameState) -> bool {
+        self.from.1.abs_diff(self.to.1) > 1
+            && state.board.squares[self.from.0][self.from.1]
+                .occupant
+                .is_some_and(|p| p.piece_type == PieceType::King)
+    }
+}
+
+/// State saved by [`GameState::make_move`] so the move can be taken back.
+#[derive(Clone, Debug)]
+pub struct UndoInfo {
+    moved: Piece,
+    captured: Option<Piece>,
+    captured_square: (usize, usize),
+    castling_rights: CastlingRights,
+    en_passant_target: Option<(usize, usize)>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    checks_given: [u32; 2],
+    zobrist: u64,
+}
+
+impl UndoInfo {
+    /// The piece the move took, if any.
+    pub fn captured(&self) -> Option<Piece> {
+        self.captured
+    }
+}
+
+/// Everything needed to describe a position: the board plus the side to move,
+/// castling rights, en passant target and move counters (i.e. what a FEN holds),
+/// plus the variant being played and anything it needs to keep track of.
+#[derive(Clone)]
+pub struct GameState {
+    pub board: ChessBoard,
+    pub turn: PieceColor,
+    pub castling_rights: CastlingRights,
+    pub en_passant_target: Option<(usize, usize)>, // Square where en passant is possible
+    pub halfmove_clock: u32, // Number of halfmoves since the last capture or pawn move
+    pub fullmove_number: u32, // Fullmove count (increments after Black's turn)
+    pub variant: Variant,
+    pub(super) checks_given: [u32; 2], // Checks given by White and Black, for Three-Check
+    pub(super) zobrist: u64,           // Hash of all of the above, see zobrist_key()
+    // The evaluation network's hidden layers, only while searching with one
+    pub(super) accumulator: Option<Accumulator>,
+}
+
+impl Default for GameState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GameState {
+    pub fn new() -> Self {
+        let mut state = Self {
+            board: ChessBoard::new_standard(),
+            turn: PieceColor::White,
+            castling_rights: CastlingRights::ALL,
+            en_passant_target: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            variant: Variant::Standard,
+            checks_given: [0; 2],
+            zobrist: 0,
+            accumulator: None,
+        };
+        state.refresh_zobrist();
+        state
+    }
+
+    // Checks if a move is legal for the side to move.
+    pub fn validate_move(&self, start: (usize, usize), end: (usize, usize)) -> bool {
+        // Ensure both squares are on the board
+        if !self.board.contains(start) || !self.board.contains(end) {
+            return false;
+        }
+
+        let bitboards = Bitboards::from_board(&self.board);
+        self.legal_moves_from(&bitboards, bitboards.geometry().bit(start))
+            .iter()
+            .any(|mv| mv.to == end)
+    }
+
+    /// Whether `mv` is legal for the side to move, promotion piece and all.
+    /// A promotion without a piece counts as promoting to a queen; a piece
+    /// that can't be promoted to here, or given for a move that doesn't
+    /// promote, makes the move illegal.
+    pub fn is_legal_move(&self, mv: Move) -> bool {
+        let promotes = mv.is_pawn_move(self) && self.board.is_back_rank(mv.to.0);
+        let promotion_allowed = match mv.promotion {
+            None => true,
+            Some(piece_type) => promotes && self.promotion_pieces().contains(&piece_type),
+        };
+        promotion_allowed && self.validate_move(mv.from, mv.to)
+    }
+
+    /// Plays `mv` on the board, handling captures, en passant, castling,
+    /// promotion (to a queen unless the move says otherwise), castling rights,
+    /// clocks and the turn. The move must already be known to be legal.
+    ///
+    /// The halfmove clock goes back to zero after a pawn move or a capture:
+    ///
+    /// ```
+    /// use itsjustchess::chess_core::{algebraic_to_square, GameState, Move};
+    ///
+    /// let square = |name| algebraic_to_square(name).unwrap();
+    /// let mv = |from, to| Move::new(square(from), square(to));
+    /// let mut state = GameState::new();
+    /// state.from_fen("4k3/8/8/3p4/8/8/8/R3K1N1 w - - 7 30").unwrap();
+    /// state.make_move(mv("g1", "f3"));
+    /// assert_eq!(state.to_fen(), "4k3/8/8/3p4/8/5N2/8/R3K3 b - - 8 30");
+    /// state.make_move(mv("d5", "d4"));
+    /// assert_eq!(state.to_fen(), "4k3/8/8/8/3p4/5N2/8/R3K3 w - - 0 31");
+    /// state.make_move(mv("a1", "a2"));
+    /// state.make_move(mv("e8", "e7"));
+    /// state.make_move(mv("f3", "d4"));
+    /// assert_eq!(state.to_fen(), "8/4k3/8/8/3N4/8/R7/4K3 b - - 0 32");
+    /// ```
+    pub fn make_move(&mut self, mv: Move) -> UndoInfo {
+        let is_capture = mv.is_capture(self);
+        let is_pawn_move = mv.is_pawn_move(self);
+        let is_castling = mv.is_castling(self);
+        let piece = self.board.squares[mv.from.0][mv.from.1]
+            .occupant
+            .expect("make_move called without a piece on the start square");
+
+        let mut undo = UndoInfo {
+            moved: piece,
+            captured: self.board.squares[mv.to.0][mv.to.1].occupant,
+            captured_square: mv.to,
+            castling_rights: self.castling_rights,
+            en_passant_target: self.en_passant_target,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            checks_given: self.checks_given,
+            zobrist: self.zobrist,
+        };
+
+        // Take out everything about the old position the move can change
+        self.zobrist ^= castling_key(self.castling_rights) ^ self.en_passant_key();
+        self.zobrist ^= piece_key(piece, mv.from);
+        self.accumulate_piece(piece, mv.from, -1);
+
+        // En passant: a diagonal pawn move onto an empty square
+        if piece.piece_type == PieceType::Pawn && mv.from.1 != mv.to.1 && undo.captured.is_none() {
+            undo.captured_square = (mv.from.0, mv.to.1);
+            undo.captured = self.board.squares[mv.from.0][mv.to.1].occupant.take();
+        }
+        if let Some(captured) = undo.captured {
+            self.zobrist ^= piece_key(captured, undo.captured_square);
+            self.accumulate_piece(captured, undo.captured_square, -1);
+        }
+
+        self.update_castling_rights(mv);
+
+        let mut moved = piece;
+        moved.has_moved = true;
+        if piece.piece_type == PieceType::Pawn && self.board.is_back_rank(mv.to.0) {
+            moved.piece_type = mv.promotion.unwrap_or(PieceType::Queen);
+        }
+        self.board.squares[mv.from.0][mv.from.1].occupant = None;
+        self.board.squares[mv.to.0][mv.to.1].occupant = Some(moved);
+        self.zobrist ^= piece_key(moved, mv.to);
+        self.accumulate_piece(moved, mv.to, 1);
+
+        if is_castling {
+            self.perform_castling(mv.from, mv.to);
+        }
+
+        self.en_passant_target = if piece.piece_type == PieceType::Pawn
+            && (mv.to.0 as isize - mv.from.0 as isize).abs() == 2
+        {
+            Some(((mv.from.0 + mv.to.0) / 2, mv.from.1))
+        } else {
+            None
+        };
+
+        if is_pawn_move || is_capture {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if self.turn == PieceColor::Black {
+            self.fullmove_number += 1;
+        }
+        self.turn = self.turn.opposite();
+        self.zobrist ^= castling_key(self.castling_rights) ^ self.en_passant_key() ^ turn_key();
+
+        if self.variant.counts_checks() && self.is_king_in_check(self.turn) {
+            self.checks_given[color_index(self.turn.opposite())] += 1;
+        }
+
+        undo
+    }
+
+    /// Reverts a move made with [`GameState::make_move`].
+    pub fn unmake_move(&mut self, mv: Move, undo: UndoInfo) {
+        self.turn = self.turn.opposite();
+
+        if let Some(moved) = self.board.squares[mv.to.0][mv.to.1].occupant.take() {
+            self.accumulate_piece(moved, mv.to, -1);
+        }
+        self.board.squares[mv.from.0][mv.from.1].occupant = Some(undo.moved);
+        self.accumulate_piece(undo.moved, mv.from, 1);
+        if let Some(captured) = undo.captured {
+            self.board.squares[undo.captured_square.0][undo.captured_square.1].occupant =
+                Some(captured);
+            self.accumulate_piece(captured, undo.captured_square, 1);
+        }
+
+        // Put the castling rook back in its corner
+        if mv.is_castling(self) {
+            let is_king_side = mv.to.1 > mv.from.1;
+            let rook_start_col = if is_king_side {
+                self.board.files - 1
+            } else {
+                0
+            };
+            let rook_end_col = if is_king_side {
+                mv.to.1 - 1
+            } else {
+                mv.to.1 + 1
+            };
+            let rook = self.board.squares[mv.from.0][rook_end_col].occupant.take();
+            if let Some(rook) = rook {
+                self.accumulate_piece(rook, (mv.from.0, rook_end_col), -1);
+                self.accumulate_piece(rook, (mv.from.0, rook_start_col), 1);
+            }
+            self.board.squares[mv.from.0][rook_start_col].occupant = rook;
+        }
+
+        self.castling_rights = undo.castling_rights;
+        self.en_passant_target = undo.en_passant_target;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.fullmove_number = undo.fullmove_number;
+        self.checks_given = undo.checks_given;
+        self.zobrist = undo.zobrist;
+    }
+
+    pub fn perform_castling(&mut self, start: (usize, usize), end: (usize, usize)) {
+        let (start_row, start_col) = start;
+        let is_king_side = end.1 > start_col;
+
+        // Move the rook
+        let rook_start_col = if is_king_side {
+            self.board.files - 1
+        } else {
+            0
+        };
+        let rook_end_col = if is_king_side { end.1 - 1 } else { end.1 + 1 };
+
+        let rook = self.board.squares[start_row][rook_start_col]
+            .occupant
+            .take();
+        if let Some(rook) = rook {
+            self.zobrist ^= piece_key(rook, (start_row, rook_start_col));
+            self.zobrist ^= piece_key(rook, (start_row, rook_end_col));
+            self.accumulate_piece(rook, (start_row, rook_start_col), -1);
+            self.accumulate_piece(rook, (start_row, rook_end_col), 1);
+        }
+        self.board.squares[start_row][rook_end_col].occupant = rook;
+    }
+
+    pub fn promote_pawn(&mut self, position: (usize, usize), new_piece_type: PieceType) {
+        let (row, col) = position;
+        if let Some(piece) = self.board.squares[row][col].occupant {
+            if piece.piece_type == PieceType::Pawn {
+                // Create a new piece with the promoted type
+                let promoted_piece = Piece {
+                    piece_type: new_piece_type,
+                    color: piece.color,
+                    has_moved: piece.has_moved,
+                };
+
+                // Replace the occupant with the promoted piece
+                self.board.squares[row][col].occupant = Some(promoted_piece);
+                self.zobrist ^= piece_key(piece, position) ^ piece_key(promoted_piece, position);
+                self.accumulate_piece(piece, position, -1);
+                self.accumulate_piece(promoted_piece, position, 1);
+            } else {
+                log::warn!("Piece at {:?} is not a pawn!", position);
+            }
+        } else {
+            log::warn!("No piece found at {:?}", position);
+        }
+    }
+
+    /// The pieces a pawn can promote to in this position, strongest first:
+    /// every kind of piece but the pawn and the king, with the fairy pieces
+    /// only while one of them is on the board, as it is in Capablanca chess.
+    /// Without check, as in Antichess, the king is an ordinary piece and
+    /// comes last.
+    pub fn promotion_pieces(&self) -> Vec<PieceType> {
+        let on_board = |piece_type: PieceType| {
+            self.board
+                .squares
+                .iter()
+                .flatten()
+                .any(|square| square.occupant.is_some_and(|p| p.piece_type == piece_type))
+        };
+        let mut pieces: Vec<PieceType> = PieceType::ALL
+            .into_iter()
+            .filter(|&piece_type| match piece_type {
+                PieceType::Pawn => false,
+                PieceType::King => !self.variant.has_check(),
+                piece_type => !piece_type.is_fairy() || on_board(piece_type),
+            })
+            .collect();
+        // Bishops before knights, as they have always been listed
+        pieces.sort_by_key(|&piece_type| {
+            (
+                Reverse(piece_type.points()),
+                piece_type == PieceType::Knight,
+            )
+        });
+        pieces
+    }
+
+    pub fn path_is_clear(&self, start: (usize, usize), end: (usize, usize)) -> bool {
+        let (start_row, start_col) = start;
+        let (end_row, end_col) = end;
+
+        let row_step = (end_row as isize - start_row as isize).signum();
+        let col_step = (end_col as isize - start_col as isize).signum();
+
+        let mut current_row = start_row as isize + row_step;
+        let mut current_col = start_col as isize + col_step;
+
+        while current_row != end_row as isize || current_col != end_col as isize {
+            if self.board.squares[current_row as usize][current_col as usize]
+                .occupant
+                .is_some()
+            {
+                return false;
+            }
+
+            current_row += row_step;
+            current_col += col_step;
+        }
+
+        true
+    }
+
+    /// Takes away the castling rights `mv` ends: both of a side's when its
+    /// king moves, and the one for a corner when its rook moves away or is
+    /// captured there. Call before the move is made on the board.
+    pub fn update_castling_rights(&mut self, mv: Move) {
+        if let Some(piece) = self.board.squares[mv.from.0][mv.from.1].occupant {
+            if piece.piece_type == PieceType::King {
+                self.castling_rights
+                    .remove(CastlingRights::both(piece.color));
+            }
+        }
+        self.castling_rights
+            .remove(CastlingRights::for_corner(&self.board, mv.from));
+        self.castling_rights
+            .remove(CastlingRights::for_corner(&self.board, mv.to));
+    }
+
+    pub fn is_king_in_check(&self, color: PieceColor) -> bool {
+        // The king is in check if any opposing piece attacks its square,
+        // in the variants that have check at all
+        if !self.variant.has_check() {
+            return false;
+        }
+        match self.find_king(color) {
+            Some(king_square) => self.is_square_attacked(king_square, color),
+            None => false,
+        }
+    }
+
+    pub fn find_king(&self, color: PieceColor) -> Option<(usize, usize)> {
+        for row in 0..self.board.ranks {
+            for col in 0..self.board.files {
+                if let Some(piece) = self.board.squares[row][col].occupant {
+                    if piece.piece_type == PieceType::King && piece.color == color {
+                        return Some((row, col));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Whether `color` is checkmated: in check with no legal move.
+    ///
+    /// ```
+    /// use itsjustchess::chess_core::{GameState, PieceColor};
+    ///
+    /// let mut state = GameState::new();
+    /// state.from_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3").unwrap();
+    /// assert!(state.is_checkmate(PieceColor::White));
+    /// assert!(!state.is_stalemate(PieceColor::White));
+    /// ```
+    pub fn is_checkmate(&self, color: PieceColor) -> bool {
+        self.is_king_in_check(color) && self.generate_valid_moves(color).is_empty()
+    }
+
+    /// Whether `color` is stalemated: not in check, but with no legal move.
+    ///
+    /// ```
+    /// use itsjustchess::chess_core::{GameState, PieceColor};
+    ///
+    /// // The king in the corner, boxed in by the queen
+    /// let mut state = GameState::new();
+    /// state.from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+    /// assert!(state.is_stalemate(PieceColor::Black));
+    /// assert!(!state.is_checkmate(PieceColor::Black));
+    ///
+    /// // With the queen one square over, guarded by the king, it is mate
+    /// state.from_fen("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+    /// assert!(state.is_checkmate(PieceColor::Black));
+    /// assert!(!state.is_stalemate(PieceColor::Black));
+    /// ```
+    pub fn is_stalemate(&self, color: PieceColor) -> bool {
+        !self.is_king_in_check(color) && self.generate_valid_moves(color).is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(fen: &str) -> GameState {
+        let mut state = GameState::new();
+        state.from_fen(fen).unwrap();
+        state
+    }
+
+    #[test]
+    fn check() {
+        let state = position("4k3/8/8/8/8/8/8/4R1K1 b - - 0 1");
+        assert!(state.is_king_in_check(PieceColor::Black));
+        assert!(!state.is_checkmate(PieceColor::Black));
+        // Only king moves off the file get out of it
+        let moves = state.generate_valid_moves(PieceColor::Black);
+        assert_eq!(moves.len(), 4);
+        assert!(moves.iter().all(|mv| mv.from == (0, 4) && mv.to.1 != 4));
+
+        // A check can also be met by capturing the checker or blocking
+        let state = position("4k3/8/8/8/8/6N1/4B3/4K2r w - - 0 1");
+        assert!(state.is_king_in_check(PieceColor::White));
+        assert!(state.validate_move((5, 6), (7, 7))); // Nxh1
+        assert!(state.validate_move((6, 4), (7, 5))); // Bf1
+        assert!(state.validate_move((7, 4), (6, 3))); // Kd2
+        assert!(!state.validate_move((6, 4), (5, 3))); // Bd3 ignores the check
+    }
+
+    #[test]
+    fn checkmate() {
+        // Back-rank mate
+        let state = position("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        let mut mated = state.clone();
+        mated.make_move(Move::new((7, 0), (0, 0)));
+        assert!(mated.is_checkmate(PieceColor::Black));
+        assert!(!mated.is_stalemate(PieceColor::Black));
+
+        // Fool's mate
+        let state = position("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3");
+        assert!(state.is_checkmate(PieceColor::White));
+        assert!(state.generate_valid_moves(PieceColor::White).is_empty());
+    }
+
+    #[test]
+    fn stalemate() {
+        let state = position("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        assert!(state.is_stalemate(PieceColor::Black));
+        assert!(!state.is_checkmate(PieceColor::Black));
+        assert!(!state.is_king_in_check(PieceColor::Black));
+
+        // A pawn that can still move means it isn't stalemate
+        let state = position("7k/5Q2/6K1/8/8/8/p7/8 b - - 0 1");
+        assert!(!state.is_stalemate(PieceColor::Black));
+    }
+
+    #[test]
+    fn pins() {
+        // The knight is pinned to its king by the bishop
+        let state = position("4k3/8/8/8/1b6/8/3N4/4K3 w - - 0 1");
+        let knight_moves = state
+            .generate_valid_moves(PieceColor::White)
+            .into_iter()
+            .filter(|mv| mv.from == (6, 3));
+        assert_eq!(knight_moves.count(), 0);
+
+        // A pinned rook may still move along the pin, up to the pinner
+        let state = position("4r1k1/8/8/8/8/8/4R3/4K3 w - - 0 1");
+        let rook_moves: Vec<Move> = state
+            .generate_valid_moves(PieceColor::White)
+            .into_iter()
+            .filter(|mv| mv.from == (6, 4))
+            .collect();
+        assert_eq!(rook_moves.len(), 6);
+        assert!(rook_moves.iter().all(|mv| mv.to.1 == 4));
+
+        // En passant that would leave the king open along the rank
+        let state = position("8/8/8/K2pP2r/8/8/8/7k w - d6 0 1");
+        assert!(!state.validate_move((3, 4), (2, 3)));
+    }
+
+    #[test]
+    fn promotion_to_fairy_pieces_only_where_they_are_played() {
+        use PieceType::*;
+        assert_eq!(
+            position("8/4P3/8/8/8/8/8/k1K5 w - - 0 1").promotion_pieces(),
+            [Queen, Rook, Bishop, Knight]
+        );
+
+        let state = position("10/4P5/10/10/10/10/10/k1K4a2 w - - 0 1");
+        assert_eq!(
+            state.promotion_pieces(),
+            [Queen, Archbishop, Rook, Bishop, Knight]
+        );
+        let mv = Move::from_san("e8=A", &state).unwrap();
+        assert_eq!(mv.promotion, Some(Archbishop));
+        assert!(Move::from_san("e8=C", &state).is_none());
+    }
+
+    #[test]
+    fn promotion_piece_must_be_one_that_can_be_promoted_to() {
+        let state = position("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1");
+        let uci = |uci| state.is_legal_move(Move::from_uci(uci).unwrap());
+        assert!(uci("b7b8"));
+        assert!(uci("b7b8n"));
+        assert!(!uci("b7b8k"));
+        assert!(!uci("b7b8a"));
+        assert!(!uci("e1d1q"));
+
+        let mut antichess = GameState::with_variant(Variant::Antichess);
+        antichess.from_fen("8/1P6/8/8/8/8/8/7k w - - 0 1").unwrap();
+        assert!(antichess.is_legal_move(Move::from_uci("b7b8k").unwrap()));
+    }
+}
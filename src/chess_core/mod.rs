@@ -0,0 +1,54 @@
+//! Board representation, move validation, whole games, FEN and move
+//! notation, Zobrist hashing, the opening book and opening names, a library
+//! of well-known positions, odds setups, position evaluation (handcrafted, or
+//! by a loaded network), the built-in AI, a tablebase for the basic endgames
+//! and game review.
+
+mod ai;
+mod bitboard;
+mod board;
+mod book;
+mod castling;
+mod clock;
+mod difficulty;
+mod eco;
+mod endgame;
+mod eval;
+mod fen;
+mod game;
+mod game_state;
+mod nnue;
+mod notation;
+mod odds;
+mod pgn;
+mod positions;
+mod review;
+mod status;
+mod variant;
+mod zobrist;
+
+pub use ai::{Analysis, SearchStats};
+pub use board::{
+    ChessBoard, Movement, Piece, PieceColor, PieceType, Square, MAX_FILES, MAX_RANKS, MIN_SIZE,
+};
+pub use book::OpeningBook;
+pub use castling::CastlingRights;
+pub use clock::{format_clock, ChessClock, ClockMode, TimeControl};
+pub use difficulty::Difficulty;
+pub use eco::{Opening, OpeningNames};
+pub use endgame::{Endgame, EndgameResult};
+pub use eval::PawnInfo;
+pub use fen::{algebraic_to_square, square_to_algebraic, FenError};
+pub use game::{Game, MoveError};
+pub use game_state::{GameState, Move, UndoInfo};
+pub use nnue::Network;
+pub use notation::{format_moves, Notation};
+pub use odds::Odds;
+pub use pgn::{
+    format_pgn, format_pgn_with_variations, parse_pgn, parse_pgn_games, parse_pgn_lines,
+    pgn_date_today, split_pgn_games, MoveAnnotation, PgnGame, PgnMove,
+};
+pub use positions::{LibraryPosition, POSITION_LIBRARY, SCRAMBLE_POSITIONS};
+pub use review::{MoveQuality, MoveReview};
+pub use status::GameStatus;
+pub use variant::Variant;
@@ -0,0 +1,212 @@
+use std::sync::OnceLock;
+
+use super::bitboard::{color_index, type_index};
+use super::board::Piece;
+use super::game_state::GameState;
+
+// A network file starts with these bytes, then the hidden layer size
+const MAGIC: &[u8; 4] = b"IJNN";
+
+// A piece of each colour and type on each square
+const INPUTS: usize = 768;
+
+// Anything bigger is more likely a corrupt file than a network worth the
+// search time
+const MAX_HIDDEN: usize = 4096;
+
+// The hidden layer is clipped to 0..=QA and the output weights are scaled
+// by QB; the output comes out in units of 1/EVAL_SCALE of a pawn's worth
+const QA: i32 = 255;
+const QB: i32 = 64;
+const EVAL_SCALE: i32 = 400;
+
+// The network the AI evaluates with, once one has been installed
+static NETWORK: OnceLock<Network> = OnceLock::new();
+
+/// A small NNUE-style evaluation network: a hidden layer fed by 768 inputs
+/// (one for each piece type of each side on each square, seen from one
+/// player's side of the board), kept for both players at once, and a single
+/// output from both hidden layers through a clipped ReLU, the side to move's
+/// first.
+///
+/// The file holds the magic bytes `IJNN`, the hidden layer size as a
+/// little-endian `u32` and then, as little-endian `i16`s, the input weights
+/// (one row of the hidden size for each input), the hidden biases and the
+/// output weights (twice the hidden size), followed by the output bias as an
+/// `i32`. Input `i` of a piece is `384 * side + 64 * piece + square`, where
+/// `side` is 0 for the player's own pieces, `piece` runs pawn, knight,
+/// bishop, rook, queen, king and `square` runs a1 = 0 to h8 = 63, with the
+/// board turned round for Black. The fairy pieces have no inputs, so
+/// positions with them are left to the handcrafted evaluation.
+pub struct Network {
+    hidden: usize,
+    input_weights: Vec<i16>,
+    hidden_biases: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+    fingerprint: u64, // FNV-1a of the file, to tell one network from another
+}
+
+impl Network {
+    /// Reads a network file.
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes =
+            std::fs::read(path).map_err(|e| format!("Failed to read network {path}: {e}"))?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let invalid = |why: &str| format!("Invalid network: {why}");
+        if bytes.len() < 8 || &bytes[..4] != MAGIC {
+            return Err(invalid("not a network file"));
+        }
+        let hidden = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+        if hidden == 0 || hidden > MAX_HIDDEN {
+            return Err(invalid(&format!(
+                "hidden layer size {hidden} is not from 1 to {MAX_HIDDEN}"
+            )));
+        }
+        let weights = INPUTS * hidden + hidden + 2 * hidden;
+        if bytes.len() != 8 + 2 * weights + 4 {
+            return Err(invalid("length doesn't match the hidden layer size"));
+        }
+
+        let mut values = bytes[8..8 + 2 * weights]
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]));
+        let mut take = |count: usize| values.by_ref().take(count).collect::<Vec<i16>>();
+        let input_weights = take(INPUTS * hidden);
+        let hidden_biases = take(hidden);
+        let output_weights = take(2 * hidden);
+        let output_bias = i32::from_le_bytes(bytes[8 + 2 * weights..].try_into().unwrap());
+        let fingerprint = bytes.iter().fold(0xcbf29ce484222325, |hash: u64, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+        Ok(Network {
+            hidden,
+            input_weights,
+            hidden_biases,
+            output_weights,
+            output_bias,
+            fingerprint,
+        })
+    }
+
+    /// Makes the AI evaluate positions with this network in place of the
+    /// handcrafted evaluation, from then on. There can only be one.
+    pub fn install(self) -> Result<(), String> {
+        NETWORK
+            .set(self)
+            .map_err(|_| "A network is already installed".to_string())
+    }
+
+    /// A hash of the installed network's file, the same from one run to the
+    /// next, or `None` if there is no network.
+    pub fn installed_fingerprint() -> Option<u64> {
+        NETWORK.get().map(|network| network.fingerprint)
+    }
+
+    // The hidden layers for `state` worked out from scratch
+    fn accumulate(&self, state: &GameState) -> Accumulator {
+        let mut accumulator = Accumulator {
+            values: [0, 1].map(|_| self.hidden_biases.iter().map(|&b| i32::from(b)).collect()),
+            fairies: 0,
+        };
+        for row in 0..state.board.ranks {
+            for col in 0..state.board.files {
+                if let Some(piece) = state.board.squares[row][col].occupant {
+                    accumulator.update(self, piece, (row, col), 1);
+                }
+            }
+        }
+        accumulator
+    }
+
+    // In centipawns, from the point of view of `us` (a colour index); `None`
+    // with fairy pieces on the board, which the network can't see
+    fn evaluate(&self, accumulator: &Accumulator, us: usize) -> Option<i32> {
+        if accumulator.fairies > 0 {
+            return None;
+        }
+        let (ours, theirs) = self.output_weights.split_at(self.hidden);
+        let layer = |values: &[i32], weights: &[i16]| -> i32 {
+            values
+                .iter()
+                .zip(weights)
+                .map(|(&value, &weight)| value.clamp(0, QA) * i32::from(weight))
+                .sum()
+        };
+        let output = layer(&accumulator.values[us], ours)
+            + layer(&accumulator.values[1 - us], theirs)
+            + self.output_bias;
+        Some(output * EVAL_SCALE / (QA * QB))
+    }
+}
+
+/// The network's hidden layer for the current position from each player's
+/// side (by colour index), kept up to date move by move during a search.
+#[derive(Clone, Debug)]
+pub(super) struct Accumulator {
+    values: [Vec<i32>; 2],
+    fairies: i32, // Pieces on the board the network has no inputs for
+}
+
+impl Accumulator {
+    // Adds (`sign` 1) or takes away (-1) `piece` on `square`
+    fn update(&mut self, network: &Network, piece: Piece, square: (usize, usize), sign: i32) {
+        if piece.piece_type.is_fairy() {
+            self.fairies += sign;
+            return;
+        }
+        for (perspective, values) in self.values.iter_mut().enumerate() {
+            let input = input_index(perspective, piece, square);
+            let weights = &network.input_weights[input * network.hidden..][..network.hidden];
+            for (value, &weight) in values.iter_mut().zip(weights) {
+                *value += sign * i32::from(weight);
+            }
+        }
+    }
+}
+
+// Which input `piece` on `square` is, seen from `perspective`'s side
+fn input_index(perspective: usize, piece: Piece, square: (usize, usize)) -> usize {
+    let side = usize::from(color_index(piece.color) != perspective);
+    // Row 0 is the eighth rank, so White has to flip to count from a1
+    let square = square.0 * 8 + square.1;
+    let square = if perspective == 0 {
+        square ^ 56
+    } else {
+        square
+    };
+    384 * side + 64 * type_index(piece.piece_type) + square
+}
+
+impl GameState {
+    /// The network's evaluation in centipawns from the side to move's point
+    /// of view, or `None` if no network is installed or it can't evaluate
+    /// the position, as it can't off the standard board.
+    pub(super) fn network_evaluate(&self) -> Option<i32> {
+        let network = NETWORK.get().filter(|_| self.board.is_standard_size())?;
+        let us = color_index(self.turn);
+        match &self.accumulator {
+            Some(accumulator) => network.evaluate(accumulator, us),
+            None => network.evaluate(&network.accumulate(self), us),
+        }
+    }
+
+    /// Sets up the hidden layers for a search from this position, if a
+    /// network is installed and the board is one it knows, so that each
+    /// evaluation after that only has to account for the moves made since.
+    pub(super) fn refresh_accumulator(&mut self) {
+        let network = NETWORK.get().filter(|_| self.board.is_standard_size());
+        self.accumulator = network.map(|network| network.accumulate(self));
+    }
+
+    // Keeps the hidden layers in step with `piece` arriving on (`sign` 1) or
+    // leaving (-1) `square`
+    pub(super) fn accumulate_piece(&mut self, piece: Piece, square: (usize, usize), sign: i32) {
+        if let (Some(accumulator), Some(network)) = (&mut self.accumulator, NETWORK.get()) {
+            accumulator.update(network, piece, square, sign);
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use std::fmt;
+
+use super::board::{ChessBoard, Piece, PieceColor, PieceType};
+use super::fen::{char_to_piece, piece_to_fen_char};
+use super::game_state::{GameState, Move};
+
+/// A way of writing moves down, offered when exporting the move list.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Notation {
+    /// Standard algebraic, e.g. "Nf3"
+    San,
+    /// Long algebraic, giving the origin square as well, e.g. "Ng1-f3"
+    LongAlgebraic,
+    /// As UCI engines take it, e.g. "g1f3"
+    Uci,
+    /// SAN with the pieces as Unicode chess symbols, e.g. "♘f3"
+    FigurineSan,
+}
+
+impl Notation {
+    pub const ALL: [Notation; 4] = [
+        Notation::San,
+        Notation::LongAlgebraic,
+        Notation::Uci,
+        Notation::FigurineSan,
+    ];
+
+    /// The notation after this one in ALL, going round to the first.
+    pub fn next(self) -> Notation {
+        let i = Notation::ALL.iter().position(|&n| n == self).unwrap_or(0);
+        Notation::ALL[(i + 1) % Notation::ALL.len()]
+    }
+}
+
+impl fmt::Display for Notation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Notation::San => "SAN",
+            Notation::LongAlgebraic => "long algebraic",
+            Notation::Uci => "UCI",
+            Notation::FigurineSan => "figurine SAN",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Move {
+    /// The move in UCI long algebraic notation, e.g. "e2e4" or "e7e8n", on
+    /// the standard board.
+    pub fn to_uci(&self) -> String {
+        self.to_uci_on(&ChessBoard::empty())
+    }
+
+    /// The move in UCI long algebraic notation on `board`, whose size
+    /// decides how its ranks are numbered.
+    pub fn to_uci_on(&self, board: &ChessBoard) -> String {
+        let mut uci = board.square_name(self.from);
+        uci.push_str(&board.square_name(self.to));
+        if let Some(promotion) = self.promotion {
+            uci.push(piece_to_fen_char(Piece {
+                piece_type: promotion,
+                color: PieceColor::Black,
+                has_moved: false,
+            }));
+        }
+        uci
+    }
+
+    /// Parses a move in UCI long algebraic notation on the standard board.
+    pub fn from_uci(uci: &str) -> Option<Move> {
+        Move::from_uci_on(uci, &ChessBoard::empty())
+    }
+
+    /// Parses a move in UCI long algebraic notation on `board`.
+    pub fn from_uci_on(uci: &str, board: &ChessBoard) -> Option<Move> {
+        if !uci.is_ascii() || !(4..=5).contains(&uci.len()) {
+            return None;
+        }
+        let promotion = match uci[4..].chars().next() {
+            Some(ch) => match char_to_piece(ch)?.piece_type {
+                PieceType::Pawn => return None,
+                piece_type => Some(piece_type),
+            },
+            None => None,
+        };
+        Some(Move {
+            from: board.parse_square(&uci[..2])?,
+            to: board.parse_square(&uci[2..4])?,
+            promotion,
+        })
+    }
+
+    /// The move in standard algebraic notation as played in `state`, e.g.
+    /// "Nbd2", "exd6", "e8=Q+" or "O-O#". The origin file or rank is only
+    /// given when another piece of the same kind could legally go to the
+    /// same square.
+    pub fn to_san(&self, state: &GameState) -> String {
+        let mut san = self.san_body(state);
+        san.extend(self.check_mark(state));
+        san
+    }
+
+    /// The move in long algebraic notation as played in `state`, e.g.
+    /// "Ng1-f3", "e4xd5", "e7-e8=Q+" or "O-O".
+    pub fn to_long_algebraic(&self, state: &GameState) -> String {
+        let Some(piece) = state.board.squares[self.from.0][self.from.1].occupant else {
+            return String::new();
+        };
+        let mut text = self.san_body(state);
+        if !text.starts_with('O') {
+            text.clear();
+            if piece.piece_type != PieceType::Pawn {
+                text.push(piece.piece_type.letter());
+            }
+            text.push_str(&state.board.square_name(self.from));
+            text.push(if self.is_capture(state) { 'x' } else { '-' });
+            text.push_str(&state.board.square_name(self.to));
+            if self.is_promotion(state) {
+                text.push('=');
+                text.push(self.promotion.unwrap_or(PieceType::Queen).letter());
+            }
+        }
+        text.extend(self.check_mark(state));
+        text
+    }
+
+    /// The move in SAN with each piece letter swapped for the mover's
+    /// Unicode chess symbol, e.g. "♘f3" or "exd8=♛#".
+    pub fn to_figurine_san(&self, state: &GameState) -> String {
+        let symbols = match state.turn {
+            PieceColor::White => ['♔', '♕', '♖', '♗', '♘'],
+            PieceColor::Black => ['♚', '♛', '♜', '♝', '♞'],
+        };
+        self.to_san(state)
+            .chars()
+            .map(|c| match "KQRBN".find(c) {
+                Some(i) => symbols[i],
+                None => c,
+            })
+            .collect()
+    }
+
+    /// The move as played in `state`, written in `notation`.
+    pub fn to_notation(&self, state: &GameState, notation: Notation) -> String {
+        match notation {
+            Notation::San => self.to_san(state),
+            Notation::LongAlgebraic => self.to_long_algebraic(state),
+            Notation::Uci => self.to_uci_on(&state.board),
+            Notation::FigurineSan => self.to_figurine_san(state),
+        }
+    }
+
+    /// The legal move in `state` written as `san`. Check marks, annotations
+    /// such as "!?", a missing "=" before a promotion piece and castling
+    /// written with zeros are all accepted.
+    pub fn from_san(san: &str, state: &GameState) -> Option<Move> {
+        let wanted = normalize_san(san);
+        state
+            .generate_valid_moves(state.turn)
+            .into_iter()
+            .flat_map(|mv| {
+                // Legal moves come without a promotion piece; try each
+                let choices = if mv.is_promotion(state) {
+                    state.promotion_pieces().into_iter().map(Some).collect()
+                } else {
+                    vec![None]
+                };
+                choices
+                    .into_iter()
+                    .map(move |promotion| Move { promotion, ..mv })
+            })
+            .find(|mv| normalize_san(&mv.san_body(state)) == wanted)
+    }
+
+    pub(super) fn is_promotion(&self, state: &GameState) -> bool {
+        self.is_pawn_move(state) && state.board.is_back_rank(self.to.0)
+    }
+
+    // '+' if the move gives check, '#' if mate
+    fn check_mark(&self, state: &GameState) -> Option<char> {
+        let mut after = state.clone();
+        after.make_move(*self);
+        if !after.is_king_in_check(after.turn) {
+            None
+        } else if after.generate_valid_moves(after.turn).is_empty() {
+            Some('#')
+        } else {
+            Some('+')
+        }
+    }
+
+    // SAN without the check or mate suffix
+    fn san_body(&self, state: &GameState) -> String {
+        let piece = match state.board.squares[self.from.0][self.from.1].occupant {
+            Some(p) => p,
+            None => return String::new(),
+        };
+
+        if self.is_castling(state) {
+            return if self.to.1 > self.from.1 {
+                "O-O".to_string()
+            } else {
+                "O-O-O".to_string()
+            };
+        }
+
+        let is_capture = self.is_capture(state);
+        let destination = state.board.square_name(self.to);
+        let mut san = String::new();
+
+        if piece.piece_type == PieceType::Pawn {
+            if is_capture {
+                san.push((b'a' + self.from.1 as u8) as char);
+                san.push('x');
+            }
+            san.push_str(&destination);
+            // make_move queens unless told otherwise, so the SAN does too
+            if self.is_promotion(state) {
+                san.push('=');
+                san.push(self.promotion.unwrap_or(PieceType::Queen).letter());
+            }
+            return san;
+        }
+
+        san.push(piece.piece_type.letter());
+
+        // Disambiguate when another piece of the same kind can reach the same square
+        let mut same_file = false;
+        let mut same_rank = false;
+        let mut ambiguous = false;
+        for row in 0..state.board.ranks {
+            for col in 0..state.board.files {
+                if (row, col) == self.from {
+                    continue;
+                }
+                if let Some(other) = state.board.squares[row][col].occupant {
+                    if other.piece_type == piece.piece_type
+                        && other.color == piece.color
+                        && state.validate_move((row, col), self.to)
+                    {
+                        ambiguous = true;
+                        same_file |= col == self.from.1;
+                        same_rank |= row == self.from.0;
+                    }
+                }
+            }
+        }
+        if ambiguous {
+            let origin = state.board.square_name(self.from);
+            if !same_file {
+                san.push_str(&origin[..1]);
+            } else if !same_rank {
+                san.push_str(&origin[1..]);
+            } else {
+                san.push_str(&origin);
+            }
+        }
+
+        if is_capture {
+            san.push('x');
+        }
+        san.push_str(&destination);
+        san
+    }
+}
+
+/// `moves` played from `start` written in `notation`, numbered as in PGN,
+/// e.g. "1. e4 e5 2. Nf3", or for UCI bare, e.g. "e2e4 e7e5 g1f3", as
+/// engines and other tools take them.
+pub fn format_moves(start: &GameState, moves: &[Move], notation: Notation) -> String {
+    let mut state = start.clone();
+    let mut tokens = Vec::new();
+    for (i, &mv) in moves.iter().enumerate() {
+        if notation != Notation::Uci {
+            if state.turn == PieceColor::White {
+                tokens.push(format!("{}.", state.fullmove_number));
+            } else if i == 0 {
+                tokens.push(format!("{}...", state.fullmove_number));
+            }
+        }
+        tokens.push(mv.to_notation(&state, notation));
+        state.make_move(mv);
+    }
+    tokens.join(" ")
+}
+
+// SAN reduced to the parts that pick out the move
+fn normalize_san(san: &str) -> String {
+    san.trim_end_matches(['+', '#', '!', '?'])
+        .replace('0', "O")
+        .replace('=', "")
+}
@@ -0,0 +1,70 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::game_state::GameState;
+use super::variant::Variant;
+
+/// A material handicap taken on by the stronger player, who sets up without
+/// a piece. Piece odds are given by White, as is traditional; pawn and move
+/// by Black, who also gives White the first move as usual.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Odds {
+    /// White's queen's knight, on b1
+    Knight,
+    /// White's queen's rook, on a1, and with it castling queenside
+    Rook,
+    Queen,
+    /// Black's f-pawn
+    PawnAndMove,
+}
+
+impl Odds {
+    /// The position to start from, with castling rights to match.
+    pub fn fen(self) -> &'static str {
+        match self {
+            Odds::Knight => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/R1BQKBNR w KQkq - 0 1",
+            Odds::Rook => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/1NBQKBNR w Kkq - 0 1",
+            Odds::Queen => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNB1KBNR w KQkq - 0 1",
+            Odds::PawnAndMove => "rnbqkbnr/ppppp1pp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        }
+    }
+}
+
+impl FromStr for Odds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "knight" | "n" => Ok(Odds::Knight),
+            "rook" | "r" => Ok(Odds::Rook),
+            "queen" | "q" => Ok(Odds::Queen),
+            "pawn-and-move" | "pawn" | "f-pawn" => Ok(Odds::PawnAndMove),
+            _ => Err(format!(
+                "Unknown odds '{s}': expected knight, rook, queen or pawn-and-move"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Odds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Odds::Knight => "Knight odds",
+            Odds::Rook => "Rook odds",
+            Odds::Queen => "Queen odds",
+            Odds::PawnAndMove => "Pawn and move",
+        };
+        f.write_str(name)
+    }
+}
+
+impl GameState {
+    /// The starting position for `variant` with `odds` given.
+    pub fn with_odds(variant: Variant, odds: Odds) -> Self {
+        let mut state = GameState::with_variant(variant);
+        state
+            .from_fen(odds.fen())
+            .expect("built-in odds position is valid FEN");
+        state
+    }
+}
@@ -0,0 +1,546 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(target_arch = "wasm32")]
+use web_time::{SystemTime, UNIX_EPOCH};
+
+use super::game_state::{GameState, Move};
+use super::variant::Variant;
+
+/// A game read by [`parse_pgn`].
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub start: GameState,
+    pub moves: Vec<Move>,
+    /// What each of `moves` was annotated with.
+    pub annotations: Vec<MoveAnnotation>,
+}
+
+impl PgnGame {
+    /// The value of the tag called `name`, if the game has one.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// The move-quality glyphs, by their numeric annotation glyph (NAG) less
+/// one: $1 is "!", $2 "?" and so on to $6, "?!".
+const MOVE_GLYPHS: [&str; 6] = ["!", "?", "!!", "??", "!?", "?!"];
+
+/// What a move has been annotated with: numeric annotation glyphs (NAGs,
+/// as in "$1" for a good move) and a comment.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct MoveAnnotation {
+    pub nags: Vec<u8>,
+    pub comment: String,
+}
+
+impl MoveAnnotation {
+    pub fn is_empty(&self) -> bool {
+        self.nags.is_empty() && self.comment.is_empty()
+    }
+
+    /// The glyph of the move's quality NAG, if it has one, e.g. "!?" for $5.
+    pub fn glyph(&self) -> Option<&'static str> {
+        self.nags.iter().find_map(|&nag| nag_glyph(nag))
+    }
+
+    /// Gives the move the quality NAG `nag` (1 to 6) in place of any other,
+    /// or takes it away if the move has it already.
+    pub fn toggle_glyph(&mut self, nag: u8) {
+        let had = self.nags.contains(&nag);
+        self.nags.retain(|&other| nag_glyph(other).is_none());
+        if !had {
+            self.nags.insert(0, nag);
+        }
+    }
+
+    // The NAGs, then the comment in braces, split into words so that long
+    // comments wrap
+    fn push_tokens(&self, tokens: &mut Vec<String>) {
+        tokens.extend(self.nags.iter().map(|nag| format!("${nag}")));
+        let comment = self.comment.replace('}', ")");
+        let mut words: Vec<String> = comment.split_whitespace().map(str::to_string).collect();
+        if let Some(first) = words.first_mut() {
+            first.insert(0, '{');
+        }
+        if let Some(last) = words.last_mut() {
+            last.push('}');
+        }
+        tokens.extend(words);
+    }
+}
+
+fn nag_glyph(nag: u8) -> Option<&'static str> {
+    MOVE_GLYPHS.get(usize::from(nag).checked_sub(1)?).copied()
+}
+
+/// A move as [`format_pgn_with_variations`] writes it.
+#[derive(Clone, Default)]
+pub struct PgnMove {
+    pub san: String,
+    pub annotation: MoveAnnotation,
+}
+
+/// Today's date in the PGN `YYYY.MM.DD` format (UTC).
+pub fn pgn_date_today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    // Convert days since the epoch to a civil date (Howard Hinnant's algorithm)
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{year:04}.{month:02}.{day:02}")
+}
+
+/// Builds a PGN document from tag pairs and SAN moves. `first_move_number`
+/// and `black_moves_first` describe the starting position so games set up
+/// from a FEN are numbered correctly.
+pub fn format_pgn(
+    tags: &[(&str, String)],
+    first_move_number: u32,
+    black_moves_first: bool,
+    sans: &[String],
+    result: &str,
+) -> String {
+    let moves: Vec<PgnMove> = sans
+        .iter()
+        .map(|san| PgnMove {
+            san: san.clone(),
+            ..PgnMove::default()
+        })
+        .collect();
+    format_pgn_with_variations(
+        tags,
+        first_move_number,
+        black_moves_first,
+        &moves,
+        &[],
+        result,
+    )
+}
+
+/// Like [`format_pgn`], with the moves' annotations, and `variations` written
+/// in parentheses where they leave the moves, e.g. "1. e4 e5 $2 {Risky}
+/// (1... c5 2. Nf3) 2. Nf3". Each variation is a whole line of moves from
+/// the start, and may leave another one in turn, which nests it inside that
+/// one.
+pub fn format_pgn_with_variations(
+    tags: &[(&str, String)],
+    first_move_number: u32,
+    black_moves_first: bool,
+    moves: &[PgnMove],
+    variations: &[Vec<PgnMove>],
+    result: &str,
+) -> String {
+    let mut pgn = String::new();
+    for (name, value) in tags {
+        pgn.push_str(&format!("[{name} \"{}\"]\n", value.replace('"', "'")));
+    }
+    pgn.push('\n');
+
+    let numbering = Numbering {
+        first_move_number,
+        black_moves_first,
+    };
+    let lines: Vec<&[PgnMove]> = std::iter::once(moves)
+        .chain(variations.iter().map(Vec::as_slice))
+        .collect();
+    let mut tokens = Vec::new();
+    push_line(&mut tokens, &lines, 0, numbering);
+    tokens.push(result.to_string());
+
+    // Keep movetext lines under 80 characters as the PGN spec recommends
+    let mut line_len = 0;
+    for token in tokens {
+        if line_len > 0 && line_len + 1 + token.len() > 79 {
+            pgn.push('\n');
+            line_len = 0;
+        } else if line_len > 0 {
+            pgn.push(' ');
+            line_len += 1;
+        }
+        line_len += token.len();
+        pgn.push_str(&token);
+    }
+    pgn.push('\n');
+
+    pgn
+}
+
+// How the half-moves of a game are numbered
+#[derive(Copy, Clone)]
+struct Numbering {
+    first_move_number: u32,
+    black_moves_first: bool,
+}
+
+impl Numbering {
+    // The move number of the `ply`th half-move from the start (counting from
+    // 0), and whether it is White's
+    fn at(self, ply: usize) -> (u32, bool) {
+        let half = ply + usize::from(self.black_moves_first);
+        (
+            self.first_move_number + (half / 2) as u32,
+            half.is_multiple_of(2),
+        )
+    }
+}
+
+// Writes the moves of `lines[0]` from `from` on, with each of the other
+// lines that leaves it in parentheses after the move it replaces. All of
+// `lines` share the moves before `from`.
+fn push_line(tokens: &mut Vec<String>, lines: &[&[PgnMove]], from: usize, numbering: Numbering) {
+    let main = lines[0];
+    let mut others: Vec<&[PgnMove]> = lines[1..].to_vec();
+    // The first move of a line, and the first after a comment or a
+    // variation, is numbered even when it is Black's
+    let mut renumber = true;
+    for (ply, mv) in main.iter().enumerate().skip(from) {
+        let san = &mv.san;
+        let (number, white) = numbering.at(ply);
+        if white {
+            tokens.push(format!("{number}."));
+        } else if renumber {
+            tokens.push(format!("{number}..."));
+        }
+        tokens.push(san.clone());
+        mv.annotation.push_tokens(tokens);
+        renumber = !mv.annotation.comment.trim().is_empty();
+
+        // The lines playing another move here, those with the same one
+        // together, in the order they were given
+        let mut alternatives: Vec<Vec<&[PgnMove]>> = Vec::new();
+        for &line in others
+            .iter()
+            .filter(|line| line.len() > ply && line[ply].san != *san)
+        {
+            match alternatives
+                .iter_mut()
+                .find(|group| group[0][ply].san == line[ply].san)
+            {
+                Some(group) => group.push(line),
+                None => alternatives.push(vec![line]),
+            }
+        }
+        for group in alternatives {
+            let start = tokens.len();
+            push_line(tokens, &group, ply, numbering);
+            tokens[start].insert(0, '(');
+            if let Some(last) = tokens.last_mut() {
+                last.push(')');
+            }
+            renumber = true;
+        }
+        others.retain(|line| line.len() > ply + 1 && line[ply].san == *san);
+    }
+}
+
+/// Splits `pgn`, a database of games, into the text of each game.
+pub fn split_pgn_games(pgn: &str) -> Vec<&str> {
+    let mut games = Vec::new();
+    let mut start = 0;
+    let mut end = 0;
+    let mut in_movetext = false;
+    for line in pgn.split_inclusive('\n') {
+        let trimmed = line.trim();
+        // A tag after some moves starts the next game
+        if trimmed.starts_with('[') && in_movetext {
+            games.push(&pgn[start..end]);
+            start = end;
+            in_movetext = false;
+        } else if !trimmed.is_empty() && !trimmed.starts_with('[') && !trimmed.starts_with('%') {
+            in_movetext = true;
+        }
+        end += line.len();
+    }
+    if !pgn[start..].trim().is_empty() {
+        games.push(&pgn[start..]);
+    }
+    games
+}
+
+/// Reads each game in `pgn`, a database of them, as [`parse_pgn`] reads one.
+pub fn parse_pgn_games(pgn: &str) -> Vec<Result<PgnGame, String>> {
+    split_pgn_games(pgn).into_iter().map(parse_pgn).collect()
+}
+
+/// Reads the last game in `pgn`: its tag pairs, the starting position (from
+/// the FEN and Variant tags if present), the moves and what they are
+/// annotated with. Variations, move numbers and the result are skipped.
+pub fn parse_pgn(pgn: &str) -> Result<PgnGame, String> {
+    let (tags, movetext) = read_tags(pgn)?;
+    let start = start_position(&tags)?;
+
+    // Drop (possibly nested) variations and comments after ';', and set the
+    // comments in braces aside, each leaving a "{" in its place
+    let mut plain = String::new();
+    let mut comments = Vec::new();
+    let mut comment: Option<String> = None;
+    let mut rest_of_line = false;
+    let mut depth = 0;
+    for ch in movetext.chars() {
+        rest_of_line &= ch != '\n';
+        if rest_of_line {
+            continue;
+        }
+        match (ch, &mut comment) {
+            ('}', Some(_)) => {
+                let text = comment.take().unwrap_or_default();
+                if depth == 0 {
+                    comments.push(text.split_whitespace().collect::<Vec<_>>().join(" "));
+                    plain.push_str(" { ");
+                }
+            }
+            (_, Some(text)) => text.push(ch),
+            ('{', None) => comment = Some(String::new()),
+            (';', None) => rest_of_line = true,
+            ('(', None) => depth += 1,
+            (')', None) => depth -= 1,
+            _ if depth > 0 => {}
+            _ => plain.push(ch),
+        }
+    }
+
+    let mut comments = comments.into_iter();
+    let mut state = start.clone();
+    let mut moves = Vec::new();
+    let mut annotations: Vec<MoveAnnotation> = Vec::new();
+    for token in plain.split_whitespace() {
+        // Comments and NAGs are the last move's; any before the first move
+        // are about the game and dropped
+        if token == "{" {
+            let comment = comments.next().unwrap_or_default();
+            if let Some(annotation) = annotations.last_mut() {
+                if !annotation.comment.is_empty() {
+                    annotation.comment.push(' ');
+                }
+                annotation.comment.push_str(&comment);
+            }
+            continue;
+        }
+        if let Some(nag) = token.strip_prefix('$') {
+            if let (Ok(nag), Some(annotation)) = (nag.parse(), annotations.last_mut()) {
+                annotation.nags.push(nag);
+            }
+            continue;
+        }
+        if ["1-0", "0-1", "1/2-1/2", "*"].contains(&token) {
+            continue;
+        }
+        // Move numbers may be written against the move, as in "1.e4", and
+        // so may glyphs, as in "e4!?"
+        let token = token.rsplit('.').next().unwrap_or(token);
+        let san = token.trim_end_matches(['!', '?']);
+        let nag = MOVE_GLYPHS
+            .iter()
+            .position(|&glyph| glyph == &token[san.len()..])
+            .map(|index| index as u8 + 1);
+        if san.is_empty() {
+            if let (Some(nag), Some(annotation)) = (nag, annotations.last_mut()) {
+                annotation.nags.push(nag);
+            }
+            continue;
+        }
+        let mv =
+            Move::from_san(san, &state).ok_or_else(|| format!("Illegal move in PGN: {token}"))?;
+        state.make_move(mv);
+        moves.push(mv);
+        annotations.push(MoveAnnotation {
+            nags: nag.into_iter().collect(),
+            comment: String::new(),
+        });
+    }
+
+    Ok(PgnGame {
+        tags,
+        start,
+        moves,
+        annotations,
+    })
+}
+
+/// Reads the last game in `pgn` with its variations, as every line through
+/// it: the starting position, then the moves from there to the end of the
+/// main line and to the end of each (possibly nested) variation. A
+/// repertoire is written this way, as one game branching at each reply to
+/// be ready for. Comments, NAGs, move numbers and the result are skipped.
+pub fn parse_pgn_lines(pgn: &str) -> Result<(GameState, Vec<Vec<Move>>), String> {
+    // A line being read: its moves, where it left the line it branches from,
+    // and the positions after and before its latest move
+    struct Branch {
+        moves: Vec<Move>,
+        fork: usize,
+        state: GameState,
+        before: GameState,
+    }
+
+    let (tags, movetext) = read_tags(pgn)?;
+    let start = start_position(&tags)?;
+
+    let mut plain = String::new();
+    let mut in_comment = false;
+    let mut rest_of_line = false; // In a comment after ';'
+    for ch in movetext.chars() {
+        rest_of_line &= ch != '\n';
+        if rest_of_line {
+            continue;
+        }
+        match ch {
+            '{' => in_comment = true,
+            '}' => in_comment = false,
+            _ if in_comment => {}
+            ';' => rest_of_line = true,
+            '(' | ')' => {
+                plain.push(' ');
+                plain.push(ch);
+                plain.push(' ');
+            }
+            _ => plain.push(ch),
+        }
+    }
+
+    let mut lines = Vec::new();
+    let mut branches = vec![Branch {
+        moves: Vec::new(),
+        fork: 0,
+        state: start.clone(),
+        before: start.clone(),
+    }];
+    for token in plain.split_whitespace() {
+        match token {
+            // A variation is played instead of the latest move
+            "(" => {
+                let Some(branch) = branches.last() else {
+                    return Err("Unbalanced variation in PGN".to_string());
+                };
+                if branch.moves.len() == branch.fork {
+                    return Err("Variation before any move in PGN".to_string());
+                }
+                let moves = branch.moves[..branch.moves.len() - 1].to_vec();
+                branches.push(Branch {
+                    fork: moves.len(),
+                    moves,
+                    state: branch.before.clone(),
+                    before: branch.before.clone(),
+                });
+            }
+            ")" => {
+                let branch = branches.pop().filter(|_| !branches.is_empty());
+                let Some(branch) = branch else {
+                    return Err("Unbalanced variation in PGN".to_string());
+                };
+                if branch.moves.len() > branch.fork {
+                    lines.push(branch.moves);
+                }
+            }
+            _ if token.starts_with('$') => {}
+            "1-0" | "0-1" | "1/2-1/2" | "*" => {}
+            _ => {
+                let san = token.rsplit('.').next().unwrap_or(token);
+                let san = san.trim_end_matches(['!', '?']);
+                if san.is_empty() {
+                    continue;
+                }
+                let Some(branch) = branches.last_mut() else {
+                    return Err("Unbalanced variation in PGN".to_string());
+                };
+                let mv = Move::from_san(san, &branch.state)
+                    .ok_or_else(|| format!("Illegal move in PGN: {token}"))?;
+                branch.before = branch.state.clone();
+                branch.state.make_move(mv);
+                branch.moves.push(mv);
+            }
+        }
+    }
+    match branches.pop() {
+        Some(main) if branches.is_empty() => {
+            if !main.moves.is_empty() {
+                lines.push(main.moves);
+            }
+        }
+        _ => return Err("Unclosed variation in PGN".to_string()),
+    }
+    Ok((start, lines))
+}
+
+// The tag pairs of the last game in `pgn`, and its movetext with any lines
+// escaped with '%' left out
+fn read_tags(pgn: &str) -> Result<(Vec<(String, String)>, String), String> {
+    let mut tags = Vec::new();
+    let mut movetext = String::new();
+    for line in pgn.lines() {
+        let line = line.trim();
+        if let Some(tag) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            // A tag after some moves starts the next game
+            if !movetext.trim().is_empty() {
+                tags.clear();
+                movetext.clear();
+            }
+            let Some((name, value)) = tag.split_once(' ') else {
+                return Err(format!("Invalid PGN tag: {line}"));
+            };
+            tags.push((name.to_string(), value.trim().trim_matches('"').to_string()));
+        } else if !line.starts_with('%') {
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+    Ok((tags, movetext))
+}
+
+// The position a game with `tags` starts from, by its FEN and Variant tags
+fn start_position(tags: &[(String, String)]) -> Result<GameState, String> {
+    let tag = |name: &str| {
+        tags.iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    };
+    let variant = match tag("Variant") {
+        Some(variant) => variant.replace(' ', "-").parse()?,
+        None => Variant::Standard,
+    };
+    let mut start = GameState::with_variant(variant);
+    if let Some(fen) = tag("FEN") {
+        start.from_fen(fen).map_err(|err| err.to_string())?;
+    }
+    Ok(start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Ten moves, with a ';' inside a comment in braces and a comment after
+    // one, which runs only to the end of its line
+    const GAME: &str = "[Event \"Test\"]
+
+1. e4 {Open; as ever} e5 2. Nf3 Nc6 ; the usual
+3. Bb5 a6 4. Ba4 (4. Bxc6 dxc6) Nf6 5. O-O Be7 *
+";
+
+    #[test]
+    fn semicolons_in_braces_do_not_end_the_movetext() {
+        let game = parse_pgn(GAME).unwrap();
+        assert_eq!(game.moves.len(), 10);
+        assert_eq!(game.annotations[0].comment, "Open; as ever");
+        assert_eq!(game.annotations[3].comment, "");
+
+        let (_, lines) = parse_pgn_lines(GAME).unwrap();
+        let lengths: Vec<usize> = lines.iter().map(Vec::len).collect();
+        assert_eq!(lengths, [8, 10]);
+    }
+}
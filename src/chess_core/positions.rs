@@ -0,0 +1,118 @@
+/// A well-known position to play or study from.
+pub struct LibraryPosition {
+    pub name: &'static str,
+    pub about: &'static str, // What to look for, shown when it is loaded
+    pub fen: &'static str,
+}
+
+/// The built-in library of positions: the classic rook endgames and
+/// studies, the turning points of three famous games, the basic mates and
+/// the starting positions of games on other sizes of board.
+pub const POSITION_LIBRARY: [LibraryPosition; 12] = [
+    LibraryPosition {
+        name: "Lucena",
+        about: "Rook endgame: White wins by building a bridge with the rook",
+        fen: "1K1k4/1P6/8/8/8/8/r7/2R5 w - - 0 1",
+    },
+    LibraryPosition {
+        name: "Philidor",
+        about: "Rook endgame: Black draws by holding the rook on the sixth rank",
+        fen: "4k3/7R/r7/3KP3/8/8/8/8 b - - 0 1",
+    },
+    LibraryPosition {
+        name: "Saavedra",
+        about: "Study (1895): White wins by promoting to a rook, not a queen",
+        fen: "8/8/1KP5/3r4/8/8/8/k7 w - - 0 1",
+    },
+    LibraryPosition {
+        name: "Reti study",
+        about: "Study (1921): White's king catches the pawn by going two ways at once",
+        fen: "7K/8/k1P5/7p/8/8/8/8 w - - 0 1",
+    },
+    LibraryPosition {
+        name: "Immortal Game",
+        about: "Anderssen v Kieseritzky, London 1851: White to play 18. Bd6!",
+        fen: "rnb1k1nr/p2p1ppp/8/1pbN1N1P/4PBP1/3P1Q2/PqP5/R4KR1 w kq - 0 18",
+    },
+    LibraryPosition {
+        name: "Evergreen Game",
+        about: "Anderssen v Dufresne, Berlin 1852: White to play 20. Rxe7+!",
+        fen: "1r2k1r1/pbppnp1p/1bn2P2/8/Q7/B1PB1q2/P4PPP/3RR1K1 w - - 0 20",
+    },
+    LibraryPosition {
+        name: "Opera Game",
+        about: "Morphy v the Duke of Brunswick and Count Isouard, Paris 1858: \
+                White to play 13. Rxd7!",
+        fen: "3rkb1r/p2nqppp/5n2/1B2p1B1/4P3/1Q6/PPP2PPP/2KR3R w k - 3 13",
+    },
+    LibraryPosition {
+        name: "Queen mate",
+        about: "Basic mate: king and queen against king",
+        fen: "8/8/8/4k3/8/8/8/3QK3 w - - 0 1",
+    },
+    LibraryPosition {
+        name: "Rook mate",
+        about: "Basic mate: king and rook against king",
+        fen: "8/8/8/4k3/8/8/8/R3K3 w - - 0 1",
+    },
+    LibraryPosition {
+        name: "Bishop-knight mate",
+        about: "Basic mate: king, bishop and knight against king",
+        fen: "8/8/8/4k3/8/8/8/2B1KN2 w - - 0 1",
+    },
+    LibraryPosition {
+        name: "Capablanca chess",
+        about: "10x8: an archbishop and a chancellor each beside the bishops",
+        fen: "rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR w KQkq - 0 1",
+    },
+    LibraryPosition {
+        name: "Minichess",
+        about: "5x6: a whole army on a board small enough to see every line",
+        fen: "rnbqk/ppppp/5/5/PPPPP/RNBQK w - - 0 1",
+    },
+];
+
+/// Sharp middlegames from the main lines of attacking openings, White to
+/// move in each, for practising with little time on the clock.
+pub const SCRAMBLE_POSITIONS: [LibraryPosition; 8] = [
+    LibraryPosition {
+        name: "Dragon, Yugoslav Attack",
+        about: "Opposite-side castling: both sides race to open the other king",
+        fen: "2rq1rk1/pp1bppb1/3p1np1/4n2p/3NP2P/1BN1BP2/PPPQ2P1/2KR3R w - - 0 13",
+    },
+    LibraryPosition {
+        name: "Najdorf, English Attack",
+        about: "Pawn storms on both wings: White's on the king, Black's on the queen",
+        fen: "r2q1rk1/3nbppp/p2pbn2/4p1P1/1p2P3/1NN1BP2/PPPQ3P/2KR1B1R w - - 0 13",
+    },
+    LibraryPosition {
+        name: "King's Indian, Mar del Plata",
+        about: "White breaks through on the queenside before Black's kingside attack lands",
+        fen: "r1bq1rk1/pppnn1bp/3p4/3Pp1p1/2P1Pp2/2N2P2/PP2BBPP/R2QNRK1 w - - 0 13",
+    },
+    LibraryPosition {
+        name: "Marshall Attack",
+        about: "Black has given a pawn for an attack on White's king",
+        fen: "r1b2rk1/5ppp/p1pb4/1p1n4/3P4/1BP3Pq/PP3P1P/RNBQR1K1 w - - 1 15",
+    },
+    LibraryPosition {
+        name: "Semi-Slav, Botvinnik",
+        about: "Material is level, but both sides have passed pawns and loose kings",
+        fen: "r1bqkb1r/p2n1p2/2p1pn2/1p2P1B1/2pP4/2N5/PP3PPP/R2QKB1R w KQkq - 1 11",
+    },
+    LibraryPosition {
+        name: "Grunfeld, Exchange",
+        about: "White's big centre against Black's pressure on d4",
+        fen: "r2q1rk1/pp2ppbp/4b1p1/n7/3PP3/3BBP2/P3N1PP/R2Q1RK1 w - - 1 14",
+    },
+    LibraryPosition {
+        name: "French, Poisoned Pawn",
+        about: "White's h-pawn against Black's c-pawn, with both kings in the centre",
+        fen: "r1b1k1r1/ppq1np1Q/2n1p3/3pP3/5P2/P1p5/2P1N1PP/R1B1KB1R w KQq - 0 12",
+    },
+    LibraryPosition {
+        name: "Sveshnikov",
+        about: "White's grip on d5 against Black's bishops and central pawns",
+        fen: "r1bqkb1r/5p1p/p1np4/1p1Npp2/4P3/N7/PPP2PPP/R2QKB1R w KQkq - 0 11",
+    },
+];
@@ -0,0 +1,104 @@
+use std::fmt;
+use std::sync::atomic::AtomicBool;
+
+use super::ai::{Analysis, SearchStats};
+use super::board::PieceColor;
+use super::game_state::{GameState, Move};
+
+// Scores are capped at this many centipawns before moves are compared, so
+// that missing a mate in three for a mate in five isn't called a blunder
+const SCORE_CAP: i32 = 1_000;
+
+/// How a move compares with the engine's choice, going by the centipawns it
+/// gives away.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MoveQuality {
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl MoveQuality {
+    /// The quality of a move that loses `loss` centipawns against the best.
+    pub fn from_loss(loss: i32) -> Self {
+        match loss {
+            ..=0 => MoveQuality::Best,
+            1..=49 => MoveQuality::Good,
+            50..=99 => MoveQuality::Inaccuracy,
+            100..=299 => MoveQuality::Mistake,
+            _ => MoveQuality::Blunder,
+        }
+    }
+
+    /// The annotation symbol written after the move, e.g. "?!".
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            MoveQuality::Best | MoveQuality::Good => "",
+            MoveQuality::Inaccuracy => "?!",
+            MoveQuality::Mistake => "?",
+            MoveQuality::Blunder => "??",
+        }
+    }
+}
+
+impl fmt::Display for MoveQuality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            MoveQuality::Best => "Best move",
+            MoveQuality::Good => "Good move",
+            MoveQuality::Inaccuracy => "Inaccuracy",
+            MoveQuality::Mistake => "Mistake",
+            MoveQuality::Blunder => "Blunder",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// A played move checked against the engine: its best line from the position
+/// before the move, what the move played scores and how it compares.
+#[derive(Clone, Debug)]
+pub struct MoveReview {
+    pub best: Analysis,
+    /// The played move's line, scored at the same depth as `best`
+    pub played: Analysis,
+    pub quality: MoveQuality,
+}
+
+impl GameState {
+    /// Searches this position `depth` plies deep and compares `mv` with the
+    /// best move found, or returns None if there are no legal moves.
+    pub fn review_move(&self, mv: Move, depth: u32) -> Option<MoveReview> {
+        let never = AtomicBool::new(false);
+        let (best_score, line) = self.search_root(depth, &never)?;
+        let (played_score, quality) = if line[0] == mv {
+            (best_score, MoveQuality::Best)
+        } else {
+            let played_score = self.score_move(mv, depth);
+            let loss =
+                best_score.clamp(-SCORE_CAP, SCORE_CAP) - played_score.clamp(-SCORE_CAP, SCORE_CAP);
+            (played_score, MoveQuality::from_loss(loss))
+        };
+
+        let white_view = |score: i32| match self.turn {
+            PieceColor::White => score,
+            PieceColor::Black => -score,
+        };
+        Some(MoveReview {
+            best: Analysis {
+                depth,
+                score: white_view(best_score),
+                line,
+                stats: SearchStats::default(),
+            },
+            played: Analysis {
+                depth,
+                score: white_view(played_score),
+                line: vec![mv],
+                stats: SearchStats::default(),
+            },
+            quality,
+        })
+    }
+}
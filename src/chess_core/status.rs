@@ -0,0 +1,181 @@
+use super::board::{PieceColor, PieceType};
+use super::game_state::GameState;
+use super::variant::Variant;
+
+/// Whether the game is still in progress, and if not, how it ended.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GameStatus {
+    Ongoing,
+    Checkmate {
+        winner: PieceColor,
+    },
+    Timeout {
+        winner: PieceColor,
+    },
+    /// Won by a variant's own rule, e.g. reaching the hill in King of the Hill
+    VariantWin {
+        winner: PieceColor,
+        variant: Variant,
+    },
+    Stalemate,
+    ThreefoldRepetition,
+    FiftyMoveRule,
+    /// A draw, which Black wins under Armageddon rules; `draw` is how it was
+    /// drawn, e.g. "stalemate"
+    ArmageddonDraw {
+        draw: &'static str,
+    },
+}
+
+impl GameStatus {
+    pub fn is_over(&self) -> bool {
+        *self != GameStatus::Ongoing
+    }
+
+    /// The side that won, or `None` for a draw or a game still in progress.
+    pub fn winner(&self) -> Option<PieceColor> {
+        match self {
+            GameStatus::Checkmate { winner }
+            | GameStatus::Timeout { winner }
+            | GameStatus::VariantWin { winner, .. } => Some(*winner),
+            GameStatus::ArmageddonDraw { .. } => Some(PieceColor::Black),
+            _ => None,
+        }
+    }
+
+    /// The status under Armageddon rules, where Black, who has less time,
+    /// wins a game that would otherwise be drawn.
+    pub fn under_armageddon(self) -> GameStatus {
+        let draw = match self {
+            GameStatus::Stalemate => "stalemate",
+            GameStatus::ThreefoldRepetition => "threefold repetition",
+            GameStatus::FiftyMoveRule => "the fifty-move rule",
+            _ => return self,
+        };
+        GameStatus::ArmageddonDraw { draw }
+    }
+
+    /// The PGN result token for this status ("1-0", "0-1", "1/2-1/2" or "*").
+    pub fn result_tag(&self) -> &'static str {
+        match self {
+            GameStatus::Ongoing => "*",
+            GameStatus::Checkmate { winner }
+            | GameStatus::Timeout { winner }
+            | GameStatus::VariantWin { winner, .. } => match winner {
+                PieceColor::White => "1-0",
+                PieceColor::Black => "0-1",
+            },
+            GameStatus::ArmageddonDraw { .. } => "0-1",
+            GameStatus::Stalemate | GameStatus::ThreefoldRepetition | GameStatus::FiftyMoveRule => {
+                "1/2-1/2"
+            }
+        }
+    }
+
+    /// A human-readable summary such as "White wins by checkmate".
+    pub fn description(&self) -> String {
+        match self {
+            GameStatus::Ongoing => "Game in progress".to_string(),
+            GameStatus::Checkmate { winner } => format!("{winner:?} wins by checkmate"),
+            GameStatus::Timeout { winner } => format!("{winner:?} wins on time"),
+            GameStatus::VariantWin { winner, variant } => match variant {
+                Variant::KingOfTheHill => format!("{winner:?} wins by reaching the hill"),
+                Variant::ThreeCheck => format!("{winner:?} wins by giving three checks"),
+                Variant::Antichess => format!("{winner:?} wins by running out of pieces or moves"),
+                Variant::Standard => format!("{winner:?} wins"),
+            },
+            GameStatus::Stalemate => "Draw by stalemate".to_string(),
+            GameStatus::ThreefoldRepetition => "Draw by threefold repetition".to_string(),
+            GameStatus::FiftyMoveRule => "Draw by the fifty-move rule".to_string(),
+            GameStatus::ArmageddonDraw { draw } => {
+                format!("Black wins Armageddon by a draw ({draw})")
+            }
+        }
+    }
+}
+
+impl GameState {
+    /// Works out the status of the position for the side to move. A variant
+    /// win comes first, and mate and stalemate take precedence over the
+    /// fifty-move rule.
+    pub fn status(&self) -> GameStatus {
+        if let Some(winner) = self.variant_winner() {
+            return GameStatus::VariantWin {
+                winner,
+                variant: self.variant,
+            };
+        }
+        if !self.generate_valid_moves(self.turn).is_empty() {
+            if self.halfmove_clock >= 100 {
+                GameStatus::FiftyMoveRule
+            } else {
+                GameStatus::Ongoing
+            }
+        } else if self.variant == Variant::Antichess {
+            // Being stalemated is a win rather than a draw
+            GameStatus::VariantWin {
+                winner: self.turn,
+                variant: self.variant,
+            }
+        } else if self.is_king_in_check(self.turn) {
+            GameStatus::Checkmate {
+                winner: self.turn.opposite(),
+            }
+        } else {
+            GameStatus::Stalemate
+        }
+    }
+
+    /// Like [`GameState::status`], but also declares a draw when the current
+    /// position appears three times in `position_history` (a list of
+    /// [`GameState::position_key`]s that includes the current position).
+    pub fn status_with_history(&self, position_history: &[String]) -> GameStatus {
+        let status = self.status();
+        if status.is_over() {
+            return status;
+        }
+
+        let key = self.position_key();
+        if position_history.iter().filter(|k| **k == key).count() >= 3 {
+            GameStatus::ThreefoldRepetition
+        } else {
+            status
+        }
+    }
+
+    /// Identifies a position for repetition purposes: placement, side to move,
+    /// castling rights and the en passant square, but only when an en passant
+    /// capture is actually possible. In Three-Check the check counts are part
+    /// of it too.
+    pub fn position_key(&self) -> String {
+        let fen = self.to_fen();
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        let en_passant = match self.en_passant_target {
+            Some(target) if self.can_capture_en_passant(target) => fields[3],
+            _ => "-",
+        };
+        let mut key = format!("{} {} {} {}", fields[0], fields[1], fields[2], en_passant);
+        if self.variant.counts_checks() {
+            // The same placement with different check counts is a different position
+            key += &format!(" +{}+{}", self.checks_given[0], self.checks_given[1]);
+        }
+        key
+    }
+
+    fn can_capture_en_passant(&self, target: (usize, usize)) -> bool {
+        let pawn_row = if self.turn == PieceColor::White {
+            target.0 + 1
+        } else {
+            target.0.wrapping_sub(1)
+        };
+        [target.1.wrapping_sub(1), target.1 + 1]
+            .into_iter()
+            .filter(|&col| self.board.contains((pawn_row, col)))
+            .any(|col| {
+                self.board.squares[pawn_row][col]
+                    .occupant
+                    .is_some_and(|p| p.piece_type == PieceType::Pawn && p.color == self.turn)
+                    && self.validate_move((pawn_row, col), target)
+            })
+    }
+}
@@ -0,0 +1,226 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::bitboard::color_index;
+use super::board::{ChessBoard, PieceColor};
+use super::castling::CastlingRights;
+use super::game_state::GameState;
+
+// How many checks win a game of Three-Check
+const CHECKS_TO_WIN: u32 = 3;
+
+/// The rules a game is played by. Pieces move the same way in every variant;
+/// what changes is how a game can be won, and in Antichess which moves are
+/// allowed.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum Variant {
+    #[default]
+    Standard,
+    /// Also won by getting your king to one of the centre squares
+    KingOfTheHill,
+    /// Also won by giving check three times
+    ThreeCheck,
+    /// Won by losing every piece or having no move. Captures are compulsory,
+    /// kings are ordinary pieces that can be taken, and there is no castling.
+    Antichess,
+}
+
+impl Variant {
+    /// Whether `square` is one of the centre squares of `board` a king has
+    /// to reach in King of the Hill: the middle two ranks and files, or the
+    /// middle one of an odd number.
+    pub fn is_hill(board: &ChessBoard, (row, col): (usize, usize)) -> bool {
+        ((board.ranks - 1) / 2..=board.ranks / 2).contains(&row)
+            && ((board.files - 1) / 2..=board.files / 2).contains(&col)
+    }
+
+    // Whether make_move needs to keep count of checks
+    pub(super) fn counts_checks(self) -> bool {
+        self == Variant::ThreeCheck
+    }
+
+    // Whether kings can be in check, which also means each side has exactly
+    // one and can castle. Only Antichess does without.
+    pub(super) fn has_check(self) -> bool {
+        self != Variant::Antichess
+    }
+
+    // Whether a side that can capture has to
+    pub(super) fn forces_captures(self) -> bool {
+        self == Variant::Antichess
+    }
+}
+
+impl FromStr for Variant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "standard" | "chess" => Ok(Variant::Standard),
+            "king-of-the-hill" | "kingofthehill" | "koth" => Ok(Variant::KingOfTheHill),
+            "three-check" | "threecheck" | "3check" => Ok(Variant::ThreeCheck),
+            "antichess" | "losing" | "giveaway" => Ok(Variant::Antichess),
+            _ => Err(format!(
+                "Unknown variant '{s}': expected standard, king-of-the-hill, three-check or antichess"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Variant {
+    // The names used by the PGN Variant tag
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Variant::Standard => "Standard",
+            Variant::KingOfTheHill => "King of the Hill",
+            Variant::ThreeCheck => "Three-check",
+            Variant::Antichess => "Antichess",
+        };
+        f.write_str(name)
+    }
+}
+
+impl GameState {
+    /// The starting position for `variant`.
+    pub fn with_variant(variant: Variant) -> Self {
+        let mut state = GameState {
+            variant,
+            ..GameState::new()
+        };
+        if !variant.has_check() {
+            state.castling_rights = CastlingRights::NONE;
+            state.refresh_zobrist();
+        }
+        state
+    }
+
+    /// How many times `color` has given check (only counted in Three-Check).
+    pub fn checks_given(&self, color: PieceColor) -> u32 {
+        self.checks_given[color_index(color)]
+    }
+
+    /// The player who has won by the variant's own rule, if either has. Once
+    /// there is one the game is over and there are no legal moves.
+    pub fn variant_winner(&self) -> Option<PieceColor> {
+        let colors = [PieceColor::White, PieceColor::Black];
+        match self.variant {
+            Variant::Standard => None,
+            Variant::KingOfTheHill => colors.into_iter().find(|&color| {
+                self.find_king(color)
+                    .is_some_and(|king| Variant::is_hill(&self.board, king))
+            }),
+            Variant::ThreeCheck => colors
+                .into_iter()
+                .find(|&color| self.checks_given(color) >= CHECKS_TO_WIN),
+            // Running out of moves also wins, which status() sees to
+            Variant::Antichess => colors.into_iter().find(|&color| {
+                self.board
+                    .squares
+                    .iter()
+                    .flatten()
+                    .all(|square| square.occupant.is_none_or(|piece| piece.color != color))
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess_core::{GameStatus, Move, PieceType};
+
+    fn antichess(fen: &str) -> GameState {
+        let mut state = GameState::with_variant(Variant::Antichess);
+        state.from_fen(fen).unwrap();
+        state
+    }
+
+    fn san_moves(state: &GameState) -> Vec<String> {
+        state
+            .generate_valid_moves(state.turn)
+            .iter()
+            .map(|mv| mv.to_san(state))
+            .collect()
+    }
+
+    fn perft(state: &mut GameState, depth: u32) -> u64 {
+        let moves = state.generate_valid_moves(state.turn);
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        moves
+            .into_iter()
+            .map(|mv| {
+                let undo = state.make_move(mv);
+                let nodes = perft(state, depth - 1);
+                state.unmake_move(mv, undo);
+                nodes
+            })
+            .sum()
+    }
+
+    #[test]
+    fn antichess_perft() {
+        let mut state = GameState::with_variant(Variant::Antichess);
+        let nodes: Vec<u64> = (1..=3).map(|depth| perft(&mut state, depth)).collect();
+        assert_eq!(nodes, [20, 400, 8067]);
+    }
+
+    #[test]
+    fn antichess_captures_are_compulsory() {
+        let mut state = GameState::with_variant(Variant::Antichess);
+        for uci in ["e2e3", "b7b5"] {
+            state.make_move(Move::from_uci(uci).unwrap());
+        }
+        assert_eq!(san_moves(&state), ["Bxb5"]);
+        assert!(!state.validate_move((7, 6), (5, 5)));
+
+        // Any piece may make the capture, the king too, and nothing is check
+        let state = antichess("8/8/8/8/8/8/1q6/K1R5 w - - 0 1");
+        assert_eq!(san_moves(&state), ["Kxb2"]);
+        assert!(!state.is_king_in_check(PieceColor::White));
+
+        // With nothing to take, every move is allowed
+        let state = antichess("8/8/8/8/8/8/8/K6k w - - 0 1");
+        assert_eq!(san_moves(&state), ["Ka2", "Kb2", "Kb1"]);
+    }
+
+    #[test]
+    fn antichess_is_won_by_losing_every_piece_or_move() {
+        let mut state = antichess("8/8/8/8/8/8/8/Kq6 w - - 0 1");
+        state.make_move(Move::from_uci("a1b1").unwrap());
+        assert_eq!(state.variant_winner(), Some(PieceColor::Black));
+        assert_eq!(
+            state.status(),
+            GameStatus::VariantWin {
+                winner: PieceColor::Black,
+                variant: Variant::Antichess
+            }
+        );
+
+        // White's pawn is blocked, which wins rather than draws
+        let state = antichess("8/8/8/8/8/p7/P7/8 w - - 0 1");
+        assert!(san_moves(&state).is_empty());
+        assert_eq!(
+            state.status(),
+            GameStatus::VariantWin {
+                winner: PieceColor::White,
+                variant: Variant::Antichess
+            }
+        );
+    }
+
+    #[test]
+    fn antichess_pawns_promote_to_kings() {
+        let mut state = antichess("8/P7/8/8/8/8/8/7k w - - 0 1");
+        assert_eq!(state.promotion_pieces().last(), Some(&PieceType::King));
+        let mv = Move::from_san("a8=K", &state).unwrap();
+        assert_eq!(mv.promotion, Some(PieceType::King));
+        state.make_move(mv);
+        let king = state.board.squares[0][0].occupant.unwrap();
+        assert_eq!(king.piece_type, PieceType::King);
+
+        let standard = GameState::new();
+        assert!(!standard.promotion_pieces().contains(&PieceType::King));
+    }
+}
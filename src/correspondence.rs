@@ -0,0 +1,115 @@
+use std::io;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+
+use itsjustchess::chess_core::{parse_pgn, PgnGame, PieceColor};
+
+/// A slow game kept in a PGN file that the players pass back and forth: it
+/// is saved after every move, and each player opens it to play their next
+/// move whenever it suits them. After each local move the opponent can be
+/// told, by a webhook or a command of the player's choosing that could
+/// also send them the file.
+pub struct Correspondence {
+    pub path: PathBuf,
+    pub local_color: PieceColor,
+    notify: Option<String>, // A URL to post to, or a shell command to run
+}
+
+impl Correspondence {
+    pub fn new(path: PathBuf, local_color: PieceColor, notify: Option<String>) -> Self {
+        Correspondence {
+            path,
+            local_color,
+            notify,
+        }
+    }
+
+    /// The game so far, or None if it has yet to start.
+    pub fn load(&self) -> Result<Option<PgnGame>, String> {
+        let pgn = match std::fs::read_to_string(&self.path) {
+            Ok(pgn) => pgn,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Failed to read {}: {e}", self.path.display())),
+        };
+        parse_pgn(&pgn).map(Some)
+    }
+
+    /// Saves the game by way of a temporary file, so a crash part way
+    /// through leaves the last save as it was.
+    pub fn save(&self, pgn: &str) -> io::Result<()> {
+        let temporary = self.path.with_extension("pgn.tmp");
+        std::fs::write(&temporary, pgn)?;
+        std::fs::rename(&temporary, &self.path)
+    }
+
+    /// Tells the opponent that `san` was just played, leading to `fen`, on
+    /// a background thread. A URL gets the move, the FEN and the saved PGN
+    /// posted to it as JSON. A command is run by the shell with them in
+    /// CHESS_MOVE, CHESS_FEN and CHESS_PGN_FILE.
+    pub fn notify(&self, san: &str, fen: &str) {
+        let Some(target) = self.notify.clone() else {
+            return;
+        };
+        let (san, fen, path) = (san.to_string(), fen.to_string(), self.path.clone());
+        thread::spawn(move || {
+            let result = if target.starts_with("http://") || target.starts_with("https://") {
+                let pgn = std::fs::read_to_string(&path).unwrap_or_default();
+                let body = format!(
+                    r#"{{"move":{},"fen":{},"pgn":{}}}"#,
+                    json_string(&san),
+                    json_string(&fen),
+                    json_string(&pgn)
+                );
+                ureq::post(&target)
+                    .set("Content-Type", "application/json")
+                    .send_string(&body)
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            } else {
+                let (shell, flag) = if cfg!(windows) {
+                    ("cmd", "/C")
+                } else {
+                    ("sh", "-c")
+                };
+                Command::new(shell)
+                    .args([flag, &target])
+                    .env("CHESS_MOVE", &san)
+                    .env("CHESS_FEN", &fen)
+                    .env("CHESS_PGN_FILE", &path)
+                    .stdin(Stdio::null())
+                    .status()
+                    .map_err(|e| e.to_string())
+                    .and_then(|status| {
+                        if status.success() {
+                            Ok(())
+                        } else {
+                            Err(format!("it exited with {status}"))
+                        }
+                    })
+            };
+            match result {
+                Ok(()) => log::info!("Notified the opponent of {san}"),
+                Err(e) => log::warn!("Failed to notify the opponent of {san}: {e}"),
+            }
+        });
+    }
+}
+
+// `text` as a JSON string, quotes included
+fn json_string(text: &str) -> String {
+    let mut json = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
@@ -0,0 +1,589 @@
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+
+use itsjustchess::chess_core::{
+    parse_pgn, split_pgn_games, GameState, OpeningNames, PgnGame, PieceColor, PieceType,
+};
+
+use crate::settings::config_path;
+
+// The games database, kept in the config directory beside the settings
+const DATABASE_FILE: &str = "games.sqlite";
+
+// The most games a search turns up
+const SEARCH_LIMIT: usize = 200;
+
+// The pieces in the order a material signature lists them, with their letters
+const MATERIAL_ORDER: [(PieceType, char); 8] = [
+    (PieceType::King, 'K'),
+    (PieceType::Queen, 'Q'),
+    (PieceType::Chancellor, 'C'),
+    (PieceType::Archbishop, 'A'),
+    (PieceType::Rook, 'R'),
+    (PieceType::Bishop, 'B'),
+    (PieceType::Knight, 'N'),
+    (PieceType::Pawn, 'P'),
+];
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS games (
+        id INTEGER PRIMARY KEY,
+        white TEXT NOT NULL,
+        black TEXT NOT NULL,
+        event TEXT NOT NULL,
+        date TEXT NOT NULL,
+        result TEXT NOT NULL,
+        eco TEXT NOT NULL,
+        opening TEXT NOT NULL,
+        pgn TEXT NOT NULL,
+        moves_hash INTEGER
+    );
+    CREATE TABLE IF NOT EXISTS positions (
+        game INTEGER NOT NULL REFERENCES games(id),
+        ply INTEGER NOT NULL,
+        zobrist INTEGER NOT NULL,
+        material TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS positions_zobrist ON positions(zobrist);
+    CREATE INDEX IF NOT EXISTS positions_material ON positions(material);
+";
+
+// Indexes columns added to the schema since it was first written, once an
+// older database has been given them
+const LATER_INDEXES: &str = "
+    CREATE INDEX IF NOT EXISTS games_moves_hash ON games(moves_hash);
+";
+
+/// What an import does with a game the database already has: one with the
+/// same moves, the same players and result and a date that agrees.
+#[derive(Copy, Clone, PartialEq, Debug, Default, ValueEnum)]
+pub enum Duplicates {
+    /// Leave the game in the database as it is
+    #[default]
+    Skip,
+    /// Fill in the tags the database doesn't know from the imported game,
+    /// and keep whichever PGN has more to it, e.g. comments
+    Merge,
+}
+
+/// How an import went.
+#[derive(Copy, Clone, Default, PartialEq, Debug)]
+pub struct ImportSummary {
+    pub added: usize,
+    pub duplicates: usize, // Skipped or merged, as asked
+    pub unreadable: usize,
+}
+
+/// What to look for in the database. Every part that is set has to match.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct GameQuery {
+    pub player: Option<String>,   // Part of either player's name
+    pub opening: Option<String>,  // An ECO code, or part of the opening's name
+    pub position: Option<u64>,    // The Zobrist key of a position to reach
+    pub material: Option<String>, // A material signature; see material_signature
+    pub result: Option<String>,   // As in the PGN Result tag
+}
+
+impl GameQuery {
+    /// Reads a query typed as terms like "player:Carlsen opening:Sicilian
+    /// Defence result:1-0". A term's value runs on to the next term, so it
+    /// may have spaces in it; words before the first term are a player.
+    /// The terms are player, opening, fen (a position to reach exactly),
+    /// material (e.g. "KRPvKR") and result ("1-0", "0-1", "1/2-1/2", or
+    /// "white", "black" or "draw").
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut terms: Vec<(&str, String)> = vec![("player", String::new())];
+        for word in text.split_whitespace() {
+            let term = word.split_once(':').filter(|(name, _)| {
+                ["player", "opening", "fen", "material", "result"].contains(name)
+            });
+            match (term, terms.last_mut()) {
+                (Some((name, value)), _) => terms.push((name, value.to_string())),
+                (None, Some((_, value))) => {
+                    if !value.is_empty() {
+                        value.push(' ');
+                    }
+                    value.push_str(word);
+                }
+                (None, None) => {}
+            }
+        }
+
+        let mut query = GameQuery::default();
+        for (name, value) in terms {
+            if value.is_empty() {
+                continue;
+            }
+            match name {
+                "player" => query.player = Some(value),
+                "opening" => query.opening = Some(value),
+                "fen" => {
+                    let mut position = GameState::new();
+                    position
+                        .from_fen(&value)
+                        .map_err(|err| format!("Invalid FEN: {err}"))?;
+                    query.position = Some(position.zobrist_key());
+                }
+                "material" => query.material = Some(parse_material(&value)?),
+                _ => {
+                    query.result = Some(match value.as_str() {
+                        "1-0" | "white" => "1-0".to_string(),
+                        "0-1" | "black" => "0-1".to_string(),
+                        "1/2-1/2" | "draw" => "1/2-1/2".to_string(),
+                        _ => return Err(format!("Unknown result: {value}")),
+                    });
+                }
+            }
+        }
+        Ok(query)
+    }
+}
+
+/// A game found by a search, as the results list shows it.
+#[derive(Clone, PartialEq, Debug)]
+pub struct GameSummary {
+    pub id: i64,
+    pub white: String,
+    pub black: String,
+    pub event: String,
+    pub date: String,
+    pub result: String,
+    pub opening: String, // ECO code and name, empty if it isn't known
+}
+
+/// One of a player's games, with the side they had, for reports.
+pub struct PlayerGame {
+    pub color: PieceColor,
+    pub eco: String, // Empty if the opening isn't known
+    pub opening: String,
+    pub result: String,
+    pub game: PgnGame,
+}
+
+/// A local database of games, in SQLite, for searching by the players, the
+/// opening, the positions reached and the result.
+pub struct Database {
+    connection: Connection,
+}
+
+impl Database {
+    /// Opens the database in the config directory, creating it if need be.
+    pub fn open() -> Result<Self, String> {
+        let path = config_path(DATABASE_FILE)
+            .ok_or_else(|| "No config directory for the games database".to_string())?;
+        Self::open_at(path)
+    }
+
+    fn open_at(path: PathBuf) -> Result<Self, String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        let connection = Connection::open(&path).map_err(|e| e.to_string())?;
+        connection
+            .execute_batch(SCHEMA)
+            .map_err(|e| e.to_string())?;
+        let mut database = Database { connection };
+        database.add_moves_hashes().map_err(|e| e.to_string())?;
+        database
+            .connection
+            .execute_batch(LATER_INDEXES)
+            .map_err(|e| e.to_string())?;
+        Ok(database)
+    }
+
+    // Gives a database from before duplicates were looked for the column
+    // they are found by, and hashes the moves of the games already in it.
+    fn add_moves_hashes(&mut self) -> rusqlite::Result<()> {
+        let columns: i64 = self.connection.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('games') WHERE name = 'moves_hash'",
+            params![],
+            |row| row.get(0),
+        )?;
+        if columns > 0 {
+            return Ok(());
+        }
+        let transaction = self.connection.transaction()?;
+        transaction.execute("ALTER TABLE games ADD COLUMN moves_hash INTEGER", params![])?;
+        {
+            let mut select = transaction.prepare("SELECT id, pgn FROM games")?;
+            let games = select
+                .query_map(params![], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut update =
+                transaction.prepare("UPDATE games SET moves_hash = ?1 WHERE id = ?2")?;
+            for (id, pgn) in games {
+                if let Ok(game) = parse_pgn(&pgn) {
+                    update.execute(params![moves_hash(&game), id])?;
+                }
+            }
+        }
+        transaction.commit()
+    }
+
+    /// Adds each game in `pgn` with the positions it reaches, naming its
+    /// opening from `openings` if it has no ECO tag. Games the database
+    /// already has are skipped or merged into it as `duplicates` says, so
+    /// that importing a tournament again doesn't count its games twice.
+    pub fn import(
+        &mut self,
+        pgn: &str,
+        openings: &OpeningNames,
+        duplicates: Duplicates,
+    ) -> Result<ImportSummary, String> {
+        let transaction = self.connection.transaction().map_err(|e| e.to_string())?;
+        let mut summary = ImportSummary::default();
+        {
+            let mut insert_game = transaction
+                .prepare(
+                    "INSERT INTO games (white, black, event, date, result, eco, opening, pgn, moves_hash)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                )
+                .map_err(|e| e.to_string())?;
+            let mut insert_position = transaction
+                .prepare(
+                    "INSERT INTO positions (game, ply, zobrist, material) VALUES (?1, ?2, ?3, ?4)",
+                )
+                .map_err(|e| e.to_string())?;
+            let mut same_moves = transaction
+                .prepare(
+                    "SELECT id, event, date, eco, opening, pgn FROM games
+                     WHERE moves_hash = ?1 AND white = ?2 AND black = ?3 AND result = ?4",
+                )
+                .map_err(|e| e.to_string())?;
+            let mut merge = transaction
+                .prepare(
+                    "UPDATE games SET event = ?1, date = ?2, eco = ?3, opening = ?4, pgn = ?5
+                     WHERE id = ?6",
+                )
+                .map_err(|e| e.to_string())?;
+
+            for text in split_pgn_games(pgn) {
+                let game = match parse_pgn(text) {
+                    Ok(game) => game,
+                    Err(err) => {
+                        log::warn!("Skipping a game the database can't read: {err}");
+                        summary.unreadable += 1;
+                        continue;
+                    }
+                };
+                let positions = positions(&game);
+                let (eco, opening) = match (game.tag("ECO"), game.tag("Opening")) {
+                    (Some(eco), name) => (eco.to_string(), name.unwrap_or("").to_string()),
+                    (None, _) => match positions.iter().rev().find_map(|p| openings.lookup(p)) {
+                        Some(opening) => (opening.eco.to_string(), opening.name.to_string()),
+                        None => (String::new(), String::new()),
+                    },
+                };
+                let tag = |name| game.tag(name).unwrap_or("?").to_string();
+                let (white, black, event, date) =
+                    (tag("White"), tag("Black"), tag("Event"), tag("Date"));
+                let result = game.tag("Result").unwrap_or("*").to_string();
+                let text = text.trim().to_string();
+                let hash = moves_hash(&game);
+
+                let found = same_moves
+                    .query_map(params![hash, white, black, result], |row| {
+                        Ok(StoredGame {
+                            id: row.get(0)?,
+                            event: row.get(1)?,
+                            date: row.get(2)?,
+                            eco: row.get(3)?,
+                            opening: row.get(4)?,
+                            pgn: row.get(5)?,
+                        })
+                    })
+                    .map_err(|e| e.to_string())?
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| e.to_string())?;
+                if let Some(stored) = found
+                    .into_iter()
+                    .find(|stored| dates_agree(&stored.date, &date))
+                {
+                    log::debug!("{white} - {black} ({date}) is already in the database");
+                    summary.duplicates += 1;
+                    if duplicates == Duplicates::Merge {
+                        let known = |stored: String, imported: String| {
+                            if stored.is_empty() || stored == "?" {
+                                imported
+                            } else {
+                                stored
+                            }
+                        };
+                        let date = if unknown_digits(&date) < unknown_digits(&stored.date) {
+                            date
+                        } else {
+                            stored.date
+                        };
+                        let (eco, opening) = if stored.eco.is_empty() {
+                            (eco, opening)
+                        } else {
+                            (stored.eco, stored.opening)
+                        };
+                        let pgn = if text.len() > stored.pgn.len() {
+                            text
+                        } else {
+                            stored.pgn
+                        };
+                        merge
+                            .execute(params![
+                                known(stored.event, event),
+                                date,
+                                eco,
+                                opening,
+                                pgn,
+                                stored.id,
+                            ])
+                            .map_err(|e| e.to_string())?;
+                    }
+                    continue;
+                }
+
+                let id = insert_game
+                    .insert(params![
+                        white, black, event, date, result, eco, opening, text, hash
+                    ])
+                    .map_err(|e| e.to_string())?;
+                for (ply, position) in positions.iter().enumerate() {
+                    insert_position
+                        .execute(params![
+                            id,
+                            ply as i64,
+                            position.zobrist_key() as i64,
+                            material_signature(position),
+                        ])
+                        .map_err(|e| e.to_string())?;
+                }
+                summary.added += 1;
+            }
+        }
+        transaction.commit().map_err(|e| e.to_string())?;
+        Ok(summary)
+    }
+
+    /// The games matching `query`, the latest first.
+    pub fn search(&self, query: &GameQuery) -> Result<Vec<GameSummary>, String> {
+        let mut sql = "SELECT id, white, black, event, date, result, eco, opening FROM games g
+                       WHERE 1 = 1"
+            .to_string();
+        let mut values: Vec<Value> = Vec::new();
+        if let Some(player) = &query.player {
+            values.push(Value::Text(format!("%{player}%")));
+            sql.push_str(&format!(
+                " AND (white LIKE ?{0} OR black LIKE ?{0})",
+                values.len()
+            ));
+        }
+        if let Some(opening) = &query.opening {
+            values.push(Value::Text(opening.clone()));
+            values.push(Value::Text(format!("%{opening}%")));
+            sql.push_str(&format!(
+                " AND (eco = ?{} OR opening LIKE ?{})",
+                values.len() - 1,
+                values.len()
+            ));
+        }
+        if let Some(position) = query.position {
+            values.push(Value::Integer(position as i64));
+            sql.push_str(&format!(
+                " AND EXISTS (SELECT 1 FROM positions p WHERE p.game = g.id AND p.zobrist = ?{})",
+                values.len()
+            ));
+        }
+        if let Some(material) = &query.material {
+            values.push(Value::Text(material.clone()));
+            sql.push_str(&format!(
+                " AND EXISTS (SELECT 1 FROM positions p WHERE p.game = g.id AND p.material = ?{})",
+                values.len()
+            ));
+        }
+        if let Some(result) = &query.result {
+            values.push(Value::Text(result.clone()));
+            sql.push_str(&format!(" AND result = ?{}", values.len()));
+        }
+        sql.push_str(&format!(
+            " ORDER BY date DESC, id DESC LIMIT {SEARCH_LIMIT}"
+        ));
+
+        let mut statement = self.connection.prepare(&sql).map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map(params_from_iter(values), |row| {
+                let eco: String = row.get(6)?;
+                let name: String = row.get(7)?;
+                Ok(GameSummary {
+                    id: row.get(0)?,
+                    white: row.get(1)?,
+                    black: row.get(2)?,
+                    event: row.get(3)?,
+                    date: row.get(4)?,
+                    result: row.get(5)?,
+                    opening: format!("{eco} {name}").trim().to_string(),
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    }
+
+    /// Every game `player` played, matched by their whole name in any case.
+    /// Games that can no longer be read are left out.
+    pub fn player_games(&self, player: &str) -> Result<Vec<PlayerGame>, String> {
+        let mut statement = self
+            .connection
+            .prepare(
+                "SELECT white, eco, opening, result, pgn FROM games
+                 WHERE white = ?1 COLLATE NOCASE OR black = ?1 COLLATE NOCASE",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map(params![player], |row| {
+                let white: String = row.get(0)?;
+                Ok((
+                    white,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+
+        let mut games = Vec::new();
+        for (white, eco, opening, result, pgn) in rows {
+            let game = match parse_pgn(&pgn) {
+                Ok(game) => game,
+                Err(err) => {
+                    log::warn!("Leaving out a game the database can't read: {err}");
+                    continue;
+                }
+            };
+            let color = if white.eq_ignore_ascii_case(player) {
+                PieceColor::White
+            } else {
+                PieceColor::Black
+            };
+            games.push(PlayerGame {
+                color,
+                eco,
+                opening,
+                result,
+                game,
+            });
+        }
+        Ok(games)
+    }
+
+    /// The game kept under `id`.
+    pub fn game(&self, id: i64) -> Result<PgnGame, String> {
+        let pgn: Option<String> = self
+            .connection
+            .query_row("SELECT pgn FROM games WHERE id = ?1", params![id], |row| {
+                row.get(0)
+            })
+            .optional()
+            .map_err(|e| e.to_string())?;
+        parse_pgn(&pgn.ok_or_else(|| format!("No game {id} in the database"))?)
+    }
+}
+
+// A game as the database keeps it, for merging a duplicate into
+struct StoredGame {
+    id: i64,
+    event: String,
+    date: String,
+    eco: String,
+    opening: String,
+    pgn: String,
+}
+
+// A hash of the moves of `game` from its starting position, the same however
+// they were written. FNV-1a, which unlike the standard library's hasher is
+// the same from one build to the next, as a hash kept in the database must be.
+fn moves_hash(game: &PgnGame) -> i64 {
+    let mut text = format!("{:016x}", game.start.zobrist_key());
+    for mv in &game.moves {
+        text.push(' ');
+        text.push_str(&mv.to_uci_on(&game.start.board));
+    }
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in text.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash as i64
+}
+
+// Whether two PGN dates could be the same day, a "?" standing for any digit,
+// e.g. "2023.??.??" and "2023.05.14".
+fn dates_agree(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.chars()
+            .zip(b.chars())
+            .all(|(a, b)| a == b || a == '?' || b == '?')
+        || a.starts_with('?')
+        || b.starts_with('?')
+}
+
+// How much of a PGN date isn't known.
+fn unknown_digits(date: &str) -> usize {
+    date.chars().filter(|&c| c == '?').count()
+}
+
+// The positions of `game` from its start, one after each move.
+fn positions(game: &PgnGame) -> Vec<GameState> {
+    let mut position = game.start.clone();
+    let mut positions = vec![position.clone()];
+    for &mv in &game.moves {
+        position.make_move(mv);
+        positions.push(position.clone());
+    }
+    positions
+}
+
+// The pieces on the board as White's then Black's letters in the order
+// KQRBNP, split by a "v", e.g. "KRPPvKR".
+fn material_signature(position: &GameState) -> String {
+    let side = |color| {
+        let mut letters = String::new();
+        for (piece_type, letter) in MATERIAL_ORDER {
+            let count = position
+                .board
+                .squares
+                .iter()
+                .flatten()
+                .filter_map(|square| square.occupant)
+                .filter(|piece| piece.color == color && piece.piece_type == piece_type)
+                .count();
+            letters.extend(std::iter::repeat_n(letter, count));
+        }
+        letters
+    };
+    format!("{}v{}", side(PieceColor::White), side(PieceColor::Black))
+}
+
+// A material signature as typed, in any order and case, e.g. "krp v rk",
+// put the way material_signature writes it.
+fn parse_material(text: &str) -> Result<String, String> {
+    let text = text.to_ascii_uppercase().replace(' ', "");
+    let Some((white, black)) = text.split_once('V') else {
+        return Err(format!("Material should be like KRPvKR, not {text}"));
+    };
+    let side = |letters: &str| {
+        if let Some(other) = letters
+            .chars()
+            .find(|&c| !MATERIAL_ORDER.iter().any(|&(_, letter)| letter == c))
+        {
+            return Err(format!("Not a piece: {other}"));
+        }
+        Ok(MATERIAL_ORDER
+            .iter()
+            .flat_map(|&(_, letter)| letters.chars().filter(move |&c| c == letter))
+            .collect::<String>())
+    };
+    Ok(format!("{}v{}", side(white)?, side(black)?))
+}
@@ -0,0 +1,105 @@
+use itsjustchess::chess_core::{CastlingRights, FenError, GameState, Piece, PieceColor, PieceType};
+
+/// A position being set up by hand: pieces are placed with the current
+/// brush, and the side to move, castling rights and en passant square are
+/// toggled directly. Nothing is checked until [`Editor::finish`].
+pub struct Editor {
+    position: GameState,
+    pub brush: Option<Piece>, // None rubs pieces out
+}
+
+impl Editor {
+    pub fn new(start: &GameState) -> Self {
+        Editor {
+            position: start.clone(),
+            brush: Some(piece(PieceType::Pawn, PieceColor::White)),
+        }
+    }
+
+    pub fn position(&self) -> &GameState {
+        &self.position
+    }
+
+    /// Puts the brush piece on `square`, or clears the square if it already
+    /// holds that piece. A side has only one king, so placing a king moves it.
+    pub fn paint(&mut self, (row, col): (usize, usize)) {
+        let Some(brush) = self.brush else {
+            self.erase((row, col));
+            return;
+        };
+        let occupant = self.position.board.squares[row][col].occupant;
+        if occupant.is_some_and(|p| p.piece_type == brush.piece_type && p.color == brush.color) {
+            self.erase((row, col));
+            return;
+        }
+        if brush.piece_type == PieceType::King {
+            if let Some((king_row, king_col)) = self.position.find_king(brush.color) {
+                self.position.board.squares[king_row][king_col].occupant = None;
+            }
+        }
+        self.position.board.squares[row][col].occupant = Some(brush);
+    }
+
+    pub fn erase(&mut self, (row, col): (usize, usize)) {
+        self.position.board.squares[row][col].occupant = None;
+    }
+
+    pub fn toggle_turn(&mut self) {
+        self.position.turn = self.position.turn.opposite();
+    }
+
+    /// Grants or removes one of the castling rights.
+    pub fn toggle_castling(&mut self, right: CastlingRights) {
+        self.position.castling_rights.toggle(right);
+    }
+
+    pub fn has_castling_right(&self, right: CastlingRights) -> bool {
+        self.position.castling_rights.contains(right)
+    }
+
+    /// Marks `square` as the en passant square, or unmarks it if it already is.
+    pub fn toggle_en_passant(&mut self, square: (usize, usize)) {
+        self.position.en_passant_target = if self.position.en_passant_target == Some(square) {
+            None
+        } else {
+            Some(square)
+        };
+    }
+
+    /// Takes every piece off the board.
+    pub fn clear(&mut self) {
+        for row in 0..self.position.board.ranks {
+            for col in 0..self.position.board.files {
+                self.erase((row, col));
+            }
+        }
+        self.position.castling_rights = CastlingRights::NONE;
+        self.position.en_passant_target = None;
+    }
+
+    /// Goes back to the standard starting position.
+    pub fn reset(&mut self) {
+        self.position = GameState::with_variant(self.position.variant);
+    }
+
+    pub fn to_fen(&self) -> String {
+        self.position.to_fen()
+    }
+
+    /// The position ready to play from, after the same checks as a FEN
+    /// given on the command line.
+    pub fn finish(&self) -> Result<GameState, FenError> {
+        let mut state = GameState::with_variant(self.position.variant);
+        state.from_fen(&self.to_fen())?;
+        Ok(state)
+    }
+}
+
+/// A piece as it appears in the palette.
+pub fn piece(piece_type: PieceType, color: PieceColor) -> Piece {
+    Piece {
+        piece_type,
+        color,
+        has_moved: false,
+    }
+}
@@ -0,0 +1,77 @@
+use rand::Rng;
+
+use itsjustchess::chess_core::{Endgame, EndgameResult, GameState, GameStatus};
+
+// The moves allowed beyond the fewest it takes to mate: half as many again,
+// and never fewer than this
+const MIN_SPARE_MOVES: u32 = 3;
+
+/// The endgame trainer: a won basic endgame to convert as White against the
+/// tablebase's best defence, mating within a budget of moves.
+pub struct EndgameTrainer {
+    pub endgame: Endgame,
+    pub budget: u32,  // Moves White is allowed to mate in
+    verdict: Verdict, // After the player's latest move
+}
+
+/// How the position stands after one of the player's moves.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Verdict {
+    OnTrack,
+    Mated,
+    /// The move gave away the win, and the position is now a draw
+    ThrownAway,
+    /// Still winning, but mate can no longer come within the budget
+    TooSlow,
+}
+
+impl EndgameTrainer {
+    /// A random position of `endgame` to start from, and the trainer for it.
+    pub fn start(endgame: Endgame, rng: &mut impl Rng) -> (Self, GameState) {
+        let position = endgame.random_position(rng);
+        let fewest = match position.endgame_result() {
+            Some(EndgameResult::Win(moves)) => moves,
+            _ => unreachable!("random endgame positions are won"),
+        };
+        let budget = fewest + (fewest / 2).max(MIN_SPARE_MOVES);
+        let trainer = EndgameTrainer {
+            endgame,
+            budget,
+            verdict: Verdict::OnTrack,
+        };
+        (trainer, position)
+    }
+
+    /// Judges the position after the player's latest move, with `played`
+    /// moves made so far. The verdict is only given when it changes, so a
+    /// mistake is pointed out once rather than after every move that follows.
+    pub fn judge(
+        &mut self,
+        position: &GameState,
+        status: GameStatus,
+        played: u32,
+    ) -> Option<Verdict> {
+        let verdict = match (status, position.endgame_result()) {
+            (GameStatus::Checkmate { .. }, _) if played <= self.budget => Verdict::Mated,
+            (GameStatus::Checkmate { .. }, _) => Verdict::TooSlow,
+            // Drawn by repetition or the fifty-move rule
+            (status, _) if status.is_over() => Verdict::ThrownAway,
+            (_, Some(EndgameResult::Loss(moves))) if played + moves <= self.budget => {
+                Verdict::OnTrack
+            }
+            (_, Some(EndgameResult::Loss(_))) => Verdict::TooSlow,
+            _ => Verdict::ThrownAway,
+        };
+        let changed = verdict != self.verdict;
+        self.verdict = verdict;
+        changed.then_some(verdict)
+    }
+
+    /// A line for the status bar.
+    pub fn describe(&self, played: u32) -> String {
+        format!(
+            "{} trainer: mate within {} moves ({played} played)",
+            self.endgame, self.budget
+        )
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use itsjustchess::chess_core::{
+    Analysis, ChessBoard, GameState, Move, MoveQuality, MoveReview, Network, PieceColor,
+    SearchStats,
+};
+
+use crate::settings::config_path;
+
+// Where evaluations are kept between sessions with --keep-evals
+const EVALS_FILE: &str = "evals.cache";
+
+// The qualities of reviewed moves, by the digit they are saved as
+const QUALITIES: [MoveQuality; 5] = [
+    MoveQuality::Best,
+    MoveQuality::Good,
+    MoveQuality::Inaccuracy,
+    MoveQuality::Mistake,
+    MoveQuality::Blunder,
+];
+
+/// The searches already done this session, by the position searched and
+/// the rules and engine it was searched with, so that going back to a
+/// position in analysis or reviewing a game again doesn't search it all over.
+#[derive(Default)]
+pub struct EvalCache {
+    analyses: HashMap<u64, Vec<Analysis>>, // Each depth searched, shallowest first
+    reviews: HashMap<(u64, Move, u32), MoveReview>, // By position, move and depth
+    boards: HashMap<u64, (usize, usize)>,  // Files and ranks of positions not on 8x8
+    file: Option<PathBuf>,                 // Set if kept between sessions
+}
+
+impl EvalCache {
+    /// The evaluations kept by earlier sessions, to be saved again with
+    /// [`EvalCache::save`]; empty if there are none.
+    pub fn kept() -> Self {
+        let mut cache = EvalCache {
+            file: config_path(EVALS_FILE),
+            ..EvalCache::default()
+        };
+        let Some(text) = cache
+            .file
+            .as_ref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+        else {
+            return cache;
+        };
+        for line in text.lines() {
+            if cache.decode(line).is_none() {
+                log::warn!("Ignoring malformed evaluation line: {line}");
+            }
+        }
+        log::info!(
+            "Loaded {} analysed positions and {} reviewed moves",
+            cache.analyses.len(),
+            cache.reviews.len()
+        );
+        cache
+    }
+
+    /// Writes the evaluations out if they are kept between sessions.
+    pub fn save(&self) {
+        let Some(path) = &self.file else {
+            return;
+        };
+        let result = match path.parent() {
+            Some(dir) => std::fs::create_dir_all(dir),
+            None => Ok(()),
+        }
+        .and_then(|_| std::fs::write(path, self.encode()));
+        if let Err(e) = result {
+            log::warn!("Failed to save evaluations to {}: {e}", path.display());
+        }
+    }
+
+    // Every evaluation as lines to be read back by `decode`
+    fn encode(&self) -> String {
+        // Board sizes come first, so the moves after them are read on the
+        // right board
+        let mut text = String::new();
+        for (key, (files, ranks)) in &self.boards {
+            text.push_str(&format!("s {key:016x} {files} {ranks}\n"));
+        }
+        for (key, analyses) in &self.analyses {
+            let board = self.board(*key);
+            for analysis in analyses {
+                text.push_str(&format!(
+                    "a {key:016x} {} {}{}\n",
+                    analysis.depth,
+                    analysis.score,
+                    encode_line(&analysis.line, &board)
+                ));
+            }
+        }
+        for ((key, mv, depth), review) in &self.reviews {
+            let quality = QUALITIES
+                .iter()
+                .position(|&q| q == review.quality)
+                .unwrap_or(0);
+            let board = self.board(*key);
+            text.push_str(&format!(
+                "r {key:016x} {depth} {} {quality} {} {}{}\n",
+                mv.to_uci_on(&board),
+                review.played.score,
+                review.best.score,
+                encode_line(&review.best.line, &board)
+            ));
+        }
+        text
+    }
+
+    // The board the searches kept under `key` were made on
+    fn board(&self, key: u64) -> ChessBoard {
+        self.boards
+            .get(&key)
+            .and_then(|&(files, ranks)| ChessBoard::with_size(files, ranks))
+            .unwrap_or_else(ChessBoard::empty)
+    }
+
+    /// The deepest search of `position` done so far.
+    pub fn analysis(&self, position: &GameState) -> Option<&Analysis> {
+        self.analyses.get(&search_key(position))?.last()
+    }
+
+    /// Keeps a search of `position`, unless one as deep is already kept.
+    pub fn store_analysis(&mut self, position: &GameState, analysis: &Analysis) {
+        let key = search_key(position);
+        if !position.board.is_standard_size() {
+            let board = &position.board;
+            self.boards.insert(key, (board.files, board.ranks));
+        }
+        let analyses = self.analyses.entry(key).or_default();
+        if let Err(index) = analyses.binary_search_by_key(&analysis.depth, |a| a.depth) {
+            analyses.insert(index, analysis.clone());
+        }
+    }
+
+    /// The review of `mv` played in `position`, searched `depth` plies deep.
+    pub fn review(&self, position: &GameState, mv: Move, depth: u32) -> Option<&MoveReview> {
+        self.reviews.get(&(search_key(position), mv, depth))
+    }
+
+    /// Keeps the review of `mv` played in `position`, and the search of the
+    /// position that came with it.
+    pub fn store_review(&mut self, position: &GameState, mv: Move, review: &MoveReview) {
+        self.store_analysis(position, &review.best);
+        self.reviews.insert(
+            (search_key(position), mv, review.best.depth),
+            review.clone(),
+        );
+    }
+
+    // Reads one saved line, e.g. "a 9d39247e33776d41 5 35 e2e4 e7e5" for an
+    // analysis (the key, depth, score and line), "r 9d39247e33776d41 4
+    // a2a3 1 20 35 e2e4" for a review (the key, depth, move, quality, the
+    // move's score and then the best score and line) or "s 9d39247e33776d41
+    // 10 8" for the files and ranks of a board that isn't 8x8.
+    fn decode(&mut self, line: &str) -> Option<()> {
+        let mut fields = line.split_whitespace();
+        let kind = fields.next()?;
+        let key = u64::from_str_radix(fields.next()?, 16).ok()?;
+        if kind == "s" {
+            let files = fields.next()?.parse().ok()?;
+            let ranks = fields.next()?.parse().ok()?;
+            ChessBoard::with_size(files, ranks)?;
+            self.boards.insert(key, (files, ranks));
+            return Some(());
+        }
+        let board = self.board(key);
+        let depth: u32 = fields.next()?.parse().ok()?;
+        let parse = |uci| Move::from_uci_on(uci, &board);
+        match kind {
+            "a" => {
+                let score = fields.next()?.parse().ok()?;
+                let line = fields.map(parse).collect::<Option<Vec<_>>>()?;
+                let analyses = self.analyses.entry(key).or_default();
+                if let Err(index) = analyses.binary_search_by_key(&depth, |a| a.depth) {
+                    analyses.insert(index, cached(depth, score, line));
+                }
+            }
+            "r" => {
+                let mv = parse(fields.next()?)?;
+                let quality = *QUALITIES.get(fields.next()?.parse::<usize>().ok()?)?;
+                let played = fields.next()?.parse().ok()?;
+                let best = fields.next()?.parse().ok()?;
+                let line = fields.map(parse).collect::<Option<Vec<_>>>()?;
+                let review = MoveReview {
+                    best: cached(depth, best, line),
+                    played: cached(depth, played, vec![mv]),
+                    quality,
+                };
+                self.reviews.insert((key, mv, depth), review);
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+}
+
+// The key searches of `position` are kept under: its Zobrist key, with the
+// variant, the checks given in Three-Check, the engine's version and the
+// installed network, which all change what a search finds. FNV-1a, which
+// unlike the standard library's hasher is the same from one build to the
+// next, as a key kept with --keep-evals must be.
+fn search_key(position: &GameState) -> u64 {
+    let text = format!(
+        "{:016x} {} +{}+{} {} {:016x}",
+        position.zobrist_key(),
+        position.variant,
+        position.checks_given(PieceColor::White),
+        position.checks_given(PieceColor::Black),
+        env!("CARGO_PKG_VERSION"),
+        Network::installed_fingerprint().unwrap_or(0)
+    );
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in text.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// A search read back from the file, which doesn't keep the work it took.
+fn cached(depth: u32, score: i32, line: Vec<Move>) -> Analysis {
+    Analysis {
+        depth,
+        score,
+        line,
+        stats: SearchStats::default(),
+    }
+}
+
+// A line of moves in UCI on `board`, each after a space.
+fn encode_line(line: &[Move], board: &ChessBoard) -> String {
+    line.iter()
+        .map(|mv| format!(" {}", mv.to_uci_on(board)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_are_read_back_on_the_board_they_were_made_on() {
+        let mut position = GameState::new();
+        position
+            .from_fen("rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR w KQkq - 0 1")
+            .unwrap();
+        let line = ["i1h3", "j7j6"].map(|uci| Move::from_uci_on(uci, &position.board).unwrap());
+        let mut cache = EvalCache::default();
+        cache.store_analysis(&position, &cached(2, 15, line.to_vec()));
+
+        let mut kept = EvalCache::default();
+        for text in cache.encode().lines() {
+            kept.decode(text).unwrap();
+        }
+        assert_eq!(kept.analysis(&position).unwrap().line, line);
+    }
+}
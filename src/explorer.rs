@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+
+use itsjustchess::chess_core::{parse_pgn_games, GameState, Move};
+
+use crate::settings::config_path;
+
+// The games database, kept in the config directory beside the settings
+const EXPLORER_FILE: &str = "explorer.db";
+
+// How far into each game its moves are counted; the explorer is for the
+// opening, and this keeps the database small
+const EXPLORER_PLIES: usize = 40;
+
+/// How often a move was played in a position of the database, and how the
+/// games it was played in ended.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct MoveStats {
+    pub mv: Move,
+    pub white_wins: u32,
+    pub draws: u32,
+    pub black_wins: u32,
+}
+
+impl MoveStats {
+    pub fn games(&self) -> u32 {
+        self.white_wins + self.draws + self.black_wins
+    }
+
+    // One line of the database file: the position's Zobrist key in hex, then
+    // the move in UCI and the games it won for White, drew and won for
+    // Black, e.g. "9d39247e33776d41 e2e4 412 380 301"
+    fn encode(&self, key: u64) -> String {
+        format!(
+            "{key:016x} {} {} {} {}",
+            self.mv.to_uci(),
+            self.white_wins,
+            self.draws,
+            self.black_wins
+        )
+    }
+
+    fn decode(line: &str) -> Option<(u64, MoveStats)> {
+        let mut fields = line.split_whitespace();
+        let key = u64::from_str_radix(fields.next()?, 16).ok()?;
+        let mv = Move::from_uci(fields.next()?)?;
+        let mut count = || fields.next()?.parse().ok();
+        let stats = MoveStats {
+            mv,
+            white_wins: count()?,
+            draws: count()?,
+            black_wins: count()?,
+        };
+        Some((key, stats))
+    }
+}
+
+/// The moves played from each position of a database of games, by the
+/// position's Zobrist key, for the opening explorer.
+#[derive(Default)]
+pub struct Explorer {
+    positions: HashMap<u64, Vec<MoveStats>>,
+}
+
+impl Explorer {
+    /// The database saved by earlier imports; empty if there is none.
+    pub fn load() -> Self {
+        let mut explorer = Explorer::default();
+        let Some(text) = config_path(EXPLORER_FILE).and_then(|p| std::fs::read_to_string(p).ok())
+        else {
+            return explorer;
+        };
+        for line in text.lines() {
+            match MoveStats::decode(line) {
+                Some((key, stats)) => explorer.positions.entry(key).or_default().push(stats),
+                None => log::warn!("Ignoring malformed explorer line: {line}"),
+            }
+        }
+        explorer
+    }
+
+    pub fn save(&self) {
+        let Some(path) = config_path(EXPLORER_FILE) else {
+            return;
+        };
+        let mut text = String::new();
+        for (key, moves) in &self.positions {
+            for stats in moves {
+                text.push_str(&stats.encode(*key));
+                text.push('\n');
+            }
+        }
+
+        let result = match path.parent() {
+            Some(dir) => std::fs::create_dir_all(dir),
+            None => Ok(()),
+        }
+        .and_then(|_| std::fs::write(&path, text));
+        if let Err(e) = result {
+            log::warn!(
+                "Failed to save the explorer database to {}: {e}",
+                path.display()
+            );
+        }
+    }
+
+    /// Counts the opening moves of each finished game in `pgn`, returning
+    /// how many games were added. Games without a result, that can't be
+    /// read or that aren't played on the standard board are left out.
+    pub fn import(&mut self, pgn: &str) -> usize {
+        let mut added = 0;
+        for game in parse_pgn_games(pgn) {
+            let game = match game {
+                Ok(game) => game,
+                Err(err) => {
+                    log::warn!("Skipping a game the explorer can't read: {err}");
+                    continue;
+                }
+            };
+            let result = match game.tag("Result") {
+                Some("1-0") => [1, 0, 0],
+                Some("1/2-1/2") => [0, 1, 0],
+                Some("0-1") => [0, 0, 1],
+                _ => continue,
+            };
+            // The moves are saved in UCI for the standard board
+            if !game.start.board.is_standard_size() {
+                continue;
+            }
+            let mut position = game.start.clone();
+            for &mv in game.moves.iter().take(EXPLORER_PLIES) {
+                let moves = self.positions.entry(position.zobrist_key()).or_default();
+                let index = match moves.iter().position(|stats| stats.mv == mv) {
+                    Some(index) => index,
+                    None => {
+                        moves.push(MoveStats {
+                            mv,
+                            white_wins: 0,
+                            draws: 0,
+                            black_wins: 0,
+                        });
+                        moves.len() - 1
+                    }
+                };
+                let stats = &mut moves[index];
+                stats.white_wins += result[0];
+                stats.draws += result[1];
+                stats.black_wins += result[2];
+                position.make_move(mv);
+            }
+            added += 1;
+        }
+        added
+    }
+
+    /// The moves played from `position`, the most played first.
+    pub fn moves(&self, position: &GameState) -> Vec<MoveStats> {
+        let mut moves = self
+            .positions
+            .get(&position.zobrist_key())
+            .cloned()
+            .unwrap_or_default();
+        moves.sort_by_key(|stats| std::cmp::Reverse(stats.games()));
+        moves
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+}
@@ -0,0 +1,74 @@
+use itsjustchess::chess_core::{pgn_date_today, PgnGame};
+
+/// What a game is called in its PGN besides its moves: the event, the site,
+/// the round and the players, with their ratings. Whatever isn't set is
+/// written as for a casual game at this board.
+#[derive(Clone, Default, Debug)]
+pub struct GameInfo {
+    pub event: Option<String>,
+    pub site: Option<String>,
+    pub round: Option<String>,
+    pub white: Option<String>,
+    pub black: Option<String>,
+    pub white_elo: Option<u32>,
+    pub black_elo: Option<u32>,
+}
+
+impl GameInfo {
+    /// The tags of `game` that name it. Players whose names `named_by_default`
+    /// says the game would have given them anyway are left unset, so that
+    /// they follow the game, as when the AI's level changes.
+    pub fn from_pgn(game: &PgnGame, named_by_default: impl Fn(&str) -> bool) -> Self {
+        let text = |tag| game.tag(tag).map(str::to_string);
+        let player = |tag| text(tag).filter(|name| !named_by_default(name));
+        // Unknown ratings are written "-" or left empty
+        let elo = |tag| game.tag(tag).and_then(|elo| elo.parse().ok());
+        GameInfo {
+            event: text("Event"),
+            site: text("Site"),
+            round: text("Round"),
+            white: player("White"),
+            black: player("Black"),
+            white_elo: elo("WhiteElo"),
+            black_elo: elo("BlackElo"),
+        }
+    }
+
+    /// This, with what isn't set here taken from `other`.
+    pub fn or(self, other: GameInfo) -> Self {
+        GameInfo {
+            event: self.event.or(other.event),
+            site: self.site.or(other.site),
+            round: self.round.or(other.round),
+            white: self.white.or(other.white),
+            black: self.black.or(other.black),
+            white_elo: self.white_elo.or(other.white_elo),
+            black_elo: self.black_elo.or(other.black_elo),
+        }
+    }
+
+    /// The Seven Tag Roster for a game with `result`, dated today, with the
+    /// players' ratings after it if they are known. `white` and `black` name
+    /// the players not named here.
+    pub fn tags(&self, white: &str, black: &str, result: &str) -> Vec<(&'static str, String)> {
+        let or = |value: &Option<String>, default: &str| {
+            value.clone().unwrap_or_else(|| default.to_string())
+        };
+        let mut tags = vec![
+            ("Event", or(&self.event, "Casual game")),
+            ("Site", or(&self.site, "itsjustchess")),
+            ("Date", pgn_date_today()),
+            ("Round", or(&self.round, "1")),
+            ("White", or(&self.white, white)),
+            ("Black", or(&self.black, black)),
+            ("Result", result.to_string()),
+        ];
+        if let Some(elo) = self.white_elo {
+            tags.push(("WhiteElo", elo.to_string()));
+        }
+        if let Some(elo) = self.black_elo {
+            tags.push(("BlackElo", elo.to_string()));
+        }
+        tags
+    }
+}
@@ -0,0 +1,400 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use itsjustchess::chess_core::{
+    format_pgn, pgn_date_today, Difficulty, GameState, GameStatus, Move, OpeningBook, PieceColor,
+    Variant,
+};
+
+// The chances of the SPRT accepting the wrong hypothesis, each way
+const SPRT_ALPHA: f64 = 0.05;
+const SPRT_BETA: f64 = 0.05;
+
+// How many standard errors either side of the score the Elo margin spans (95%)
+const MARGIN_Z: f64 = 1.96;
+
+/// One AI player of a match: the plain search to a fixed depth, as with
+/// --ai-depth, or a difficulty level, as with --difficulty.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Engine {
+    Depth(u32),
+    Level(Difficulty),
+}
+
+impl Engine {
+    pub fn choose_move(
+        self,
+        state: &GameState,
+        book: &OpeningBook,
+        rng: &mut impl Rng,
+    ) -> Option<Move> {
+        match self {
+            Engine::Depth(depth) => state.choose_ai_move(depth, Some(book), rng),
+            Engine::Level(difficulty) => {
+                // Untimed, so the seed replays the same games
+                state.choose_move_at(difficulty, Some(book), false, rng)
+            }
+        }
+    }
+}
+
+impl FromStr for Engine {
+    type Err = String;
+
+    /// Parses "depth:3" or "level:5".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid engine \"{s}\": expected depth:N or level:N");
+        let (kind, value) = s.trim().split_once(':').ok_or_else(invalid)?;
+        match kind {
+            "depth" => value.parse().map(Engine::Depth).map_err(|_| invalid()),
+            "level" => value.parse().map(Engine::Level),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Engine::Depth(depth) => write!(f, "depth {depth}"),
+            Engine::Level(difficulty) => write!(f, "level {difficulty}"),
+        }
+    }
+}
+
+/// A sequential probability ratio test between two hypotheses about how
+/// many Elo the first engine of a pairing is stronger by, `elo0` and
+/// `elo1`. The pairing stops as soon as the games played decide between
+/// them. Parsed from "elo0,elo1", e.g. "0,10".
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+}
+
+impl Sprt {
+    /// The log-likelihood ratio of `elo1` over `elo0` given `results`, by
+    /// the normal approximation to the score.
+    pub fn llr(&self, results: Results) -> f64 {
+        let Some((score, variance)) = results.score_and_variance() else {
+            return 0.0;
+        };
+        if variance <= 0.0 {
+            return 0.0;
+        }
+        let (s0, s1) = (expected_score(self.elo0), expected_score(self.elo1));
+        let games = f64::from(results.games());
+        (s1 - s0) * (2.0 * score - s0 - s1) * games / (2.0 * variance)
+    }
+
+    /// Whether `results` accept `elo1` (true) or `elo0` (false), or `None`
+    /// while the test can't tell yet.
+    pub fn verdict(&self, results: Results) -> Option<bool> {
+        let llr = self.llr(results);
+        if llr >= ((1.0 - SPRT_BETA) / SPRT_ALPHA).ln() {
+            Some(true)
+        } else if llr <= (SPRT_BETA / (1.0 - SPRT_ALPHA)).ln() {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+impl FromStr for Sprt {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid SPRT bounds \"{s}\": expected ELO0,ELO1, e.g. 0,10");
+        let (elo0, elo1) = s.split_once(',').ok_or_else(invalid)?;
+        let elo0: f64 = elo0.trim().parse().map_err(|_| invalid())?;
+        let elo1: f64 = elo1.trim().parse().map_err(|_| invalid())?;
+        if elo0 >= elo1 {
+            return Err(format!("SPRT bounds \"{s}\" need ELO0 below ELO1"));
+        }
+        Ok(Sprt { elo0, elo1 })
+    }
+}
+
+/// Settings for a match or tournament between AI players, run without a
+/// window.
+pub struct MatchConfig {
+    pub engines: Vec<Engine>,
+    /// Games per pairing, at most if `sprt` is set
+    pub games: u32,
+    /// Play the first engine against each of the others, rather than every
+    /// engine against every other (a round robin)
+    pub gauntlet: bool,
+    /// Swap colours every game; otherwise the first engine of a pairing
+    /// always has White
+    pub alternate_colors: bool,
+    pub sprt: Option<Sprt>,
+    /// Where to write each game's PGN, one file a game, instead of printing
+    pub pgn_dir: Option<PathBuf>,
+    pub start: GameState,
+    pub book: OpeningBook,
+    /// Seeds the AI's choices, so the same seed plays the same games
+    pub seed: u64,
+}
+
+impl MatchConfig {
+    // The engines that meet, by index, the first of each with the first
+    // White
+    fn pairings(&self) -> Vec<(usize, usize)> {
+        let count = self.engines.len();
+        if self.gauntlet {
+            (1..count).map(|other| (0, other)).collect()
+        } else {
+            (0..count)
+                .flat_map(|a| (a + 1..count).map(move |b| (a, b)))
+                .collect()
+        }
+    }
+}
+
+/// Wins, draws and losses from one side's point of view.
+#[derive(Default, Copy, Clone, PartialEq, Debug)]
+pub struct Results {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Results {
+    // Counts a finished game for the side that had `color`
+    fn add(&mut self, status: GameStatus, color: PieceColor) {
+        match status.winner() {
+            Some(winner) if winner == color => self.wins += 1,
+            Some(_) => self.losses += 1,
+            None if status.is_over() => self.draws += 1,
+            None => {}
+        }
+    }
+
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Points scored, a half for each draw.
+    pub fn points(&self) -> f64 {
+        f64::from(self.wins) + f64::from(self.draws) / 2.0
+    }
+
+    // The average score a game and the variance of one game's score
+    fn score_and_variance(&self) -> Option<(f64, f64)> {
+        let games = f64::from(self.games());
+        if games == 0.0 {
+            return None;
+        }
+        let (wins, draws) = (f64::from(self.wins) / games, f64::from(self.draws) / games);
+        let score = wins + draws / 2.0;
+        Some((score, wins + draws / 4.0 - score * score))
+    }
+
+    /// How many Elo stronger the side is than its opponents by these
+    /// results, and the margin of error either way. `None` without games
+    /// or when every game went the same way, as the difference is then
+    /// unbounded.
+    pub fn elo_estimate(&self) -> Option<(f64, f64)> {
+        let (score, variance) = self.score_and_variance()?;
+        if score <= 0.0 || score >= 1.0 {
+            return None;
+        }
+        let error = MARGIN_Z * (variance / f64::from(self.games())).sqrt();
+        let low = elo_difference((score - error).max(f64::EPSILON));
+        let high = elo_difference((score + error).min(1.0 - f64::EPSILON));
+        Some((elo_difference(score), (high - low) / 2.0))
+    }
+}
+
+impl fmt::Display for Results {
+    /// e.g. "+5 =3 -2 (6.5/10), Elo +53 ± 112".
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "+{} ={} -{} ({}/{})",
+            self.wins,
+            self.draws,
+            self.losses,
+            self.points(),
+            self.games()
+        )?;
+        match self.elo_estimate() {
+            Some((elo, margin)) => write!(f, ", Elo {elo:+.0} ± {margin:.0}"),
+            None => Ok(()),
+        }
+    }
+}
+
+// The expected score of a player `elo` stronger than the opponent
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+// The Elo difference an expected score comes from
+fn elo_difference(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+/// A finished game: its moves in SAN (with check marks) and how it ended.
+pub struct GameRecord {
+    pub sans: Vec<String>,
+    pub status: GameStatus,
+}
+
+/// Plays one game from `start` to the end between `engines`, White's
+/// first, with `rng` making their random choices.
+pub fn play_game(
+    start: &GameState,
+    engines: [Engine; 2],
+    book: &OpeningBook,
+    rng: &mut impl Rng,
+) -> GameRecord {
+    let mut state = start.clone();
+    let mut position_history = vec![state.position_key()];
+    let mut sans = Vec::new();
+    let mut status = state.status_with_history(&position_history);
+
+    while !status.is_over() {
+        let engine = match state.turn {
+            PieceColor::White => engines[0],
+            PieceColor::Black => engines[1],
+        };
+        let mv = match engine.choose_move(&state, book, rng) {
+            Some(mv) => mv,
+            None => break, // status() would have caught this
+        };
+        sans.push(mv.to_san(&state));
+        state.make_move(mv);
+        position_history.push(state.position_key());
+        status = state.status_with_history(&position_history);
+    }
+
+    GameRecord { sans, status }
+}
+
+/// Plays every pairing of the match in turn, printing or saving each game's
+/// PGN as it finishes, then prints each pairing's result and, with more
+/// than two engines, the standings.
+pub fn run(config: &MatchConfig) {
+    if let Some(dir) = &config.pgn_dir {
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            eprintln!("Failed to create {}: {e}", dir.display());
+        }
+    }
+
+    eprintln!("Seed {}", config.seed);
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let pairings = config.pairings();
+    let mut pairing_results = Vec::new();
+    let mut standings = vec![Results::default(); config.engines.len()];
+    let mut game_number = 0;
+    for (pairing, &(a, b)) in pairings.iter().enumerate() {
+        let mut results = Results::default(); // From the first engine's side
+        for game in 1..=config.games {
+            let first_is_white = !config.alternate_colors || game % 2 == 1;
+            let (white, black) = if first_is_white { (a, b) } else { (b, a) };
+            let engines = [config.engines[white], config.engines[black]];
+            let record = play_game(&config.start, engines, &config.book, &mut rng);
+            game_number += 1;
+
+            let (a_color, b_color) = if first_is_white {
+                (PieceColor::White, PieceColor::Black)
+            } else {
+                (PieceColor::Black, PieceColor::White)
+            };
+            results.add(record.status, a_color);
+            standings[a].add(record.status, a_color);
+            standings[b].add(record.status, b_color);
+
+            let round = format!("{}.{game}", pairing + 1);
+            let pgn = match_pgn(config, &round, engines, &record);
+            match &config.pgn_dir {
+                Some(dir) => {
+                    let path = dir.join(format!("game_{game_number:04}.pgn"));
+                    if let Err(e) = std::fs::write(&path, pgn) {
+                        eprintln!("Failed to write {}: {e}", path.display());
+                    }
+                }
+                None => println!("{pgn}"),
+            }
+            eprintln!(
+                "Game {game_number}, {} - {}: {} ({})",
+                engines[0],
+                engines[1],
+                record.status.result_tag(),
+                record.status.description()
+            );
+
+            if let Some(sprt) = config.sprt {
+                if let Some(accepted) = sprt.verdict(results) {
+                    let elo = if accepted { sprt.elo1 } else { sprt.elo0 };
+                    eprintln!(
+                        "SPRT accepts {} being {elo:+} Elo against {} after {game} games",
+                        config.engines[a], config.engines[b]
+                    );
+                    break;
+                }
+            }
+        }
+        pairing_results.push((a, b, results));
+    }
+
+    for (a, b, results) in pairing_results {
+        println!(
+            "Match result: {} against {}: {results}",
+            config.engines[a], config.engines[b]
+        );
+    }
+    if config.engines.len() > 2 {
+        let mut order: Vec<usize> = (0..config.engines.len()).collect();
+        order.sort_by(|&x, &y| standings[y].points().total_cmp(&standings[x].points()));
+        println!("Standings:");
+        for (place, engine) in order.into_iter().enumerate() {
+            println!(
+                "{}. {}: {}",
+                place + 1,
+                config.engines[engine],
+                standings[engine]
+            );
+        }
+    }
+}
+
+fn match_pgn(
+    config: &MatchConfig,
+    round: &str,
+    engines: [Engine; 2],
+    record: &GameRecord,
+) -> String {
+    let result = record.status.result_tag();
+    let mut tags = vec![
+        ("Event", "Engine match".to_string()),
+        ("Site", "itsjustchess".to_string()),
+        ("Date", pgn_date_today()),
+        ("Round", round.to_string()),
+        ("White", format!("itsjustchess AI ({})", engines[0])),
+        ("Black", format!("itsjustchess AI ({})", engines[1])),
+        ("Result", result.to_string()),
+    ];
+    if config.start.variant != Variant::Standard {
+        tags.push(("Variant", config.start.variant.to_string()));
+    }
+    let start_fen = config.start.to_fen();
+    if start_fen != GameState::new().to_fen() {
+        tags.push(("SetUp", "1".to_string()));
+        tags.push(("FEN", start_fen));
+    }
+
+    format_pgn(
+        &tags,
+        config.start.fullmove_number,
+        config.start.turn == PieceColor::Black,
+        &record.sans,
+        result,
+    )
+}
@@ -0,0 +1,451 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{LineWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Instant;
+
+use ggez::event::winit_event::TouchPhase;
+use ggez::event::{EventHandler, MouseButton};
+use ggez::input::keyboard::{KeyCode, KeyInput, KeyMods};
+use ggez::{Context, GameError, GameResult};
+
+// The keys the app reacts to, which are written by name. Any other key is
+// read back as one ggez has no code for, which the app ignores all the same.
+const KEYS: [KeyCode; 48] = [
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::Escape,
+    KeyCode::Return,
+    KeyCode::NumpadEnter,
+    KeyCode::Space,
+    KeyCode::Tab,
+    KeyCode::Back,
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::Up,
+    KeyCode::Down,
+    KeyCode::Delete,
+    KeyCode::F11,
+];
+
+const MODS: [(KeyMods, &str); 4] = [
+    (KeyMods::SHIFT, "shift"),
+    (KeyMods::CTRL, "ctrl"),
+    (KeyMods::ALT, "alt"),
+    (KeyMods::LOGO, "logo"),
+];
+
+/// An event ggez handed the app, as written to an input log.
+#[derive(Copy, Clone, Debug)]
+pub enum InputEvent {
+    KeyDown(KeyInput, bool), // The key, and whether it is held down repeating
+    KeyUp(KeyInput),
+    Text(char),
+    MouseDown(MouseButton, f32, f32),
+    MouseUp(MouseButton, f32, f32),
+    MouseMotion(f32, f32, f32, f32), // Where to, and by how far
+    MouseWheel(f32, f32),
+    Touch(TouchPhase, f64, f64),
+    Resize(f32, f32),
+    Quit,
+}
+
+impl InputEvent {
+    // Hands the event to `handler` as ggez would have
+    fn dispatch(self, handler: &mut impl EventHandler<GameError>, ctx: &mut Context) -> GameResult {
+        match self {
+            InputEvent::KeyDown(input, repeat) => handler.key_down_event(ctx, input, repeat),
+            InputEvent::KeyUp(input) => handler.key_up_event(ctx, input),
+            InputEvent::Text(character) => handler.text_input_event(ctx, character),
+            InputEvent::MouseDown(button, x, y) => {
+                handler.mouse_button_down_event(ctx, button, x, y)
+            }
+            InputEvent::MouseUp(button, x, y) => handler.mouse_button_up_event(ctx, button, x, y),
+            InputEvent::MouseMotion(x, y, dx, dy) => handler.mouse_motion_event(ctx, x, y, dx, dy),
+            InputEvent::MouseWheel(x, y) => handler.mouse_wheel_event(ctx, x, y),
+            InputEvent::Touch(phase, x, y) => handler.touch_event(ctx, phase, x, y),
+            InputEvent::Resize(width, height) => handler.resize_event(ctx, width, height),
+            InputEvent::Quit => {
+                ctx.request_quit();
+                Ok(())
+            }
+        }
+    }
+}
+
+fn write_key(f: &mut fmt::Formatter, input: &KeyInput) -> fmt::Result {
+    match input.keycode {
+        Some(key) => write!(f, "{} {key:?} ", input.scancode)?,
+        None => write!(f, "{} - ", input.scancode)?,
+    }
+    let mods: Vec<&str> = MODS
+        .iter()
+        .filter(|(m, _)| input.mods.contains(*m))
+        .map(|(_, name)| *name)
+        .collect();
+    if mods.is_empty() {
+        write!(f, "-")
+    } else {
+        write!(f, "{}", mods.join("+"))
+    }
+}
+
+fn write_button(f: &mut fmt::Formatter, button: MouseButton) -> fmt::Result {
+    match button {
+        MouseButton::Left => write!(f, "left"),
+        MouseButton::Right => write!(f, "right"),
+        MouseButton::Middle => write!(f, "middle"),
+        MouseButton::Other(n) => write!(f, "{n}"),
+    }
+}
+
+/// e.g. "key_down 28 Return ctrl 0", "mouse_down left 212.5 380" or
+/// "text 101", a character by its code so spaces survive.
+impl fmt::Display for InputEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InputEvent::KeyDown(input, repeat) => {
+                write!(f, "key_down ")?;
+                write_key(f, input)?;
+                write!(f, " {}", u8::from(*repeat))
+            }
+            InputEvent::KeyUp(input) => {
+                write!(f, "key_up ")?;
+                write_key(f, input)
+            }
+            InputEvent::Text(character) => write!(f, "text {}", u32::from(*character)),
+            InputEvent::MouseDown(button, x, y) => {
+                write!(f, "mouse_down ")?;
+                write_button(f, *button)?;
+                write!(f, " {x} {y}")
+            }
+            InputEvent::MouseUp(button, x, y) => {
+                write!(f, "mouse_up ")?;
+                write_button(f, *button)?;
+                write!(f, " {x} {y}")
+            }
+            InputEvent::MouseMotion(x, y, dx, dy) => write!(f, "mouse_motion {x} {y} {dx} {dy}"),
+            InputEvent::MouseWheel(x, y) => write!(f, "mouse_wheel {x} {y}"),
+            InputEvent::Touch(phase, x, y) => write!(f, "touch {phase:?} {x} {y}"),
+            InputEvent::Resize(width, height) => write!(f, "resize {width} {height}"),
+            InputEvent::Quit => write!(f, "quit"),
+        }
+    }
+}
+
+fn parse_number<T: FromStr>(field: Option<&str>) -> Result<T, String> {
+    let field = field.ok_or("missing a number")?;
+    field
+        .parse()
+        .map_err(|_| format!("\"{field}\" is not a number"))
+}
+
+fn parse_key(fields: &mut std::str::SplitWhitespace) -> Result<KeyInput, String> {
+    let scancode = parse_number(fields.next())?;
+    let name = fields.next().ok_or("missing the key")?;
+    let keycode = KEYS.iter().copied().find(|key| format!("{key:?}") == name);
+    let mods = fields.next().ok_or("missing the modifiers")?;
+    let mods = mods
+        .split('+')
+        .filter(|&m| m != "-")
+        .try_fold(KeyMods::NONE, |mods, name| {
+            match MODS.iter().find(|(_, n)| *n == name) {
+                Some(&(m, _)) => Ok(mods | m),
+                None => Err(format!("unknown modifier \"{name}\"")),
+            }
+        })?;
+    Ok(KeyInput {
+        scancode,
+        keycode,
+        mods,
+    })
+}
+
+fn parse_button(field: Option<&str>) -> Result<MouseButton, String> {
+    match field.ok_or("missing the button")? {
+        "left" => Ok(MouseButton::Left),
+        "right" => Ok(MouseButton::Right),
+        "middle" => Ok(MouseButton::Middle),
+        n => parse_number(Some(n)).map(MouseButton::Other),
+    }
+}
+
+impl FromStr for InputEvent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split_whitespace();
+        let event = match fields.next().ok_or("empty event")? {
+            "key_down" => {
+                let input = parse_key(&mut fields)?;
+                InputEvent::KeyDown(input, parse_number::<u8>(fields.next())? != 0)
+            }
+            "key_up" => InputEvent::KeyUp(parse_key(&mut fields)?),
+            "text" => {
+                let code = parse_number(fields.next())?;
+                let character =
+                    char::from_u32(code).ok_or_else(|| format!("{code} is not a character"))?;
+                InputEvent::Text(character)
+            }
+            "mouse_down" => InputEvent::MouseDown(
+                parse_button(fields.next())?,
+                parse_number(fields.next())?,
+                parse_number(fields.next())?,
+            ),
+            "mouse_up" => InputEvent::MouseUp(
+                parse_button(fields.next())?,
+                parse_number(fields.next())?,
+                parse_number(fields.next())?,
+            ),
+            "mouse_motion" => InputEvent::MouseMotion(
+                parse_number(fields.next())?,
+                parse_number(fields.next())?,
+                parse_number(fields.next())?,
+                parse_number(fields.next())?,
+            ),
+            "mouse_wheel" => {
+                InputEvent::MouseWheel(parse_number(fields.next())?, parse_number(fields.next())?)
+            }
+            "touch" => {
+                let phase = match fields.next().ok_or("missing the touch phase")? {
+                    "Started" => TouchPhase::Started,
+                    "Moved" => TouchPhase::Moved,
+                    "Ended" => TouchPhase::Ended,
+                    "Cancelled" => TouchPhase::Cancelled,
+                    other => return Err(format!("unknown touch phase \"{other}\"")),
+                };
+                InputEvent::Touch(
+                    phase,
+                    parse_number(fields.next())?,
+                    parse_number(fields.next())?,
+                )
+            }
+            "resize" => {
+                InputEvent::Resize(parse_number(fields.next())?, parse_number(fields.next())?)
+            }
+            "quit" => InputEvent::Quit,
+            other => return Err(format!("unknown event \"{other}\"")),
+        };
+        match fields.next() {
+            Some(extra) => Err(format!("unexpected \"{extra}\"")),
+            None => Ok(event),
+        }
+    }
+}
+
+/// A line of an input log: the event, and the frame it came in before,
+/// counted in updates since the start. The milliseconds are only for reading.
+struct Entry {
+    frame: u64,
+    event: InputEvent,
+}
+
+enum Mode {
+    Record(LineWriter<File>),
+    Replay(VecDeque<Entry>),
+}
+
+/// Wraps the app to record the input it gets to a file with
+/// --record-input, or to play a recording back into it with --replay-input,
+/// for tests of the UI like dragging a piece or picking a promotion.
+///
+/// Each line of the log is "FRAME MS EVENT". Playback goes by the frame, not
+/// the time, so each event reaches the app at the same point of the game as
+/// it did when it was recorded, however fast the frames come. The input
+/// given to the window during playback is ignored. Playback ends by quitting
+/// when the recording did, so with --print-board a run can be compared with
+/// the output of an earlier one.
+pub struct InputLog<H> {
+    handler: H,
+    mode: Mode,
+    frame: u64,
+    started: Instant,
+}
+
+impl<H: EventHandler<GameError>> InputLog<H> {
+    /// Records the input `handler` gets to `path`, replacing it.
+    pub fn record(handler: H, path: &Path) -> GameResult<Self> {
+        let file = File::create(path).map_err(|e| {
+            GameError::CustomError(format!("Failed to create {}: {e}", path.display()))
+        })?;
+        let mut out = LineWriter::new(file);
+        writeln!(out, "# itsjustchess input log: frame, milliseconds, event")?;
+        Ok(Self::new(handler, Mode::Record(out)))
+    }
+
+    /// Plays the input recorded in `path` into `handler`.
+    pub fn replay(handler: H, path: &Path) -> GameResult<Self> {
+        let failed =
+            |e: &dyn fmt::Display| GameError::CustomError(format!("{}: {e}", path.display()));
+        let text = fs::read_to_string(path).map_err(|e| failed(&e))?;
+        let mut entries = VecDeque::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parsed = line.split_once(' ').and_then(|(frame, rest)| {
+                let (_ms, event) = rest.split_once(' ')?;
+                Some((frame.parse().ok()?, event))
+            });
+            let Some((frame, event)) = parsed else {
+                return Err(failed(&format!("line {}: expected FRAME MS EVENT", i + 1)));
+            };
+            let event = event
+                .parse()
+                .map_err(|e| failed(&format!("line {}: {e}", i + 1)))?;
+            entries.push_back(Entry { frame, event });
+        }
+        log::info!("Replaying {} events from {}", entries.len(), path.display());
+        Ok(Self::new(handler, Mode::Replay(entries)))
+    }
+
+    fn new(handler: H, mode: Mode) -> Self {
+        InputLog {
+            handler,
+            mode,
+            frame: 0,
+            started: Instant::now(),
+        }
+    }
+
+    // Writes `event` down when recording and passes it on; when replaying,
+    // the window's own input is dropped
+    fn input(&mut self, ctx: &mut Context, event: InputEvent) -> GameResult {
+        match &mut self.mode {
+            Mode::Record(out) => {
+                let ms = self.started.elapsed().as_millis();
+                if let Err(e) = writeln!(out, "{} {ms} {event}", self.frame) {
+                    log::warn!("Failed to record input: {e}");
+                }
+            }
+            Mode::Replay(_) => return Ok(()),
+        }
+        event.dispatch(&mut self.handler, ctx)
+    }
+}
+
+impl<H: EventHandler<GameError>> EventHandler<GameError> for InputLog<H> {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        if let Mode::Replay(entries) = &mut self.mode {
+            while entries
+                .front()
+                .is_some_and(|entry| entry.frame <= self.frame)
+            {
+                if let Some(entry) = entries.pop_front() {
+                    entry.event.dispatch(&mut self.handler, ctx)?;
+                }
+            }
+        }
+        self.frame += 1;
+        self.handler.update(ctx)
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.handler.draw(ctx)
+    }
+
+    fn key_down_event(&mut self, ctx: &mut Context, input: KeyInput, repeat: bool) -> GameResult {
+        self.input(ctx, InputEvent::KeyDown(input, repeat))
+    }
+
+    fn key_up_event(&mut self, ctx: &mut Context, input: KeyInput) -> GameResult {
+        self.input(ctx, InputEvent::KeyUp(input))
+    }
+
+    fn text_input_event(&mut self, ctx: &mut Context, character: char) -> GameResult {
+        self.input(ctx, InputEvent::Text(character))
+    }
+
+    fn mouse_button_down_event(
+        &mut self,
+        ctx: &mut Context,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+    ) -> GameResult {
+        self.input(ctx, InputEvent::MouseDown(button, x, y))
+    }
+
+    fn mouse_button_up_event(
+        &mut self,
+        ctx: &mut Context,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+    ) -> GameResult {
+        self.input(ctx, InputEvent::MouseUp(button, x, y))
+    }
+
+    fn mouse_motion_event(
+        &mut self,
+        ctx: &mut Context,
+        x: f32,
+        y: f32,
+        dx: f32,
+        dy: f32,
+    ) -> GameResult {
+        self.input(ctx, InputEvent::MouseMotion(x, y, dx, dy))
+    }
+
+    fn mouse_wheel_event(&mut self, ctx: &mut Context, x: f32, y: f32) -> GameResult {
+        self.input(ctx, InputEvent::MouseWheel(x, y))
+    }
+
+    fn touch_event(&mut self, ctx: &mut Context, phase: TouchPhase, x: f64, y: f64) -> GameResult {
+        self.input(ctx, InputEvent::Touch(phase, x, y))
+    }
+
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) -> GameResult {
+        self.input(ctx, InputEvent::Resize(width, height))
+    }
+
+    // Closing the window is recorded too, so playback ends where the
+    // recording did
+    fn quit_event(&mut self, ctx: &mut Context) -> GameResult<bool> {
+        let cancelled = self.handler.quit_event(ctx)?;
+        if let (Mode::Record(out), false) = (&mut self.mode, cancelled) {
+            let ms = self.started.elapsed().as_millis();
+            if let Err(e) = writeln!(out, "{} {ms} {}", self.frame, InputEvent::Quit) {
+                log::warn!("Failed to record input: {e}");
+            }
+        }
+        Ok(cancelled)
+    }
+}
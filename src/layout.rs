@@ -0,0 +1,327 @@
+use ggez::graphics::Rect;
+
+use itsjustchess::chess_core::PieceColor;
+
+// The board's size in squares until a game on another size of board is shown
+const STANDARD_SIZE: usize = 8;
+
+// Height of the captured piece trays above and below the board, in tiles
+const CAPTURE_TRAY_TILES: f32 = 0.5;
+
+// Height of the clock bar below the board, in tiles
+const CLOCK_BAR_TILES: f32 = 0.75;
+
+// Width of the margin holding outside coordinate labels, in tiles
+const LABEL_MARGIN_TILES: f32 = 0.4;
+
+// Width of the move history panel to the right of the board, in tiles
+const HISTORY_PANEL_TILES: f32 = 3.0;
+
+// Height of one row (move pair) in the move history panel, in tiles
+const HISTORY_ROW_TILES: f32 = 0.4;
+
+// Width of the evaluation bar beside the board in analysis mode, in tiles
+const EVAL_BAR_TILES: f32 = 0.25;
+
+// Height of the engine line box below the move list in analysis mode, in tiles
+const ANALYSIS_LINE_TILES: f32 = 1.2;
+
+// How much taller the engine line box gets for each line after the first
+const ANALYSIS_EXTRA_LINE_TILES: f32 = 0.4;
+
+// Space around the text of the engine line box, in tiles
+const ANALYSIS_PADDING_TILES: f32 = 0.1;
+
+// Width of the buttons playing the engine's moves, in tiles
+const ANALYSIS_BUTTON_TILES: f32 = 0.55;
+
+// How much one notch of the scroll wheel zooms in, and how far in it can go
+const ZOOM_STEP: f32 = 1.25;
+const MAX_ZOOM: f32 = 4.0;
+
+/// Where each part of the window goes. Everything is measured in "board
+/// space": the board's top-left corner is the origin, the label margin sits
+/// at negative x and Black's captures tray at negative y (beside it, the
+/// game tabs), with White's tray below the board (beside it, the status
+/// bar), the move list to the right of it and the clock bar along the bottom
+/// of the window. In analysis mode the evaluation bar and
+/// engine line take space from the move list panel.
+///
+/// Everything is centred in the window, which may be bigger than it needs
+/// to be, and can be zoomed and panned; `view_rect` and `to_board_space`
+/// are the two sides of that transform.
+pub struct Layout {
+    pub tile_size: f32,
+    pub files: usize, // The board's size in squares, which it is drawn for
+    pub ranks: usize,
+    pub label_margin: f32,
+    pub panel_width: f32,
+    pub tray_height: f32,
+    pub clock_bar_height: f32,
+    pub show_analysis: bool,
+    pub analysis_lines: usize, // The engine lines shown in analysis mode
+    window: (f32, f32),
+    zoom: f32,
+    pan: (f32, f32), // How far the view has moved from the centre, in board space
+}
+
+impl Layout {
+    /// The layout for a standard board `board_size` pixels across.
+    pub fn new(board_size: f32, outside_labels: bool, show_clocks: bool) -> Self {
+        let tile_size = board_size / STANDARD_SIZE as f32;
+        let mut layout = Layout {
+            tile_size,
+            files: STANDARD_SIZE,
+            ranks: STANDARD_SIZE,
+            label_margin: if outside_labels {
+                tile_size * LABEL_MARGIN_TILES
+            } else {
+                0.0
+            },
+            panel_width: tile_size * HISTORY_PANEL_TILES,
+            tray_height: tile_size * CAPTURE_TRAY_TILES,
+            clock_bar_height: if show_clocks {
+                tile_size * CLOCK_BAR_TILES
+            } else {
+                0.0
+            },
+            show_analysis: false,
+            analysis_lines: 1,
+            window: (0.0, 0.0),
+            zoom: 1.0,
+            pan: (0.0, 0.0),
+        };
+        layout.window = layout.window_size();
+        layout
+    }
+
+    /// Makes room for the clock bar, or takes it away.
+    pub fn set_show_clocks(&mut self, show_clocks: bool) {
+        self.clock_bar_height = if show_clocks {
+            self.tile_size * CLOCK_BAR_TILES
+        } else {
+            0.0
+        };
+    }
+
+    /// Lays everything out for a board `files` wide and `ranks` high, scaled
+    /// to fit the same window as before.
+    pub fn set_board_dimensions(&mut self, files: usize, ranks: usize) {
+        if (files, ranks) != (self.files, self.ranks) {
+            self.files = files;
+            self.ranks = ranks;
+            self.fit(self.window.0, self.window.1);
+        }
+    }
+
+    /// How big a standard board would be at this scale, in pixels.
+    pub fn standard_board_size(&self) -> f32 {
+        self.tile_size * STANDARD_SIZE as f32
+    }
+
+    pub fn board_width(&self) -> f32 {
+        self.tile_size * self.files as f32
+    }
+
+    pub fn board_height(&self) -> f32 {
+        self.tile_size * self.ranks as f32
+    }
+
+    /// The window size needed to fit everything, in pixels.
+    pub fn window_size(&self) -> (f32, f32) {
+        (
+            self.label_margin + self.board_width() + self.panel_width,
+            self.board_height()
+                + self.label_margin
+                + 2.0 * self.tray_height
+                + self.clock_bar_height,
+        )
+    }
+
+    /// Scales everything to the biggest board that leaves room for the rest
+    /// in a `width` by `height` window, which is the size the view is
+    /// worked out for from then on.
+    pub fn fit(&mut self, width: f32, height: f32) {
+        let (needed_width, needed_height) = self.window_size();
+        let scale = (width / needed_width).min(height / needed_height);
+        self.tile_size *= scale;
+        self.label_margin *= scale;
+        self.panel_width *= scale;
+        self.tray_height *= scale;
+        self.clock_bar_height *= scale;
+        self.pan = (self.pan.0 * scale, self.pan.1 * scale);
+        self.window = (width, height);
+    }
+
+    /// The screen coordinates to give the canvas so it draws in board space.
+    pub fn view_rect(&self) -> Rect {
+        let (left, top) = self.view_origin();
+        Rect::new(
+            left,
+            top,
+            self.window.0 / self.zoom,
+            self.window.1 / self.zoom,
+        )
+    }
+
+    /// Converts a window position (e.g. a mouse click) to board space.
+    pub fn to_board_space(&self, x: f32, y: f32) -> (f32, f32) {
+        let (left, top) = self.view_origin();
+        (left + x / self.zoom, top + y / self.zoom)
+    }
+
+    /// Zooms in by `steps` notches of the scroll wheel (out if negative),
+    /// keeping what is under window position (`x`, `y`) where it is. Zoomed
+    /// all the way out, everything is centred again.
+    pub fn zoom_at(&mut self, steps: f32, x: f32, y: f32) {
+        let before = self.to_board_space(x, y);
+        self.zoom = (self.zoom * ZOOM_STEP.powf(steps)).clamp(1.0, MAX_ZOOM);
+        if self.zoom == 1.0 {
+            self.pan = (0.0, 0.0);
+            return;
+        }
+        let after = self.to_board_space(x, y);
+        self.pan_by_board(before.0 - after.0, before.1 - after.1);
+    }
+
+    /// Drags the view by (`dx`, `dy`) window pixels, while zoomed in.
+    pub fn pan_by(&mut self, dx: f32, dy: f32) {
+        if self.zoom > 1.0 {
+            self.pan_by_board(-dx / self.zoom, -dy / self.zoom);
+        }
+    }
+
+    // Moves the view, but never so far that the middle of the window is off
+    // the edge of what there is to see
+    fn pan_by_board(&mut self, dx: f32, dy: f32) {
+        let (width, height) = self.window_size();
+        self.pan = (
+            (self.pan.0 + dx).clamp(-width / 2.0, width / 2.0),
+            (self.pan.1 + dy).clamp(-height / 2.0, height / 2.0),
+        );
+    }
+
+    // The board space position of the window's top-left corner
+    fn view_origin(&self) -> (f32, f32) {
+        let (width, height) = self.window_size();
+        let centre_x = -self.label_margin + width / 2.0 + self.pan.0;
+        let centre_y = -self.tray_height + height / 2.0 + self.pan.1;
+        (
+            centre_x - self.window.0 / (2.0 * self.zoom),
+            centre_y - self.window.1 / (2.0 * self.zoom),
+        )
+    }
+
+    pub fn history_panel_rect(&self) -> Rect {
+        let height = self.board_height() + self.label_margin;
+        if self.show_analysis {
+            let eval_bar = self.eval_bar_rect();
+            let line = self.analysis_line_rect();
+            Rect::new(line.x, 0.0, self.panel_width - eval_bar.w, height - line.h)
+        } else {
+            Rect::new(self.board_width(), 0.0, self.panel_width, height)
+        }
+    }
+
+    pub fn eval_bar_rect(&self) -> Rect {
+        Rect::new(
+            self.board_width(),
+            0.0,
+            self.tile_size * EVAL_BAR_TILES,
+            self.board_height(),
+        )
+    }
+
+    pub fn analysis_line_rect(&self) -> Rect {
+        let eval_bar = self.eval_bar_rect();
+        let extra_lines = self.analysis_lines.saturating_sub(1) as f32;
+        let height =
+            self.tile_size * (ANALYSIS_LINE_TILES + ANALYSIS_EXTRA_LINE_TILES * extra_lines);
+        Rect::new(
+            eval_bar.x + eval_bar.w,
+            self.board_height() + self.label_margin - height,
+            self.panel_width - eval_bar.w,
+            height,
+        )
+    }
+
+    /// The text of the engine line box, inside its padding.
+    pub fn analysis_text_rect(&self) -> Rect {
+        let area = self.analysis_line_rect();
+        let padding = self.tile_size * ANALYSIS_PADDING_TILES;
+        Rect::new(
+            area.x + padding,
+            area.y + padding,
+            area.w - 2.0 * padding,
+            area.h - 2.0 * padding,
+        )
+    }
+
+    /// The row of the engine line box showing the `index`th best line when
+    /// there are several, under the row giving the depth.
+    pub fn analysis_row_rect(&self, index: usize) -> Rect {
+        let text = self.analysis_text_rect();
+        let row = self.tile_size * ANALYSIS_EXTRA_LINE_TILES;
+        Rect::new(text.x, text.y + row * (index + 1) as f32, text.w, row)
+    }
+
+    /// The `index`th of the buttons along the top of the engine line box,
+    /// counting from the right.
+    pub fn analysis_button_rect(&self, index: usize) -> Rect {
+        let text = self.analysis_text_rect();
+        let height = self.tile_size * ANALYSIS_EXTRA_LINE_TILES * 0.9;
+        let width = self.tile_size * ANALYSIS_BUTTON_TILES;
+        let gap = self.tile_size * ANALYSIS_PADDING_TILES / 2.0;
+        Rect::new(
+            text.x + text.w - (width + gap) * (index + 1) as f32 + gap,
+            text.y,
+            width,
+            height,
+        )
+    }
+
+    pub fn history_row_height(&self) -> f32 {
+        self.tile_size * HISTORY_ROW_TILES
+    }
+
+    /// The tray showing the pieces `captured_by` has taken: Black's above
+    /// the board and White's below it.
+    pub fn capture_tray_rect(&self, captured_by: PieceColor) -> Rect {
+        let y = match captured_by {
+            PieceColor::Black => -self.tray_height,
+            PieceColor::White => self.board_height() + self.label_margin,
+        };
+        Rect::new(0.0, y, self.board_width(), self.tray_height)
+    }
+
+    /// The strip above the move list holding the tabs of the open games.
+    pub fn tab_bar_rect(&self) -> Rect {
+        Rect::new(
+            self.board_width(),
+            -self.tray_height,
+            self.panel_width,
+            self.tray_height,
+        )
+    }
+
+    /// The strip below the move list, beside White's tray, for the series
+    /// score and the name of the opening.
+    pub fn status_bar_rect(&self) -> Rect {
+        Rect::new(
+            self.board_width(),
+            self.board_height() + self.label_margin,
+            self.panel_width,
+            self.tray_height,
+        )
+    }
+
+    pub fn clock_bar_rect(&self) -> Rect {
+        let (width, _) = self.window_size();
+        Rect::new(
+            -self.label_margin,
+            self.board_height() + self.label_margin + self.tray_height,
+            width,
+            self.clock_bar_height,
+        )
+    }
+}
@@ -0,0 +1,31 @@
+//! Rules engine for itsjustchess.
+//!
+//! Everything in here is independent of the ggez frontend so it can be reused
+//! by other frontends and exercised headlessly. [`Game`] is the place to
+//! start: it keeps a whole game, lists and checks legal moves, and says when
+//! and how the game ended.
+//!
+//! ```
+//! use itsjustchess::{Color, Game, GameStatus};
+//!
+//! let mut game = Game::new();
+//! for san in ["f3", "e5", "g4", "Qh4#"] {
+//!     game.play(san).unwrap();
+//! }
+//! assert_eq!(game.status(), GameStatus::Checkmate { winner: Color::Black });
+//! assert!(game.legal_moves().is_empty());
+//! println!("{}", game.fen());
+//! ```
+//!
+//! The types are also available under their original names from
+//! [`chess_core`], along with the lower-level pieces: single positions
+//! ([`GameState`]), FEN and PGN, the opening book and the AI.
+
+pub mod chess_core;
+#[cfg(target_arch = "wasm32")]
+pub mod web;
+
+pub use chess_core::{
+    ChessBoard as Board, FenError, Game, GameState, GameStatus, Move, MoveError, Piece,
+    PieceColor as Color, PieceType, Variant,
+};
@@ -0,0 +1,94 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// Writes log records to stderr and, with `--log-file`, to a file as well,
+/// each stamped with the seconds since startup and where it came from.
+struct Logger {
+    started: Instant,
+    terminal: LevelFilter,
+    file: Option<(Mutex<File>, LevelFilter)>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.terminal
+            || self
+                .file
+                .as_ref()
+                .is_some_and(|(_, level)| metadata.level() <= *level)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format!(
+            "{:>9.3} {:<5} {}: {}",
+            self.started.elapsed().as_secs_f64(),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        if record.level() <= self.terminal {
+            eprintln!("{line}");
+        }
+        if let Some((file, level)) = &self.file {
+            if record.level() <= *level {
+                if let Ok(mut file) = file.lock() {
+                    let _ = writeln!(file, "{line}");
+                }
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Some((file, _)) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+// The most detailed level shown for `verbosity`, the number of times
+// --verbose was given: warnings alone by default, then moves, FEN loads and
+// the AI's choices, then network messages and search progress, then
+// everything.
+fn level_for(verbosity: u8) -> LevelFilter {
+    match verbosity {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Starts logging at `verbosity` on the terminal. A `log_file` gets at
+/// least the debug records whatever the verbosity, so a bug report can
+/// include a full trace without flooding the terminal. Fails if the file
+/// can't be created.
+pub fn init(verbosity: u8, log_file: Option<&Path>) -> io::Result<()> {
+    let terminal = level_for(verbosity);
+    let file = match log_file {
+        Some(path) => Some((
+            Mutex::new(File::create(path)?),
+            terminal.max(LevelFilter::Debug),
+        )),
+        None => None,
+    };
+    let max_level = file.as_ref().map_or(terminal, |(_, level)| *level);
+    let logger = Logger {
+        started: Instant::now(),
+        terminal,
+        file,
+    };
+    // Only fails if a logger is already set, which can't happen here
+    let _ = log::set_boxed_logger(Box::new(logger));
+    log::set_max_level(max_level);
+    Ok(())
+}
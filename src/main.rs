@@ -1,12 +1,95 @@
 use ggez::conf::{WindowMode, WindowSetup};
-use ggez::event::{self, EventHandler, MouseButton};
-use ggez::graphics::{Canvas, Color, DrawMode, DrawParam, Mesh, Rect};
+use ggez::event::{self, MouseButton};
+use ggez::graphics::{
+    Canvas, Color, DrawMode, DrawParam, Image, ImageEncodingFormat, ImageFormat, Mesh, PxScale,
+    Rect, Text,
+};
+use ggez::input::keyboard::KeyMods;
 use ggez::{Context, ContextBuilder, GameError, GameResult};
 
-use clap::Parser;
-
+use clap::{Parser, ValueEnum};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::{Ref, RefCell};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use itsjustchess::chess_core::{
+    format_clock, format_moves, format_pgn_with_variations, parse_pgn, pgn_date_today, Analysis,
+    CastlingRights, ChessBoard, ChessClock, ClockMode, Difficulty, Endgame, GameState, GameStatus,
+    Move, MoveAnnotation, MoveQuality, Network, Notation, Odds, Opening, OpeningBook, OpeningNames,
+    PgnGame, PgnMove, Piece, PieceColor, PieceType, TimeControl, Variant, SCRAMBLE_POSITIONS,
+};
+
+mod accessibility;
+mod analysis;
+mod animation;
+mod audio;
+mod correspondence;
+mod database;
+mod editor;
+mod endgame_trainer;
+mod evals;
+mod explorer;
+mod game_info;
+mod headless;
+mod input_log;
+mod layout;
+mod logging;
+mod menu;
+mod network;
 mod pieces;
+mod recovery;
+mod render;
+mod repertoire;
+mod report;
+mod review;
+mod scene;
+mod series;
+mod server;
+mod settings;
+mod spectator;
+mod stats;
+mod stdin_moves;
+mod toast;
+mod touch;
+mod trainer;
+mod tui;
+mod uci;
+mod ui;
+use accessibility::{Announce, Announcer, BoardTheme};
+use analysis::{Analyser, DEFAULT_ANALYSIS_DEPTH};
+use animation::ReplayExport;
+use audio::{Sound, SoundEffects};
+use correspondence::Correspondence;
+use database::{Database, Duplicates};
+use editor::Editor;
+use endgame_trainer::{EndgameTrainer, Verdict};
+use evals::EvalCache;
+use explorer::{Explorer, MoveStats};
+use game_info::GameInfo;
+use headless::{Engine, MatchConfig, Sprt};
+use input_log::InputLog;
+use layout::Layout;
+use menu::NewGameMenu;
+use network::{Connection, Message, NetEvent};
 use pieces::Pieces;
+use render::{BoardView, GgezRenderer, Renderer};
+use repertoire::{DrillEvent, Repertoire, RepertoireDrill};
+use report::Report;
+use review::Reviewer;
+use scene::App;
+use series::Series;
+use server::Server;
+use settings::Settings;
+use spectator::{Source, Spectator, SpectatorEvent};
+use stats::{GameRecord, Opponent, Outcome, Stats};
+use stdin_moves::StdinMoves;
+use toast::Toasts;
+use tui::TuiConfig;
+use ui::{Align, Button, Label, ScrollList};
 
 /// Command-line arguments for the chess game.
 #[derive(Parser)]
@@ -17,158 +100,646 @@ struct Args {
     /// FEN string to initialize the game state
     #[arg(short, long)]
     fen: Option<String>,
-    /// Set the board size in pixels
-    #[arg(short, long, default_value = "800")]
-    board_size: f32,
-    /// Play against an AI opponent as white (EXPERIMENTAL)
+    /// Reject a --fen whose castling rights or en passant square don't fit the board, instead of dropping them
+    #[arg(long)]
+    strict_fen: bool,
+    /// Set the board size in pixels [default: 800, or the size last used]
+    #[arg(short, long)]
+    board_size: Option<f32>,
+    /// Play against an AI opponent as white (same as --ai-color black)
     #[arg(short, long, default_value = "false")]
     opponent: bool,
+    /// Which side(s) the AI plays; "both" lets two AIs play each other
+    #[arg(long, value_enum)]
+    ai_color: Option<AiColor>,
+    /// Minimum delay before each AI move, in milliseconds [default: 300, or the delay last used]
+    #[arg(long)]
+    ai_move_delay: Option<u64>,
+    /// How many plies the AI searches ahead [default: 3, or the depth last used]
+    #[arg(long)]
+    ai_depth: Option<u32>,
+    /// How well the AI plays, from 1 (beginner) to 8 (full strength); replaces --ai-depth
+    #[arg(long, conflicts_with = "ai_depth")]
+    difficulty: Option<Difficulty>,
+    /// Polyglot opening book for the AI to use instead of the built-in one
+    #[arg(long)]
+    book: Option<String>,
+    /// Evaluation network for the AI to use instead of the handcrafted evaluation
+    #[arg(long)]
+    nn: Option<String>,
+    /// Seed the AI's random choices (between book moves, and the blunders of the lower difficulties) to have it play the same way again, searching each level to its full depth however long that takes; each run's seed is logged with -v
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Play with clocks, e.g. "5+3" for five minutes plus three seconds per move, "5d3" for a
+    /// three-second delay before the clock runs or "5b3" for a three-second Bronstein delay
+    #[arg(short, long)]
+    time_control: Option<TimeControl>,
+    /// White's time control, if it differs from Black's
+    #[arg(long)]
+    time_white: Option<TimeControl>,
+    /// Black's time control, if it differs from White's
+    #[arg(long)]
+    time_black: Option<TimeControl>,
+    /// Play Armageddon: a draw counts as a win for Black, who has less time (5+0 against 4+0 unless set)
+    #[arg(long)]
+    armageddon: bool,
+    /// Rules to play by: standard, king-of-the-hill, three-check or antichess
+    #[arg(long, default_value = "standard")]
+    variant: Variant,
+    /// Start without a piece as a handicap for the stronger player: knight, rook or queen (given by White), or pawn-and-move (Black's f-pawn)
+    #[arg(long, conflicts_with_all = ["fen", "resume", "correspondence"])]
+    odds: Option<Odds>,
+    /// Where to draw the file and rank labels
+    #[arg(long, value_enum, default_value = "outside")]
+    coordinates: CoordinateLabels,
+    /// Piece images to use: a directory, or a set name under assets/pieces
+    #[arg(long)]
+    piece_set: Option<String>,
+    /// Announce each move in words: "text" prints it for a screen reader, "speech" also reads it out [default: off, or the mode last used]
+    #[arg(long)]
+    announce: Option<Announce>,
+    /// Use strongly contrasting board colours that stay distinct under colour blindness
+    #[arg(long)]
+    high_contrast: bool,
+    /// Mark each piece with its letter (K, Q, R, B, N, P)
+    #[arg(long)]
+    piece_letters: bool,
+    /// In a two-player game at one computer, turn the board round after every move so the side to move is at the bottom
+    #[arg(long)]
+    auto_flip: bool,
+    /// Only play a move once its destination is clicked a second time or Enter is pressed, to forgive misclicks
+    #[arg(long)]
+    confirm_moves: bool,
+    /// Outline your pieces the opponent attacks, in red where they hang, as a teaching aid (toggle with W)
+    #[arg(long)]
+    show_threats: bool,
+    /// Hide the pieces for memory training: "full" hides them all and "half" shows only your own (cycle with B, hold Space to peek)
+    #[arg(long, value_enum, default_value = "off")]
+    blindfold: Blindfold,
+    /// Start with sound effects turned off (toggle with A)
+    #[arg(long)]
+    mute: bool,
+    /// Sound effect volume from 0.0 to 1.0 [default: 1.0, or the volume last used]
+    #[arg(long)]
+    volume: Option<f32>,
+    /// Host a network game on this address, e.g. 0.0.0.0:5000 (the host plays White)
+    #[arg(long, conflicts_with = "connect")]
+    host: Option<String>,
+    /// Join a network game hosted at this address, e.g. 192.168.1.2:5000 (plays Black)
+    #[arg(long)]
+    connect: Option<String>,
+    /// Carry on a game adjourned with J from the file it was saved to (in a network game both players do, with --host or --connect as before)
+    #[arg(long, conflicts_with_all = ["fen", "edit", "watch"])]
+    resume: Option<String>,
+    /// Keep the series score against the AI from one session to the next, in the stats file
+    #[arg(long)]
+    keep_series: bool,
+    /// Keep the engine's evaluations from analysis and reviews from one session to the next, so positions seen before needn't be searched again
+    #[arg(long)]
+    keep_evals: bool,
+    /// Add the finished games in a PGN file to the opening explorer's database (O shows the explorer); may be given more than once
+    #[arg(long)]
+    explorer_import: Vec<PathBuf>,
+    /// Add the games in a PGN file to the games database, to search from the main menu; may be given more than once
+    #[arg(long)]
+    db_import: Vec<PathBuf>,
+    /// Drill the opening repertoire in a PGN file, its variations being the replies to be ready for, from the Positions menu; replaces any repertoire imported before
+    #[arg(long)]
+    repertoire_import: Option<PathBuf>,
+    /// What --db-import does with a game the database already has
+    #[arg(long, value_enum, default_value_t = Duplicates::Skip)]
+    db_duplicates: Duplicates,
+    /// Write a report on a player's games in the games database to a CSV file, after any --db-import: their score with each opening as White and as Black, the average game length, and the positions they blundered in most
+    #[arg(long, num_args = 2, value_names = ["PLAYER", "CSV"], conflicts_with_all = ["headless", "uci", "export_gif"])]
+    report: Option<Vec<String>>,
+    /// The event to name in the PGN [default: "Casual game"]
+    #[arg(long)]
+    event: Option<String>,
+    /// The site to name in the PGN [default: "itsjustchess"]
+    #[arg(long)]
+    site: Option<String>,
+    /// The round to name in the PGN [default: 1]
+    #[arg(long)]
+    round: Option<String>,
+    /// White's name in the PGN [default: "Human", or the AI and its level, e.g. "itsjustchess AI level 4"]
+    #[arg(long)]
+    white: Option<String>,
+    /// Black's name in the PGN [default: "Human", or the AI and its level]
+    #[arg(long)]
+    black: Option<String>,
+    /// White's rating, for the PGN's WhiteElo tag
+    #[arg(long)]
+    white_elo: Option<u32>,
+    /// Black's rating, for the PGN's BlackElo tag
+    #[arg(long)]
+    black_elo: Option<u32>,
+    /// Start in the position editor (also Ctrl+E), setting up from --fen if given
+    #[arg(long)]
+    edit: bool,
+    /// Watch a game instead of playing: "lichess-tv", or a PGN file to follow as moves are added to it
+    #[arg(long, conflicts_with_all = ["host", "connect", "edit", "opponent", "ai_color", "time_control"])]
+    watch: Option<Source>,
+    /// Start playing straight away instead of on the main menu (also skipped
+    /// for --fen, --edit, --watch and network games)
+    #[arg(long)]
+    no_menu: bool,
+    /// Replay the game in a PGN file into an animated GIF, or into numbered PNG frames in a directory if OUT doesn't end in .gif
+    #[arg(long, num_args = 2, value_names = ["PGN", "OUT"], conflicts_with_all = ["headless", "watch", "host", "connect", "resume"])]
+    export_gif: Option<Vec<PathBuf>>,
+    /// Play AI against AI without a window, printing each game's PGN
+    #[arg(long)]
+    headless: bool,
+    /// Number of games to play in headless mode, for each pairing of engines (at most, with --sprt)
+    #[arg(long, default_value = "1")]
+    games: u32,
+    /// Search depth for White in headless mode (defaults to --ai-depth)
+    #[arg(long)]
+    white_depth: Option<u32>,
+    /// Search depth for Black in headless mode (defaults to --ai-depth)
+    #[arg(long)]
+    black_depth: Option<u32>,
+    /// An AI for a headless tournament, as depth:N (the plain search) or level:N (a difficulty); give two or more, in place of --white-depth and --black-depth, and they swap colours every game
+    #[arg(long = "engine", conflicts_with_all = ["white_depth", "black_depth"])]
+    engines: Vec<Engine>,
+    /// Play the first --engine against each of the others instead of a round robin
+    #[arg(long)]
+    gauntlet: bool,
+    /// Stop each pairing once a sequential probability ratio test decides whether the first engine is ELO0 or ELO1 Elo stronger, e.g. "0,10"
+    #[arg(long, value_name = "ELO0,ELO1")]
+    sprt: Option<Sprt>,
+    /// Write each headless game to its own PGN file in this directory instead of printing it
+    #[arg(long)]
+    pgn_dir: Option<PathBuf>,
+    /// Run the AI as a UCI engine on stdin and stdout, for other chess GUIs, instead of opening a window
+    #[arg(long, conflicts_with_all = ["headless", "export_gif"])]
+    uci: bool,
+    /// Also play moves written to stdin in UCI notation (e.g. "e2e4 e7e5"), so a script can drive the board
+    #[arg(long, conflicts_with_all = ["uci", "headless", "export_gif", "watch"])]
+    stdin_moves: bool,
+    /// Where to play: in the window, or in the terminal, e.g. over SSH
+    #[arg(long, value_enum, default_value = "gui", conflicts_with_all = ["uci", "headless", "export_gif"])]
+    frontend: Frontend,
+    /// Print the board to stdout in Unicode after each move, to follow the game in a terminal
+    #[arg(long, conflicts_with_all = ["uci", "headless", "export_gif"])]
+    print_board: bool,
+    /// Serve the game over HTTP on this address, e.g. 127.0.0.1:8080, for other programs to get the FEN or PGN, play moves and follow them over a WebSocket. Playing a move needs the session's token, written to server.token in the config directory
+    #[arg(long, value_name = "ADDR", conflicts_with_all = ["uci", "headless", "export_gif"])]
+    serve: Option<String>,
+    /// Record the window's keyboard, mouse and touch input to this file, to play back with --replay-input
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["uci", "headless", "export_gif", "replay_input"])]
+    record_input: Option<PathBuf>,
+    /// Play input recorded with --record-input back into the window, ignoring its own, and quit where the recording did, e.g. to test the UI
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["uci", "headless", "export_gif"])]
+    replay_input: Option<PathBuf>,
+    /// Play a slow game kept in this PGN file: it is saved after every move and picked up from it at each launch, with the opponent's latest move highlighted
+    #[arg(long, value_name = "PGN", conflicts_with_all = ["fen", "resume", "edit", "watch", "host", "connect", "opponent", "ai_color", "time_control", "headless", "uci", "export_gif"])]
+    correspondence: Option<PathBuf>,
+    /// The side played at this board in a correspondence game
+    #[arg(long, value_enum, default_value = "white", requires = "correspondence")]
+    play_as: Side,
+    /// After each of your moves in a correspondence game, post the move, FEN and PGN as JSON to this URL, or run this shell command with them in CHESS_MOVE, CHESS_FEN and CHESS_PGN_FILE
+    #[arg(long, requires = "correspondence")]
+    notify: Option<String>,
+    /// Log more detail to stderr: moves and the AI's choices, then with -vv network messages and search progress, and with -vvv everything
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Also write the log to this file, always in at least -vv detail, to attach to a bug report
+    #[arg(long)]
+    log_file: Option<PathBuf>,
 }
 
-const BOARD_SIZE: usize = 8;
+/// The side played at this board in a correspondence game.
+#[derive(Copy, Clone, PartialEq, Debug, ValueEnum)]
+enum Side {
+    White,
+    Black,
+}
 
-#[derive(Copy, Clone, PartialEq, Debug)]
-enum PieceColor {
+#[derive(Copy, Clone, PartialEq, Debug, ValueEnum)]
+enum AiColor {
     White,
     Black,
+    Both,
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
-enum PieceType {
-    Pawn,
-    Knight,
-    Bishop,
-    Rook,
-    Queen,
-    King,
+impl AiColor {
+    fn plays(self, color: PieceColor) -> bool {
+        match self {
+            AiColor::White => color == PieceColor::White,
+            AiColor::Black => color == PieceColor::Black,
+            AiColor::Both => true,
+        }
+    }
+
+    // The AI's side in a rematch, with the colours swapped.
+    fn opposite(self) -> Self {
+        match self {
+            AiColor::White => AiColor::Black,
+            AiColor::Black => AiColor::White,
+            AiColor::Both => AiColor::Both,
+        }
+    }
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
-struct Piece {
-    piece_type: PieceType,
-    color: PieceColor,
-    has_moved: bool,
+/// Where the game is played.
+#[derive(Copy, Clone, PartialEq, Debug, ValueEnum)]
+enum Frontend {
+    /// The window
+    Gui,
+    /// The terminal, with Unicode pieces and the cursor keys
+    Tui,
 }
 
-#[derive(Copy, Clone, Debug)]
-struct Square {
-    occupant: Option<Piece>,
+/// Placement of the a-h / 1-8 coordinate labels.
+#[derive(Copy, Clone, PartialEq, Debug, ValueEnum)]
+enum CoordinateLabels {
+    /// In a margin to the left of and below the board
+    Outside,
+    /// In the corners of the edge squares, for compact windows
+    Inside,
+    /// No labels
+    Off,
 }
 
-struct ChessBoard {
-    squares: [[Square; BOARD_SIZE]; BOARD_SIZE],
+/// Training modes that hide pieces while the game goes on as normal.
+#[derive(Copy, Clone, PartialEq, Debug, ValueEnum)]
+enum Blindfold {
+    /// Every piece is shown
+    Off,
+    /// Only the empty board is shown
+    Full,
+    /// Only your own pieces are shown
+    Half,
 }
 
-impl ChessBoard {
-    fn empty() -> Self {
-        ChessBoard {
-            squares: [[Square { occupant: None }; BOARD_SIZE]; BOARD_SIZE],
+impl Blindfold {
+    fn next(self) -> Self {
+        match self {
+            Blindfold::Off => Blindfold::Full,
+            Blindfold::Full => Blindfold::Half,
+            Blindfold::Half => Blindfold::Off,
         }
     }
+}
+
+// Defaults for settings that are neither given as flags nor remembered
+const DEFAULT_BOARD_SIZE: f32 = 800.0;
+const DEFAULT_AI_DEPTH: u32 = 3;
+const DEFAULT_AI_MOVE_DELAY_MS: u64 = 300;
+
+// The window can't be made smaller than it would be with a board this size
+const MIN_BOARD_SIZE: f32 = 320.0;
+
+// Left edges of the white and black columns of the move list, in tiles
+const HISTORY_MOVE_COLUMNS: [f32; 2] = [0.75, 1.85];
+
+// Where the opening explorer's game counts and result bars start, in tiles
+// from the left of the panel
+const EXPLORER_COLUMNS: [f32; 2] = [0.75, 1.45];
+
+// How many half-moves of a variation the move list shows
+const VARIATION_PLIES: usize = 3;
+
+// How much of a comment the move list shows
+const COMMENT_CHARS: usize = 32;
+
+// How many moves of each line are shown when analysis shows several
+const ANALYSIS_LINE_MOVES: usize = 6;
+
+// The buttons of the engine line box in analysis mode, from the right: the
+// engine's best move, and the whole of its line, played on the board
+const ANALYSIS_BUTTONS: [&str; 2] = ["Line", "Best"];
+
+// How long each move of an engine line being played out stays on the board
+// before the next
+const ENGINE_LINE_DELAY: Duration = Duration::from_millis(600);
+
+// How long a hint arrow stays on the board
+const HINT_DURATION: Duration = Duration::from_secs(3);
+
+// The clocks in Armageddon when no time control is given: Black gets less
+// time in return for winning draws
+const ARMAGEDDON_TIME_CONTROLS: (TimeControl, TimeControl) = (
+    TimeControl {
+        base: Duration::from_secs(5 * 60),
+        increment: Duration::ZERO,
+        mode: ClockMode::Increment,
+    },
+    TimeControl {
+        base: Duration::from_secs(4 * 60),
+        increment: Duration::ZERO,
+        mode: ClockMode::Increment,
+    },
+);
+
+// The clocks in time-scramble practice: the player's, and the AI's, which
+// is long enough that it never loses on time while it searches
+const SCRAMBLE_TIME_CONTROLS: (TimeControl, TimeControl) = (
+    TimeControl {
+        base: Duration::from_secs(30),
+        increment: Duration::from_secs(1),
+        mode: ClockMode::Increment,
+    },
+    TimeControl {
+        base: Duration::from_secs(10 * 60),
+        increment: Duration::ZERO,
+        mode: ClockMode::Increment,
+    },
+);
+
+// The PGN tags an adjourned game keeps each side's remaining time in
+const CLOCK_TAGS: [(&str, PieceColor); 2] = [
+    ("WhiteClock", PieceColor::White),
+    ("BlackClock", PieceColor::Black),
+];
+
+// The names the players go under in saved games, unless named otherwise:
+// the AI's followed by its level when it plays at one
+const AI_PLAYER_NAME: &str = "itsjustchess AI";
+const HUMAN_PLAYER_NAME: &str = "Human";
+const REMOTE_PLAYER_NAME: &str = "Remote player";
+
+/// A game against another instance over the network.
+struct NetworkGame {
+    connection: Connection,
+    local_color: PieceColor,
+    is_host: bool,
+}
 
-    fn new_standard() -> Self {
-        let mut board = Self::empty();
+/// A move that has been played, together with its SAN for the move list/PGN.
+#[derive(Clone)]
+struct MoveRecord {
+    mv: Move,
+    san: String,
+    captured: Option<Piece>,
+    position: GameState, // The position after the move, for browsing the history
+    annotation: MoveAnnotation,
+}
 
-        // Place pawns
-        for file in 0..BOARD_SIZE {
-            board.squares[6][file].occupant = Some(Piece {
-                piece_type: PieceType::Pawn,
-                color: PieceColor::White,
-                has_moved: false,
-            });
-            board.squares[1][file].occupant = Some(Piece {
-                piece_type: PieceType::Pawn,
-                color: PieceColor::Black,
-                has_moved: false,
-            });
-        }
+/// A row of the move list: a move pair of the current line, by its index,
+/// the comment on one of its moves, by the half-moves before that one, or a
+/// variation leaving it, by its side line and the half-moves played before
+/// it leaves.
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum HistoryRow {
+    Moves(usize),
+    Comment(usize),
+    Variation(usize, usize),
+}
 
-        // Place back ranks with `has_moved` set to false
-        fn place_back_rank(row: usize, color: PieceColor, board: &mut ChessBoard) {
-            board.squares[row][0].occupant = Some(Piece {
-                piece_type: PieceType::Rook,
-                color,
-                has_moved: false,
-            });
-            board.squares[row][7].occupant = Some(Piece {
-                piece_type: PieceType::Rook,
-                color,
-                has_moved: false,
-            });
-            board.squares[row][1].occupant = Some(Piece {
-                piece_type: PieceType::Knight,
-                color,
-                has_moved: false,
-            });
-            board.squares[row][6].occupant = Some(Piece {
-                piece_type: PieceType::Knight,
-                color,
-                has_moved: false,
-            });
-            board.squares[row][2].occupant = Some(Piece {
-                piece_type: PieceType::Bishop,
-                color,
-                has_moved: false,
-            });
-            board.squares[row][5].occupant = Some(Piece {
-                piece_type: PieceType::Bishop,
-                color,
-                has_moved: false,
-            });
-            board.squares[row][3].occupant = Some(Piece {
-                piece_type: PieceType::Queen,
-                color,
-                has_moved: false,
-            });
-            board.squares[row][4].occupant = Some(Piece {
-                piece_type: PieceType::King,
-                color,
-                has_moved: false,
-            });
-        }
+/// Where the local player is in making a move.
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum MoveInput {
+    /// Picking a piece and where it goes (see `ChessGame::selected`)
+    Selecting,
+    /// A pawn has been moved to the last rank and the promotion piece is yet
+    /// to be chosen. Nothing has been played: the turn, clocks and position
+    /// only change once it is.
+    PendingPromotion(Move),
+    /// With --confirm-moves, a move has been picked and waits for its
+    /// destination to be clicked again or Enter before it is played
+    Confirming(Move),
+}
+
+/// A right-click drawing on the board: an arrow, or a circled square when
+/// `from` and `to` are the same.
+#[derive(Copy, Clone, PartialEq)]
+struct Annotation {
+    from: (usize, usize),
+    to: (usize, usize),
+    color: AnnotationColor,
+}
 
-        place_back_rank(0, PieceColor::Black, &mut board);
-        place_back_rank(7, PieceColor::White, &mut board);
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum AnnotationColor {
+    Green,
+    Red,
+    Blue,
+    Yellow,
+}
+
+impl AnnotationColor {
+    // Plain right-drag draws green; Shift, Alt or both pick the others
+    fn from_mods(mods: KeyMods) -> Self {
+        match (mods.contains(KeyMods::SHIFT), mods.contains(KeyMods::ALT)) {
+            (false, false) => AnnotationColor::Green,
+            (true, false) => AnnotationColor::Red,
+            (false, true) => AnnotationColor::Blue,
+            (true, true) => AnnotationColor::Yellow,
+        }
+    }
 
-        board
+    fn color(self) -> Color {
+        match self {
+            AnnotationColor::Green => Color::from_rgba(21, 120, 27, 200),
+            AnnotationColor::Red => Color::from_rgba(136, 32, 32, 200),
+            AnnotationColor::Blue => Color::from_rgba(0, 48, 136, 200),
+            AnnotationColor::Yellow => Color::from_rgba(230, 143, 0, 200),
+        }
     }
 }
 
+// The palette of the position editor: White's pieces, Black's, then the
+// eraser, four to a row
+const PALETTE_COLUMNS: usize = 4;
+const PALETTE_PIECES: [PieceType; 8] = [
+    PieceType::King,
+    PieceType::Queen,
+    PieceType::Chancellor,
+    PieceType::Archbishop,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+    PieceType::Pawn,
+];
+
+/// The controls below the editor's palette.
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum EditorButton {
+    Turn,
+    Castling(CastlingRights),
+    StartPosition,
+    Clear,
+    CopyFen,
+    Play,
+    Cancel,
+}
+
 struct ChessGame {
-    board: ChessBoard,
+    state: GameState,
+    initial_state: GameState,
+    history: Vec<MoveRecord>,
+    viewed_ply: Option<usize>, // Set while browsing back through the move list
+    side_lines: Vec<Vec<MoveRecord>>, // The other lines played, each from the start
+    history_scroll: usize,     // First move list row shown
+    position_history: Vec<String>, // Position keys for repetition detection
+    status: GameStatus,
     selected: Option<(usize, usize)>,
+    last_move: Option<((usize, usize), (usize, usize))>, // (from, to) of the latest move
     valid_moves: Vec<(usize, usize)>,
+    hovered: Option<(usize, usize)>, // The square under the pointer
+    panning: bool,                   // The middle button is dragging the view
+    legal_moves: RefCell<(Option<u64>, Vec<Move>)>, // Zobrist key of the position they are for, and the moves
     show_possible_moves: bool,
-    pieces: Pieces,
-    turn: PieceColor,
+    high_contrast: bool,
+    piece_letters: bool,
+    auto_flip: bool,     // Black at the bottom on Black's turn in a two-player game
+    confirm_moves: bool, // Moves wait in MoveInput::Confirming before being played
+    show_threats: bool,  // Outline the player's attacked and hanging pieces (W)
+    show_control: bool,  // Count each side's attackers on every square (X)
+    show_pawns: bool,    // Mark weak and passed pawns (K)
+    announcer: Announcer,
+    blindfold: Blindfold,
+    revealed: bool,     // Space is held to see through the blindfold
+    pieces: Rc<Pieces>, // Shared with the other tabs, as are the book, sounds and settings
     needs_redraw: bool,
-    castling_rights: String,
-    en_passant_target: Option<(usize, usize)>, // Square where en passant is possible
-    halfmove_clock: u32, // Number of halfmoves since the last capture or pawn move
-    fullmove_number: u32, // Fullmove count (increments after Black's turn)
-    has_ai_opponent: bool,
-    tile_size: f32,
-    promotion_square: Option<(usize, usize)>,
+    image_request: Option<bool>, // Ctrl+P waiting for the next frame; true for a plain board
+    ai_color: Option<AiColor>,
+    ai_depth: u32,
+    difficulty: Option<Difficulty>, // Overrides ai_depth for the AI's own moves
+    odds: Option<Odds>,             // The handicap new games start with
+    armageddon: bool,               // Draws count as wins for Black
+    book: Rc<OpeningBook>,
+    rng: StdRng,  // For the AI's random choices, seeded with --seed
+    seeded: bool, // --seed was given, so the AI searches by depth alone to replay exactly
+    openings: Rc<OpeningNames>,
+    ai_move_delay: Duration,
+    last_move_time: Instant,
+    layout: Layout,
+    coordinates: CoordinateLabels,
+    move_input: MoveInput,
+    clock: Option<ChessClock>,
+    last_clock_tick: Instant,
+    sounds: Rc<RefCell<SoundEffects>>,
+    toasts: Toasts,
+    network: Option<NetworkGame>,
+    analyser: Option<Analyser>,       // Running while analysis mode is on
+    search_overlay: Option<Analyser>, // Running while the search overlay (D) is on
+    explorer: Option<Explorer>,       // Loaded while the opening explorer (O) is shown
+    review: Option<Reviewer>,         // Set while reviewing a finished game
+    evals: Arc<Mutex<EvalCache>>,     // Searches done this session, shared by the tabs
+    hint: Option<(Move, Instant)>,    // Suggested move and when it was shown
+    annotations: Vec<Annotation>,
+    annotation_start: Option<(usize, usize)>, // Where a right-drag began
+    engine_line: Option<(u64, Vec<Move>)>,    // Position key and the engine line still to play
+    takeback_offer: Option<usize>,            // Ply the remote player asked to go back to
+    adjourn_offer: bool,                      // The remote player asked to adjourn
+    paused: bool,                             // Clocks stopped and board hidden (P)
+    adjourned: bool,                          // Saved to be finished another time (J)
+    autosave: bool,                           // Keeps the recovery file up to date
+    autosaved: bool,                          // The recovery file holds this game
+    last_autosave: Instant,                   // When the recovery file was last saved
+    series: Series,                           // Score against the same opponent this session
+    stats_entry: Option<usize>,               // This game's place in the stats, once over
+    keep_series: bool,                        // Series against the AI kept in the stats file
+    info: GameInfo,                           // Names the game and its players in the PGN
+    editor: Option<Editor>,                   // Set while setting up a position
+    endgame_trainer: Option<EndgameTrainer>,  // Set while converting a basic endgame
+    repertoire_drill: Option<RepertoireDrill>, // Set while drilling the opening repertoire
+    time_scramble: Option<usize>, // The SCRAMBLE_POSITIONS index while practising a time scramble
+    typed_move: Option<String>,   // Set while a move is being typed
+    typed_comment: Option<String>, // Set while a move's comment is being typed
+    spectator: Option<Spectator>, // Set when watching a game rather than playing
+    stdin_moves: Option<StdinMoves>, // Set with --stdin-moves
+    server: Option<Server>,       // Set with --serve
+    print_board: bool,            // Set with --print-board
+    correspondence: Option<Correspondence>, // Set with --correspondence
+    settings: Rc<RefCell<Settings>>,
+    stats: Rc<RefCell<Stats>>,
 }
 
 impl ChessGame {
-    fn new(has_ai_opponent: bool, tile_size: f32) -> GameResult<Self> {
-        let pieces = Pieces::new(); // Initialize the Pieces struct
+    fn new(
+        ai_color: Option<AiColor>,
+        ai_depth: u32,
+        ai_move_delay: Duration,
+        layout: Layout,
+        coordinates: CoordinateLabels,
+        clock: Option<ChessClock>,
+        sounds: Rc<RefCell<SoundEffects>>,
+    ) -> GameResult<Self> {
+        let pieces = Rc::new(Pieces::new()); // Initialize the Pieces struct
         Ok(Self {
-            board: ChessBoard::new_standard(),
+            state: GameState::new(),
+            initial_state: GameState::new(),
+            history: Vec::new(),
+            viewed_ply: None,
+            side_lines: Vec::new(),
+            history_scroll: 0,
+            position_history: vec![GameState::new().position_key()],
+            status: GameStatus::Ongoing,
             selected: None,
+            last_move: None,
             valid_moves: Vec::new(),
+            hovered: None,
+            panning: false,
+            legal_moves: RefCell::new((None, Vec::new())),
             show_possible_moves: true,
-            turn: PieceColor::White,
+            high_contrast: false,
+            piece_letters: false,
+            auto_flip: false,
+            confirm_moves: false,
+            show_threats: false,
+            show_control: false,
+            show_pawns: false,
+            announcer: Announcer::new(Announce::Off),
+            blindfold: Blindfold::Off,
+            revealed: false,
             needs_redraw: true,
-            castling_rights: "KQkq".to_string(),
-            en_passant_target: None,
-            halfmove_clock: 0,
-            fullmove_number: 1,
+            image_request: None,
             pieces,
-            has_ai_opponent,
-            tile_size,
-            promotion_square: None,
+            ai_color,
+            ai_depth,
+            difficulty: None,
+            odds: None,
+            armageddon: false,
+            book: Rc::new(OpeningBook::builtin()),
+            rng: StdRng::from_os_rng(),
+            seeded: false,
+            openings: Rc::new(OpeningNames::builtin()),
+            ai_move_delay,
+            last_move_time: Instant::now(),
+            layout,
+            coordinates,
+            move_input: MoveInput::Selecting,
+            clock,
+            last_clock_tick: Instant::now(),
+            sounds,
+            toasts: Toasts::default(),
+            network: None,
+            analyser: None,
+            search_overlay: None,
+            explorer: None,
+            review: None,
+            engine_line: None,
+            evals: Arc::new(Mutex::new(EvalCache::default())),
+            hint: None,
+            annotations: Vec::new(),
+            annotation_start: None,
+            takeback_offer: None,
+            adjourn_offer: false,
+            paused: false,
+            adjourned: false,
+            autosave: false,
+            autosaved: false,
+            last_autosave: Instant::now(),
+            series: Series::default(),
+            stats_entry: None,
+            keep_series: false,
+            info: GameInfo::default(),
+            editor: None,
+            endgame_trainer: None,
+            repertoire_drill: None,
+            time_scramble: None,
+            typed_move: None,
+            typed_comment: None,
+            spectator: None,
+            stdin_moves: None,
+            server: None,
+            print_board: false,
+            correspondence: None,
+            settings: Rc::new(RefCell::new(Settings::default())),
+            stats: Rc::new(RefCell::new(Stats::default())),
         })
     }
 
@@ -176,1214 +747,4355 @@ impl ChessGame {
         if x < 0.0 || y < 0.0 {
             return None;
         }
-        let col = (x / self.tile_size) as usize;
-        let row = (y / self.tile_size) as usize;
-        if row < BOARD_SIZE && col < BOARD_SIZE {
+        let col = (x / self.layout.tile_size) as usize;
+        let row = (y / self.layout.tile_size) as usize;
+        if row < self.layout.ranks && col < self.layout.files {
             Some((row, col))
         } else {
             None
         }
     }
 
-    // Checks if a move is valid based on piece type, turn, and rules.
-    fn validate_move(&self, start: (usize, usize), end: (usize, usize)) -> bool {
-        let (start_row, start_col) = start;
-        let (end_row, end_col) = end;
+    // Whether the board is drawn with Black at the bottom: in a two-player
+    // game at one computer with auto-flip on, while Black is to move.
+    fn board_flipped(&self) -> bool {
+        self.auto_flip
+            && self.state.turn == PieceColor::Black
+            && self.ai_color.is_none()
+            && self.network.is_none()
+            && self.spectator.is_none()
+            && self.editor.is_none()
+    }
 
-        // Ensure both squares are on the board
-        if start_row >= BOARD_SIZE
-            || start_col >= BOARD_SIZE
-            || end_row >= BOARD_SIZE
-            || end_col >= BOARD_SIZE
-        {
-            return false;
+    // Where `square` is drawn on the screen, as a (row, col), which is turned
+    // round while the board is flipped. Turning it round again puts it back,
+    // so this also takes a square on the screen to the one on the board.
+    fn screen_square(&self, (row, col): (usize, usize)) -> (usize, usize) {
+        if self.board_flipped() {
+            (self.layout.ranks - 1 - row, self.layout.files - 1 - col)
+        } else {
+            (row, col)
         }
+    }
 
-        let start_square = self.board.squares[start_row][start_col];
-        let end_square = self.board.squares[end_row][end_col];
-
-        let piece = match start_square.occupant {
-            Some(p) => p,
-            None => return false,
-        };
+    // The square under a point in board space, allowing for the flip.
+    fn square_at(&self, x: f32, y: f32) -> Option<(usize, usize)> {
+        self.coords_to_square(x, y)
+            .map(|square| self.screen_square(square))
+    }
 
-        // Ensure it's the correct player's turn
-        if piece.color != self.turn {
-            return false;
+    // The displayed position as the renderer is to draw it, with `board` in
+    // place of its pieces. The selected square is highlighted, as is the
+    // destination of a move waiting to be confirmed.
+    fn board_view<'a>(&self, board: &'a ChessBoard) -> BoardView<'a> {
+        let mut highlighted: Vec<(usize, usize)> = self.selected.into_iter().collect();
+        if let MoveInput::Confirming(mv) = self.move_input {
+            highlighted.push(mv.to);
         }
+        BoardView {
+            board,
+            flipped: self.board_flipped(),
+            last_move: self.displayed_last_move(),
+            highlighted,
+            targets: Vec::new(), // Marked by draw_move_targets instead
+            cursor: None,
+        }
+    }
 
-        // Ensure the end square is not occupied by a friendly piece
-        if let Some(occupant) = end_square.occupant {
-            if occupant.color == piece.color {
-                return false;
-            }
+    fn renderer<'a>(&'a self, ctx: &'a mut Context, canvas: &'a mut Canvas) -> GgezRenderer<'a> {
+        GgezRenderer {
+            ctx,
+            canvas,
+            pieces: &self.pieces,
+            theme: BoardTheme::get(self.high_contrast),
+            tile_size: self.layout.tile_size,
         }
+    }
 
-        // Validate movement based on piece type and return the result
-        let is_valid = match piece.piece_type {
-            PieceType::Pawn => self.validate_pawn_move(start, end, piece.color),
-            PieceType::Knight => self.validate_knight_move(start, end),
-            PieceType::Bishop => self.validate_bishop_move(start, end),
-            PieceType::Rook => self.validate_rook_move(start, end),
-            PieceType::Queen => self.validate_queen_move(start, end),
-            PieceType::King => self.validate_king_move(start, end),
-        };
+    // The rect `square` is drawn in, allowing for the flip.
+    fn square_rect(&self, square: (usize, usize)) -> Rect {
+        let (row, col) = self.screen_square(square);
+        let tile = self.layout.tile_size;
+        Rect::new(col as f32 * tile, row as f32 * tile, tile, tile)
+    }
 
-        // Simulate the move to ensure the king is not left in check
-        if is_valid {
-            let mut simulated_game = self.clone();
-            let piece = simulated_game.board.squares[start.0][start.1]
-                .occupant
-                .take()
-                .unwrap();
-            simulated_game.board.squares[end.0][end.1].occupant = Some(piece);
+    // The square under the pointer, if clicking it would pick up one of the
+    // mover's pieces or move the selected one there.
+    fn hover_target(&self) -> Option<(usize, usize)> {
+        let square = self.hovered?;
+        if self.viewed_ply.is_some()
+            || self.status.is_over()
+            || self.input_locked()
+            || self.editor.is_some()
+            || self.move_input != MoveInput::Selecting
+        {
+            return None;
+        }
+        let own_piece = self.state.board.squares[square.0][square.1]
+            .occupant
+            .is_some_and(|piece| piece.color == self.state.turn);
+        (own_piece || self.valid_moves.contains(&square)).then_some(square)
+    }
 
-            if simulated_game.is_king_in_check(self.turn) {
-                return false; // Move is invalid if it leaves the king in check
+    // Marks where the selected piece can go: a dot on an empty square, a
+    // ring round a piece it would capture.
+    fn draw_move_targets(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let Some(selected) = self.selected else {
+            return Ok(());
+        };
+        let color = Color::from(BoardTheme::get(self.high_contrast).possible_move);
+        let tile = self.layout.tile_size;
+        for &target in &self.valid_moves {
+            let rect = self.square_rect(target);
+            let centre = [rect.x + tile / 2.0, rect.y + tile / 2.0];
+            if Move::new(selected, target).is_capture(&self.state) {
+                ui::ring(ctx, canvas, centre, tile * 0.46, tile * 0.08, color)?;
+            } else {
+                ui::dot(ctx, canvas, centre, tile * 0.16, color)?;
             }
         }
-
-        is_valid
+        Ok(())
     }
 
-    fn validate_pawn_move(
-        &self,
-        start: (usize, usize),
-        end: (usize, usize),
-        color: PieceColor,
-    ) -> bool {
-        let (start_row, start_col) = start;
-        let (end_row, end_col) = end;
-
-        let direction = if color == PieceColor::White { -1 } else { 1 };
-        let start_row = start_row as isize;
-        let start_col = start_col as isize;
-        let end_row = end_row as isize;
-        let end_col = end_col as isize;
-
-        // One-square forward move
-        if end_row == start_row + direction && end_col == start_col {
-            return self.board.squares[end_row as usize][end_col as usize]
-                .occupant
-                .is_none();
+    // With threat highlighting on, outlines the player's pieces the other
+    // side attacks in the position shown, in red where they hang.
+    fn draw_threats(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let color = self.player_color();
+        if !self.piece_visible(color) {
+            return Ok(());
+        }
+        let theme = BoardTheme::get(self.high_contrast);
+        let tile = self.layout.tile_size;
+        for (square, hangs) in self.displayed_state().threatened_pieces(color) {
+            let outline = if hangs {
+                theme.hanging
+            } else {
+                theme.threatened
+            };
+            let rect = ui::inset(self.square_rect(square), tile * 0.03);
+            let mesh = Mesh::new_rectangle(
+                ctx,
+                DrawMode::stroke(tile * 0.06),
+                rect,
+                Color::from(outline),
+            )?;
+            canvas.draw(&mesh, DrawParam::default());
         }
+        Ok(())
+    }
 
-        // Two-square forward move (if pawn hasn't moved yet)
-        if end_row == start_row + 2 * direction && end_col == start_col {
-            if let Some(piece) = self.board.squares[start_row as usize][start_col as usize].occupant
-            {
-                if !piece.has_moved
-                    && self.board.squares[(start_row + direction) as usize][start_col as usize]
-                        .occupant
-                        .is_none()
-                    && self.board.squares[end_row as usize][end_col as usize]
-                        .occupant
-                        .is_none()
-                {
-                    return true;
+    // Marks each square with how many pieces of each side attack it, White's
+    // count in a white badge and Black's in a black one beside it. Nothing is
+    // shown while the blindfold hides any pieces, as the counts would give
+    // them away.
+    fn draw_square_control(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        if !self.piece_visible(PieceColor::White) || !self.piece_visible(PieceColor::Black) {
+            return Ok(());
+        }
+        let tile = self.layout.tile_size;
+        let displayed = self.displayed_state();
+        let sides = [
+            (PieceColor::White, 0.6, Color::WHITE, Color::BLACK),
+            (PieceColor::Black, 0.84, Color::BLACK, Color::WHITE),
+        ];
+        for (color, x, badge_color, text_color) in sides {
+            let control = displayed.square_control(color);
+            for (row, counts) in control.iter().enumerate().take(displayed.board.ranks) {
+                for (col, &count) in counts.iter().enumerate().take(displayed.board.files) {
+                    if count == 0 {
+                        continue;
+                    }
+                    let rect = self.square_rect((row, col));
+                    let centre = [rect.x + x * tile, rect.y + 0.16 * tile];
+                    ui::dot(ctx, canvas, centre, tile * 0.12, badge_color)?;
+
+                    let mut number = Text::new(count.to_string());
+                    number.set_scale(PxScale::from(tile * 0.18));
+                    let size = number.measure(ctx)?;
+                    let dest = [centre[0] - size.x / 2.0, centre[1] - size.y / 2.0];
+                    canvas.draw(&number, DrawParam::default().dest(dest).color(text_color));
                 }
             }
         }
+        Ok(())
+    }
 
-        // Diagonal capture
-        if end_row == start_row + direction
-            && (end_col == start_col + 1 || end_col == start_col - 1)
-        {
-            // Regular capture
-            if self.board.squares[end_row as usize][end_col as usize]
-                .occupant
-                .is_some()
-            {
-                return true;
+    // Badges each pawn with what stands out about its place in the pawn
+    // structure, down the left of its square: I for isolated, D doubled and B
+    // backward in orange, and P for passed in green.
+    fn draw_pawn_structure(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let tile = self.layout.tile_size;
+        let displayed = self.displayed_state();
+        for color in [PieceColor::White, PieceColor::Black] {
+            if !self.piece_visible(color) {
+                continue;
             }
-
-            // En passant capture
-            if let Some((target_row, target_col)) = self.en_passant_target {
-                if (end_row as usize, end_col as usize) == (target_row, target_col) {
-                    return true;
+            for pawn in displayed.pawn_structure(color) {
+                let weak = Color::from_rgb(215, 120, 20);
+                let marks = [
+                    (pawn.passed, "P", Color::from_rgb(40, 150, 60)),
+                    (pawn.isolated, "I", weak),
+                    (pawn.doubled, "D", weak),
+                    (pawn.backward, "B", weak),
+                ];
+                let rect = self.square_rect(pawn.square);
+                let shown = marks.into_iter().filter(|&(shown, _, _)| shown);
+                for (i, (_, mark, badge_color)) in shown.enumerate() {
+                    let centre = [
+                        rect.x + 0.16 * tile,
+                        rect.y + (0.84 - 0.22 * i as f32) * tile,
+                    ];
+                    ui::dot(ctx, canvas, centre, tile * 0.1, badge_color)?;
+
+                    let mut letter = Text::new(mark);
+                    letter.set_scale(PxScale::from(tile * 0.16));
+                    let size = letter.measure(ctx)?;
+                    let dest = [centre[0] - size.x / 2.0, centre[1] - size.y / 2.0];
+                    canvas.draw(&letter, DrawParam::default().dest(dest).color(Color::WHITE));
                 }
             }
         }
-
-        false
-    }
-
-    fn validate_knight_move(&self, start: (usize, usize), end: (usize, usize)) -> bool {
-        let (start_row, start_col) = start;
-        let (end_row, end_col) = end;
-        let row_diff = (start_row as isize - end_row as isize).abs();
-        let col_diff = (start_col as isize - end_col as isize).abs();
-
-        (row_diff == 2 && col_diff == 1) || (row_diff == 1 && col_diff == 2)
+        Ok(())
     }
 
-    fn validate_bishop_move(&self, start: (usize, usize), end: (usize, usize)) -> bool {
-        let (start_row, start_col) = start;
-        let (end_row, end_col) = end;
-
-        // Check if the move is diagonal
-        let row_diff = (start_row as isize - end_row as isize).abs();
-        let col_diff = (start_col as isize - end_col as isize).abs();
-
-        if row_diff == col_diff {
-            // Ensure no pieces block the path
-            self.path_is_clear(start, end)
-        } else {
-            false
+    // The legal moves in the current position. They are generated once per
+    // position, the first time they are needed after a move, and shared by
+    // the selection highlights and the checks on moves being played.
+    fn legal_moves(&self) -> Ref<'_, Vec<Move>> {
+        let key = self.state.zobrist_key();
+        if self.legal_moves.borrow().0 != Some(key) {
+            let moves = self.state.generate_valid_moves(self.state.turn);
+            *self.legal_moves.borrow_mut() = (Some(key), moves);
         }
+        Ref::map(self.legal_moves.borrow(), |(_, moves)| moves)
     }
 
-    fn validate_rook_move(&self, start: (usize, usize), end: (usize, usize)) -> bool {
-        let (start_row, start_col) = start;
-        let (end_row, end_col) = end;
-
-        // Check if the move is horizontal or vertical
-        if start_row == end_row || start_col == end_col {
-            self.path_is_clear(start, end)
-        } else {
-            false
-        }
+    fn is_legal(&self, from: (usize, usize), to: (usize, usize)) -> bool {
+        self.legal_moves()
+            .iter()
+            .any(|mv| mv.from == from && mv.to == to)
     }
 
-    fn validate_queen_move(&self, start: (usize, usize), end: (usize, usize)) -> bool {
-        self.validate_rook_move(start, end) || self.validate_bishop_move(start, end)
+    fn is_ai_turn(&self) -> bool {
+        self.ai_color
+            .is_some_and(|ai_color| ai_color.plays(self.state.turn))
     }
 
-    fn validate_king_move(&self, start: (usize, usize), end: (usize, usize)) -> bool {
-        let (start_row, start_col) = start;
-        let (end_row, end_col) = end;
-
-        // Check if the move is within one square
-        let row_diff = (start_row as isize - end_row as isize).abs();
-        let col_diff = (start_col as isize - end_col as isize).abs();
-
-        if row_diff <= 1 && col_diff <= 1 {
-            // Simulate the move
-            let mut simulated_game = self.clone();
-            let piece = simulated_game.board.squares[start_row][start_col]
-                .occupant
-                .take()
-                .unwrap();
-            simulated_game.board.squares[end_row][end_col].occupant = Some(piece);
-
-            if simulated_game.is_square_attacked((end_row, end_col), self.turn) {
-                return false; // Move is invalid if the king would be in check
-            }
-
-            return true;
+    // The side the local player is on, or the side to move when both sides
+    // are played at this board.
+    fn player_color(&self) -> PieceColor {
+        if let Some(net) = &self.network {
+            return net.local_color;
         }
-
-        // Check for castling
-        if self.validate_king_castling(start, end) {
-            return true;
+        if let Some(correspondence) = &self.correspondence {
+            return correspondence.local_color;
+        }
+        match self.ai_color {
+            Some(AiColor::White) => PieceColor::Black,
+            Some(AiColor::Black) => PieceColor::White,
+            Some(AiColor::Both) | None => self.state.turn,
         }
-
-        false
     }
 
-    fn validate_king_castling(&self, start: (usize, usize), end: (usize, usize)) -> bool {
-        let (start_row, start_col) = start;
-        let (end_row, end_col) = end;
-
-        // King-side castling
-        if start_row == end_row && (end_col as isize - start_col as isize).abs() == 2 {
-            let is_king_side = end_col > start_col;
-            let castling_right = if self.turn == PieceColor::White {
-                if is_king_side {
-                    "K"
-                } else {
-                    "Q"
-                }
-            } else {
-                if is_king_side {
-                    "k"
-                } else {
-                    "q"
-                }
-            };
-
-            if !self.castling_rights.contains(castling_right) {
-                return false;
-            }
-
-            // Ensure squares between king and rook are empty
-            let rook_col = if is_king_side { 7 } else { 0 };
-            let step = if is_king_side { 1 } else { -1 };
-
-            for col in (start_col as isize + step..end_col as isize).map(|c| c as usize) {
-                if self.board.squares[start_row][col].occupant.is_some() {
-                    return false;
-                }
-            }
-
-            // Ensure rook is in the correct position
-            if let Some(piece) = self.board.squares[start_row][rook_col].occupant {
-                if piece.piece_type == PieceType::Rook && piece.color == self.turn {
-                    return true;
-                }
-            }
+    // Whether pieces of `color` are drawn. The blindfold comes off while
+    // Space is held and once the game is over.
+    fn piece_visible(&self, color: PieceColor) -> bool {
+        if self.revealed || self.status.is_over() {
+            return true;
+        }
+        match self.blindfold {
+            Blindfold::Off => true,
+            Blindfold::Full => false,
+            Blindfold::Half => color == self.player_color(),
         }
-
-        false
     }
 
-    fn perform_castling(&mut self, start: (usize, usize), end: (usize, usize)) {
-        let (start_row, start_col) = start;
-        let is_king_side = end.1 > start_col;
-
-        // Move the rook
-        let rook_start_col = if is_king_side { 7 } else { 0 };
-        let rook_end_col = if is_king_side { end.1 - 1 } else { end.1 + 1 };
-
-        let rook = self.board.squares[start_row][rook_start_col]
-            .occupant
-            .take();
-        self.board.squares[start_row][rook_end_col].occupant = rook;
-    }
-
-    fn promote_pawn(&mut self, position: (usize, usize), new_piece_type: PieceType) {
-        let (row, col) = position;
-        if let Some(piece) = self.board.squares[row][col].occupant {
-            if piece.piece_type == PieceType::Pawn {
-                // Create a new piece with the promoted type
-                let promoted_piece = Piece {
-                    piece_type: new_piece_type,
-                    color: piece.color,
-                    has_moved: piece.has_moved,
-                };
-
-                // Replace the occupant with the promoted piece
-                self.board.squares[row][col].occupant = Some(promoted_piece);
-                self.needs_redraw = true;
-            } else {
-                println!("Error: Piece at {:?} is not a pawn!", position);
-            }
-        } else {
-            println!("Error: No piece found at {:?}", position);
+    fn cycle_blindfold(&mut self) {
+        self.blindfold = self.blindfold.next();
+        match self.blindfold {
+            Blindfold::Off => self.toasts.info("Blindfold off"),
+            Blindfold::Full => self
+                .toasts
+                .info("Blindfold on: all pieces hidden (hold Space to peek)"),
+            Blindfold::Half => self
+                .toasts
+                .info("Blindfold on: only your pieces shown (hold Space to peek)"),
         }
+        self.needs_redraw = true;
     }
 
-    fn path_is_clear(&self, start: (usize, usize), end: (usize, usize)) -> bool {
-        let (start_row, start_col) = start;
-        let (end_row, end_col) = end;
-
-        let row_step = (end_row as isize - start_row as isize).signum();
-        let col_step = (end_col as isize - start_col as isize).signum();
-
-        let mut current_row = start_row as isize + row_step;
-        let mut current_col = start_col as isize + col_step;
-
-        while current_row != end_row as isize || current_col != end_col as isize {
-            if self.board.squares[current_row as usize][current_col as usize]
-                .occupant
-                .is_some()
-            {
-                return false;
-            }
+    // Whether the local player is barred from moving: the AI or the remote
+    // or correspondence opponent is to move, the remote player is not
+    // connected or the game is paused.
+    fn input_locked(&self) -> bool {
+        self.is_ai_turn()
+            || self.paused
+            || self.spectator.is_some()
+            || self.network.as_ref().is_some_and(|net| {
+                !net.connection.is_connected() || net.local_color != self.state.turn
+            })
+            || self
+                .correspondence
+                .as_ref()
+                .is_some_and(|correspondence| correspondence.local_color != self.state.turn)
+    }
 
-            current_row += row_step;
-            current_col += col_step;
+    fn send_to_opponent(&self, message: Message) {
+        if let Some(net) = &self.network {
+            net.connection.send(message);
         }
-
-        true
     }
 
-    fn update_castling_rights(&mut self, start: (usize, usize)) {
-        // Remove castling rights if king moves
-        if let Some(piece) = self.board.squares[start.0][start.1].occupant {
-            if piece.piece_type == PieceType::King {
-                if piece.color == PieceColor::White {
-                    self.castling_rights = self.castling_rights.replace("K", "").replace("Q", "");
-                } else {
-                    self.castling_rights = self.castling_rights.replace("k", "").replace("q", "");
+    // Handles everything that arrived from the remote player since last frame.
+    fn poll_network(&mut self) {
+        while let Some(event) = self.network.as_mut().and_then(|net| net.connection.poll()) {
+            match event {
+                NetEvent::Connected => {
+                    self.toasts.info("Opponent connected");
+                    if self.network.as_ref().is_some_and(|net| net.is_host) {
+                        self.send_to_opponent(Message::Sync {
+                            fen: self.initial_state.to_fen(),
+                            moves: self
+                                .history
+                                .iter()
+                                .map(|r| r.mv.to_uci_on(&self.state.board))
+                                .collect(),
+                        });
+                    }
                 }
-            }
-            // Remove castling rights if rook moves
-            if piece.piece_type == PieceType::Rook {
-                if piece.color == PieceColor::White {
-                    if start == (7, 0) {
-                        self.castling_rights = self.castling_rights.replace("Q", "");
-                    } else if start == (7, 7) {
-                        self.castling_rights = self.castling_rights.replace("K", "");
+                NetEvent::Disconnected => self
+                    .toasts
+                    .info("Opponent disconnected; waiting for them to return"),
+                NetEvent::Received(Message::Move(uci)) => {
+                    let remote_turn = self
+                        .network
+                        .as_ref()
+                        .is_some_and(|net| net.local_color != self.state.turn);
+                    match Move::from_uci_on(&uci, &self.state.board) {
+                        Some(mv) if remote_turn && self.state.is_legal_move(mv) => {
+                            self.apply_move(mv);
+                        }
+                        _ => self
+                            .toasts
+                            .error(format!("Ignoring illegal move from opponent: {uci}")),
                     }
-                } else {
-                    if start == (0, 0) {
-                        self.castling_rights = self.castling_rights.replace("q", "");
-                    } else if start == (0, 7) {
-                        self.castling_rights = self.castling_rights.replace("k", "");
+                }
+                NetEvent::Received(Message::NewGame) => self.new_game(),
+                NetEvent::Received(Message::Rematch) => self.rematch(),
+                NetEvent::Received(Message::Sync { fen, moves }) => {
+                    self.sync_with_host(&fen, &moves)
+                }
+                NetEvent::Received(Message::TakebackOffer(ply)) => {
+                    if ply < self.history.len() {
+                        self.takeback_offer = Some(ply);
+                    }
+                }
+                NetEvent::Received(Message::TakebackAccept(ply)) => {
+                    self.toasts.info("Takeback accepted");
+                    self.take_back_to(ply);
+                }
+                NetEvent::Received(Message::TakebackDecline) => {
+                    self.toasts.info("Takeback declined")
+                }
+                NetEvent::Received(Message::Pause) => self.set_paused(true),
+                NetEvent::Received(Message::Resume) => self.set_paused(false),
+                NetEvent::Received(Message::AdjournOffer) => {
+                    if !self.adjourned && !self.status.is_over() {
+                        self.adjourn_offer = true;
+                    }
+                }
+                NetEvent::Received(Message::AdjournAccept(clocks)) => {
+                    self.toasts.info("Adjournment accepted");
+                    if let (Some(clock), Some((white, black))) = (&mut self.clock, clocks) {
+                        clock.set_remaining(PieceColor::White, white);
+                        clock.set_remaining(PieceColor::Black, black);
                     }
+                    self.adjourn();
+                }
+                NetEvent::Received(Message::AdjournDecline) => {
+                    self.toasts.info("Adjournment declined")
                 }
             }
+            self.needs_redraw = true;
         }
     }
 
-    fn is_king_in_check(&self, color: PieceColor) -> bool {
-        let (king_row, king_col) = self.find_king(color).unwrap();
-
-        for row in 0..BOARD_SIZE {
-            for col in 0..BOARD_SIZE {
-                if let Some(piece) = self.board.squares[row][col].occupant {
-                    if piece.color != color && self.validate_move((row, col), (king_row, king_col))
-                    {
-                        return true;
-                    }
+    // Plays a move in UCI notation from another program, for whoever could
+    // type a move now, or says why it can't be played. The AI's and the
+    // remote player's moves are refused, as are any while the game waits for
+    // an answer or is over.
+    fn play_external_move(&mut self, uci: &str) -> Result<(), String> {
+        if !self.can_type_move() {
+            return Err(format!("No move can be played now: {uci}"));
+        }
+        match Move::from_uci_on(uci, &self.state.board) {
+            Some(mv) if self.state.is_legal_move(mv) => {
+                if self.viewed_ply.is_some() {
+                    self.view_ply(self.history.len());
                 }
+                self.typed_move = None;
+                self.apply_move(mv);
+                self.send_to_opponent(Message::Move(uci.to_string()));
+                Ok(())
             }
+            _ => Err(format!("Illegal move: {uci}")),
         }
-
-        false
     }
 
-    fn find_king(&self, color: PieceColor) -> Option<(usize, usize)> {
-        for row in 0..BOARD_SIZE {
-            for col in 0..BOARD_SIZE {
-                if let Some(piece) = self.board.squares[row][col].occupant {
-                    if piece.piece_type == PieceType::King && piece.color == color {
-                        return Some((row, col));
-                    }
-                }
+    // Plays the moves written to stdin since the last frame.
+    fn poll_stdin_moves(&mut self) {
+        let Some(stdin_moves) = &self.stdin_moves else {
+            return;
+        };
+        let moves: Vec<String> = std::iter::from_fn(|| stdin_moves.poll()).collect();
+        for uci in moves {
+            if let Err(err) = self.play_external_move(&uci) {
+                self.toasts
+                    .error(format!("Ignoring move from stdin. {err}"));
             }
         }
-        None
     }
 
-    fn is_checkmate(&self, color: PieceColor) -> bool {
-        for row in 0..BOARD_SIZE {
-            for col in 0..BOARD_SIZE {
-                if let Some(piece) = self.board.squares[row][col].occupant {
-                    if piece.color == color {
-                        for target_row in 0..BOARD_SIZE {
-                            for target_col in 0..BOARD_SIZE {
-                                if self.validate_move((row, col), (target_row, target_col)) {
-                                    // Clone the game to simulate the move
-                                    let mut cloned_game = self.clone();
-                                    cloned_game.board.squares[target_row][target_col].occupant =
-                                        cloned_game.board.squares[row][col].occupant.take();
-                                    if !cloned_game.is_king_in_check(color) {
-                                        return false;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+    // Plays the moves submitted to the server since the last frame, telling
+    // each client whether its move was played.
+    fn poll_server(&mut self) {
+        let Some(server) = &self.server else {
+            return;
+        };
+        let requests: Vec<_> = std::iter::from_fn(|| server.poll()).collect();
+        for request in requests {
+            let result = self.play_external_move(&request.uci);
+            request.answer(result);
         }
-        true
     }
 
-    fn calculate_positional_value(
-        &self,
-        start: (usize, usize),
-        end: (usize, usize),
-        moving_piece: Piece,
-    ) -> i32 {
-        let (start_row, start_col) = start;
-        let (end_row, end_col) = end;
-
-        match moving_piece.piece_type {
-            PieceType::Pawn => {
-                let direction = if moving_piece.color == PieceColor::White {
-                    -1
-                } else {
-                    1
-                };
-                let advancement = (end_row as isize - start_row as isize) * direction;
-                let central_bonus = if end_col == 3 || end_col == 4 { 1 } else { 0 };
-                advancement as i32 + central_bonus
-            }
-            PieceType::Knight => {
-                if (end_row == 3 || end_row == 4) && (end_col == 3 || end_col == 4) {
-                    2
-                } else {
-                    0
-                }
-            }
-            PieceType::Bishop => {
-                let open_diagonal_bonus = if self.is_diagonal_open((end_row, end_col)) {
-                    2
-                } else {
-                    0
+    // Saves a correspondence game after a move or takeback, and if `moved`
+    // by the local player lets the opponent know it is their turn.
+    fn save_correspondence(&self, moved: bool) {
+        let Some(correspondence) = &self.correspondence else {
+            return;
+        };
+        if let Err(e) = correspondence.save(&self.to_pgn()) {
+            let path = correspondence.path.display();
+            self.toasts
+                .error(format!("Failed to save the game to {path}: {e}"));
+            return;
+        }
+        let local_moved = self.state.turn != correspondence.local_color;
+        if let Some(last) = self.history.last().filter(|_| moved && local_moved) {
+            correspondence.notify(&last.san, &self.state.to_fen());
+        }
+    }
+
+    // Says whose move it is in a correspondence game just opened, and what
+    // the opponent played since it was last open.
+    fn greet_correspondence(&self) {
+        let Some(correspondence) = &self.correspondence else {
+            return;
+        };
+        if self.status.is_over() {
+            return;
+        }
+        match self.history.last() {
+            Some(last) if self.state.turn == correspondence.local_color => {
+                // Black's moves are numbered before the count goes up
+                let (number, dots) = match self.state.turn {
+                    PieceColor::White => (self.state.fullmove_number.saturating_sub(1), "..."),
+                    PieceColor::Black => (self.state.fullmove_number, "."),
                 };
-                open_diagonal_bonus
-            }
-            PieceType::Rook => {
-                let open_file_bonus = if self.is_file_open(end_col) { 3 } else { 0 };
-                open_file_bonus
-            }
-            PieceType::Queen => {
-                if (end_row == 3 || end_row == 4) && (end_col == 3 || end_col == 4) {
-                    1
-                } else {
-                    0
-                }
+                self.toasts
+                    .info(format!("Your opponent played {number}{dots} {}", last.san));
             }
-            PieceType::King => {
-                let safety_penalty =
-                    if self.is_square_attacked((end_row, end_col), moving_piece.color) {
-                        -10
-                    } else {
-                        0
-                    };
-                safety_penalty
+            _ if self.state.turn != correspondence.local_color => {
+                self.toasts.info("Waiting for your opponent's move")
             }
+            _ => self.toasts.info("Your move"),
         }
     }
 
-    fn is_file_open(&self, file: usize) -> bool {
-        for row in 0..BOARD_SIZE {
-            if self.board.squares[row][file].occupant.is_some() {
-                return false;
-            }
+    // Tells the server the position has changed: by the move just recorded
+    // if `moved`, otherwise by being replaced.
+    fn serve_position(&self, moved: bool) {
+        let Some(server) = &self.server else {
+            return;
+        };
+        let (fen, pgn) = (self.state.to_fen(), self.to_pgn());
+        match self.history.last().filter(|_| moved) {
+            Some(record) => server.publish_move(
+                &record.mv.to_uci_on(&self.state.board),
+                &record.san,
+                fen,
+                pgn,
+            ),
+            None => server.publish_position(fen, pgn),
         }
-        true
     }
 
-    fn is_square_attacked(&self, square: (usize, usize), color: PieceColor) -> bool {
-        let (row, col) = square;
-
-        for r in 0..BOARD_SIZE {
-            for c in 0..BOARD_SIZE {
-                if let Some(piece) = self.board.squares[r][c].occupant {
-                    // Check if the piece belongs to the opponent
-                    if piece.color != color {
-                        match piece.piece_type {
-                            PieceType::Pawn => {
-                                // Pawns attack diagonally
-                                let direction = if piece.color == PieceColor::White {
-                                    -1
-                                } else {
-                                    1
-                                };
-                                let attack_positions = [
-                                    (r as isize + direction, c as isize - 1),
-                                    (r as isize + direction, c as isize + 1),
-                                ];
-                                for &(ar, ac) in &attack_positions {
-                                    if ar == row as isize && ac == col as isize {
-                                        return true;
-                                    }
-                                }
-                            }
-                            PieceType::Knight => {
-                                // Knights have a fixed attack pattern
-                                let knight_moves = [
-                                    (-2, -1),
-                                    (-2, 1),
-                                    (2, -1),
-                                    (2, 1),
-                                    (-1, -2),
-                                    (-1, 2),
-                                    (1, -2),
-                                    (1, 2),
-                                ];
-                                for &(dr, dc) in &knight_moves {
-                                    if r as isize + dr == row as isize
-                                        && c as isize + dc == col as isize
-                                    {
-                                        return true;
-                                    }
-                                }
-                            }
-                            PieceType::Bishop => {
-                                // Bishops attack diagonally
-                                if (row as isize - r as isize).abs()
-                                    == (col as isize - c as isize).abs()
-                                    && self.path_is_clear((r, c), (row, col))
-                                {
-                                    return true;
-                                }
-                            }
-                            PieceType::Rook => {
-                                // Rooks attack in straight lines
-                                if (r == row || c == col) && self.path_is_clear((r, c), (row, col))
-                                {
-                                    return true;
-                                }
-                            }
-                            PieceType::Queen => {
-                                // Queens attack both like rooks and bishops
-                                if ((row as isize - r as isize).abs()
-                                    == (col as isize - c as isize).abs()
-                                    || r == row
-                                    || c == col)
-                                    && self.path_is_clear((r, c), (row, col))
-                                {
-                                    return true;
-                                }
-                            }
-                            PieceType::King => {
-                                // Kings attack adjacent squares
-                                let row_diff = (row as isize - r as isize).abs();
-                                let col_diff = (col as isize - c as isize).abs();
-                                if row_diff <= 1 && col_diff <= 1 {
-                                    return true;
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+    // Prints the board to stdout for --print-board, headed by the move just
+    // played if `moved`, or else as a new position.
+    fn print_position(&self, moved: bool) {
+        if !self.print_board {
+            return;
         }
-
-        false
+        match self.history.last().filter(|_| moved) {
+            // The move number has already gone up if Black moved
+            Some(record) => match self.state.turn {
+                PieceColor::Black => println!("{}. {}", self.state.fullmove_number, record.san),
+                PieceColor::White => println!(
+                    "{}... {}",
+                    self.state.fullmove_number.saturating_sub(1),
+                    record.san
+                ),
+            },
+            None => println!("{}", self.state.to_fen()),
+        }
+        println!("{}\n", self.state.board.to_text(self.board_flipped()));
     }
 
-    fn is_diagonal_open(&self, square: (usize, usize)) -> bool {
-        let (row, col) = square;
-
-        for i in 1..BOARD_SIZE {
-            let positions = [
-                (row as isize - i as isize, col as isize - i as isize),
-                (row as isize - i as isize, col as isize + i as isize),
-                (row as isize + i as isize, col as isize - i as isize),
-                (row as isize + i as isize, col as isize + i as isize),
-            ];
+    // Brings a client in line with the host after (re)connecting. If the host
+    // is only missing our latest moves they are sent again; otherwise the
+    // host's game replaces ours.
+    fn sync_with_host(&mut self, fen: &str, moves: &[String]) {
+        let ours: Vec<String> = self
+            .history
+            .iter()
+            .map(|r| r.mv.to_uci_on(&self.state.board))
+            .collect();
+        if fen == self.initial_state.to_fen() && ours.starts_with(moves) {
+            for uci in &ours[moves.len()..] {
+                self.send_to_opponent(Message::Move(uci.clone()));
+            }
+            return;
+        }
 
-            for &(r, c) in &positions {
-                if r >= 0 && r < BOARD_SIZE as isize && c >= 0 && c < BOARD_SIZE as isize {
-                    if self.board.squares[r as usize][c as usize]
-                        .occupant
-                        .is_some()
-                    {
-                        return false;
-                    }
+        let mut initial = GameState::with_variant(self.state.variant);
+        if let Err(err) = initial.from_fen(fen) {
+            self.toasts
+                .error(format!("Failed to sync with host: {err}"));
+            return;
+        }
+        self.reset_to(initial);
+        for uci in moves {
+            match Move::from_uci_on(uci, &self.state.board) {
+                Some(mv) if self.state.is_legal_move(mv) => self.apply_move(mv),
+                _ => {
+                    self.toasts
+                        .error(format!("Failed to sync with host: illegal move {uci}"));
+                    break;
                 }
             }
         }
-        true
     }
 
-    fn generate_valid_moves(&self, color: PieceColor) -> Vec<((usize, usize), (usize, usize))> {
-        let mut valid_moves = Vec::new();
-
-        for row in 0..BOARD_SIZE {
-            for col in 0..BOARD_SIZE {
-                if let Some(piece) = self.board.squares[row][col].occupant {
-                    if piece.color == color {
-                        for target_row in 0..BOARD_SIZE {
-                            for target_col in 0..BOARD_SIZE {
-                                if self.validate_move((row, col), (target_row, target_col)) {
-                                    valid_moves.push(((row, col), (target_row, target_col)));
-                                }
-                            }
+    // Shows whatever has happened in the watched game since last frame.
+    fn poll_spectator(&mut self, ctx: &mut Context) {
+        while let Some(event) = self.spectator.as_ref().and_then(Spectator::poll) {
+            match event {
+                SpectatorEvent::Game {
+                    white,
+                    black,
+                    start,
+                    moves,
+                } => {
+                    if let Some(spectator) = &mut self.spectator {
+                        if spectator.players.as_ref() != Some(&(white.clone(), black.clone())) {
+                            self.toasts.info(format!("Watching {white} vs {black}"));
+                            ctx.gfx.set_window_title(&format!("{white} vs {black}"));
+                            spectator.players = Some((white, black));
+                        }
+                    }
+                    self.follow_game(*start, &moves);
+                }
+                SpectatorEvent::Move { mv, fen } => {
+                    match mv {
+                        Some(mv) if self.state.is_legal_move(mv) => self.apply_move(mv),
+                        _ => {}
+                    }
+                    // Out of step with the feed: show its position as it is
+                    let placement = |fen: &str| fen.split(' ').next().map(str::to_string);
+                    if placement(&self.state.to_fen()) != placement(&fen) {
+                        let mut state = GameState::with_variant(self.state.variant);
+                        match state.from_fen(&fen) {
+                            Ok(()) => self.reset_to(state),
+                            Err(err) => self
+                                .toasts
+                                .error(format!("Ignoring position from the feed: {err}")),
                         }
                     }
                 }
             }
+            self.needs_redraw = true;
         }
-
-        valid_moves
     }
 
-    fn score_move(&self, start: (usize, usize), end: (usize, usize)) -> i32 {
-        let moving_piece = self.board.squares[start.0][start.1].occupant.unwrap();
-
-        // Value of the captured piece
-        let capture_value = if let Some(piece) = self.board.squares[end.0][end.1].occupant {
-            match piece.piece_type {
-                PieceType::Pawn => 1,
-                PieceType::Knight | PieceType::Bishop => 3,
-                PieceType::Rook => 5,
-                PieceType::Queen => 9,
-                PieceType::King => 1000, // Capturing the king is effectively checkmate
-            }
+    // Shows the watched game `moves` into from `start`: only the new moves
+    // are played if it carries on from the board, otherwise it is replayed.
+    fn follow_game(&mut self, start: GameState, moves: &[Move]) {
+        let ours: Vec<String> = self
+            .history
+            .iter()
+            .map(|r| r.mv.to_uci_on(&self.state.board))
+            .collect();
+        let theirs: Vec<String> = moves.iter().map(|mv| mv.to_uci_on(&start.board)).collect();
+        let played = if start.to_fen() == self.initial_state.to_fen() && theirs.starts_with(&ours) {
+            ours.len()
         } else {
+            self.reset_to(start);
             0
         };
+        for &mv in &moves[played..] {
+            self.apply_move(mv);
+        }
+    }
 
-        // Value of the moving piece
-        let moving_piece_value = if let Some(piece) = self.board.squares[start.0][start.1].occupant
-        {
-            match piece.piece_type {
-                PieceType::Pawn => 1,
-                PieceType::Knight | PieceType::Bishop => 3,
-                PieceType::Rook => 5,
-                PieceType::Queen => 9,
-                PieceType::King => 1000,
+    fn ai_turn(&mut self) -> bool {
+        let mv = match self.difficulty {
+            // The endgame trainer defends as well as it can
+            _ if self.endgame_trainer.is_some() => self.state.endgame_move(),
+            // The repertoire drill answers from the line being drilled
+            _ if self.repertoire_drill.is_some() => self
+                .repertoire_drill
+                .as_ref()
+                .and_then(|drill| drill.reply(self.history.len())),
+            Some(difficulty) => self.state.choose_move_at(
+                difficulty,
+                Some(self.book.as_ref()),
+                !self.seeded,
+                &mut self.rng,
+            ),
+            None => {
+                self.state
+                    .choose_ai_move(self.ai_depth, Some(self.book.as_ref()), &mut self.rng)
             }
-        } else {
-            0 // This should never happen for a valid move
         };
-
-        // Penalize unnecessary king moves
-        let king_penalty = if moving_piece.piece_type == PieceType::King {
-            -5 // Arbitrary penalty value for king moves
+        if let Some(mv) = mv {
+            // Charge the thinking time to the AI before the turn passes
+            self.tick_clock();
+            if self.status.is_over() {
+                return false;
+            }
+            self.apply_move(mv);
+            self.send_to_opponent(Message::Move(mv.to_uci_on(&self.state.board)));
+            true
         } else {
-            0
-        };
+            false // No valid moves, AI loses
+        }
+    }
 
-        // Reward developing pieces
-        let development_bonus = match moving_piece.piece_type {
-            PieceType::Knight | PieceType::Bishop if start.0 == 0 || start.0 == 7 => 3,
-            PieceType::Pawn if (end.0 == 2 || end.0 == 5) => 1, // Central pawn push
-            _ => 0,
+    // Plays `mv` for the side to move and records it. All the rules (captures
+    // including en passant, castling, promotion, castling rights, clocks and
+    // the turn) are handled by GameState::make_move, so human and AI moves go
+    // through the same path.
+    fn apply_move(&mut self, mv: Move) {
+        let san = mv.to_san(&self.state);
+        let mut spoken = if self.announcer.mode() == Announce::Off {
+            String::new()
+        } else {
+            accessibility::spoken_move(&self.state, mv)
         };
+        let piece = self.state.board.squares[mv.from.0][mv.from.1].occupant;
+        let is_pawn = mv.is_pawn_move(&self.state);
+        let is_king = piece.is_some_and(|p| p.piece_type == PieceType::King);
+        let is_capture = mv.is_capture(&self.state);
+        let mover = self.state.turn;
+        log::info!(
+            "{:?} played {san} ({})",
+            mover,
+            mv.to_uci_on(&self.state.board)
+        );
+
+        let undo = self.state.make_move(mv);
+        self.record_move(mv, san, undo.captured());
+        if !spoken.is_empty() {
+            if self.status.is_over() {
+                spoken.push_str(&format!(". {}", self.status.description()));
+            }
+            self.announcer.announce(&spoken);
+        }
 
-        let positional_value = self.calculate_positional_value(start, end, moving_piece);
-
-        capture_value + moving_piece_value + king_penalty + development_bonus + positional_value
+        let sound = if self.status.is_over() {
+            Sound::GameEnd
+        } else if self.state.is_king_in_check(self.state.turn) {
+            Sound::Check
+        } else if is_pawn && self.state.board.is_back_rank(mv.to.0) {
+            Sound::Promotion
+        } else if is_king && mv.from.1.abs_diff(mv.to.1) > 1 {
+            Sound::Castle
+        } else if is_capture {
+            Sound::Capture
+        } else {
+            Sound::Move
+        };
+        self.sounds.borrow_mut().queue(sound);
+        self.selected = None;
+        self.valid_moves.clear();
+        self.needs_redraw = true;
+        if mover == PieceColor::White {
+            self.judge_endgame_move();
+        }
+        self.judge_repertoire_move(mv);
     }
 
-    fn choose_ai_move(&self) -> Option<((usize, usize), (usize, usize))> {
-        let valid_moves = self.generate_valid_moves(self.turn);
+    // Starts the endgame trainer on a random position of `endgame`, with the
+    // player as White against the tablebase.
+    fn start_endgame_trainer(&mut self, endgame: Endgame) {
+        let (trainer, position) = EndgameTrainer::start(endgame, &mut self.rng);
+        log::info!("Endgame trainer set up {endgame}: {}", position.to_fen());
+        self.ai_color = Some(AiColor::Black);
+        self.reset_to(position);
+        self.toasts.info(format!(
+            "{endgame}: mate within {} moves against the best defence",
+            trainer.budget
+        ));
+        self.endgame_trainer = Some(trainer);
+    }
 
-        // Evaluate moves, prioritizing non-king moves and strategic positions
-        valid_moves
-            .iter()
-            .map(|&(start, end)| (start, end, self.score_move(start, end)))
-            .max_by_key(|&(_, _, score)| score) // Choose the move with the highest score
-            .map(|(start, end, _)| (start, end)) // Return only the move, not the score
+    // Starts drilling the repertoire saved by --repertoire-import, from the
+    // line most due, with the AI playing the other side's moves from it.
+    fn start_repertoire_drill(&mut self) {
+        let Some(repertoire) = Repertoire::load() else {
+            self.toasts
+                .info("No repertoire to drill; import one with --repertoire-import");
+            return;
+        };
+        let color = repertoire.color;
+        let due = repertoire.due();
+        self.start_drill_line(RepertoireDrill::start(repertoire));
+        self.toasts.info(format!(
+            "Drilling your repertoire as {color:?}: {due} lines due today"
+        ));
     }
 
-    fn ai_turn(&mut self) -> bool {
-        if let Some((start, end)) = self.choose_ai_move() {
-            let mut piece = self.board.squares[start.0][start.1]
-                .occupant
-                .take()
-                .unwrap();
-            piece.has_moved = true;
-            self.board.squares[end.0][end.1].occupant = Some(piece);
-
-            // Update turn
-            self.turn = match self.turn {
-                PieceColor::White => PieceColor::Black,
-                PieceColor::Black => PieceColor::White,
-            };
+    fn start_drill_line(&mut self, (drill, start): (RepertoireDrill, GameState)) {
+        self.ai_color = Some(match drill.repertoire.color {
+            PieceColor::White => AiColor::Black,
+            PieceColor::Black => AiColor::White,
+        });
+        self.reset_to(start);
+        self.repertoire_drill = Some(drill);
+    }
 
-            self.needs_redraw = true;
-            true
-        } else {
-            false // No valid moves, AI loses
+    // Starts time-scramble practice: sharp middlegames against the AI with
+    // next to no time on the player's clock.
+    fn start_time_scramble(&mut self, ctx: &mut Context) {
+        if self.clock.is_none() {
+            self.layout.set_show_clocks(true);
+            let (width, height) = ctx.gfx.drawable_size();
+            self.layout.fit(width, height);
         }
+        self.next_time_scramble();
     }
 
-    fn to_fen(&self) -> String {
-        let mut fen = String::new();
-
-        // Convert board to FEN
-        for row in (0..BOARD_SIZE).rev() {
-            let mut empty_count = 0;
+    // Sets up a random one of the scramble positions, the player taking a
+    // random side of it.
+    fn next_time_scramble(&mut self) {
+        let index = self.rng.random_range(0..SCRAMBLE_POSITIONS.len());
+        let position = &SCRAMBLE_POSITIONS[index];
+        let mut state = GameState::new();
+        if let Err(err) = state.from_fen(position.fen) {
+            log::error!("Failed to set up the {} scramble: {err}", position.name);
+            return;
+        }
+        let player = if self.rng.random_bool(0.5) {
+            PieceColor::White
+        } else {
+            PieceColor::Black
+        };
+        let (mine, ai) = SCRAMBLE_TIME_CONTROLS;
+        let (white, black, ai_color) = match player {
+            PieceColor::White => (mine, ai, AiColor::Black),
+            PieceColor::Black => (ai, mine, AiColor::White),
+        };
+        self.clock = Some(ChessClock::asymmetric(white, black));
+        self.ai_color = Some(ai_color);
+        self.reset_to(state);
+        self.time_scramble = Some(index);
+        self.toasts.info(format!(
+            "{}: {}. You have {}s as {player:?}",
+            position.name,
+            position.about,
+            mine.base.as_secs()
+        ));
+    }
 
-            for col in 0..BOARD_SIZE {
-                if let Some(piece) = self.board.squares[row][col].occupant {
-                    if empty_count > 0 {
-                        fen.push_str(&empty_count.to_string());
-                        empty_count = 0;
-                    }
-                    fen.push(piece_to_fen_char(piece));
+    // Checks the latest move against the repertoire line being drilled. A
+    // move off the line is taken back, with the one the repertoire has
+    // shown; a line played to its end is scheduled again and the next one
+    // begins.
+    fn judge_repertoire_move(&mut self, mv: Move) {
+        let Some(ply) = self.history.len().checked_sub(1) else {
+            return;
+        };
+        let Some(drill) = &mut self.repertoire_drill else {
+            return;
+        };
+        match drill.judge(ply, mv) {
+            Some(DrillEvent::Deviation { expected, known }) => {
+                self.take_back_to(ply);
+                let expected = expected.to_san(&self.state);
+                if known {
+                    self.toasts.info(format!(
+                        "That's another line of your repertoire; this one plays {expected}"
+                    ));
                 } else {
-                    empty_count += 1;
+                    self.toasts
+                        .error(format!("Off the repertoire: it plays {expected} here"));
                 }
             }
-
-            if empty_count > 0 {
-                fen.push_str(&empty_count.to_string());
+            Some(DrillEvent::Finished { missed, days }) => {
+                let again = match days {
+                    0 => "again today".to_string(),
+                    1 => "tomorrow".to_string(),
+                    days => format!("in {days} days"),
+                };
+                let how = if missed { "with a slip" } else { "perfectly" };
+                self.toasts
+                    .info(format!("Line played {how}; it comes back {again}"));
+                if let Some(mut drill) = self.repertoire_drill.take() {
+                    let start = drill.next();
+                    self.start_drill_line((drill, start));
+                }
             }
+            None => {}
+        }
+    }
 
-            if row > 0 {
-                fen.push('/');
-            }
+    // Tells the player at once when their move in the endgame trainer gave
+    // away the win or the chance to mate within the budget, or when it mated.
+    fn judge_endgame_move(&mut self) {
+        let played = self.history.len().div_ceil(2) as u32;
+        let Some(trainer) = &mut self.endgame_trainer else {
+            return;
+        };
+        match trainer.judge(&self.state, self.status, played) {
+            Some(Verdict::Mated) => self.toasts.info(format!(
+                "Mated in {played} moves, within the {} allowed",
+                trainer.budget
+            )),
+            Some(Verdict::ThrownAway) => self
+                .toasts
+                .error("That throws away the win: it's a draw now (T takes the move back)"),
+            Some(Verdict::TooSlow) => self.toasts.error(format!(
+                "Still winning, but mate can't come within {} moves now (T takes the move back)",
+                trainer.budget
+            )),
+            Some(Verdict::OnTrack) | None => {}
         }
+    }
 
-        // Add active color
-        fen.push(' ');
-        fen.push(if self.turn == PieceColor::White {
-            'w'
-        } else {
-            'b'
+    // Adds a played move to the history and re-evaluates the game status; call
+    // after the move has been applied.
+    fn record_move(&mut self, mv: Move, san: String, captured: Option<Piece>) {
+        self.last_move = Some((mv.from, mv.to));
+        self.hint = None;
+        self.annotations.clear();
+        self.last_move_time = Instant::now();
+        if let Some(clock) = &mut self.clock {
+            clock.end_move(self.state.turn.opposite());
+        }
+        self.position_history.push(self.state.position_key());
+        self.update_status();
+        self.history.push(MoveRecord {
+            mv,
+            san,
+            captured,
+            position: self.state.clone(),
+            annotation: MoveAnnotation::default(),
         });
+        if self.viewed_ply.is_none() {
+            self.scroll_to_ply(self.history.len());
+        }
+        self.autosave();
+        self.serve_position(true);
+        self.print_position(true);
+        self.save_correspondence(true);
+        self.follow_side_line();
+    }
 
-        // Add castling rights
-        fen.push(' ');
-        if self.castling_rights.is_empty() {
-            fen.push('-');
-        } else {
-            fen.push_str(&self.castling_rights);
+    // Plays the pending promotion with the chosen piece.
+    fn finish_promotion(&mut self, piece_type: PieceType) {
+        if let MoveInput::PendingPromotion(mut mv) = self.move_input {
+            self.move_input = MoveInput::Selecting;
+            mv.promotion = Some(piece_type);
+            self.apply_move(mv);
+            self.send_to_opponent(Message::Move(mv.to_uci_on(&self.state.board)));
         }
+    }
 
-        // Add en passant target square
-        fen.push(' ');
-        if let Some((row, col)) = self.en_passant_target {
-            fen.push_str(&square_to_algebraic(row, col));
+    // Plays a move picked on the board, first asking for the promotion piece
+    // if a pawn reaches the last rank.
+    fn play_clicked_move(&mut self, mv: Move) {
+        self.selected = None;
+        self.valid_moves.clear();
+        if mv.is_pawn_move(&self.state) && self.state.board.is_back_rank(mv.to.0) {
+            // Nothing is played until the piece has been chosen
+            self.move_input = MoveInput::PendingPromotion(mv);
+            self.needs_redraw = true;
         } else {
-            fen.push('-');
+            self.move_input = MoveInput::Selecting;
+            self.apply_move(mv);
+            self.send_to_opponent(Message::Move(mv.to_uci_on(&self.state.board)));
         }
+    }
 
-        // Add halfmove clock and fullmove number
-        fen.push(' ');
-        fen.push_str(&self.halfmove_clock.to_string());
-        fen.push(' ');
-        fen.push_str(&self.fullmove_number.to_string());
+    // Plays the move waiting to be confirmed, unless the game ended (on time)
+    // in the meantime.
+    fn confirm_move(&mut self) {
+        if let MoveInput::Confirming(mv) = self.move_input {
+            if self.status.is_over() {
+                self.cancel_pending_move();
+            } else {
+                self.play_clicked_move(mv);
+            }
+        }
+    }
 
-        fen
+    // Drops a move waiting for its promotion piece or to be confirmed.
+    fn cancel_pending_move(&mut self) {
+        self.move_input = MoveInput::Selecting;
+        self.selected = None;
+        self.valid_moves.clear();
+        self.needs_redraw = true;
     }
 
-    fn from_fen(&mut self, fen: &str) -> Result<(), String> {
-        let parts: Vec<&str> = fen.split_whitespace().collect();
-        if parts.len() < 6 {
-            return Err("Invalid FEN: Missing fields".to_string());
-        }
+    // Whether the player may type a move: on their turn, with nothing else
+    // waiting for an answer.
+    fn can_type_move(&self) -> bool {
+        self.editor.is_none()
+            && !self.status.is_over()
+            && !self.input_locked()
+            && self.takeback_offer.is_none()
+            && self.move_input == MoveInput::Selecting
+    }
 
-        // Parse board layout
-        let rows: Vec<&str> = parts[0].split('/').collect();
-        if rows.len() != BOARD_SIZE {
-            return Err("Invalid FEN: Incorrect number of rows".to_string());
+    // The legal moves that what has been typed so far could become, with
+    // their SAN. Either SAN or UCI may be typed.
+    fn typed_move_candidates(&self) -> Vec<(Move, String)> {
+        let typed = self.typed_move.as_deref().unwrap_or("");
+        // Castling may be typed with zeros and promotions without "="
+        let typed_san = typed.replace('0', "O").replace('=', "");
+        let typed_uci = typed.to_ascii_lowercase();
+        let mut candidates = Vec::new();
+        for &mv in self.legal_moves().iter() {
+            let is_promotion = self.state.board.squares[mv.from.0][mv.from.1]
+                .occupant
+                .is_some_and(|p| p.piece_type == PieceType::Pawn)
+                && self.state.board.is_back_rank(mv.to.0);
+            let promotions = if is_promotion {
+                self.state
+                    .promotion_pieces()
+                    .into_iter()
+                    .map(Some)
+                    .collect()
+            } else {
+                vec![None]
+            };
+            for promotion in promotions {
+                let mv = Move { promotion, ..mv };
+                let san = mv.to_san(&self.state);
+                if san.replace('=', "").starts_with(&typed_san)
+                    || mv.to_uci_on(&self.state.board).starts_with(&typed_uci)
+                {
+                    candidates.push((mv, san));
+                }
+            }
         }
+        candidates
+    }
 
-        for (row, row_data) in rows.iter().rev().enumerate() {
-            let mut col = 0;
-            for ch in row_data.chars() {
-                if ch.is_digit(10) {
-                    let empty_count = ch.to_digit(10).unwrap() as usize;
-                    for _ in 0..empty_count {
-                        self.board.squares[row][col] = Square { occupant: None };
-                        col += 1;
+    // Handles a key while a move is being typed: Enter plays it, Tab
+    // completes as much as the legal moves agree on, Backspace deletes and
+    // Escape gives up.
+    fn typed_move_key(&mut self, key: ggez::input::keyboard::KeyCode) {
+        let Some(typed) = &mut self.typed_move else {
+            return;
+        };
+        match key {
+            ggez::input::keyboard::KeyCode::Back => {
+                typed.pop();
+            }
+            ggez::input::keyboard::KeyCode::Escape => self.typed_move = None,
+            ggez::input::keyboard::KeyCode::Tab => {
+                let candidates = self.typed_move_candidates();
+                if let Some((_, first)) = candidates.first() {
+                    let common = candidates.iter().fold(first.as_str(), |common, (_, san)| {
+                        let len = common
+                            .chars()
+                            .zip(san.chars())
+                            .take_while(|(a, b)| a == b)
+                            .count();
+                        &common[..len]
+                    });
+                    let typed = self.typed_move.get_or_insert_with(String::new);
+                    if common.len() > typed.len() {
+                        *typed = common.to_string();
                     }
-                } else {
-                    let piece = char_to_piece(ch)
-                        .ok_or_else(|| format!("Invalid FEN: Unknown piece '{ch}'"))?;
-                    self.board.squares[row][col] = Square {
-                        occupant: Some(piece),
-                    };
-                    col += 1;
                 }
             }
+            ggez::input::keyboard::KeyCode::Return
+            | ggez::input::keyboard::KeyCode::NumpadEnter => self.submit_typed_move(),
+            _ => {}
+        }
+        self.needs_redraw = true;
+    }
 
-            if col != BOARD_SIZE {
-                return Err("Invalid FEN: Row length mismatch".to_string());
+    // Plays the typed move if it is legal, or the only legal move it could
+    // be the start of.
+    fn submit_typed_move(&mut self) {
+        let typed = self.typed_move.clone().unwrap_or_default();
+        let candidates = self.typed_move_candidates();
+        let exact = Move::from_san(&typed, &self.state).or_else(|| {
+            // A UCI promotion without a piece queens, as make_move does
+            let mv = Move::from_uci_on(&typed.to_ascii_lowercase(), &self.state.board)?;
+            let promotion = mv.promotion.or(Some(PieceType::Queen));
+            candidates
+                .iter()
+                .map(|&(candidate, _)| candidate)
+                .find(|candidate| {
+                    (candidate.from, candidate.to) == (mv.from, mv.to)
+                        && (candidate.promotion.is_none() || candidate.promotion == promotion)
+                })
+        });
+        let mv = match (exact, candidates.as_slice()) {
+            (Some(mv), _) => mv,
+            (None, [(only, _)]) => *only,
+            (None, []) => {
+                self.toasts.info(format!("Not a legal move: {typed}"));
+                return;
+            }
+            (None, _) => {
+                self.toasts.info(format!("Ambiguous move: {typed}"));
+                return;
             }
+        };
+
+        self.typed_move = None;
+        if self.viewed_ply.is_some() {
+            self.view_ply(self.history.len());
         }
+        self.apply_move(mv);
+        self.send_to_opponent(Message::Move(mv.to_uci_on(&self.state.board)));
+    }
 
-        // Parse active color
-        self.turn = match parts[1] {
-            "w" => PieceColor::White,
-            "b" => PieceColor::Black,
-            _ => return Err("Invalid FEN: Invalid active color".to_string()),
-        };
+    // Where each promotion choice is drawn: a row of tiles on the promotion
+    // rank, one for each piece the position allows, centred on the pawn's
+    // file but kept on the board.
+    fn promotion_choice_rects(&self, mv: Move) -> Vec<(PieceType, Rect)> {
+        let tile = self.layout.tile_size;
+        let choices = self.state.promotion_pieces();
+        let total_width = tile * choices.len() as f32;
+        let (row, col) = self.screen_square(mv.to);
+        let room = (self.layout.board_width() - total_width).max(0.0);
+        let left = ((col as f32 + 0.5) * tile - total_width / 2.0).clamp(0.0, room);
+        choices
+            .into_iter()
+            .enumerate()
+            .map(|(i, piece_type)| {
+                let rect = Rect::new(left + i as f32 * tile, row as f32 * tile, tile, tile);
+                (piece_type, rect)
+            })
+            .collect()
+    }
 
-        // Parse castling rights
-        self.castling_rights = parts[2].to_string();
+    // The position on the board: a past one while browsing the move list,
+    // otherwise the live game.
+    fn displayed_state(&self) -> &GameState {
+        if let Some(editor) = &self.editor {
+            return editor.position();
+        }
+        match self.viewed_ply {
+            Some(0) => &self.initial_state,
+            Some(ply) => &self.history[ply - 1].position,
+            None => &self.state,
+        }
+    }
 
-        // Parse en passant target square
-        self.en_passant_target = if parts[3] == "-" {
-            None
+    // The move that led to the displayed position, if any.
+    fn displayed_last_move(&self) -> Option<((usize, usize), (usize, usize))> {
+        if self.editor.is_some() {
+            return None;
+        }
+        match self.viewed_ply {
+            Some(0) => None,
+            Some(ply) => {
+                let mv = self.history[ply - 1].mv;
+                Some((mv.from, mv.to))
+            }
+            None => self.last_move,
+        }
+    }
+
+    // Shows the position after `ply` half-moves; the latest one returns to
+    // the live game.
+    fn view_ply(&mut self, ply: usize) {
+        self.viewed_ply = if ply < self.history.len() {
+            Some(ply)
         } else {
-            algebraic_to_square(parts[3])
+            None
         };
+        self.scroll_to_ply(ply);
+        self.selected = None;
+        self.valid_moves.clear();
+        self.needs_redraw = true;
+    }
 
-        // Parse halfmove clock
-        self.halfmove_clock = parts[4]
-            .parse()
-            .map_err(|_| "Invalid FEN: Invalid halfmove clock".to_string())?;
-
-        // Parse fullmove number
-        self.fullmove_number = parts[5]
-            .parse()
-            .map_err(|_| "Invalid FEN: Invalid fullmove number".to_string())?;
+    // Games set up with Black to move leave the first move list cell empty.
+    fn history_cell_offset(&self) -> usize {
+        if self.initial_state.turn == PieceColor::Black {
+            1
+        } else {
+            0
+        }
+    }
 
-        Ok(())
+    // A row for each move pair, followed by the comments on its moves and
+    // the variations leaving at either of them. Variations carrying on from
+    // the last move come last.
+    fn history_rows(&self) -> Vec<HistoryRow> {
+        let offset = self.history_cell_offset();
+        let pairs = (self.history.len() + offset).div_ceil(2);
+        let mut variations = self.variations().into_iter().peekable();
+        let mut rows = Vec::new();
+        for pair in 0..pairs {
+            rows.push(HistoryRow::Moves(pair));
+            let plies = (pair * 2).saturating_sub(offset)..(pair * 2 + 2 - offset);
+            rows.extend(
+                plies
+                    .filter(|&ply| {
+                        self.history
+                            .get(ply)
+                            .is_some_and(|record| !record.annotation.comment.is_empty())
+                    })
+                    .map(HistoryRow::Comment),
+            );
+            while let Some((index, ply)) =
+                variations.next_if(|&(_, ply)| (ply + offset) / 2 <= pair)
+            {
+                rows.push(HistoryRow::Variation(index, ply));
+            }
+        }
+        rows.extend(variations.map(|(index, ply)| HistoryRow::Variation(index, ply)));
+        rows
     }
-}
 
-fn square_to_algebraic(row: usize, col: usize) -> String {
-    let file = (b'a' + col as u8) as char;
-    let rank = (8 - row) as u8;
-    format!("{file}{rank}")
-}
+    // The comment on the `ply`th move (counting from 0) as the move list
+    // shows it, cut short if it is long
+    fn comment_text(&self, ply: usize) -> String {
+        let comment = &self.history[ply].annotation.comment;
+        match comment.char_indices().nth(COMMENT_CHARS) {
+            Some((end, _)) => format!("{{{}...}}", &comment[..end]),
+            None => format!("{{{comment}}}"),
+        }
+    }
 
-fn algebraic_to_square(pos: &str) -> Option<(usize, usize)> {
-    if pos.len() != 2 {
-        return None;
+    // A variation as the move list shows it, e.g. "(12... Nf6 13. Bg5 Be7 ...)"
+    fn variation_text(&self, index: usize, ply: usize) -> String {
+        let side = &self.side_lines[index];
+        let start = match ply {
+            0 => &self.initial_state,
+            _ => &side[ply - 1].position,
+        };
+        let moves: Vec<Move> = side[ply..]
+            .iter()
+            .take(VARIATION_PLIES)
+            .map(|record| record.mv)
+            .collect();
+        let more = if side.len() - ply > VARIATION_PLIES {
+            " ..."
+        } else {
+            ""
+        };
+        format!("({}{more})", format_moves(start, &moves, Notation::San))
     }
-    let file = pos.chars().nth(0)?;
-    let rank = pos.chars().nth(1)?.to_digit(10)?;
 
-    let col = (file as u8).wrapping_sub(b'a') as usize;
-    let row = 8 - rank as usize;
+    // The move list, one row per move pair and variation.
+    fn history_list(&self) -> ScrollList {
+        ScrollList {
+            area: self.layout.history_panel_rect(),
+            row_height: self.layout.history_row_height(),
+            len: self.history_rows().len(),
+            offset: self.history_scroll,
+        }
+    }
 
-    if col < BOARD_SIZE && row < BOARD_SIZE {
-        Some((row, col))
-    } else {
-        None
+    fn scroll_history(&mut self, rows: isize) {
+        self.history_scroll = self.history_list().scrolled(rows);
+        self.needs_redraw = true;
     }
-}
 
-fn char_to_piece(ch: char) -> Option<Piece> {
-    let color = if ch.is_uppercase() {
-        PieceColor::White
-    } else {
-        PieceColor::Black
-    };
+    // Scrolls the move list just far enough to show the row holding the
+    // `ply`th move.
+    fn scroll_to_ply(&mut self, ply: usize) {
+        if ply == 0 {
+            return;
+        }
+        let pair = (ply - 1 + self.history_cell_offset()) / 2;
+        let rows = self.history_rows();
+        let row = rows
+            .iter()
+            .position(|&row| row == HistoryRow::Moves(pair))
+            .unwrap_or(0);
+        self.history_scroll = self.history_list().scrolled_to(row);
+    }
 
-    let piece_type = match ch.to_ascii_lowercase() {
-        'p' => PieceType::Pawn,
-        'n' => PieceType::Knight,
-        'b' => PieceType::Bishop,
-        'r' => PieceType::Rook,
-        'q' => PieceType::Queen,
-        'k' => PieceType::King,
-        _ => return None,
-    };
+    // Jumps to the position after the move clicked in the move list, or
+    // commented on, or switches to the variation clicked, at its first move.
+    fn history_click(&mut self, x: f32, y: f32) {
+        if self.explorer.is_some() {
+            return;
+        }
+        let panel = self.layout.history_panel_rect();
+        let Some(row) = self.history_list().row_at(y) else {
+            return;
+        };
+        let pair = match self.history_rows().get(row) {
+            Some(&HistoryRow::Moves(pair)) => pair,
+            Some(&HistoryRow::Comment(ply)) => {
+                self.view_ply(ply + 1);
+                return;
+            }
+            Some(&HistoryRow::Variation(index, ply)) => {
+                self.switch_line(index, ply + 1);
+                return;
+            }
+            None => return,
+        };
+        let column = if x - panel.x < HISTORY_MOVE_COLUMNS[1] * self.layout.tile_size {
+            0
+        } else {
+            1
+        };
+        let cell = pair * 2 + column;
+        if let Some(ply) = cell.checked_sub(self.history_cell_offset()) {
+            if ply < self.history.len() {
+                self.view_ply(ply + 1);
+            }
+        }
+    }
 
-    Some(Piece {
-        piece_type,
-        color,
-        has_moved: false, // Assumption: FEN doesn't track this explicitly
-    })
-}
+    // Handles the takeback key. Against the AI the moves come back at once;
+    // over the network the opponent is asked first; in a local game the last
+    // move is taken back.
+    fn request_takeback(&mut self) {
+        let own_color = match (&self.network, self.ai_color) {
+            (Some(net), _) => Some(net.local_color),
+            (None, Some(AiColor::Both)) => return,
+            (None, Some(ai_color)) => Some(if ai_color.plays(PieceColor::White) {
+                PieceColor::Black
+            } else {
+                PieceColor::White
+            }),
+            (None, None) => None,
+        };
+        let ply = match own_color {
+            Some(color) => match self.takeback_target(color) {
+                Some(ply) => ply,
+                None => return,
+            },
+            None => match self.history.len().checked_sub(1) {
+                Some(ply) => ply,
+                None => return,
+            },
+        };
 
-fn piece_to_fen_char(piece: Piece) -> char {
-    let ch = match piece.piece_type {
-        PieceType::Pawn => 'p',
-        PieceType::Knight => 'n',
-        PieceType::Bishop => 'b',
-        PieceType::Rook => 'r',
-        PieceType::Queen => 'q',
-        PieceType::King => 'k',
-    };
+        if self.network.is_some() {
+            self.toasts.info("Takeback requested");
+            self.send_to_opponent(Message::TakebackOffer(ply));
+        } else {
+            self.take_back_to(ply);
+        }
+    }
 
-    if piece.color == PieceColor::White {
-        ch.to_ascii_uppercase()
-    } else {
-        ch
+    // The latest ply before our last move, i.e. the position where it was our
+    // turn before we last moved. None if we haven't moved yet.
+    fn takeback_target(&self, color: PieceColor) -> Option<usize> {
+        (0..self.history.len()).rev().find(|&ply| {
+            let position = match ply {
+                0 => &self.initial_state,
+                _ => &self.history[ply - 1].position,
+            };
+            position.turn == color
+        })
     }
-}
 
-impl Clone for ChessGame {
-    fn clone(&self) -> Self {
-        ChessGame {
-            board: ChessBoard {
-                squares: self.board.squares,
-            },
-            selected: self.selected,
-            valid_moves: self.valid_moves.clone(),
-            show_possible_moves: self.show_possible_moves,
-            pieces: Pieces::new(), // Pieces doesn't need to carry state
-            turn: self.turn,
-            needs_redraw: self.needs_redraw,
-            castling_rights: self.castling_rights.clone(),
-            en_passant_target: self.en_passant_target,
-            halfmove_clock: self.halfmove_clock,
-            fullmove_number: self.fullmove_number,
-            has_ai_opponent: self.has_ai_opponent,
-            tile_size: self.tile_size,
-            promotion_square: self.promotion_square,
+    // P stops the clocks and hides the board until pressed again, so the
+    // position can't be studied while the clocks are stopped. A network game
+    // pauses and resumes on both sides.
+    fn toggle_pause(&mut self) {
+        if self.adjourned
+            || self.status.is_over()
+            || self.spectator.is_some()
+            || self.editor.is_some()
+        {
+            return;
         }
+        self.set_paused(!self.paused);
+        self.send_to_opponent(if self.paused {
+            Message::Pause
+        } else {
+            Message::Resume
+        });
     }
-}
 
-impl Clone for ChessBoard {
-    fn clone(&self) -> Self {
-        ChessBoard {
-            squares: self.squares,
+    fn set_paused(&mut self, paused: bool) {
+        if self.adjourned || self.paused == paused {
+            return;
         }
+        self.paused = paused;
+        if paused {
+            self.toasts.info("Game paused");
+        } else {
+            self.toasts.info("Game resumed");
+        }
+        // Neither clock is charged for the pause, nor is the AI kept waiting
+        self.last_clock_tick = Instant::now();
+        self.needs_redraw = true;
     }
-}
 
-impl EventHandler<GameError> for ChessGame {
-    fn update(&mut self, _ctx: &mut Context) -> Result<(), GameError> {
-        if self.has_ai_opponent && self.turn == PieceColor::Black {
-            // AI's turn
-            if self.ai_turn() {
-                // Update turn and redraw
-                self.needs_redraw = true;
-            } else {
-                println!("AI has no valid moves. Checkmate or stalemate!");
-            }
+    // J adjourns the game, to be finished another time from the file it is
+    // saved to. Over the network the opponent is asked first.
+    fn request_adjournment(&mut self) {
+        if self.adjourned || self.status.is_over() || self.spectator.is_some() {
+            return;
+        }
+        if self.network.is_some() {
+            self.toasts.info("Adjournment requested");
+            self.send_to_opponent(Message::AdjournOffer);
+        } else {
+            self.adjourn();
         }
+    }
 
-        Ok(())
+    // Agreeing sends our clocks along so both sides save the same times.
+    fn answer_adjournment(&mut self, accept: bool) {
+        if !std::mem::take(&mut self.adjourn_offer) {
+            return;
+        }
+        if accept {
+            let clocks = self.clock.as_ref().map(|clock| {
+                (
+                    clock.remaining(PieceColor::White),
+                    clock.remaining(PieceColor::Black),
+                )
+            });
+            self.send_to_opponent(Message::AdjournAccept(clocks));
+            self.adjourn();
+        } else {
+            self.send_to_opponent(Message::AdjournDecline);
+        }
+        self.needs_redraw = true;
     }
 
-    fn draw(&mut self, ctx: &mut Context) -> Result<(), GameError> {
-        if !self.needs_redraw {
-            return Ok(());
+    // Saves the game as it stands, clocks included, and stops play for good.
+    // It carries on with --resume and the file.
+    fn adjourn(&mut self) {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let path = format!("itsjustchess_adjourned_{timestamp}.pgn");
+        if let Err(e) = std::fs::write(&path, self.adjourned_pgn()) {
+            self.toasts
+                .error(format!("Failed to save the adjourned game: {e}"));
+            return;
         }
+        self.toasts.info(format!(
+            "Game adjourned and saved to {path}; carry on with --resume {path}"
+        ));
+        self.tick_clock();
+        self.paused = true;
+        self.adjourned = true;
+        self.needs_redraw = true;
+        self.autosave();
+    }
 
-        let mut canvas = Canvas::from_frame(ctx, Color::from_rgb(34, 139, 34));
+    // Keeps the recovery file in step with this game: saved while it is
+    // under way, and removed once it is over, adjourned or started afresh.
+    // Network and watched games aren't saved, as they can't be picked up
+    // again alone, nor correspondence games, which have a file of their own.
+    fn autosave(&mut self) {
+        if !self.autosave
+            || self.network.is_some()
+            || self.spectator.is_some()
+            || self.correspondence.is_some()
+        {
+            return;
+        }
+        self.last_autosave = Instant::now();
+        if self.history.is_empty() || self.status.is_over() || self.adjourned {
+            if std::mem::take(&mut self.autosaved) {
+                recovery::clear();
+            }
+            return;
+        }
+        match recovery::save(&self.adjourned_pgn()) {
+            Ok(()) => self.autosaved = true,
+            Err(e) => {
+                self.toasts.error(format!(
+                    "Autosave failed, so this game can't be recovered: {e}"
+                ));
+                self.autosave = false;
+            }
+        }
+    }
 
-        // Draw the board squares
-        for row in 0..BOARD_SIZE {
-            for col in 0..BOARD_SIZE {
-                let is_light = (row + col) % 2 == 0;
-                let is_valid_move = self.valid_moves.contains(&(row, col));
+    // Picks up the game found in the recovery file, with the players and
+    // clocks as they were.
+    fn resume_recovered(&mut self, ctx: &mut Context, recovered: PgnGame) {
+        let ai_plays = |tag| {
+            recovered
+                .tag(tag)
+                .is_some_and(|name| name.starts_with(AI_PLAYER_NAME))
+        };
+        self.ai_color = match (ai_plays("White"), ai_plays("Black")) {
+            (true, true) => Some(AiColor::Both),
+            (true, false) => Some(AiColor::White),
+            (false, true) => Some(AiColor::Black),
+            (false, false) => None,
+        };
+        let time_control = time_control_tag(&recovered, "TimeControl");
+        if self.clock.is_some() != time_control.is_some() {
+            self.layout.set_show_clocks(time_control.is_some());
+            let (width, height) = ctx.gfx.drawable_size();
+            self.layout.fit(width, height);
+        }
+        self.clock = time_control.map(ChessClock::new);
+        self.resume_adjourned(recovered);
+        self.toasts.info("Resumed the unfinished game");
+    }
 
-                let mut color = if self.show_possible_moves {
-                    if is_valid_move {
-                        if is_light {
-                            Color::from_rgb(207, 203, 192) // Highlight light square for valid moves
-                        } else {
-                            Color::from_rgb(180, 220, 180) // Highlight dark square for valid moves
-                        }
-                    } else {
-                        if is_light {
-                            Color::from_rgb(161, 159, 151) // Regular light square color
-                        } else {
-                            Color::from_rgb(118, 150, 86) // Regular dark square color
-                        }
-                    }
-                } else {
-                    if is_light {
-                        Color::from_rgb(161, 159, 151) // Regular light square color
-                    } else {
-                        Color::from_rgb(118, 150, 86) // Regular dark square color
-                    }
-                };
+    // The game as PGN with what resuming it needs besides the moves: the
+    // time control (White's, and Black's as well if it differs) and each
+    // side's remaining time, in seconds.
+    fn adjourned_pgn(&self) -> String {
+        let mut tags = vec![("Termination", "unterminated".to_string())];
+        if let Some(clock) = &self.clock {
+            let in_seconds = |time_control: TimeControl| {
+                format!(
+                    "{}{}{}",
+                    time_control.base.as_secs(),
+                    time_control.mode.separator(),
+                    time_control.increment.as_secs()
+                )
+            };
+            tags.push((
+                "TimeControl",
+                in_seconds(clock.time_control(PieceColor::White)),
+            ));
+            if clock.is_asymmetric() {
+                tags.push((
+                    "BlackTimeControl",
+                    in_seconds(clock.time_control(PieceColor::Black)),
+                ));
+            }
+            for (tag, color) in CLOCK_TAGS {
+                let remaining = clock.remaining(color).as_secs_f64();
+                tags.push((tag, format!("{remaining:.1}")));
+            }
+        }
+        self.to_pgn_with(tags)
+    }
 
-                // Highlight selected square; overrides other colours
-                if Some((row, col)) == self.selected {
-                    color = Color::from_rgb(237, 202, 142);
+    // Sets up a game saved by adjourn: its moves are played out from its
+    // start and the clocks set to what was left. The time control has to be
+    // set already (see time_control_tag), apart from Black's if it differs.
+    fn resume_adjourned(&mut self, adjourned: PgnGame) {
+        let clocks = CLOCK_TAGS.map(|(tag, color)| {
+            let seconds = adjourned.tag(tag).and_then(|s| s.parse::<f64>().ok());
+            (color, seconds)
+        });
+        let black_time_control = time_control_tag(&adjourned, "BlackTimeControl");
+        self.play_out(adjourned);
+        if let Some(clock) = &mut self.clock {
+            if let Some(black) = black_time_control {
+                *clock = ChessClock::asymmetric(clock.time_control(PieceColor::White), black);
+            }
+            for (color, seconds) in clocks {
+                if let Some(seconds) = seconds {
+                    clock.set_remaining(color, Duration::from_secs_f64(seconds.max(0.0)));
                 }
+            }
+        }
+    }
 
-                let rect = Rect::new(
-                    col as f32 * self.tile_size,
-                    row as f32 * self.tile_size,
-                    self.tile_size,
-                    self.tile_size,
-                );
+    // Opens `game`, from the games database, to step through from its start
+    // with nobody to play for and no clocks.
+    fn open_replay(&mut self, ctx: &mut Context, game: PgnGame) {
+        self.ai_color = None;
+        if self.clock.take().is_some() {
+            self.layout.set_show_clocks(false);
+            let (width, height) = ctx.gfx.drawable_size();
+            self.layout.fit(width, height);
+        }
+        self.play_out(game);
+        self.view_ply(0);
+    }
 
-                let mesh = Mesh::new_rectangle(ctx, DrawMode::fill(), rect, color)?;
-                canvas.draw(&mesh, DrawParam::default());
+    // Starts over from the start of `game` and plays its moves, stopping at
+    // any that is illegal.
+    fn play_out(&mut self, game: PgnGame) {
+        let info = GameInfo::from_pgn(&game, named_by_default);
+        self.info = std::mem::take(&mut self.info).or(info);
+        self.reset_to(game.start);
+        for (mv, annotation) in game.moves.into_iter().zip(game.annotations) {
+            if !self.is_legal(mv.from, mv.to) {
+                self.toasts.error(format!(
+                    "Stopped at illegal move {}",
+                    mv.to_uci_on(&self.state.board)
+                ));
+                break;
+            }
+            self.apply_move(mv);
+            if let Some(record) = self.history.last_mut() {
+                record.annotation = annotation;
             }
         }
+    }
 
-        // Draw pieces
-        for row in 0..BOARD_SIZE {
-            for col in 0..BOARD_SIZE {
-                if let Some(piece) = self.board.squares[row][col].occupant {
-                    let x = col as f32 * self.tile_size;
-                    let y = row as f32 * self.tile_size;
-                    self.pieces.draw_piece(
-                        ctx,
-                        &mut canvas,
-                        piece.color,
-                        piece.piece_type,
-                        x,
-                        y,
-                        self.tile_size,
-                    )?;
-                }
+    fn answer_takeback(&mut self, accept: bool) {
+        if let Some(ply) = self.takeback_offer.take() {
+            if accept {
+                self.send_to_opponent(Message::TakebackAccept(ply));
+                self.take_back_to(ply);
+            } else {
+                self.send_to_opponent(Message::TakebackDecline);
             }
+            self.needs_redraw = true;
         }
+    }
 
-        if let Some((row, col)) = self.promotion_square {
-            if let Some(piece) = self.board.squares[row][col].occupant {
-                let pawn_color = piece.color; 
-                
-                let options = [
-                    PieceType::Queen,
-                    PieceType::Rook,
-                    PieceType::Bishop,
-                    PieceType::Knight,
-                ];
-        
-                // Determine the total width of the options
-                let total_width = self.tile_size * options.len() as f32;
-        
-                // Calculate the horizontal starting point based on board edges
-                let mut rect_x = (col as f32 - 1.5) * self.tile_size; // Default position
-                if rect_x < 0.0 {
-                    rect_x = 0.0; // Align to the left edge of the board
-                } else if rect_x + total_width > self.tile_size * BOARD_SIZE as f32 {
-                    rect_x = self.tile_size * BOARD_SIZE as f32 - total_width; // Align to the right edge
-                }
-        
-                // Vertical position depends on the pawn's color (top or bottom of the board)
-                let rect_y = if piece.color == PieceColor::White {
-                    row as f32 * self.tile_size
-                } else {
-                    (row as f32 + 1.0) * self.tile_size - self.tile_size // One row below for Black
-                };
-        
-                // Draw a background rectangle
-                let rect = Rect::new(rect_x, rect_y, total_width, self.tile_size);
-                let background_color = Color::from_rgba(196, 192, 188, 180);
-                let background_mesh = Mesh::new_rectangle(ctx, DrawMode::fill(), rect, background_color)?;
-                canvas.draw(&background_mesh, DrawParam::default());
-        
-                // Draw the promotion options on top of the background
-                for (i, piece_type) in options.iter().enumerate() {
-                    let x = rect_x + i as f32 * self.tile_size; // Adjust for horizontal positioning
-                    let y = rect_y;
-        
-                    self.pieces.draw_piece(
-                        ctx,
-                        &mut canvas,
-                        pawn_color, // Use the pawn's actual color
-                        *piece_type,
-                        x,
-                        y,
-                        self.tile_size,
-                    )?;
-                }
-            }
+    // Undoes moves until only `ply` half-moves of the game remain. The clocks
+    // keep the time already used.
+    fn take_back_to(&mut self, ply: usize) {
+        if ply >= self.history.len() {
+            return;
         }
+        let mut line = std::mem::take(&mut self.history);
+        line.truncate(ply);
+        self.set_line(line);
+    }
 
-        canvas.finish(ctx)?;
-        Ok(())
+    // Makes `line`, played from the start, the game. Switching lines doesn't
+    // touch the clocks, any more than taking back does.
+    fn set_line(&mut self, line: Vec<MoveRecord>) {
+        self.history = line;
+        self.position_history = std::iter::once(&self.initial_state)
+            .chain(self.history.iter().map(|record| &record.position))
+            .map(GameState::position_key)
+            .collect();
+        self.state = match self.history.last() {
+            Some(record) => record.position.clone(),
+            None => self.initial_state.clone(),
+        };
+        self.last_move = self
+            .history
+            .last()
+            .map(|record| (record.mv.from, record.mv.to));
+        self.status = self.state.status_with_history(&self.position_history);
+        self.viewed_ply = None;
+        self.scroll_history(0);
+        self.selected = None;
+        self.valid_moves.clear();
+        self.move_input = MoveInput::Selecting;
+        self.hint = None;
+        self.annotations.clear();
+        self.takeback_offer = None;
+        self.close_review();
+        self.last_move_time = Instant::now();
+        self.needs_redraw = true;
+        self.autosave();
+        self.serve_position(false);
+        self.print_position(false);
+        self.save_correspondence(false);
     }
 
-    fn key_down_event(
-        &mut self,
-        _ctx: &mut Context,
-        keycode: ggez::input::keyboard::KeyInput,
-        _repeat: bool,
-    ) -> Result<(), GameError> {
-        if let Some(key) = keycode.keycode {
-            match key {
-                ggez::input::keyboard::KeyCode::M => {
-                    self.show_possible_moves = !self.show_possible_moves;
-                    self.needs_redraw = true;
-                }
-                ggez::input::keyboard::KeyCode::F => {
-                    let fen = self.to_fen();
-                    if let Err(e) = arboard::Clipboard::new().and_then(|mut cb| cb.set_text(fen.clone())) {
-                        eprintln!("Failed to copy FEN to clipboard: {e}");
-                    } else {
-                        println!("FEN copied to clipboard: {fen}");
-                    }
-                }
-                _ => {}
-            }
+    // Whether a move can be played in the position being browsed, starting a
+    // variation there: only in a game played at this board, and by a player
+    // rather than the AI.
+    fn can_branch(&self) -> bool {
+        self.viewed_ply.is_some()
+            && self.network.is_none()
+            && self.correspondence.is_none()
+            && self.spectator.is_none()
+            && self.editor.is_none()
+            && !self.paused
+            && !self
+                .ai_color
+                .is_some_and(|ai_color| ai_color.plays(self.displayed_state().turn))
+    }
+
+    // Goes back to `ply` to play another move there, keeping the moves after
+    // it as a side line.
+    fn branch_at(&mut self, ply: usize) {
+        self.store_line(self.history.clone());
+        self.take_back_to(ply);
+    }
+
+    // Keeps `line` among the side lines, unless one of them already plays
+    // all of its moves, and in place of any it plays all the moves of.
+    fn store_line(&mut self, line: Vec<MoveRecord>) {
+        if self.side_lines.iter().any(|side| plays_on(&line, side)) {
+            return;
         }
-        Ok(())
+        self.side_lines.retain(|side| !plays_on(side, &line));
+        self.side_lines.push(line);
     }
 
-    fn mouse_button_down_event(
-        &mut self,
-        _ctx: &mut Context,
-        button: MouseButton,
-        x: f32,
-        y: f32,
-    ) -> Result<(), GameError> {
-        if button == MouseButton::Left {
-            if let Some((row, col)) = self.promotion_square {
-                // Determine the total width of the promotion options
-                let options = [
-                    PieceType::Queen,
-                    PieceType::Rook,
-                    PieceType::Bishop,
-                    PieceType::Knight,
-                ];
-                let total_width = self.tile_size * options.len() as f32;
-            
-                // Calculate the horizontal starting point based on board edges
-                let mut rect_x = (col as f32 - 1.5) * self.tile_size; // Default position
-                if rect_x < 0.0 {
-                    rect_x = 0.0; // Align to the left edge of the board
-                } else if rect_x + total_width > self.tile_size * BOARD_SIZE as f32 {
-                    rect_x = self.tile_size * BOARD_SIZE as f32 - total_width; // Align to the right edge
-                }
-            
-                // Vertical position depends on the pawn's color
-                let rect_y = if let Some(piece) = self.board.squares[row][col].occupant {
-                    if piece.color == PieceColor::White {
-                        row as f32 * self.tile_size
+    // Makes the `index`th side line the game in place of the current line,
+    // which is kept as a side line, showing the position after `ply`
+    // half-moves of it.
+    fn switch_line(&mut self, index: usize, ply: usize) {
+        let line = self.side_lines.remove(index);
+        let current = std::mem::take(&mut self.history);
+        if !plays_on(&current, &line) {
+            self.store_line(current);
+        }
+        self.set_line(line);
+        self.view_ply(ply);
+    }
+
+    // After a move, follows the side line that played it, if any, so that
+    // playing a variation's moves again doesn't make another one.
+    fn follow_side_line(&mut self) {
+        let ply = self.history.len();
+        let followed = self
+            .side_lines
+            .iter()
+            .position(|side| side.len() > ply && plays_on(&self.history, side));
+        if let Some(index) = followed {
+            let line = self.side_lines.remove(index);
+            self.set_line(line);
+            self.view_ply(ply);
+        }
+    }
+
+    // The side lines that leave the current one, each by the half-moves of
+    // the current line played before it leaves. Lines that leave with the
+    // same move are one variation, the first of them, as the others leave
+    // that one later.
+    fn variations(&self) -> Vec<(usize, usize)> {
+        let mut variations: Vec<(usize, usize)> = Vec::new();
+        for (index, side) in self.side_lines.iter().enumerate() {
+            let ply = shared_plies(side, &self.history);
+            if ply == side.len() {
+                continue;
+            }
+            let same = variations
+                .iter()
+                .any(|&(other, at)| at == ply && self.side_lines[other][ply].mv == side[ply].mv);
+            if !same {
+                variations.push((index, ply));
+            }
+        }
+        variations.sort_by_key(|&(_, ply)| ply);
+        variations
+    }
+
+    // Suggests a move for the player to move, taking it from the analysis
+    // when that is running and searching like the AI otherwise.
+    fn show_hint(&mut self) {
+        if self.viewed_ply.is_some() || self.status.is_over() || self.input_locked() {
+            return;
+        }
+        let analysed = self.analyser.as_mut().and_then(|analyser| {
+            analyser.set_position(&self.state);
+            analyser.latest()?.line.first().copied()
+        });
+        let hint = analysed.or_else(|| {
+            self.state
+                .choose_ai_move(self.ai_depth, Some(self.book.as_ref()), &mut self.rng)
+        });
+        if let Some(mv) = hint {
+            self.hint = Some((mv, Instant::now()));
+            self.needs_redraw = true;
+        }
+    }
+
+    // Draws an arrow from the centre of one square to the centre of another.
+    fn draw_arrow(
+        &self,
+        ctx: &mut Context,
+        canvas: &mut Canvas,
+        from: (usize, usize),
+        to: (usize, usize),
+        color: Color,
+    ) -> GameResult {
+        let tile = self.layout.tile_size;
+        let centre = |square| {
+            let (row, col) = self.screen_square(square);
+            [(col as f32 + 0.5) * tile, (row as f32 + 0.5) * tile]
+        };
+        let (start, end) = (centre(from), centre(to));
+        let (dx, dy) = (end[0] - start[0], end[1] - start[1]);
+        let length = (dx * dx + dy * dy).sqrt();
+        let (ux, uy) = (dx / length, dy / length);
+
+        // Stop the shaft where the head starts so the tip stays sharp
+        let head_length = tile * 0.35;
+        let head_width = tile * 0.25;
+        let neck = [end[0] - ux * head_length, end[1] - uy * head_length];
+        let shaft = Mesh::new_line(ctx, &[start, neck], tile * 0.12, color)?;
+        canvas.draw(&shaft, DrawParam::default());
+
+        let head = Mesh::new_polygon(
+            ctx,
+            DrawMode::fill(),
+            &[
+                end,
+                [neck[0] - uy * head_width, neck[1] + ux * head_width],
+                [neck[0] + uy * head_width, neck[1] - ux * head_width],
+            ],
+            color,
+        )?;
+        canvas.draw(&head, DrawParam::default());
+        Ok(())
+    }
+
+    // Adds an arrow or circle, or takes it away if the same one (in the same
+    // colour) is already there. Drawing it in another colour recolours it.
+    fn toggle_annotation(&mut self, annotation: Annotation) {
+        let existing = self
+            .annotations
+            .iter()
+            .position(|a| a.from == annotation.from && a.to == annotation.to);
+        match existing {
+            Some(i) if self.annotations[i].color == annotation.color => {
+                self.annotations.remove(i);
+            }
+            Some(i) => self.annotations[i] = annotation,
+            None => self.annotations.push(annotation),
+        }
+        self.needs_redraw = true;
+    }
+
+    fn draw_annotations(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let tile = self.layout.tile_size;
+        for annotation in &self.annotations {
+            let color = annotation.color.color();
+            if annotation.from == annotation.to {
+                let (row, col) = self.screen_square(annotation.from);
+                let circle = Mesh::new_circle(
+                    ctx,
+                    DrawMode::stroke(tile * 0.07),
+                    [(col as f32 + 0.5) * tile, (row as f32 + 0.5) * tile],
+                    tile * 0.45,
+                    0.5,
+                    color,
+                )?;
+                canvas.draw(&circle, DrawParam::default());
+            } else {
+                self.draw_arrow(ctx, canvas, annotation.from, annotation.to, color)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn toggle_analysis(&mut self) {
+        self.analyser = match self.analyser.take() {
+            Some(_) => None,
+            None => {
+                let settings = self.settings.borrow();
+                Some(Analyser::new(
+                    Arc::clone(&self.evals),
+                    settings.analysis_depth.unwrap_or(DEFAULT_ANALYSIS_DEPTH),
+                    settings.analysis_lines.unwrap_or(1),
+                ))
+            }
+        };
+        self.update_analysis_layout();
+    }
+
+    // Searches `depth_step` plies deeper (or shallower) in analysis mode,
+    // finding `line_step` more (or fewer) of the best moves, and keeps the
+    // choice for next time.
+    fn change_analysis(&mut self, depth_step: i32, line_step: i32) {
+        let Some(analyser) = &mut self.analyser else {
+            return;
+        };
+        analyser.set_depth(analyser.depth().saturating_add_signed(depth_step));
+        analyser.set_line_count(
+            analyser
+                .line_count()
+                .saturating_add_signed(line_step as isize),
+        );
+        let (depth, lines) = (analyser.depth(), analyser.line_count());
+        self.toasts.info(format!(
+            "Analysing to depth {depth}, {lines} line{}",
+            if lines == 1 { "" } else { "s" }
+        ));
+        let mut settings = self.settings.borrow_mut();
+        settings.analysis_depth = Some(depth);
+        settings.analysis_lines = Some(lines);
+        settings.save();
+        drop(settings);
+        self.update_analysis_layout();
+    }
+
+    // Plays the engine's move in the position shown, as if the player had:
+    // at the end of the game, or from a position browsed to, starting a
+    // variation there. Returns whether it could be played.
+    fn play_engine_move(&mut self, mv: Move) -> bool {
+        if let Some(ply) = self.viewed_ply {
+            if !self.can_branch() {
+                self.toasts.info("Can't play a move from here");
+                return false;
+            }
+            self.branch_at(ply);
+        }
+        let legal = self
+            .state
+            .generate_valid_moves(self.state.turn)
+            .contains(&mv);
+        if !legal || !self.can_type_move() {
+            self.toasts.info("Can't play the engine's move now");
+            return false;
+        }
+        self.apply_move(mv);
+        self.send_to_opponent(Message::Move(mv.to_uci_on(&self.state.board)));
+        true
+    }
+
+    // Plays the engine's best move, or starts playing out its whole line
+    // one move at a time (see play_engine_line).
+    fn play_analysis(&mut self, whole_line: bool) {
+        let Some(mut line) = self
+            .analyser
+            .as_ref()
+            .and_then(|analyser| analyser.latest())
+            .map(|analysis| analysis.line.clone())
+            .filter(|line| !line.is_empty())
+        else {
+            self.toasts.info("No engine move yet");
+            return;
+        };
+        let first = line.remove(0);
+        self.engine_line = None;
+        if self.play_engine_move(first) && whole_line && !line.is_empty() {
+            self.engine_line = Some((self.state.zobrist_key(), line));
+        }
+    }
+
+    // Plays the next move of the engine line being played out once the last
+    // has been on the board a while, giving up if the game has gone another
+    // way meanwhile.
+    fn play_engine_line(&mut self) {
+        let Some((key, _)) = &self.engine_line else {
+            return;
+        };
+        // It stops quietly where the other side is the AI's, or the game is
+        // over
+        if *key != self.state.zobrist_key() || self.viewed_ply.is_some() || !self.can_type_move() {
+            self.engine_line = None;
+            return;
+        }
+        if self.last_move_time.elapsed() < ENGINE_LINE_DELAY {
+            return;
+        }
+        let Some((_, mut line)) = self.engine_line.take() else {
+            return;
+        };
+        let mv = line.remove(0);
+        if self.play_engine_move(mv) && !line.is_empty() {
+            self.engine_line = Some((self.state.zobrist_key(), line));
+        }
+    }
+
+    // Keeps the engine line clicked in the analysis box as a variation from
+    // the position shown, to step through in the move list, or plays the
+    // engine's moves with the buttons along its top.
+    fn analysis_click(&mut self, x: f32, y: f32) {
+        let pressed = (0..ANALYSIS_BUTTONS.len())
+            .find(|&index| self.layout.analysis_button_rect(index).contains([x, y]));
+        if let Some(index) = pressed {
+            self.play_analysis(index == 0);
+            return;
+        }
+        let Some(analyser) = &self.analyser else {
+            return;
+        };
+        let lines = analyser.lines();
+        let index = if analyser.line_count() == 1 {
+            0
+        } else {
+            match (0..lines.len()).find(|&i| {
+                let row = self.layout.analysis_row_rect(i);
+                y >= row.y && y < row.y + row.h
+            }) {
+                Some(index) => index,
+                None => return,
+            }
+        };
+        let Some(line) = lines.get(index).map(|analysis| analysis.line.clone()) else {
+            return;
+        };
+
+        let ply = self.viewed_ply.unwrap_or(self.history.len());
+        let mut records = self.history[..ply].to_vec();
+        let mut position = self.displayed_state().clone();
+        for mv in line {
+            let san = mv.to_san(&position);
+            let captured = position.make_move(mv).captured();
+            records.push(MoveRecord {
+                mv,
+                san,
+                captured,
+                position: position.clone(),
+                annotation: MoveAnnotation::default(),
+            });
+        }
+        if plays_on(&records, &self.history) {
+            self.toasts.info("The game already plays that line");
+            return;
+        }
+        self.store_line(records);
+        self.toasts.info("Added the line as a variation");
+        self.needs_redraw = true;
+    }
+
+    fn toggle_search_overlay(&mut self) {
+        self.search_overlay = match self.search_overlay.take() {
+            Some(_) => None,
+            None => Some(Analyser::new(
+                Arc::clone(&self.evals),
+                DEFAULT_ANALYSIS_DEPTH,
+                1,
+            )),
+        };
+        self.needs_redraw = true;
+    }
+
+    // Shows the opening explorer in place of the move list, or the move list
+    // again.
+    fn toggle_explorer(&mut self) {
+        self.explorer = match self.explorer.take() {
+            Some(_) => None,
+            None => {
+                let explorer = Explorer::load();
+                if explorer.is_empty() {
+                    self.toasts
+                        .info("No games to explore; add some with --explorer-import");
+                    return;
+                }
+                Some(explorer)
+            }
+        };
+        self.needs_redraw = true;
+    }
+
+    fn toggle_review(&mut self) {
+        if self.review.is_some() {
+            self.close_review();
+        } else {
+            self.start_review();
+        }
+    }
+
+    // Starts checking every move of the game against the engine.
+    fn start_review(&mut self) {
+        if self.review.is_some() {
+            return;
+        }
+        if self.history.is_empty() {
+            self.toasts.info("No moves to review");
+            return;
+        }
+        let moves: Vec<Move> = self.history.iter().map(|record| record.mv).collect();
+        self.review = Some(Reviewer::new(
+            &self.initial_state,
+            &moves,
+            Arc::clone(&self.evals),
+        ));
+        self.update_analysis_layout();
+    }
+
+    fn close_review(&mut self) {
+        if self.review.take().is_some() {
+            self.typed_comment = None;
+            self.update_analysis_layout();
+        }
+    }
+
+    // The move whose position is shown, by the half-moves before it.
+    fn shown_move(&self) -> Option<usize> {
+        self.viewed_ply.unwrap_or(self.history.len()).checked_sub(1)
+    }
+
+    // Gives the move shown the quality glyph `nag`, or takes it away.
+    fn toggle_glyph(&mut self, nag: u8) {
+        if let Some(ply) = self.shown_move() {
+            self.history[ply].annotation.toggle_glyph(nag);
+            self.needs_redraw = true;
+        }
+    }
+
+    // Starts typing a comment on the move shown, from the one it has.
+    fn start_comment(&mut self) {
+        match self.shown_move() {
+            Some(ply) => {
+                self.typed_comment = Some(self.history[ply].annotation.comment.clone());
+                self.needs_redraw = true;
+            }
+            None => self.toasts.info("No move to comment on"),
+        }
+    }
+
+    // Handles a key while a comment is being typed: Enter gives it to the
+    // move shown (an empty one takes its comment away) and Escape drops it.
+    fn typed_comment_key(&mut self, key: ggez::input::keyboard::KeyCode) {
+        match key {
+            ggez::input::keyboard::KeyCode::Back => {
+                if let Some(typed) = &mut self.typed_comment {
+                    typed.pop();
+                }
+            }
+            ggez::input::keyboard::KeyCode::Escape => self.typed_comment = None,
+            ggez::input::keyboard::KeyCode::Return
+            | ggez::input::keyboard::KeyCode::NumpadEnter => {
+                let comment = self.typed_comment.take().unwrap_or_default();
+                if let Some(ply) = self.shown_move() {
+                    self.history[ply].annotation.comment = comment.trim().to_string();
+                }
+            }
+            _ => {}
+        }
+        self.needs_redraw = true;
+    }
+
+    // Makes room for the evaluation bar while analysing or reviewing.
+    fn update_analysis_layout(&mut self) {
+        self.layout.show_analysis = self.analyser.is_some() || self.review.is_some();
+        self.layout.analysis_lines = self.analyser.as_ref().map_or(1, Analyser::line_count);
+        // The move list has changed size
+        self.scroll_history(0);
+        self.needs_redraw = true;
+    }
+
+    // Draws the depth, score and best line found so far for the displayed
+    // position. Analysis takes the panel over from a review while it is on.
+    fn draw_analysis(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let analyser = match &self.analyser {
+            Some(analyser) => analyser,
+            None => return self.draw_review(ctx, canvas),
+        };
+        let analysis = analyser.latest();
+
+        let displayed = self.displayed_state();
+        if analyser.line_count() > 1 {
+            return self.draw_analysis_lines(ctx, canvas, analyser);
+        }
+        let summary = match analysis {
+            Some(analysis) => format!(
+                "{}  (depth {})\n{}",
+                analysis.score_text(),
+                analysis.depth,
+                line_to_san(displayed, &analysis.line)
+            ),
+            None if displayed.generate_valid_moves(displayed.turn).is_empty() => {
+                "No moves to analyse".to_string()
+            }
+            None => "Analysing...".to_string(),
+        };
+        self.draw_eval_panel(ctx, canvas, analysis, summary)?;
+        self.draw_analysis_buttons(ctx, canvas)
+    }
+
+    fn draw_analysis_buttons(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let scale = self.layout.history_row_height() * 0.5;
+        for (index, label) in ANALYSIS_BUTTONS.into_iter().enumerate() {
+            Button::new(label, self.layout.analysis_button_rect(index), false)
+                .draw(ctx, canvas, scale)?;
+        }
+        Ok(())
+    }
+
+    // Draws the best few moves found for the displayed position, each with
+    // its score and the start of its line, under the depth reached.
+    fn draw_analysis_lines(
+        &self,
+        ctx: &mut Context,
+        canvas: &mut Canvas,
+        analyser: &Analyser,
+    ) -> GameResult {
+        let displayed = self.displayed_state();
+        let lines = analyser.lines();
+        let header = match lines.first() {
+            Some(best) => format!("Depth {} of {}", best.depth, analyser.depth()),
+            None if displayed.generate_valid_moves(displayed.turn).is_empty() => {
+                "No moves to analyse".to_string()
+            }
+            None => "Analysing...".to_string(),
+        };
+        self.draw_eval_panel(ctx, canvas, lines.first(), header)?;
+
+        let scale = self.layout.history_row_height() * 0.55;
+        for (index, analysis) in lines.iter().enumerate() {
+            let moves = &analysis.line[..analysis.line.len().min(ANALYSIS_LINE_MOVES)];
+            let text = format!(
+                "{}. {}  {}",
+                index + 1,
+                analysis.score_text(),
+                line_to_san(displayed, moves)
+            );
+            Label::new(text, scale).draw(ctx, canvas, self.layout.analysis_row_rect(index))?;
+        }
+        self.draw_analysis_buttons(ctx, canvas)
+    }
+
+    // Draws what the search overlay's engine is doing with the displayed
+    // position in the top corner of the board: how deep it has got, how many
+    // positions it has visited and how fast, how many of them it had already
+    // reached by another move order, and its score and best line.
+    fn draw_search_overlay(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let Some(overlay) = &self.search_overlay else {
+            return Ok(());
+        };
+        let displayed = self.displayed_state();
+        let summary = match overlay.latest() {
+            Some(analysis) => format!(
+                "Depth {}  Eval {}\nNodes {}  ({} nodes/s)\nRepeated positions {:.1}%\nPV {}",
+                analysis.depth,
+                analysis.score_text(),
+                analysis.stats.nodes,
+                analysis.stats.nodes_per_second(),
+                analysis.stats.repeated_rate(),
+                line_to_san(displayed, &analysis.line)
+            ),
+            None if displayed.generate_valid_moves(displayed.turn).is_empty() => {
+                "No moves to search".to_string()
+            }
+            None => "Searching...".to_string(),
+        };
+
+        let padding = self.layout.tile_size * 0.1;
+        let label = Label::new(summary, self.layout.tile_size * 0.22).wrap();
+        let size = label.measure(ctx, self.layout.board_width() / 2.0)?;
+        let panel = Rect::new(0.0, 0.0, size.x + 2.0 * padding, size.y + 2.0 * padding);
+        ui::fill(ctx, canvas, panel, Color::from_rgba(0, 0, 0, 190))?;
+        label.draw(ctx, canvas, ui::inset(panel, padding))
+    }
+
+    // Draws how the move that led to the displayed position compares with
+    // the engine's choice, or at the start of the game how many errors each
+    // side made.
+    fn draw_review(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let review = match &self.review {
+            Some(review) => review,
+            None => return Ok(()),
+        };
+        let ply = self.viewed_ply.unwrap_or(self.history.len());
+        let (done, total) = review.progress();
+
+        // The score of the displayed position is the best one from it, or
+        // after the last move, what that move scored
+        let evaluation = match review.get(ply + 1) {
+            Some(next) => Some(&next.best),
+            None => review.get(ply).map(|last| &last.played),
+        };
+
+        let summary = match review.get(ply) {
+            Some(move_review) => {
+                let before = match ply {
+                    1 => &self.initial_state,
+                    _ => &self.history[ply - 2].position,
+                };
+                let record = &self.history[ply - 1];
+                let cell = ply - 1 + self.history_cell_offset();
+                let number = self.initial_state.fullmove_number as usize + cell / 2;
+                let dots = if cell.is_multiple_of(2) { "." } else { "..." };
+                let mut summary = format!(
+                    "{number}{dots} {}{} {}  ({} to {})",
+                    record.san,
+                    move_review.quality.symbol(),
+                    move_review.quality,
+                    move_review.best.score_text(),
+                    move_review.played.score_text(),
+                );
+                if move_review.quality != MoveQuality::Best {
+                    summary.push_str(&format!(
+                        "\nBest was {}",
+                        line_to_san(before, &move_review.best.line)
+                    ));
+                }
+                summary
+            }
+            None if done < total => format!("Reviewing the game: {done} of {total} moves"),
+            None => {
+                let errors = |color| {
+                    let [inaccuracies, mistakes, blunders] = review.error_counts(color);
+                    format!("{inaccuracies} inaccuracies, {mistakes} mistakes, {blunders} blunders")
+                };
+                format!(
+                    "White: {}\nBlack: {}",
+                    errors(PieceColor::White),
+                    errors(PieceColor::Black)
+                )
+            }
+        };
+        self.draw_eval_panel(ctx, canvas, evaluation, summary)
+    }
+
+    // Draws the evaluation bar (White's share growing from the bottom) and
+    // `summary` in the panel under the move list.
+    fn draw_eval_panel(
+        &self,
+        ctx: &mut Context,
+        canvas: &mut Canvas,
+        analysis: Option<&Analysis>,
+        summary: String,
+    ) -> GameResult {
+        let bar = self.layout.eval_bar_rect();
+        ui::fill(ctx, canvas, bar, ui::SIDEBAR)?;
+        // Scores are squashed so a few pawns fills most of the bar
+        let white_share = match analysis {
+            Some(analysis) if analysis.mate_in().is_some() => {
+                if analysis.score > 0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Some(analysis) => 0.5 + 0.5 * (analysis.score as f32 / 400.0).tanh(),
+            None => 0.5,
+        };
+        let white_height = bar.h * white_share;
+        if white_height > 0.0 {
+            let white = Rect::new(bar.x, bar.y + bar.h - white_height, bar.w, white_height);
+            ui::fill(ctx, canvas, white, Color::from_rgb(235, 235, 235))?;
+        }
+
+        let area = self.layout.analysis_line_rect();
+        ui::fill(ctx, canvas, area, Color::from_rgb(30, 30, 30))?;
+        Label::new(summary, self.layout.history_row_height() * 0.55)
+            .wrap()
+            .draw(ctx, canvas, ui::inset(area, self.layout.tile_size * 0.1))
+    }
+
+    // Draws the move list, highlighting the move whose position is shown,
+    // with the variations under the moves they leave at.
+    fn draw_history(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        if let Some(explorer) = &self.explorer {
+            return self.draw_explorer(ctx, canvas, explorer);
+        }
+        let list = self.history_list();
+        ui::fill(ctx, canvas, list.area, ui::SIDEBAR)?;
+
+        let padding = self.layout.tile_size * 0.1;
+        let scale = list.row_height * 0.6;
+        let current = self.viewed_ply.unwrap_or(self.history.len());
+        let offset = self.history_cell_offset();
+        let rows = self.history_rows();
+
+        for row in list.rows() {
+            let row_rect = list.row_rect(row);
+            let text_y = row_rect.y + list.row_height * 0.2;
+
+            let pair = match rows[row] {
+                HistoryRow::Moves(pair) => pair,
+                HistoryRow::Comment(ply) => {
+                    self.draw_history_aside(ctx, canvas, row_rect, self.comment_text(ply))?;
+                    continue;
+                }
+                HistoryRow::Variation(index, ply) => {
+                    let text = self.variation_text(index, ply);
+                    self.draw_history_aside(ctx, canvas, row_rect, text)?;
+                    continue;
+                }
+            };
+            let number = format!("{}.", self.initial_state.fullmove_number as usize + pair);
+            Label::new(number, scale).color(ui::MUTED).draw(
+                ctx,
+                canvas,
+                Rect::new(row_rect.x + padding, text_y, row_rect.w, list.row_height),
+            )?;
+
+            for (column, left) in HISTORY_MOVE_COLUMNS.iter().enumerate() {
+                let x = row_rect.x + left * self.layout.tile_size;
+                let cell = pair * 2 + column;
+                let (san, is_current) = match cell.checked_sub(offset) {
+                    Some(ply) if ply < self.history.len() => {
+                        (self.history[ply].san.as_str(), ply + 1 == current)
+                    }
+                    Some(_) => continue,
+                    None => ("...", false),
+                };
+                // Reviewed moves are marked with their errors
+                let quality = cell
+                    .checked_sub(offset)
+                    .and_then(|ply| self.review.as_ref()?.get(ply + 1))
+                    .map(|review| review.quality);
+
+                let width = if column == 0 {
+                    (HISTORY_MOVE_COLUMNS[1] - left) * self.layout.tile_size
+                } else {
+                    row_rect.x + row_rect.w - x
+                };
+                if is_current {
+                    let highlight = Rect::new(x - padding / 2.0, row_rect.y, width, row_rect.h);
+                    ui::fill(ctx, canvas, highlight, ui::HIGHLIGHT)?;
+                }
+
+                // though a glyph given to the move takes the place of the review's
+                let glyph = cell
+                    .checked_sub(offset)
+                    .and_then(|ply| self.history[ply].annotation.glyph());
+                let symbol = glyph.unwrap_or(quality.map_or("", |quality| quality.symbol()));
+                let color = match quality {
+                    _ if is_current => Color::BLACK,
+                    Some(MoveQuality::Inaccuracy) => Color::from_rgb(230, 200, 80),
+                    Some(MoveQuality::Mistake) => Color::from_rgb(230, 140, 50),
+                    Some(MoveQuality::Blunder) => Color::from_rgb(220, 70, 60),
+                    _ => Color::WHITE,
+                };
+                Label::new(format!("{san}{symbol}"), scale)
+                    .color(color)
+                    .draw(ctx, canvas, Rect::new(x, text_y, width, list.row_height))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Draws the opening explorer in place of the move list: the moves played
+    // from the displayed position in the imported games, the most played
+    // first, each with the number of games and a bar of how they ended,
+    // White's wins, draws and Black's wins from left to right.
+    fn draw_explorer(
+        &self,
+        ctx: &mut Context,
+        canvas: &mut Canvas,
+        explorer: &Explorer,
+    ) -> GameResult {
+        let area = self.layout.history_panel_rect();
+        ui::fill(ctx, canvas, area, ui::SIDEBAR)?;
+
+        let row_height = self.layout.history_row_height();
+        let padding = self.layout.tile_size * 0.1;
+        let scale = row_height * 0.6;
+        let position = self.displayed_state();
+        let moves = explorer.moves(position);
+        let heading = match moves.iter().map(MoveStats::games).sum::<u32>() {
+            0 => "No games reach this position".to_string(),
+            1 => "1 game".to_string(),
+            games => format!("{games} games"),
+        };
+        Label::new(heading, row_height * 0.5)
+            .color(ui::MUTED)
+            .draw(
+                ctx,
+                canvas,
+                Rect::new(
+                    area.x + padding,
+                    area.y + row_height * 0.2,
+                    area.w,
+                    row_height,
+                ),
+            )?;
+
+        let rows = ((area.h / row_height) as usize).saturating_sub(1);
+        for (row, stats) in moves.iter().take(rows).enumerate() {
+            let y = area.y + (row + 1) as f32 * row_height;
+            let text_y = y + row_height * 0.2;
+            let [count_x, bar_x] =
+                EXPLORER_COLUMNS.map(|left| area.x + left * self.layout.tile_size);
+            Label::new(stats.mv.to_san(position), scale).draw(
+                ctx,
+                canvas,
+                Rect::new(area.x + padding, text_y, count_x - area.x, row_height),
+            )?;
+            Label::new(stats.games().to_string(), scale * 0.8)
+                .color(ui::MUTED)
+                .draw(
+                    ctx,
+                    canvas,
+                    Rect::new(count_x, text_y, bar_x - count_x, row_height),
+                )?;
+
+            let bar_width = area.x + area.w - padding - bar_x;
+            let mut x = bar_x;
+            for (count, color) in [
+                (stats.white_wins, Color::WHITE),
+                (stats.draws, ui::MUTED),
+                (stats.black_wins, Color::from_rgb(20, 20, 20)),
+            ] {
+                let width = bar_width * count as f32 / stats.games() as f32;
+                if width > 0.0 {
+                    let segment = Rect::new(x, y + row_height * 0.3, width, row_height * 0.4);
+                    ui::fill(ctx, canvas, segment, color)?;
+                }
+                x += width;
+            }
+        }
+        Ok(())
+    }
+
+    // Draws a comment or variation row of the move list, in smaller type
+    // under the moves.
+    fn draw_history_aside(
+        &self,
+        ctx: &mut Context,
+        canvas: &mut Canvas,
+        row_rect: Rect,
+        text: String,
+    ) -> GameResult {
+        let row_height = self.layout.history_row_height();
+        let x = row_rect.x + HISTORY_MOVE_COLUMNS[0] * self.layout.tile_size;
+        let text_y = row_rect.y + row_height * 0.2;
+        Label::new(text, row_height * 0.5).color(ui::MUTED).draw(
+            ctx,
+            canvas,
+            Rect::new(x, text_y, row_rect.w, row_height),
+        )
+    }
+
+    fn update_status(&mut self) {
+        self.status = self.state.status_with_history(&self.position_history);
+        if self.armageddon {
+            self.status = self.status.under_armageddon();
+        }
+        if self.status.is_over() {
+            println!("{}", self.status.description());
+            self.record_result();
+        }
+    }
+
+    fn new_game(&mut self) {
+        if let Some(trainer) = &self.endgame_trainer {
+            self.start_endgame_trainer(trainer.endgame);
+            return;
+        }
+        if let Some(mut drill) = self.repertoire_drill.take() {
+            let start = drill.next();
+            self.start_drill_line((drill, start));
+            return;
+        }
+        if self.time_scramble.is_some() {
+            self.next_time_scramble();
+            return;
+        }
+        self.reset_to(self.starting_position(self.state.variant));
+    }
+
+    // The position a new game starts from, with the odds being given if any.
+    fn starting_position(&self, variant: Variant) -> GameState {
+        match self.odds {
+            Some(odds) => GameState::with_odds(variant, odds),
+            None => GameState::with_variant(variant),
+        }
+    }
+
+    // Starts the next game of the series with the colours swapped; over the
+    // network the opponent swaps too.
+    fn rematch(&mut self) {
+        if let Some(net) = &mut self.network {
+            net.local_color = net.local_color.opposite();
+        }
+        self.ai_color = self.ai_color.map(AiColor::opposite);
+        self.new_game();
+    }
+
+    // Who the series is against: the AI or the remote player. Games between
+    // two players at this board, or two AIs, aren't kept score of.
+    fn series_opponent(&self) -> Option<&'static str> {
+        if self.spectator.is_some()
+            || self.endgame_trainer.is_some()
+            || self.repertoire_drill.is_some()
+            || self.time_scramble.is_some()
+        {
+            return None;
+        }
+        match (&self.network, self.ai_color) {
+            (Some(_), _) => Some("Opponent"),
+            (None, Some(AiColor::White | AiColor::Black)) => Some("AI"),
+            (None, Some(AiColor::Both) | None) => None,
+        }
+    }
+
+    // Counts the game just finished in the series and the stats, once
+    // however often it is taken back and finished again.
+    fn record_result(&mut self) {
+        if self.stats_entry.is_some() || self.series_opponent().is_none() {
+            return;
+        }
+        let Some(outcome) = Outcome::of(self.status, self.player_color()) else {
+            return;
+        };
+        self.series.record(outcome);
+        let game = GameRecord {
+            date: pgn_date_today(),
+            opponent: match self.network {
+                Some(_) => Opponent::Remote,
+                None => Opponent::Ai(self.difficulty),
+            },
+            color: self.player_color(),
+            outcome,
+            opening: self.opening_at(self.history.len()).map(|o| o.to_string()),
+            accuracy: None,
+        };
+        let mut stats = self.stats.borrow_mut();
+        if self.keep_series && self.network.is_none() {
+            stats.series = self.series;
+        }
+        self.stats_entry = Some(stats.record(game));
+    }
+
+    // Puts the player's accuracy in the stats once the review of the game
+    // is done.
+    fn record_accuracy(&mut self) {
+        let (Some(index), Some(review)) = (self.stats_entry, &self.review) else {
+            return;
+        };
+        if let Some(accuracy) = review.accuracy(self.player_color()) {
+            self.stats.borrow_mut().set_accuracy(index, accuracy);
+        }
+    }
+
+    // Starts over from `initial`, clearing the history, selection and clocks.
+    fn reset_to(&mut self, initial: GameState) {
+        self.state = initial.clone();
+        self.initial_state = initial;
+        self.history.clear();
+        self.side_lines.clear();
+        self.viewed_ply = None;
+        self.history_scroll = 0;
+        self.position_history = vec![self.state.position_key()];
+        self.status = self.state.status();
+        self.selected = None;
+        self.last_move = None;
+        self.valid_moves.clear();
+        self.move_input = MoveInput::Selecting;
+        self.hint = None;
+        self.annotations.clear();
+        self.takeback_offer = None;
+        self.adjourn_offer = false;
+        self.paused = false;
+        self.adjourned = false;
+        self.stats_entry = None;
+        self.endgame_trainer = None;
+        self.repertoire_drill = None;
+        self.time_scramble = None;
+        self.close_review();
+        if let Some(clock) = &mut self.clock {
+            clock.reset();
+        }
+        self.last_clock_tick = Instant::now();
+        self.last_move_time = Instant::now();
+        self.needs_redraw = true;
+        self.autosave();
+        self.serve_position(false);
+        self.print_position(false);
+    }
+
+    // Runs down the clock of the side to move by the time since the last tick.
+    fn tick_clock(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.last_clock_tick;
+        self.last_clock_tick = now;
+
+        if self.status.is_over() {
+            return;
+        }
+        if let Some(clock) = &mut self.clock {
+            if clock.tick(self.state.turn, elapsed) {
+                self.status = GameStatus::Timeout {
+                    winner: self.state.turn.opposite(),
+                };
+                println!("{}", self.status.description());
+                self.sounds.borrow_mut().queue(Sound::GameEnd);
+                self.record_result();
+            }
+            self.needs_redraw = true;
+        }
+    }
+
+    // Draws the file letters and rank numbers, either in the margin or in the
+    // corners of the edge squares.
+    fn draw_coordinates(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        if self.coordinates == CoordinateLabels::Off {
+            return Ok(());
+        }
+        let board_height = self.layout.board_height();
+        let margin = self.layout.label_margin;
+        let inside = self.coordinates == CoordinateLabels::Inside;
+        let scale = if inside {
+            self.layout.tile_size * 0.2
+        } else {
+            margin * 0.6
+        };
+        let padding = self.layout.tile_size * 0.05;
+
+        let (ranks, files) = (self.layout.ranks, self.layout.files);
+        // Rank numbers down the left-hand side
+        for i in 0..ranks {
+            let offset = i as f32 * self.layout.tile_size;
+            // The rank drawn in screen row i
+            let (row, _) = self.screen_square((i, 0));
+            let mut rank = Text::new(format!("{}", ranks - row));
+            rank.set_scale(PxScale::from(scale));
+            let size = rank.measure(ctx)?;
+            let (dest, color) = if inside {
+                ([padding, offset + padding], self.label_color((i, 0)))
+            } else {
+                (
+                    [
+                        -(margin + size.x) / 2.0,
+                        offset + (self.layout.tile_size - size.y) / 2.0,
+                    ],
+                    Color::WHITE,
+                )
+            };
+            canvas.draw(&rank, DrawParam::default().dest(dest).color(color));
+        }
+
+        // File letters along the bottom
+        for i in 0..files {
+            let offset = i as f32 * self.layout.tile_size;
+            // The file drawn in screen column i
+            let (_, col) = self.screen_square((0, i));
+            let mut file = Text::new(((b'a' + col as u8) as char).to_string());
+            file.set_scale(PxScale::from(scale));
+            let size = file.measure(ctx)?;
+            let (dest, color) = if inside {
+                (
+                    [
+                        offset + self.layout.tile_size - size.x - padding,
+                        board_height - size.y - padding,
+                    ],
+                    self.label_color((ranks - 1, i)),
+                )
+            } else {
+                (
+                    [
+                        offset + (self.layout.tile_size - size.x) / 2.0,
+                        board_height + (margin - size.y) / 2.0,
+                    ],
+                    Color::WHITE,
+                )
+            };
+            canvas.draw(&file, DrawParam::default().dest(dest).color(color));
+        }
+
+        Ok(())
+    }
+
+    // Marks each piece with its letter in a badge of the other side's colour,
+    // so pieces can be told apart without relying on their shapes or shading.
+    fn draw_piece_letters(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let tile = self.layout.tile_size;
+        let displayed = self.displayed_state();
+        for row in 0..displayed.board.ranks {
+            for col in 0..displayed.board.files {
+                let occupant = displayed.board.squares[row][col].occupant;
+                let Some(piece) = occupant.filter(|p| self.piece_visible(p.color)) else {
+                    continue;
+                };
+                let (text_color, badge_color) = match piece.color {
+                    PieceColor::White => (Color::WHITE, Color::BLACK),
+                    PieceColor::Black => (Color::BLACK, Color::WHITE),
+                };
+                let rect = self.square_rect((row, col));
+                let centre = [rect.x + 0.8 * tile, rect.y + 0.8 * tile];
+                let badge =
+                    Mesh::new_circle(ctx, DrawMode::fill(), centre, tile * 0.16, 0.5, badge_color)?;
+                canvas.draw(&badge, DrawParam::default());
+
+                let mut letter = Text::new(accessibility::piece_letter(piece.piece_type));
+                letter.set_scale(PxScale::from(tile * 0.24));
+                let size = letter.measure(ctx)?;
+                let dest = [centre[0] - size.x / 2.0, centre[1] - size.y / 2.0];
+                canvas.draw(&letter, DrawParam::default().dest(dest).color(text_color));
+            }
+        }
+        Ok(())
+    }
+
+    // Inside labels take the colour of the opposite square shade so they stay
+    // readable on both; `screen_square` is where the label is drawn.
+    fn label_color(&self, screen_square: (usize, usize)) -> Color {
+        let theme = BoardTheme::get(self.high_contrast);
+        let square = self.screen_square(screen_square);
+        BoardTheme::shade(theme.square, !self.displayed_state().board.is_light(square))
+    }
+
+    // Draws both clocks in a bar below the board, highlighting the side to move.
+    fn draw_clocks(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let clock = match &self.clock {
+            Some(clock) => clock,
+            None => return Ok(()),
+        };
+        let bar = self.layout.clock_bar_rect();
+
+        for (i, color) in [PieceColor::White, PieceColor::Black]
+            .into_iter()
+            .enumerate()
+        {
+            let rect = Rect::new(bar.x + i as f32 * bar.w / 2.0, bar.y, bar.w / 2.0, bar.h);
+            let is_active = color == self.state.turn && !self.status.is_over();
+            let background = if is_active {
+                Color::from_rgb(237, 202, 142)
+            } else {
+                Color::from_rgb(60, 60, 60)
+            };
+            let mesh = Mesh::new_rectangle(ctx, DrawMode::fill(), rect, background)?;
+            canvas.draw(&mesh, DrawParam::default());
+
+            let mut label = format!("{:?}  {}", color, format_clock(clock.remaining(color)));
+            if let Some(mode) = clock.time_control(color).mode_label() {
+                label += &format!("  {mode}");
+            }
+            if self.state.variant == Variant::ThreeCheck {
+                label += &format!("  +{}", self.state.checks_given(color));
+            }
+            let mut text = Text::new(label);
+            text.set_scale(PxScale::from(bar.h * 0.6));
+            let size = text.measure(ctx)?;
+            let text_color = if is_active {
+                Color::BLACK
+            } else {
+                Color::WHITE
+            };
+            canvas.draw(
+                &text,
+                DrawParam::default()
+                    .dest([
+                        rect.x + (rect.w - size.x) / 2.0,
+                        rect.y + (rect.h - size.y) / 2.0,
+                    ])
+                    .color(text_color),
+            );
+        }
+
+        Ok(())
+    }
+
+    // Draws the pieces each side has taken up to the displayed position in
+    // the trays above and below the board, followed by the material lead of
+    // whichever side is ahead.
+    fn draw_captures(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let ply = self.viewed_ply.unwrap_or(self.history.len());
+        let board = &self.displayed_state().board;
+        let lead =
+            board.material(PieceColor::White) as i32 - board.material(PieceColor::Black) as i32;
+
+        for color in [PieceColor::White, PieceColor::Black] {
+            // The side at the bottom has its captures below the board
+            let tray = match self.board_flipped() {
+                true => self.layout.capture_tray_rect(color.opposite()),
+                false => self.layout.capture_tray_rect(color),
+            };
+            let mut captured: Vec<PieceType> = self.history[..ply]
+                .iter()
+                .filter_map(|record| record.captured)
+                .filter(|piece| piece.color != color)
+                .map(|piece| piece.piece_type)
+                .collect();
+            captured.sort_by_key(|&piece_type| (piece_type.points(), piece_type as u8));
+
+            // Overlap the pieces so a long row still fits
+            let size = tray.h;
+            let step = size * 0.55;
+            let mut x = tray.x;
+            for piece_type in captured {
+                self.pieces.draw_piece(
+                    ctx,
+                    canvas,
+                    color.opposite(),
+                    piece_type,
+                    x,
+                    tray.y,
+                    size,
+                )?;
+                x += step;
+            }
+
+            let lead = match color {
+                PieceColor::White => lead,
+                PieceColor::Black => -lead,
+            };
+            if lead > 0 {
+                let mut text = Text::new(format!("+{lead}"));
+                text.set_scale(PxScale::from(size * 0.6));
+                let height = text.measure(ctx)?.y;
+                canvas.draw(
+                    &text,
+                    DrawParam::default()
+                        .dest([
+                            x + size - step + size * 0.15,
+                            tray.y + (size - height) / 2.0,
+                        ])
+                        .color(Color::WHITE),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    // The opening of the position after `ply` half-moves: the latest
+    // position leading up to it that is a known opening, so the name follows
+    // transpositions and stays once the game leaves the book.
+    fn opening_at(&self, ply: usize) -> Option<Opening> {
+        self.history[..ply]
+            .iter()
+            .rev()
+            .map(|record| &record.position)
+            .chain([&self.initial_state])
+            .find_map(|state| self.openings.lookup(state))
+    }
+
+    // Shows the series score in the status bar, above the opening of the
+    // displayed position.
+    fn draw_status_bar(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let opening = self.opening_at(self.viewed_ply.unwrap_or(self.history.len()));
+        let series = self
+            .series_opponent()
+            .filter(|_| !self.series.is_empty())
+            .map(|opponent| self.series.describe(opponent));
+        let trainer = self.endgame_trainer.as_ref().map(|trainer| {
+            let played = self.history.len().div_ceil(2) as u32;
+            trainer.describe(played)
+        });
+        let drill = self
+            .repertoire_drill
+            .as_ref()
+            .map(RepertoireDrill::describe);
+        let scramble = self
+            .time_scramble
+            .map(|i| format!("Time scramble: {}", SCRAMBLE_POSITIONS[i].name));
+        let lines: Vec<String> = trainer
+            .into_iter()
+            .chain(drill)
+            .chain(scramble)
+            .chain(series)
+            .chain(opening.map(|opening| opening.to_string()))
+            .collect();
+        if lines.is_empty() {
+            return Ok(());
+        }
+
+        let area = self.layout.status_bar_rect();
+        let padding = self.layout.tile_size * 0.1;
+        let mut text = Text::new(lines.join("\n"));
+        text.set_scale(PxScale::from(self.layout.history_row_height() * 0.45));
+        text.set_bounds([area.w - 2.0 * padding, area.h]);
+        text.set_wrap(true);
+        let height = text.measure(ctx)?.y;
+        canvas.draw(
+            &text,
+            DrawParam::default()
+                .dest([area.x + padding, area.y + (area.h - height).max(0.0) / 2.0])
+                .color(Color::from_rgb(220, 220, 220)),
+        );
+        Ok(())
+    }
+
+    // Covers the board while the game is paused so the position can't be
+    // studied off the clock.
+    fn draw_paused(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let (board_width, board_height) = (self.layout.board_width(), self.layout.board_height());
+        let cover = Mesh::new_rectangle(
+            ctx,
+            DrawMode::fill(),
+            Rect::new(0.0, 0.0, board_width, board_height),
+            Color::from_rgb(30, 30, 30),
+        )?;
+        canvas.draw(&cover, DrawParam::default());
+        let message = if self.adjourned {
+            "Game adjourned"
+        } else {
+            "Paused: press P to resume"
+        };
+        self.draw_dialog(ctx, canvas, message, &[])
+    }
+
+    // Draws the move being typed in the status bar, with the legal moves it
+    // could still become.
+    fn draw_typed_move(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let area = self.layout.status_bar_rect();
+        let background =
+            Mesh::new_rectangle(ctx, DrawMode::fill(), area, Color::from_rgb(30, 30, 30))?;
+        canvas.draw(&background, DrawParam::default());
+
+        let candidates = self.typed_move_candidates();
+        let suggestions = if candidates.is_empty() {
+            "No legal move".to_string()
+        } else {
+            let sans: Vec<&str> = candidates.iter().map(|(_, san)| san.as_str()).collect();
+            sans.join(" ")
+        };
+        let padding = self.layout.tile_size * 0.1;
+        let scale = PxScale::from(self.layout.history_row_height() * 0.45);
+        let mut text = Text::new(format!(
+            "Move: {}_",
+            self.typed_move.as_deref().unwrap_or("")
+        ));
+        text.set_scale(scale);
+        canvas.draw(
+            &text,
+            DrawParam::default()
+                .dest([area.x + padding, area.y + padding / 2.0])
+                .color(Color::WHITE),
+        );
+        let mut text = Text::new(suggestions);
+        text.set_scale(scale);
+        text.set_bounds([area.w - 2.0 * padding, area.h / 2.0]);
+        canvas.draw(
+            &text,
+            DrawParam::default()
+                .dest([area.x + padding, area.y + area.h / 2.0])
+                .color(Color::from_rgb(161, 159, 151)),
+        );
+        Ok(())
+    }
+
+    // Draws the comment being typed in the status bar.
+    fn draw_typed_comment(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let area = self.layout.status_bar_rect();
+        let background =
+            Mesh::new_rectangle(ctx, DrawMode::fill(), area, Color::from_rgb(30, 30, 30))?;
+        canvas.draw(&background, DrawParam::default());
+
+        let padding = self.layout.tile_size * 0.1;
+        let scale = PxScale::from(self.layout.history_row_height() * 0.45);
+        let mut text = Text::new(format!(
+            "Comment: {}_",
+            self.typed_comment.as_deref().unwrap_or("")
+        ));
+        text.set_scale(scale);
+        text.set_bounds([area.w - 2.0 * padding, area.h / 2.0]);
+        canvas.draw(
+            &text,
+            DrawParam::default()
+                .dest([area.x + padding, area.y + padding / 2.0])
+                .color(Color::WHITE),
+        );
+        let mut text = Text::new("Enter to keep it, Escape to drop it");
+        text.set_scale(scale);
+        canvas.draw(
+            &text,
+            DrawParam::default()
+                .dest([area.x + padding, area.y + area.h / 2.0])
+                .color(Color::from_rgb(161, 159, 151)),
+        );
+        Ok(())
+    }
+
+    // Button `index` of the `count` side by side along the bottom of a dialog.
+    fn dialog_button_rect(&self, index: usize, count: usize) -> Rect {
+        let width = self.layout.tile_size * 2.5;
+        let gap = self.layout.tile_size * 0.3;
+        let total_width = count as f32 * width + (count - 1) as f32 * gap;
+        let row = Rect::new(
+            (self.layout.board_width() - total_width) / 2.0,
+            self.layout.board_height() / 2.0 + self.layout.tile_size * 0.3,
+            total_width,
+            self.layout.tile_size * 0.7,
+        );
+        ui::columns(row, count, gap)[index]
+    }
+
+    // Dims the board and draws a banner across it with `message` above a row
+    // of buttons, laid out by dialog_button_rect.
+    fn draw_dialog(
+        &self,
+        ctx: &mut Context,
+        canvas: &mut Canvas,
+        message: &str,
+        buttons: &[&str],
+    ) -> GameResult {
+        let (board_width, board_height) = (self.layout.board_width(), self.layout.board_height());
+        let tile = self.layout.tile_size;
+        ui::fill(
+            ctx,
+            canvas,
+            Rect::new(0.0, 0.0, board_width, board_height),
+            ui::SHADE,
+        )?;
+
+        let banner = Rect::new(
+            tile,
+            board_height / 2.0 - tile * 1.25,
+            board_width - tile * 2.0,
+            tile * 2.5,
+        );
+        ui::fill(ctx, canvas, banner, ui::PANEL)?;
+        Label::new(message, tile * 0.45).align(Align::Top).draw(
+            ctx,
+            canvas,
+            Rect::new(banner.x, banner.y + tile * 0.35, banner.w, banner.h),
+        )?;
+
+        for (i, label) in buttons.iter().enumerate() {
+            Button::new(label, self.dialog_button_rect(i, buttons.len()), true).draw(
+                ctx,
+                canvas,
+                tile * 0.35,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn to_pgn(&self) -> String {
+        self.to_pgn_with(Vec::new())
+    }
+
+    /// Whether any moves have been played, for exporting.
+    pub fn has_moves(&self) -> bool {
+        !self.history.is_empty()
+    }
+
+    /// Copies the moves played so far to the clipboard in `notation`.
+    pub fn copy_moves(&self, notation: Notation) {
+        let moves: Vec<Move> = self.history.iter().map(|record| record.mv).collect();
+        copy_to_clipboard(
+            &self.toasts,
+            &format!("Moves in {notation}"),
+            format_moves(&self.initial_state, &moves, notation),
+        );
+    }
+
+    // The game as PGN with `extra_tags` after the usual ones.
+    fn to_pgn_with(&self, extra_tags: Vec<(&str, String)>) -> String {
+        let player_name = |color| match (self.ai_color, &self.network) {
+            (Some(ai_color), _) if ai_color.plays(color) => match self.difficulty {
+                Some(difficulty) => format!("{AI_PLAYER_NAME} level {difficulty}"),
+                None => AI_PLAYER_NAME.to_string(),
+            },
+            (_, Some(net)) if net.local_color != color => REMOTE_PLAYER_NAME.to_string(),
+            _ => HUMAN_PLAYER_NAME.to_string(),
+        };
+        let (white, black) = match self.spectator.as_ref().and_then(|s| s.players.as_ref()) {
+            Some((white, black)) => (white.clone(), black.clone()),
+            None => (
+                player_name(PieceColor::White),
+                player_name(PieceColor::Black),
+            ),
+        };
+        let result = self.status.result_tag();
+        let mut tags = self.info.tags(&white, &black, result);
+        if self.state.variant != Variant::Standard {
+            tags.push(("Variant", self.state.variant.to_string()));
+        }
+        let start_fen = self.initial_state.to_fen();
+        if start_fen != GameState::new().to_fen() {
+            tags.push(("SetUp", "1".to_string()));
+            tags.push(("FEN", start_fen));
+        }
+        tags.extend(extra_tags);
+
+        let pgn_moves = |line: &[MoveRecord]| {
+            line.iter()
+                .map(|r| PgnMove {
+                    san: r.san.clone(),
+                    annotation: r.annotation.clone(),
+                })
+                .collect()
+        };
+        let moves: Vec<PgnMove> = pgn_moves(&self.history);
+        let variations: Vec<Vec<PgnMove>> = self.side_lines.iter().map(|l| pgn_moves(l)).collect();
+        format_pgn_with_variations(
+            &tags,
+            self.initial_state.fullmove_number,
+            self.initial_state.turn == PieceColor::Black,
+            &moves,
+            &variations,
+            result,
+        )
+    }
+
+    fn save_pgn(&self) {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let path = format!("itsjustchess_{timestamp}.pgn");
+        match std::fs::write(&path, self.to_pgn()) {
+            Ok(()) => self.toasts.info(format!("PGN saved to {path}")),
+            Err(e) => self.toasts.error(format!("Failed to save PGN: {e}")),
+        }
+    }
+
+    // Saves the displayed position as a PNG for sharing in a chat (see
+    // render_board) in ggez's data directory, returning its path.
+    fn export_image(&self, ctx: &mut Context, plain: bool) -> GameResult<PathBuf> {
+        let image = self.render_board(ctx, ctx.gfx.surface_format(), plain)?;
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let name = format!("itsjustchess_position_{timestamp}.png");
+        image.encode(ctx, ImageEncodingFormat::Png, format!("/{name}"))?;
+        Ok(ctx.fs.user_data_dir().join(name))
+    }
+
+    // Renders the displayed position offscreen: the board with its
+    // coordinates, the last move highlighted and a caption saying how the
+    // game stands, or just the squares and pieces if `plain`.
+    fn render_board(
+        &self,
+        ctx: &mut Context,
+        format: ImageFormat,
+        plain: bool,
+    ) -> GameResult<Image> {
+        let tile = self.layout.tile_size;
+        let (board_width, board_height) = (self.layout.board_width(), self.layout.board_height());
+        let margin = if plain { 0.0 } else { self.layout.label_margin };
+        let caption_height = if plain { 0.0 } else { tile * 0.6 };
+        let area = Rect::new(
+            -margin,
+            0.0,
+            board_width + margin,
+            board_height + margin + caption_height,
+        );
+        let image =
+            Image::new_canvas_image(ctx, format, area.w.round() as u32, area.h.round() as u32, 1);
+        let mut canvas = Canvas::from_image(ctx, image.clone(), Color::from_rgb(34, 139, 34));
+        canvas.set_screen_coordinates(area);
+
+        let theme = BoardTheme::get(self.high_contrast);
+        let last_move = self.displayed_last_move().filter(|_| !plain);
+        let position = self.displayed_state();
+        for row in 0..position.board.ranks {
+            for col in 0..position.board.files {
+                let is_light = position.board.is_light((row, col));
+                let in_last_move =
+                    last_move.is_some_and(|(from, to)| from == (row, col) || to == (row, col));
+                let color = if in_last_move {
+                    BoardTheme::shade(theme.last_move, is_light)
+                } else {
+                    BoardTheme::shade(theme.square, is_light)
+                };
+                let rect = self.square_rect((row, col));
+                let mesh = Mesh::new_rectangle(ctx, DrawMode::fill(), rect, color)?;
+                canvas.draw(&mesh, DrawParam::default());
+            }
+        }
+        if !plain {
+            self.draw_coordinates(ctx, &mut canvas)?;
+        }
+
+        for row in 0..position.board.ranks {
+            for col in 0..position.board.files {
+                if let Some(piece) = position.board.squares[row][col].occupant {
+                    let rect = self.square_rect((row, col));
+                    self.pieces.draw_piece(
+                        ctx,
+                        &mut canvas,
+                        piece.color,
+                        piece.piece_type,
+                        rect.x,
+                        rect.y,
+                        tile,
+                    )?;
+                }
+            }
+        }
+
+        if !plain {
+            let standing = if self.viewed_ply.is_none() && self.status.is_over() {
+                self.status.description()
+            } else {
+                format!("{:?} to move", position.turn)
+            };
+            let opening = self.opening_at(self.viewed_ply.unwrap_or(self.history.len()));
+            let caption = match opening {
+                Some(opening) => format!("{standing}, {opening}"),
+                None => standing,
+            };
+            let mut text = Text::new(caption);
+            text.set_scale(PxScale::from(tile * 0.3));
+            text.set_bounds([area.w - tile * 0.2, caption_height]);
+            let size = text.measure(ctx)?;
+            canvas.draw(
+                &text,
+                DrawParam::default()
+                    .dest([
+                        area.x + (area.w - size.x) / 2.0,
+                        board_height + margin + (caption_height - size.y) / 2.0,
+                    ])
+                    .color(Color::WHITE),
+            );
+        }
+        canvas.finish(ctx)?;
+        Ok(image)
+    }
+
+    // Saves the board image asked for with Ctrl+P. ggez only renders within
+    // a frame, so this is called from draw rather than from the key press.
+    fn save_requested_image(&mut self, ctx: &mut Context) {
+        let Some(plain) = self.image_request.take() else {
+            return;
+        };
+        match self.export_image(ctx, plain) {
+            Ok(path) => self
+                .toasts
+                .info(format!("Board image saved to {}", path.display())),
+            Err(e) => self
+                .toasts
+                .error(format!("Failed to save the board image: {e}")),
+        }
+    }
+
+    // Starts a new game from a FEN on the clipboard.
+    fn paste_fen(&mut self) {
+        match arboard::Clipboard::new().and_then(|mut cb| cb.get_text()) {
+            Ok(text) => {
+                if let Err(err) = self.load_fen(text.trim()) {
+                    self.toasts
+                        .error(format!("Failed to load FEN from clipboard: {err}"));
+                }
+            }
+            Err(e) => self
+                .toasts
+                .error(format!("Failed to read the clipboard: {e}")),
+        }
+    }
+
+    // Starts a new game from `fen`, or says why it can't. In a network game
+    // only the host can do this, and the client is brought along with a sync.
+    fn load_fen(&mut self, fen: &str) -> Result<(), String> {
+        if self.network.as_ref().is_some_and(|net| !net.is_host) {
+            return Err("Only the host can load a position in a network game".to_string());
+        }
+        if self.spectator.is_some() {
+            return Err("Positions can't be loaded while watching a game".to_string());
+        }
+
+        let mut initial = GameState::with_variant(self.state.variant);
+        initial.from_fen(fen).map_err(|err| err.to_string())?;
+        log::info!("Loaded FEN {fen}");
+        self.reset_to(initial);
+        self.toasts.info(format!("Loaded FEN: {fen}"));
+        if self.network.is_some() {
+            self.send_to_opponent(Message::Sync {
+                fen: fen.to_string(),
+                moves: Vec::new(),
+            });
+        }
+        Ok(())
+    }
+
+    // Switches to setting up a position, starting from the one on the board.
+    // The game carries on unchanged if the editor is cancelled.
+    fn open_editor(&mut self) {
+        if self.network.is_some() || self.spectator.is_some() {
+            self.toasts
+                .info("The position editor is only available when playing locally");
+            return;
+        }
+        self.editor = Some(Editor::new(self.displayed_state()));
+        self.viewed_ply = None;
+        self.move_input = MoveInput::Selecting;
+        self.selected = None;
+        self.valid_moves.clear();
+        self.hint = None;
+        self.annotations.clear();
+        self.needs_redraw = true;
+    }
+
+    fn cancel_editor(&mut self) {
+        self.editor = None;
+        // The clocks were stopped while editing
+        self.last_clock_tick = Instant::now();
+        self.needs_redraw = true;
+    }
+
+    // Starts a new game from the edited position, or reports why it can't be
+    // played and stays in the editor.
+    fn finish_editor(&mut self) {
+        let Some(editor) = &self.editor else {
+            return;
+        };
+        match editor.finish() {
+            Ok(state) => {
+                self.toasts.info(format!("Loaded FEN: {}", state.to_fen()));
+                self.editor = None;
+                self.reset_to(state);
+            }
+            Err(err) => self.toasts.error(err.to_string()),
+        }
+    }
+
+    // The palette cells in the side panel, each with the piece it selects
+    // (None for the eraser).
+    fn palette_rects(&self) -> Vec<(Option<Piece>, Rect)> {
+        let panel = self.layout.history_panel_rect();
+        let cell = panel.w / PALETTE_COLUMNS as f32;
+        let pieces = [PieceColor::White, PieceColor::Black]
+            .into_iter()
+            .flat_map(|color| {
+                PALETTE_PIECES
+                    .into_iter()
+                    .map(move |piece_type| Some(editor::piece(piece_type, color)))
+            })
+            .chain([None]);
+        pieces
+            .enumerate()
+            .map(|(i, piece)| {
+                let (row, col) = (i / PALETTE_COLUMNS, i % PALETTE_COLUMNS);
+                let rect = Rect::new(
+                    panel.x + col as f32 * cell,
+                    panel.y + row as f32 * cell,
+                    cell,
+                    cell,
+                );
+                (piece, rect)
+            })
+            .collect()
+    }
+
+    // The editor's buttons, in rows below the palette.
+    fn editor_button_rects(&self) -> Vec<(EditorButton, Rect)> {
+        let panel = self.layout.history_panel_rect();
+        let palette_bottom = self
+            .palette_rects()
+            .last()
+            .map_or(panel.y, |(_, rect)| rect.y + rect.h);
+        let height = self.layout.tile_size * 0.45;
+        let gap = self.layout.tile_size * 0.08;
+        let rows: [&[EditorButton]; 5] = [
+            &[EditorButton::Turn],
+            &[
+                EditorButton::Castling(CastlingRights::WHITE_KINGSIDE),
+                EditorButton::Castling(CastlingRights::WHITE_QUEENSIDE),
+                EditorButton::Castling(CastlingRights::BLACK_KINGSIDE),
+                EditorButton::Castling(CastlingRights::BLACK_QUEENSIDE),
+            ],
+            &[EditorButton::StartPosition, EditorButton::Clear],
+            &[EditorButton::CopyFen],
+            &[EditorButton::Play, EditorButton::Cancel],
+        ];
+
+        let mut rects = Vec::new();
+        for (row, buttons) in rows.iter().enumerate() {
+            let y = palette_bottom + gap + row as f32 * (height + gap);
+            let width = (panel.w - gap) / buttons.len() as f32 - gap;
+            for (i, &button) in buttons.iter().enumerate() {
+                let x = panel.x + gap + i as f32 * (width + gap);
+                rects.push((button, Rect::new(x, y, width, height)));
+            }
+        }
+        rects
+    }
+
+    // Handles a left click while editing: on the board it places the brush
+    // piece (or with Shift marks the en passant square), in the panel it
+    // picks a brush or presses a button.
+    fn editor_click(&mut self, x: f32, y: f32, shift: bool) {
+        if let Some(square) = self.coords_to_square(x, y) {
+            if let Some(editor) = &mut self.editor {
+                if shift {
+                    editor.toggle_en_passant(square);
+                } else {
+                    editor.paint(square);
+                }
+            }
+        } else if let Some((piece, _)) = self
+            .palette_rects()
+            .into_iter()
+            .find(|(_, rect)| rect.contains([x, y]))
+        {
+            if let Some(editor) = &mut self.editor {
+                editor.brush = piece;
+            }
+        } else if let Some((button, _)) = self
+            .editor_button_rects()
+            .into_iter()
+            .find(|(_, rect)| rect.contains([x, y]))
+        {
+            self.press_editor_button(button);
+        }
+        self.needs_redraw = true;
+    }
+
+    fn press_editor_button(&mut self, button: EditorButton) {
+        let Some(editor) = &mut self.editor else {
+            return;
+        };
+        match button {
+            EditorButton::Turn => editor.toggle_turn(),
+            EditorButton::Castling(right) => editor.toggle_castling(right),
+            EditorButton::StartPosition => editor.reset(),
+            EditorButton::Clear => editor.clear(),
+            EditorButton::CopyFen => copy_to_clipboard(&self.toasts, "FEN", editor.to_fen()),
+            EditorButton::Play => self.finish_editor(),
+            EditorButton::Cancel => self.cancel_editor(),
+        }
+        self.needs_redraw = true;
+    }
+
+    // Draws the palette and buttons in place of the move list.
+    fn draw_editor(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let Some(editor) = &self.editor else {
+            return Ok(());
+        };
+        let panel = self.layout.history_panel_rect();
+        let background =
+            Mesh::new_rectangle(ctx, DrawMode::fill(), panel, Color::from_rgb(40, 40, 40))?;
+        canvas.draw(&background, DrawParam::default());
+
+        for (piece, rect) in self.palette_rects() {
+            if piece == editor.brush {
+                let highlight = Mesh::new_rectangle(
+                    ctx,
+                    DrawMode::fill(),
+                    rect,
+                    Color::from_rgb(237, 202, 142),
+                )?;
+                canvas.draw(&highlight, DrawParam::default());
+            }
+            match piece {
+                Some(piece) => self.pieces.draw_piece(
+                    ctx,
+                    canvas,
+                    piece.color,
+                    piece.piece_type,
+                    rect.x,
+                    rect.y,
+                    rect.w,
+                )?,
+                None => {
+                    let mut text = Text::new("Erase");
+                    text.set_scale(PxScale::from(rect.h * 0.25));
+                    let size = text.measure(ctx)?;
+                    let color = if editor.brush.is_none() {
+                        Color::BLACK
                     } else {
-                        (row as f32 + 1.0) * self.tile_size - self.tile_size // Below for Black
+                        Color::WHITE
+                    };
+                    canvas.draw(
+                        &text,
+                        DrawParam::default()
+                            .dest([
+                                rect.x + (rect.w - size.x) / 2.0,
+                                rect.y + (rect.h - size.y) / 2.0,
+                            ])
+                            .color(color),
+                    );
+                }
+            }
+        }
+
+        for (button, rect) in self.editor_button_rects() {
+            let (label, active) = match button {
+                EditorButton::Turn => (format!("{:?} to move", editor.position().turn), false),
+                EditorButton::Castling(right) => {
+                    (right.to_string(), editor.has_castling_right(right))
+                }
+                EditorButton::StartPosition => ("Start".to_string(), false),
+                EditorButton::Clear => ("Clear".to_string(), false),
+                EditorButton::CopyFen => ("Copy FEN".to_string(), false),
+                EditorButton::Play => ("Play".to_string(), false),
+                EditorButton::Cancel => ("Cancel".to_string(), false),
+            };
+            let (background, text_color) = if active {
+                (Color::from_rgb(237, 202, 142), Color::BLACK)
+            } else {
+                (Color::from_rgb(70, 70, 70), Color::WHITE)
+            };
+            let mesh = Mesh::new_rectangle(ctx, DrawMode::fill(), rect, background)?;
+            canvas.draw(&mesh, DrawParam::default());
+
+            let mut text = Text::new(label);
+            text.set_scale(PxScale::from(rect.h * 0.55));
+            let size = text.measure(ctx)?;
+            canvas.draw(
+                &text,
+                DrawParam::default()
+                    .dest([
+                        rect.x + (rect.w - size.x) / 2.0,
+                        rect.y + (rect.h - size.y) / 2.0,
+                    ])
+                    .color(text_color),
+            );
+        }
+
+        // Mark the en passant square, which otherwise doesn't show
+        if let Some((row, col)) = editor.position().en_passant_target {
+            let tile = self.layout.tile_size;
+            let marker = Mesh::new_circle(
+                ctx,
+                DrawMode::stroke(tile * 0.04),
+                [(col as f32 + 0.5) * tile, (row as f32 + 0.5) * tile],
+                tile * 0.2,
+                0.5,
+                Color::from_rgb(40, 120, 220),
+            )?;
+            canvas.draw(&marker, DrawParam::default());
+        }
+
+        Ok(())
+    }
+
+    // Starts a new game with the choices from the main menu, fitting the
+    // board to the window again if the clock bar comes or goes.
+    fn start_game(&mut self, ctx: &mut Context, choices: &NewGameMenu) -> GameResult {
+        self.ai_color = choices.ai_color;
+        self.difficulty = choices.difficulty;
+        self.settings.borrow_mut().difficulty = choices.difficulty;
+        self.settings.borrow().save();
+
+        if self.clock.is_some() != choices.time_control.is_some() {
+            self.layout.set_show_clocks(choices.time_control.is_some());
+            let (width, height) = ctx.gfx.drawable_size();
+            self.layout.fit(width, height);
+        }
+        self.clock = choices.time_control.map(ChessClock::new);
+
+        self.reset_to(self.starting_position(choices.variant));
+        Ok(())
+    }
+
+    // Picks up again after another scene was on top, without charging the
+    // time spent there to either clock.
+    fn resume(&mut self) {
+        self.last_clock_tick = Instant::now();
+        self.needs_redraw = true;
+    }
+
+    // A fresh game for another tab: two players on the standard board with
+    // no clock, sharing this game's pieces, book, sounds and settings.
+    fn new_tab(&self) -> GameResult<Self> {
+        let layout = Layout::new(
+            self.layout.standard_board_size(),
+            self.layout.label_margin > 0.0,
+            false,
+        );
+        let mut game = ChessGame::new(
+            None,
+            self.ai_depth,
+            self.ai_move_delay,
+            layout,
+            self.coordinates,
+            None,
+            Rc::clone(&self.sounds),
+        )?;
+        game.difficulty = self.difficulty;
+        game.show_possible_moves = self.show_possible_moves;
+        game.high_contrast = self.high_contrast;
+        game.piece_letters = self.piece_letters;
+        game.auto_flip = self.auto_flip;
+        game.confirm_moves = self.confirm_moves;
+        game.show_threats = self.show_threats;
+        game.autosave = self.autosave;
+        game.announcer = Announcer::new(self.announcer.mode());
+        game.pieces = Rc::clone(&self.pieces);
+        game.book = Rc::clone(&self.book);
+        game.rng = self.rng.clone();
+        game.openings = Rc::clone(&self.openings);
+        game.settings = Rc::clone(&self.settings);
+        game.stats = Rc::clone(&self.stats);
+        game.evals = Arc::clone(&self.evals);
+        Ok(game)
+    }
+
+    // The main menu's choices, starting from the current game's.
+    fn menu_choices(&self) -> NewGameMenu {
+        NewGameMenu {
+            ai_color: self.ai_color,
+            difficulty: self.difficulty,
+            variant: self.state.variant,
+            time_control: self
+                .clock
+                .as_ref()
+                .map(|clock| clock.time_control(PieceColor::White)),
+        }
+    }
+}
+
+// Whether `name` is one a player is given when not named otherwise.
+fn named_by_default(name: &str) -> bool {
+    name == HUMAN_PLAYER_NAME || name == REMOTE_PLAYER_NAME || name.starts_with(AI_PLAYER_NAME)
+}
+
+// How many half-moves `a` and `b` start with in common.
+fn shared_plies(a: &[MoveRecord], b: &[MoveRecord]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a.mv == b.mv).count()
+}
+
+// Whether `line` starts with all the moves of `start`.
+fn plays_on(start: &[MoveRecord], line: &[MoveRecord]) -> bool {
+    start.len() <= line.len() && shared_plies(start, line) == start.len()
+}
+
+// Spells out `line` in SAN, played from `position`.
+fn line_to_san(position: &GameState, line: &[Move]) -> String {
+    let mut position = position.clone();
+    let mut sans = Vec::new();
+    for &mv in line {
+        sans.push(mv.to_san(&position));
+        position.make_move(mv);
+    }
+    sans.join(" ")
+}
+
+// A time control saved in `tag` of an adjourned game, written in seconds as
+// in PGN, e.g. "300+3", or with a delay as "300d3" or "300b3".
+fn time_control_tag(adjourned: &PgnGame, tag: &str) -> Option<TimeControl> {
+    let (base, mode, increment) = ClockMode::split(adjourned.tag(tag)?);
+    Some(TimeControl {
+        base: Duration::from_secs(base.parse().ok()?),
+        increment: Duration::from_secs(increment.parse().ok()?),
+        mode,
+    })
+}
+
+// The position --headless and --frontend tui start from: the variant's, or
+// the odds given, or the FEN given.
+fn start_position(args: &Args) -> GameResult<GameState> {
+    let mut start = match args.odds {
+        Some(odds) => GameState::with_odds(args.variant, odds),
+        None => GameState::with_variant(args.variant),
+    };
+    if let Some(fen) = &args.fen {
+        let loaded = if args.strict_fen {
+            start.from_fen_strict(fen)
+        } else {
+            start.from_fen(fen)
+        };
+        loaded.map_err(|err| GameError::CustomError(err.to_string()))?;
+    }
+    Ok(start)
+}
+
+// Puts `text` on the system clipboard, reporting it as `what` (e.g. "FEN").
+fn copy_to_clipboard(toasts: &Toasts, what: &str, text: String) {
+    match arboard::Clipboard::new().and_then(|mut cb| cb.set_text(text)) {
+        Ok(()) => toasts.info(format!("{what} copied to clipboard")),
+        Err(e) => toasts.error(format!("Failed to copy {what} to clipboard: {e}")),
+    }
+}
+
+// The game scene's event handling, passed on by App while the game is the
+// top scene.
+impl ChessGame {
+    fn update(&mut self, ctx: &mut Context) -> Result<(), GameError> {
+        // The board is drawn as big as the position on it, which a loaded
+        // FEN or the editor can change
+        let board = self.displayed_state().board;
+        if (board.files, board.ranks) != (self.layout.files, self.layout.ranks) {
+            self.layout.set_board_dimensions(board.files, board.ranks);
+            self.needs_redraw = true;
+        }
+
+        // Everything waits while a position is being set up
+        if self.editor.is_some() {
+            return Ok(());
+        }
+
+        if !self.paused {
+            self.tick_clock();
+        }
+        // Saved now and then for the clocks, and at once if they ended it
+        let autosave_due =
+            self.clock.is_some() && self.last_autosave.elapsed() >= recovery::AUTOSAVE_INTERVAL;
+        if autosave_due || (self.autosaved && self.status.is_over()) {
+            self.autosave();
+        }
+        self.poll_network();
+        self.poll_spectator(ctx);
+        self.poll_stdin_moves();
+        self.poll_server();
+
+        // A half-typed move is dropped if the game moves on without it
+        if self.typed_move.is_some() && !self.can_type_move() {
+            self.typed_move = None;
+            self.needs_redraw = true;
+        }
+
+        if self.is_ai_turn()
+            && !self.status.is_over()
+            && !self.paused
+            && self.last_move_time.elapsed() >= self.ai_move_delay
+        {
+            // AI's turn
+            if self.ai_turn() {
+                // Update turn and redraw
+                self.needs_redraw = true;
+            }
+        }
+
+        if !self.paused {
+            self.play_engine_line();
+        }
+
+        if self
+            .hint
+            .is_some_and(|(_, shown)| shown.elapsed() >= HINT_DURATION)
+        {
+            self.hint = None;
+            self.needs_redraw = true;
+        }
+
+        // Keep the analysis following whichever position is on the board
+        if let Some(mut analyser) = self.analyser.take() {
+            analyser.set_position(self.displayed_state());
+            if analyser.poll() {
+                self.needs_redraw = true;
+            }
+            self.analyser = Some(analyser);
+        }
+        if let Some(mut overlay) = self.search_overlay.take() {
+            overlay.set_position(self.displayed_state());
+            if overlay.poll() {
+                self.needs_redraw = true;
+            }
+            self.search_overlay = Some(overlay);
+        }
+        if self.review.as_mut().is_some_and(Reviewer::poll) {
+            self.record_accuracy();
+            self.needs_redraw = true;
+        }
+
+        self.sounds.borrow_mut().play_queued(ctx);
+
+        Ok(())
+    }
+
+    // Draws the board and panels. App sets up the canvas in board space (see
+    // Layout) and draws any other scenes over the top.
+    fn draw(&mut self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        // Only the pieces the blindfold leaves showing are drawn
+        let mut shown = self.displayed_state().board;
+        for square in shown.squares.iter_mut().flatten() {
+            square.occupant = square.occupant.filter(|p| self.piece_visible(p.color));
+        }
+        let view = self.board_view(&shown);
+        let theme = BoardTheme::get(self.high_contrast);
+        self.renderer(ctx, canvas).draw_board(&view)?;
+
+        if let Some(square) = self.hover_target() {
+            let hover = Color::from(theme.hover);
+            ui::fill(ctx, canvas, self.square_rect(square), hover)?;
+        }
+
+        // Mark the centre squares the kings race for in King of the Hill
+        if self.state.variant == Variant::KingOfTheHill {
+            // The hill's corners are the first and last of its squares on screen
+            let board = &self.state.board;
+            let (first, last) = {
+                let mut hill = (0..board.ranks)
+                    .flat_map(|row| (0..board.files).map(move |col| (row, col)))
+                    .filter(|&square| Variant::is_hill(board, square))
+                    .map(|square| self.square_rect(square));
+                let first = hill.next().unwrap_or_default();
+                (first, hill.last().unwrap_or(first))
+            };
+            let hill = Rect::new(
+                first.x.min(last.x),
+                first.y.min(last.y),
+                (first.x - last.x).abs() + self.layout.tile_size,
+                (first.y - last.y).abs() + self.layout.tile_size,
+            );
+            let outline = Mesh::new_rectangle(
+                ctx,
+                DrawMode::stroke(self.layout.tile_size * 0.04),
+                hill,
+                Color::from_rgb(218, 165, 32),
+            )?;
+            canvas.draw(&outline, DrawParam::default());
+        }
+
+        // Outline the king in red when it is in check
+        let displayed = self.displayed_state();
+        if displayed.is_king_in_check(displayed.turn) && self.piece_visible(displayed.turn) {
+            if let Some(king) = displayed.find_king(displayed.turn) {
+                let rect = self.square_rect(king);
+                let outline = Mesh::new_rectangle(
+                    ctx,
+                    DrawMode::stroke(self.layout.tile_size * 0.06),
+                    rect,
+                    Color::RED,
+                )?;
+                canvas.draw(&outline, DrawParam::default());
+            }
+        }
+
+        if self.show_threats {
+            self.draw_threats(ctx, canvas)?;
+        }
+
+        self.draw_coordinates(ctx, canvas)?;
+
+        render::draw_pieces(&mut self.renderer(ctx, canvas), &view)?;
+
+        if self.show_possible_moves {
+            self.draw_move_targets(ctx, canvas)?;
+        }
+
+        if self.piece_letters {
+            self.draw_piece_letters(ctx, canvas)?;
+        }
+
+        if self.show_control {
+            self.draw_square_control(ctx, canvas)?;
+        }
+
+        if self.show_pawns {
+            self.draw_pawn_structure(ctx, canvas)?;
+        }
+
+        self.draw_annotations(ctx, canvas)?;
+
+        if let Some((mv, _)) = self.hint.filter(|_| self.viewed_ply.is_none()) {
+            let color = Color::from_rgba(40, 120, 220, 180);
+            self.draw_arrow(ctx, canvas, mv.from, mv.to, color)?;
+        }
+
+        if let (MoveInput::PendingPromotion(mv), None) = (self.move_input, self.viewed_ply) {
+            let choices = self.promotion_choice_rects(mv);
+            let mut background = choices[0].1;
+            background.w *= choices.len() as f32;
+            let background_mesh = Mesh::new_rectangle(
+                ctx,
+                DrawMode::fill(),
+                background,
+                Color::from_rgba(196, 192, 188, 180),
+            )?;
+            canvas.draw(&background_mesh, DrawParam::default());
+
+            for (piece_type, rect) in choices {
+                self.pieces.draw_piece(
+                    ctx,
+                    canvas,
+                    self.state.turn,
+                    piece_type,
+                    rect.x,
+                    rect.y,
+                    self.layout.tile_size,
+                )?;
+            }
+        }
+
+        if self.editor.is_some() {
+            self.draw_editor(ctx, canvas)?;
+            return self.draw_clocks(ctx, canvas);
+        }
+
+        self.draw_history(ctx, canvas)?;
+        self.draw_analysis(ctx, canvas)?;
+        self.draw_clocks(ctx, canvas)?;
+        self.draw_captures(ctx, canvas)?;
+        if self.typed_move.is_some() {
+            self.draw_typed_move(ctx, canvas)?;
+        } else if self.typed_comment.is_some() {
+            self.draw_typed_comment(ctx, canvas)?;
+        } else {
+            self.draw_status_bar(ctx, canvas)?;
+        }
+        self.draw_search_overlay(ctx, canvas)?;
+
+        if self.paused {
+            self.draw_paused(ctx, canvas)?;
+        } else if self.takeback_offer.is_some() {
+            self.draw_dialog(
+                ctx,
+                canvas,
+                "Opponent asks for a takeback",
+                &["Accept", "Decline"],
+            )?;
+        }
+        if self.adjourn_offer {
+            self.draw_dialog(
+                ctx,
+                canvas,
+                "Opponent asks to adjourn",
+                &["Accept", "Decline"],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        _ctx: &mut Context,
+        keycode: ggez::input::keyboard::KeyInput,
+        _repeat: bool,
+    ) -> Result<(), GameError> {
+        if let Some(key) = keycode.keycode {
+            // The editor has its own keys: Enter plays the position, Escape or
+            // Ctrl+E leaves it, Ctrl+C copies its FEN and Delete clears the board
+            if let Some(editor) = &mut self.editor {
+                let ctrl = keycode.mods.contains(KeyMods::CTRL);
+                match key {
+                    ggez::input::keyboard::KeyCode::Return => self.finish_editor(),
+                    ggez::input::keyboard::KeyCode::Escape => self.cancel_editor(),
+                    ggez::input::keyboard::KeyCode::E if ctrl => self.cancel_editor(),
+                    ggez::input::keyboard::KeyCode::C if ctrl => {
+                        copy_to_clipboard(&self.toasts, "FEN", editor.to_fen());
+                    }
+                    ggez::input::keyboard::KeyCode::Delete => editor.clear(),
+                    _ => {}
+                }
+                self.needs_redraw = true;
+                return Ok(());
+            }
+
+            // Keys go to the move being typed, if there is one
+            if self.typed_move.is_some() {
+                self.typed_move_key(key);
+                return Ok(());
+            }
+            // and so to the comment being typed
+            if self.typed_comment.is_some() {
+                self.typed_comment_key(key);
+                return Ok(());
+            }
+
+            // While analysing, Up and Down show more or fewer of the best
+            // moves; + and - change the depth (see text_input_event)
+            if self.analyser.is_some() {
+                match key {
+                    ggez::input::keyboard::KeyCode::Up => {
+                        self.change_analysis(0, 1);
+                        return Ok(());
+                    }
+                    ggez::input::keyboard::KeyCode::Down => {
+                        self.change_analysis(0, -1);
+                        return Ok(());
+                    }
+                    _ => {}
+                }
+            }
+
+            // While reviewing, 1 to 6 give the move shown a glyph, from "!" to
+            // "?!", or take it away again; C comments on it (see
+            // text_input_event)
+            if self.review.is_some() {
+                let nag = match key {
+                    ggez::input::keyboard::KeyCode::Key1 => Some(1),
+                    ggez::input::keyboard::KeyCode::Key2 => Some(2),
+                    ggez::input::keyboard::KeyCode::Key3 => Some(3),
+                    ggez::input::keyboard::KeyCode::Key4 => Some(4),
+                    ggez::input::keyboard::KeyCode::Key5 => Some(5),
+                    ggez::input::keyboard::KeyCode::Key6 => Some(6),
+                    _ => None,
+                };
+                if let Some(nag) = nag {
+                    self.toggle_glyph(nag);
+                    return Ok(());
+                }
+            }
+
+            // While choosing a promotion piece, its letter picks it (Q/R/B/N,
+            // A/C for the fairy pieces or K in Antichess) and Escape cancels
+            if let MoveInput::PendingPromotion(_) = self.move_input {
+                let letter = match key {
+                    ggez::input::keyboard::KeyCode::Q => Some('Q'),
+                    ggez::input::keyboard::KeyCode::R => Some('R'),
+                    ggez::input::keyboard::KeyCode::B => Some('B'),
+                    ggez::input::keyboard::KeyCode::N => Some('N'),
+                    ggez::input::keyboard::KeyCode::A => Some('A'),
+                    ggez::input::keyboard::KeyCode::C => Some('C'),
+                    ggez::input::keyboard::KeyCode::K => Some('K'),
+                    ggez::input::keyboard::KeyCode::Escape => {
+                        self.cancel_pending_move();
+                        return Ok(());
+                    }
+                    _ => None,
+                };
+                let choice = letter
+                    .and_then(PieceType::from_letter)
+                    .filter(|piece_type| self.state.promotion_pieces().contains(piece_type));
+                if let Some(piece_type) = choice {
+                    self.finish_promotion(piece_type);
+                    return Ok(());
+                }
+            }
+
+            // Enter plays a move waiting to be confirmed and Escape drops it
+            if let MoveInput::Confirming(_) = self.move_input {
+                match key {
+                    ggez::input::keyboard::KeyCode::Return
+                    | ggez::input::keyboard::KeyCode::NumpadEnter => {
+                        self.confirm_move();
+                        return Ok(());
+                    }
+                    ggez::input::keyboard::KeyCode::Escape => {
+                        self.cancel_pending_move();
+                        return Ok(());
+                    }
+                    _ => {}
+                }
+            }
+
+            // Ctrl+C copies the FEN (Ctrl+Shift+C the PGN), Ctrl+V loads a FEN,
+            // Ctrl+E opens the position editor and Ctrl+P saves the board as an
+            // image (Ctrl+Shift+P without coordinates, highlight or caption).
+            // Ctrl+N, the main menu, and Ctrl+L, typing in a FEN, are App's.
+            if keycode.mods.contains(KeyMods::CTRL) {
+                match key {
+                    ggez::input::keyboard::KeyCode::C if keycode.mods.contains(KeyMods::SHIFT) => {
+                        copy_to_clipboard(&self.toasts, "PGN", self.to_pgn());
+                    }
+                    ggez::input::keyboard::KeyCode::C => {
+                        copy_to_clipboard(&self.toasts, "FEN", self.state.to_fen());
+                    }
+                    ggez::input::keyboard::KeyCode::V => {
+                        self.paste_fen();
+                    }
+                    ggez::input::keyboard::KeyCode::E => {
+                        self.open_editor();
+                    }
+                    // Drawing has to wait for a frame; see save_requested_image
+                    ggez::input::keyboard::KeyCode::P => {
+                        self.image_request = Some(keycode.mods.contains(KeyMods::SHIFT));
+                        self.needs_redraw = true;
+                    }
+                    _ => {}
+                }
+                return Ok(());
+            }
+
+            match key {
+                ggez::input::keyboard::KeyCode::M => {
+                    self.show_possible_moves = !self.show_possible_moves;
+                    self.settings.borrow_mut().show_possible_moves = Some(self.show_possible_moves);
+                    self.settings.borrow().save();
+                    self.needs_redraw = true;
+                }
+                ggez::input::keyboard::KeyCode::W => {
+                    self.show_threats = !self.show_threats;
+                    let shown = if self.show_threats { "shown" } else { "hidden" };
+                    self.toasts.info(format!("Threats {shown}"));
+                    self.settings.borrow_mut().show_threats = Some(self.show_threats);
+                    self.settings.borrow().save();
+                    self.needs_redraw = true;
+                }
+                ggez::input::keyboard::KeyCode::X => {
+                    self.show_control = !self.show_control;
+                    self.needs_redraw = true;
+                }
+                ggez::input::keyboard::KeyCode::K => {
+                    self.show_pawns = !self.show_pawns;
+                    if self.show_pawns {
+                        self.toasts
+                            .info("Pawn structure: I isolated, D doubled, B backward, P passed");
                     }
-                } else {
-                    return Ok(()); // No piece at promotion square; ignore
-                };
-            
-                // Check if the click falls within one of the promotion options
-                for (i, piece_type) in options.iter().enumerate() {
-                    let option_x = rect_x + i as f32 * self.tile_size;
-                    let option_y = rect_y;
-            
-                    if option_x <= x && x < option_x + self.tile_size && option_y <= y && y < option_y + self.tile_size {
-                        self.promote_pawn((row, col), *piece_type); // Promote to the selected piece
-                        self.promotion_square = None; // Clear promotion state
-                        self.needs_redraw = true;
-                        return Ok(());
+                    self.needs_redraw = true;
+                }
+                ggez::input::keyboard::KeyCode::F => {
+                    copy_to_clipboard(&self.toasts, "FEN", self.state.to_fen());
+                }
+                ggez::input::keyboard::KeyCode::S => {
+                    self.save_pgn();
+                }
+                ggez::input::keyboard::KeyCode::A => {
+                    let muted = self.sounds.borrow_mut().toggle_mute();
+                    self.toasts
+                        .info(format!("Sound {}", if muted { "off" } else { "on" }));
+                    self.settings.borrow_mut().muted = Some(muted);
+                    self.settings.borrow().save();
+                }
+                ggez::input::keyboard::KeyCode::E => {
+                    self.toggle_analysis();
+                }
+                ggez::input::keyboard::KeyCode::H => {
+                    self.show_hint();
+                }
+                ggez::input::keyboard::KeyCode::D => {
+                    self.toggle_search_overlay();
+                }
+                ggez::input::keyboard::KeyCode::O => {
+                    self.toggle_explorer();
+                }
+                ggez::input::keyboard::KeyCode::B => {
+                    self.cycle_blindfold();
+                }
+                ggez::input::keyboard::KeyCode::Space if self.blindfold != Blindfold::Off => {
+                    self.revealed = true;
+                    self.needs_redraw = true;
+                }
+                ggez::input::keyboard::KeyCode::R if self.status.is_over() => {
+                    self.toggle_review();
+                }
+                ggez::input::keyboard::KeyCode::T => {
+                    self.request_takeback();
+                }
+                ggez::input::keyboard::KeyCode::P => {
+                    self.toggle_pause();
+                }
+                ggez::input::keyboard::KeyCode::J => {
+                    self.request_adjournment();
+                }
+                // Step through the move list
+                ggez::input::keyboard::KeyCode::Left => {
+                    let current = self.viewed_ply.unwrap_or(self.history.len());
+                    if current > 0 {
+                        self.view_ply(current - 1);
+                    }
+                }
+                // and on from the end of a line into a variation carrying on
+                // from it
+                ggez::input::keyboard::KeyCode::Right => {
+                    let live = self.history.len();
+                    if let Some(ply) = self.viewed_ply {
+                        self.view_ply(ply + 1);
+                    } else if let Some((index, _)) =
+                        self.variations().into_iter().find(|&(_, ply)| ply == live)
+                    {
+                        self.switch_line(index, live + 1);
                     }
                 }
+                _ => {}
             }
+        }
+        Ok(())
+    }
 
-            if let Some((row, col)) = self.coords_to_square(x, y) {
-                if let Some(selected) = self.selected {
-                    if selected == (row, col) {
-                        // Unselect the currently selected square
-                        self.selected = None;
-                        self.valid_moves.clear();
+    // Letting go of Space puts the blindfold back on.
+    fn key_up_event(
+        &mut self,
+        _ctx: &mut Context,
+        input: ggez::input::keyboard::KeyInput,
+    ) -> Result<(), GameError> {
+        if input.keycode == Some(ggez::input::keyboard::KeyCode::Space) && self.revealed {
+            self.revealed = false;
+            self.needs_redraw = true;
+        }
+        Ok(())
+    }
+
+    fn mouse_button_up_event(
+        &mut self,
+        ctx: &mut Context,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+    ) -> Result<(), GameError> {
+        if button == MouseButton::Middle {
+            self.panning = false;
+        }
+        if button == MouseButton::Right {
+            let (x, y) = self.layout.to_board_space(x, y);
+            if let (Some(from), Some(to)) = (self.annotation_start.take(), self.square_at(x, y)) {
+                self.toggle_annotation(Annotation {
+                    from,
+                    to,
+                    color: AnnotationColor::from_mods(ctx.keyboard.active_mods()),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // "/" starts typing a move; the characters after it are the move. While
+    // reviewing, "c" starts typing a comment on the move shown.
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) -> Result<(), GameError> {
+        if let Some(typed) = &mut self.typed_comment {
+            if !character.is_control() {
+                typed.push(character);
+                self.needs_redraw = true;
+            }
+            return Ok(());
+        }
+        if self.review.is_some() && self.typed_move.is_none() && character == 'c' {
+            self.start_comment();
+            return Ok(());
+        }
+        if self.analyser.is_some() && self.typed_move.is_none() {
+            let step = match character {
+                '+' | '=' => 1,
+                '-' => -1,
+                _ => 0,
+            };
+            if step != 0 {
+                self.change_analysis(step, 0);
+                return Ok(());
+            }
+        }
+        let can_type = self.can_type_move();
+        match &mut self.typed_move {
+            None if character == '/' && can_type => self.typed_move = Some(String::new()),
+            Some(typed) if character.is_ascii_alphanumeric() || "=+#-".contains(character) => {
+                typed.push(character);
+            }
+            _ => return Ok(()),
+        }
+        self.needs_redraw = true;
+        Ok(())
+    }
+
+    // Drags the view while the middle button is down, and keeps track of
+    // the square under the pointer for the hover highlight.
+    fn mouse_motion_event(
+        &mut self,
+        _ctx: &mut Context,
+        x: f32,
+        y: f32,
+        dx: f32,
+        dy: f32,
+    ) -> Result<(), GameError> {
+        if self.panning {
+            self.layout.pan_by(dx, dy);
+            self.needs_redraw = true;
+        }
+        let (x, y) = self.layout.to_board_space(x, y);
+        let hovered = self.square_at(x, y);
+        if hovered != self.hovered {
+            self.hovered = hovered;
+            self.needs_redraw = true;
+        }
+        Ok(())
+    }
+
+    // Scrolls the move list when the pointer is over it and zooms the view
+    // anywhere else. Positive y is the wheel turning away from the user.
+    fn mouse_wheel_event(&mut self, ctx: &mut Context, _x: f32, y: f32) -> Result<(), GameError> {
+        let pointer = ctx.mouse.position();
+        let over = self.layout.to_board_space(pointer.x, pointer.y);
+        if self.layout.history_panel_rect().contains([over.0, over.1]) {
+            if y > 0.0 {
+                self.scroll_history(-1);
+            } else if y < 0.0 {
+                self.scroll_history(1);
+            }
+        } else {
+            self.layout.zoom_at(y, pointer.x, pointer.y);
+            self.needs_redraw = true;
+        }
+        Ok(())
+    }
+
+    fn mouse_button_down_event(
+        &mut self,
+        ctx: &mut Context,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+    ) -> Result<(), GameError> {
+        // A middle-drag pans the view, wherever it starts
+        if button == MouseButton::Middle {
+            self.panning = true;
+            return Ok(());
+        }
+
+        let (x, y) = self.layout.to_board_space(x, y);
+
+        // In the editor a right click takes a piece off the board
+        if self.editor.is_some() {
+            match button {
+                MouseButton::Left => {
+                    let shift = ctx.keyboard.active_mods().contains(KeyMods::SHIFT);
+                    self.editor_click(x, y, shift);
+                }
+                MouseButton::Right => {
+                    if let (Some(square), Some(editor)) =
+                        (self.coords_to_square(x, y), &mut self.editor)
+                    {
+                        editor.erase(square);
                         self.needs_redraw = true;
-                    } else if self.validate_move(selected, (row, col)) {
-                        let mut piece = self.board.squares[selected.0][selected.1]
-                            .occupant
-                            .take()
-                            .unwrap();
-
-                        piece.has_moved = true;
-
-                        // Update the target square with the pawn
-                        self.board.squares[row][col].occupant = Some(piece);
-
-                        // Update en passant target for pawns moving two squares
-                        if piece.piece_type == PieceType::Pawn
-                            && (selected.0 as isize - row as isize).abs() == 2
-                        {
-                            self.en_passant_target = Some(((selected.0 + row) / 2, col));
-                        } else {
-                            self.en_passant_target = None;
-                        }
+                    }
+                }
+                _ => {}
+            }
+            return Ok(());
+        }
 
-                        if piece.piece_type == PieceType::Pawn
-                            && Some((row, col)) == self.en_passant_target
-                        {
-                            let captured_pawn_row = if piece.color == PieceColor::White {
-                                row + 1
-                            } else {
-                                row - 1
-                            };
-                            self.board.squares[captured_pawn_row][col].occupant = None;
-                        }
+        // Right-drag draws an arrow, finished in mouse_button_up_event
+        if button == MouseButton::Right {
+            self.annotation_start = self.square_at(x, y);
+            return Ok(());
+        }
 
-                        if piece.piece_type == PieceType::Pawn {
-                            let promotion_row = if piece.color == PieceColor::White {
-                                0
-                            } else {
-                                7
-                            };
-                            
-                            if row == promotion_row {
-                                self.promotion_square = Some((row, col)); // Set promotion state
-                                self.needs_redraw = true;
-                            }
-                        }
+        if button == MouseButton::Left {
+            if self.analyser.is_some() && self.layout.analysis_line_rect().contains([x, y]) {
+                self.analysis_click(x, y);
+                return Ok(());
+            }
+            if self.layout.history_panel_rect().contains([x, y]) {
+                self.history_click(x, y);
+                return Ok(());
+            }
 
-                        // Update castling rights (if a rook or king moves)
-                        if piece.piece_type == PieceType::Rook
-                            || piece.piece_type == PieceType::King
-                        {
-                            self.update_castling_rights(selected);
-                        }
+            // Clicking a piece of the side to move while browsing the history
+            // goes back there to play another move, starting a variation,
+            // where that can be done; otherwise it returns to the game
+            if let Some(ply) = self.viewed_ply {
+                let displayed = self.displayed_state();
+                let picks_up = self.square_at(x, y).is_some_and(|(row, col)| {
+                    displayed.board.squares[row][col]
+                        .occupant
+                        .is_some_and(|piece| piece.color == displayed.turn)
+                });
+                if picks_up && self.can_branch() {
+                    self.branch_at(ply);
+                } else {
+                    let live = self.history.len();
+                    self.view_ply(live);
+                    return Ok(());
+                }
+            }
 
-                        // Update move counters
-                        if piece.piece_type == PieceType::Pawn
-                            || self.board.squares[row][col].occupant.is_some()
-                        {
-                            self.halfmove_clock = 0;
-                        } else {
-                            self.halfmove_clock += 1;
-                        }
-                        if self.turn == PieceColor::Black {
-                            self.fullmove_number += 1;
-                        }
+            // An adjournment or takeback offer has to be answered before play
+            // goes on
+            if self.adjourn_offer {
+                if self.dialog_button_rect(0, 2).contains([x, y]) {
+                    self.answer_adjournment(true);
+                } else if self.dialog_button_rect(1, 2).contains([x, y]) {
+                    self.answer_adjournment(false);
+                }
+                return Ok(());
+            }
+            if self.takeback_offer.is_some() {
+                if self.dialog_button_rect(0, 2).contains([x, y]) {
+                    self.answer_takeback(true);
+                } else if self.dialog_button_rect(1, 2).contains([x, y]) {
+                    self.answer_takeback(false);
+                }
+                return Ok(());
+            }
 
-                        if piece.piece_type == PieceType::King
-                            && (selected.1 as isize - col as isize).abs() == 2
-                        {
-                            self.perform_castling(selected, (row, col));
-                        }
+            // Once the game is over it can only be looked back through
+            if self.status.is_over() {
+                return Ok(());
+            }
+
+            // Pick the promotion piece; clicking anywhere else cancels the move
+            if let MoveInput::PendingPromotion(mv) = self.move_input {
+                let choice = self
+                    .promotion_choice_rects(mv)
+                    .into_iter()
+                    .find(|(_, rect)| rect.contains([x, y]));
+                match choice {
+                    Some((piece_type, _)) => self.finish_promotion(piece_type),
+                    None => self.cancel_pending_move(),
+                }
+                return Ok(());
+            }
+
+            // Clicking the destination again plays the move waiting to be
+            // confirmed; clicking anywhere else drops it
+            if let MoveInput::Confirming(mv) = self.move_input {
+                if self.square_at(x, y) == Some(mv.to) {
+                    self.confirm_move();
+                } else {
+                    self.cancel_pending_move();
+                }
+                return Ok(());
+            }
 
-                        self.turn = match self.turn {
-                            PieceColor::White => PieceColor::Black,
-                            PieceColor::Black => PieceColor::White,
-                        };
+            // The board is locked while the AI or the remote player is to move
+            if self.input_locked() {
+                return Ok(());
+            }
+
+            if let Some((row, col)) = self.square_at(x, y) {
+                if let Some(selected) = self.selected {
+                    if selected == (row, col) {
+                        // Unselect the currently selected square
                         self.selected = None;
                         self.valid_moves.clear();
                         self.needs_redraw = true;
+                    } else if self.is_legal(selected, (row, col)) {
+                        let mv = Move::new(selected, (row, col));
+                        if self.confirm_moves {
+                            self.move_input = MoveInput::Confirming(mv);
+                            self.needs_redraw = true;
+                        } else {
+                            self.play_clicked_move(mv);
+                        }
                     } else {
                         // Invalid move, clear selection
                         self.selected = None;
@@ -1392,15 +5104,16 @@ impl EventHandler<GameError> for ChessGame {
                     }
                 } else {
                     // Select a square if it has a piece belonging to the current player
-                    if let Some(piece) = self.board.squares[row][col].occupant {
-                        if piece.color == self.turn {
+                    if let Some(piece) = self.state.board.squares[row][col].occupant {
+                        if piece.color == self.state.turn {
                             self.selected = Some((row, col));
-                            self.valid_moves = self
-                                .generate_valid_moves(self.turn)
-                                .into_iter()
-                                .filter(|(start, _)| *start == (row, col))
-                                .map(|(_, end)| end)
+                            let targets = self
+                                .legal_moves()
+                                .iter()
+                                .filter(|mv| mv.from == (row, col))
+                                .map(|mv| mv.to)
                                 .collect();
+                            self.valid_moves = targets;
                             self.needs_redraw = true;
                         }
                     }
@@ -1419,23 +5132,540 @@ impl EventHandler<GameError> for ChessGame {
 fn main() -> GameResult {
     // Parse command-line arguments
     let args = Args::parse();
+    if let Err(e) = logging::init(args.verbose, args.log_file.as_deref()) {
+        eprintln!("Failed to open the log file: {e}");
+    }
+
+    // Flags given on the command line win over the remembered settings, and
+    // are remembered in turn
+    let mut settings = Settings::load();
+    settings.board_size = args.board_size.or(settings.board_size);
+    settings.ai_depth = args.ai_depth.or(settings.ai_depth);
+    if args.ai_depth.is_some() {
+        settings.difficulty = None;
+    }
+    settings.difficulty = args.difficulty.or(settings.difficulty);
+    settings.ai_move_delay = args.ai_move_delay.or(settings.ai_move_delay);
+    settings.volume = args.volume.or(settings.volume);
+    if args.mute {
+        settings.muted = Some(true);
+    }
+    settings.announce = args.announce.or(settings.announce);
+    if args.high_contrast {
+        settings.high_contrast = Some(true);
+    }
+    if args.piece_letters {
+        settings.piece_letters = Some(true);
+    }
+    if args.auto_flip {
+        settings.auto_flip = Some(true);
+    }
+    if args.confirm_moves {
+        settings.confirm_moves = Some(true);
+    }
+    if args.show_threats {
+        settings.show_threats = Some(true);
+    }
+    let ai_depth = settings.ai_depth.unwrap_or(DEFAULT_AI_DEPTH);
+
+    // Raised before the window opens, shown once it has
+    let toasts = Toasts::default();
+    if !args.explorer_import.is_empty() {
+        let mut explorer = Explorer::load();
+        for path in &args.explorer_import {
+            match std::fs::read_to_string(path) {
+                Ok(pgn) => {
+                    let added = explorer.import(&pgn);
+                    log::info!(
+                        "Added {added} games from {} to the explorer",
+                        path.display()
+                    );
+                    toasts.info(format!("Added {added} games to the explorer"));
+                }
+                Err(err) => toasts.error(format!("Failed to read {}: {err}", path.display())),
+            }
+        }
+        explorer.save();
+    }
+    if let Some(path) = &args.repertoire_import {
+        let imported = std::fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|pgn| Repertoire::import(&pgn));
+        match imported {
+            Ok(lines) => {
+                log::info!(
+                    "Imported a repertoire of {lines} lines from {}",
+                    path.display()
+                );
+                toasts.info(format!("Imported a repertoire of {lines} lines to drill"));
+            }
+            Err(err) => toasts.error(format!("Failed to import {}: {err}", path.display())),
+        }
+    }
+    if !args.db_import.is_empty() {
+        match Database::open() {
+            Ok(mut database) => {
+                let openings = OpeningNames::builtin();
+                for path in &args.db_import {
+                    let imported = std::fs::read_to_string(path)
+                        .map_err(|err| err.to_string())
+                        .and_then(|pgn| database.import(&pgn, &openings, args.db_duplicates));
+                    match imported {
+                        Ok(summary) => {
+                            let duplicates = match args.db_duplicates {
+                                Duplicates::Skip => "skipped",
+                                Duplicates::Merge => "merged",
+                            };
+                            log::info!(
+                                "Added {} games from {} to the database; {} duplicates {duplicates}, {} unreadable",
+                                summary.added,
+                                path.display(),
+                                summary.duplicates,
+                                summary.unreadable
+                            );
+                            let mut message =
+                                format!("Added {} games to the database", summary.added);
+                            if summary.duplicates > 0 {
+                                message.push_str(&format!(
+                                    " ({} already there, {duplicates})",
+                                    summary.duplicates
+                                ));
+                            }
+                            toasts.info(message);
+                        }
+                        Err(err) => toasts.error(format!(
+                            "Failed to import {} into the database: {err}",
+                            path.display()
+                        )),
+                    }
+                }
+            }
+            Err(err) => toasts.error(format!("Failed to open the games database: {err}")),
+        }
+    }
+    if let Some([player, path]) = args.report.as_deref() {
+        let games = Database::open()
+            .and_then(|database| database.player_games(player))
+            .map_err(GameError::CustomError)?;
+        if games.is_empty() {
+            return Err(GameError::CustomError(format!(
+                "No games of {player} in the database"
+            )));
+        }
+        let report = Report::build(&games);
+        report.save(Path::new(path))?;
+        println!(
+            "Wrote a report on {} games of {player} to {path}",
+            games.len()
+        );
+        return Ok(());
+    }
+    let book = match &args.book {
+        Some(path) => OpeningBook::load(path).unwrap_or_else(|err| {
+            toasts.error(format!("{err}; using the built-in book"));
+            OpeningBook::builtin()
+        }),
+        None => OpeningBook::builtin(),
+    };
+    let network_loaded = match &args.nn {
+        Some(path) => match Network::load(path).and_then(Network::install) {
+            Ok(()) => true,
+            Err(err) => {
+                toasts.error(format!("{err}; using the handcrafted evaluation"));
+                false
+            }
+        },
+        None => false,
+    };
+
+    if args.uci {
+        uci::run(args.variant);
+        return Ok(());
+    }
+
+    // Every run is seeded, so one that goes wrong can be played again
+    let seed = args.seed.unwrap_or_else(rand::random);
+    log::info!("Seeded the AI with {seed}; --seed {seed} plays the same way again");
+
+    if args.headless {
+        let start = start_position(&args)?;
+        // Without --engine it is the one match between the two depths
+        let alternate_colors = !args.engines.is_empty();
+        let engines = if alternate_colors {
+            args.engines.clone()
+        } else {
+            vec![
+                Engine::Depth(args.white_depth.unwrap_or(ai_depth)),
+                Engine::Depth(args.black_depth.unwrap_or(ai_depth)),
+            ]
+        };
+        if engines.len() < 2 {
+            return Err(GameError::CustomError(
+                "A headless tournament needs at least two engines".to_string(),
+            ));
+        }
+        headless::run(&MatchConfig {
+            engines,
+            games: args.games,
+            gauntlet: args.gauntlet,
+            alternate_colors,
+            sprt: args.sprt,
+            pgn_dir: args.pgn_dir.clone(),
+            start,
+            book,
+            seed,
+        });
+        return Ok(());
+    }
 
-    let (ctx, event_loop) = ContextBuilder::new("chess", "YourName")
+    // Not before now: stdout belongs to the protocol in UCI mode
+    if let (true, Some(path)) = (network_loaded, &args.nn) {
+        toasts.info(format!("Loaded the evaluation network {path}"));
+    }
+
+    let ai_color = match args.ai_color {
+        Some(ai_color) => Some(ai_color),
+        None if args.opponent => Some(AiColor::Black),
+        None => None,
+    };
+
+    // An adjourned game brings its own time control
+    let adjourned = match &args.resume {
+        Some(path) => {
+            let pgn = std::fs::read_to_string(path)
+                .map_err(|e| GameError::CustomError(format!("Failed to read {path}: {e}")))?;
+            Some(parse_pgn(&pgn).map_err(GameError::CustomError)?)
+        }
+        None => None,
+    };
+    // Each side plays on its own time control if given one, otherwise the
+    // shared one, otherwise the other side's
+    let shared_time_control = args.time_control.or_else(|| {
+        adjourned
+            .as_ref()
+            .and_then(|adjourned| time_control_tag(adjourned, "TimeControl"))
+    });
+    let armageddon = args.armageddon.then_some(ARMAGEDDON_TIME_CONTROLS);
+    let time_white = args
+        .time_white
+        .or(shared_time_control)
+        .or(armageddon.map(|(white, _)| white))
+        .or(args.time_black);
+    let time_black = args
+        .time_black
+        .or(shared_time_control)
+        .or(armageddon.map(|(_, black)| black))
+        .or(args.time_white);
+    let clock = time_white
+        .zip(time_black)
+        .map(|(white, black)| ChessClock::asymmetric(white, black));
+
+    if args.frontend == Frontend::Tui {
+        let engine = match settings.difficulty {
+            Some(difficulty) => Engine::Level(difficulty),
+            None => Engine::Depth(ai_depth),
+        };
+        tui::run(TuiConfig {
+            start: start_position(&args)?,
+            ai_color,
+            engine,
+            book,
+            clock,
+            high_contrast: settings.high_contrast.unwrap_or(false),
+            seed,
+        })
+        .map_err(|e| GameError::CustomError(format!("Terminal error: {e}")))?;
+        return Ok(());
+    }
+
+    // The game to replay into a GIF, read before any window opens
+    let replay = match &args.export_gif {
+        Some(paths) => {
+            let path = paths[0].display();
+            let pgn = std::fs::read_to_string(&paths[0])
+                .map_err(|e| GameError::CustomError(format!("Failed to read {path}: {e}")))?;
+            Some(parse_pgn(&pgn).map_err(GameError::CustomError)?)
+        }
+        None => None,
+    };
+
+    let layout = Layout::new(
+        settings.board_size.unwrap_or(DEFAULT_BOARD_SIZE),
+        args.coordinates == CoordinateLabels::Outside,
+        clock.is_some(),
+    );
+    let (window_width, window_height) = layout.window_size();
+    let (min_width, min_height) = Layout::new(
+        MIN_BOARD_SIZE,
+        args.coordinates == CoordinateLabels::Outside,
+        clock.is_some(),
+    )
+    .window_size();
+
+    let (mut ctx, event_loop) = ContextBuilder::new("chess", "YourName")
         .window_setup(WindowSetup::default().title("justchess"))
-        .window_mode(WindowMode::default().dimensions(args.board_size, args.board_size))
+        .window_mode(
+            WindowMode::default()
+                .dimensions(window_width, window_height)
+                .resizable(true)
+                .min_dimensions(min_width, min_height)
+                .visible(replay.is_none()),
+        )
         .build()?;
 
-    let mut game = ChessGame::new(args.opponent, args.board_size / 8.0)?;
+    let mut sounds = SoundEffects::new(&ctx, settings.muted.unwrap_or(false));
+    if let Some(volume) = settings.volume {
+        sounds.set_volume(volume);
+    }
+    let mut game = ChessGame::new(
+        ai_color,
+        ai_depth,
+        Duration::from_millis(settings.ai_move_delay.unwrap_or(DEFAULT_AI_MOVE_DELAY_MS)),
+        layout,
+        args.coordinates,
+        clock,
+        Rc::new(RefCell::new(sounds)),
+    )?;
+
+    game.odds = args.odds;
+    game.armageddon = args.armageddon;
+    game.reset_to(game.starting_position(args.variant));
+    game.blindfold = args.blindfold;
+    game.toasts = toasts;
+
+    game.book = Rc::new(book);
+    game.rng = StdRng::seed_from_u64(seed);
+    game.seeded = args.seed.is_some();
+    game.difficulty = settings.difficulty;
+
+    if let Some(piece_set) = args.piece_set.clone().or_else(|| settings.piece_set.take()) {
+        match Pieces::load_set(&ctx, &piece_set) {
+            Ok(pieces) => {
+                game.pieces = Rc::new(pieces);
+                settings.piece_set = Some(piece_set);
+            }
+            Err(err) => game.toasts.error(format!(
+                "Failed to load piece set, using built-in pieces: {err}"
+            )),
+        }
+    }
+
+    game.show_possible_moves = settings.show_possible_moves.unwrap_or(true);
+    game.high_contrast = settings.high_contrast.unwrap_or(false);
+    game.piece_letters = settings.piece_letters.unwrap_or(false);
+    game.auto_flip = settings.auto_flip.unwrap_or(false);
+    game.confirm_moves = settings.confirm_moves.unwrap_or(false);
+    game.show_threats = settings.show_threats.unwrap_or(false);
+    game.announcer = Announcer::new(settings.announce.unwrap_or_default());
+    let stats = Stats::load();
+    if args.keep_series {
+        game.series = stats.series;
+        game.keep_series = true;
+    }
+    game.stats = Rc::new(RefCell::new(stats));
+    if args.keep_evals {
+        game.evals = Arc::new(Mutex::new(EvalCache::kept()));
+    }
+    game.info = GameInfo {
+        event: args.event.clone(),
+        site: args.site.clone(),
+        round: args.round.clone(),
+        white: args.white.clone(),
+        black: args.black.clone(),
+        white_elo: args.white_elo,
+        black_elo: args.black_elo,
+    };
+    settings.save();
+    game.settings = Rc::new(RefCell::new(settings));
+
+    if let (Some(replay), Some(paths)) = (replay, &args.export_gif) {
+        game.ai_color = None;
+        game.play_out(replay);
+        event::run(ctx, event_loop, ReplayExport::new(game, paths[1].clone()))
+    }
+
+    // Played out before anything that hears of moves is set up
+    if let Some(path) = &args.correspondence {
+        let local_color = match args.play_as {
+            Side::White => PieceColor::White,
+            Side::Black => PieceColor::Black,
+        };
+        let correspondence = Correspondence::new(path.clone(), local_color, args.notify.clone());
+        if let Some(saved) = correspondence.load().map_err(GameError::CustomError)? {
+            game.play_out(saved);
+        }
+        game.correspondence = Some(correspondence);
+        game.greet_correspondence();
+    }
+    game.autosave = true;
+    game.print_board = args.print_board;
+    if args.stdin_moves {
+        game.stdin_moves = Some(StdinMoves::read());
+    }
+    if let Some(addr) = &args.serve {
+        let server = Server::serve(addr)
+            .map_err(|e| GameError::CustomError(format!("Failed to serve on {addr}: {e}")))?;
+        game.server = Some(server);
+    }
+
+    if let Some(addr) = &args.host {
+        let connection = Connection::host(addr)
+            .map_err(|e| GameError::CustomError(format!("Failed to host on {addr}: {e}")))?;
+        game.network = Some(NetworkGame {
+            connection,
+            local_color: PieceColor::White,
+            is_host: true,
+        });
+    } else if let Some(addr) = &args.connect {
+        game.network = Some(NetworkGame {
+            connection: Connection::connect(addr),
+            local_color: PieceColor::Black,
+            is_host: false,
+        });
+    }
+
+    if let Some(source) = args.watch.clone() {
+        game.ai_color = None;
+        game.spectator = Some(Spectator::watch(source));
+    }
+
+    let show_menu = !args.no_menu
+        && args.fen.is_none()
+        && adjourned.is_none()
+        && !args.edit
+        && args.watch.is_none()
+        && args.correspondence.is_none()
+        && game.network.is_none();
 
     if let Some(fen) = args.fen {
-        match game.from_fen(&fen) {
-            Ok(_) => println!("Loaded FEN: {}", fen),
+        let loaded = if args.strict_fen {
+            game.state.from_fen_strict(&fen)
+        } else {
+            game.state.from_fen(&fen)
+        };
+        match loaded {
+            Ok(_) => {
+                game.initial_state = game.state.clone();
+                game.position_history = vec![game.state.position_key()];
+                game.status = game.state.status();
+                log::info!("Loaded FEN {fen}");
+                game.toasts.info(format!("Loaded FEN: {fen}"));
+            }
             Err(err) => {
-                eprintln!("Failed to load FEN: {}", err);
-                return Err(GameError::CustomError(err));
+                log::error!("Failed to load FEN {fen}: {err}");
+                return Err(GameError::CustomError(err.to_string()));
             }
         }
     }
 
-    event::run(ctx, event_loop, game)
+    if let Some(adjourned) = adjourned {
+        game.resume_adjourned(adjourned);
+        game.toasts.info("Resumed the adjourned game");
+    }
+
+    if args.edit {
+        game.open_editor();
+    }
+
+    // The board size is in logical pixels but the window is measured in
+    // physical ones, so on a HiDPI screen everything is scaled up to match
+    let scale_factor = ctx.gfx.window().scale_factor() as f32;
+    if scale_factor != 1.0 {
+        let (width, height) = game.layout.window_size();
+        let (width, height) = (width * scale_factor, height * scale_factor);
+        ctx.gfx.set_drawable_size(width, height)?;
+        game.layout.fit(width, height);
+    }
+
+    game.serve_position(false);
+    game.print_position(false);
+
+    // A game left unfinished last time is offered over the main menu, but
+    // not to recorded input, which has to find the same scenes when replayed
+    let logging_input = args.record_input.is_some() || args.replay_input.is_some();
+    let recovered = if show_menu && !logging_input {
+        recovery::load()
+    } else {
+        None
+    };
+    let mut app = App::new(game, show_menu);
+    if let Some(recovered) = recovered {
+        app.offer_recovery(recovered);
+    }
+    if let Some(path) = &args.record_input {
+        event::run(ctx, event_loop, InputLog::record(app, path)?)
+    } else if let Some(path) = &args.replay_input {
+        event::run(ctx, event_loop, InputLog::replay(app, path)?)
+    } else {
+        event::run(ctx, event_loop, app)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A game with the AI playing White from `fen`, without a window or audio.
+    fn game_from(fen: &str) -> ChessGame {
+        let layout = Layout::new(DEFAULT_BOARD_SIZE, true, false);
+        let sounds = Rc::new(RefCell::new(SoundEffects::silent()));
+        let mut game = ChessGame::new(
+            Some(AiColor::White),
+            2,
+            Duration::ZERO,
+            layout,
+            CoordinateLabels::Outside,
+            None,
+            sounds,
+        )
+        .unwrap();
+        let mut state = GameState::new();
+        state.from_fen(fen).unwrap();
+        game.reset_to(state);
+        game
+    }
+
+    // Checks that the AI's move was recorded as `san` and left `fen`, with the
+    // hash kept up to date along the way.
+    fn assert_ai_played(game: &mut ChessGame, san: &str, fen: &str) {
+        let ply = game.history.len();
+        assert!(game.ai_turn());
+        assert_eq!(game.history.len(), ply + 1);
+        assert_eq!(game.history[ply].san, san);
+        assert_eq!(game.state.to_fen(), fen);
+        let mut fresh = GameState::new();
+        fresh.from_fen(fen).unwrap();
+        assert_eq!(game.state.zobrist_key(), fresh.zobrist_key());
+        assert_eq!(game.position_history.last(), Some(&fresh.position_key()));
+    }
+
+    #[test]
+    fn ai_castles_through_apply_move() {
+        let mut game = game_from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        for uci in [
+            "e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6", "b5a4", "g8f6",
+        ] {
+            game.apply_move(Move::from_uci(uci).unwrap());
+        }
+        // The built-in book answers with O-O here
+        assert_ai_played(
+            &mut game,
+            "O-O",
+            "r1bqkb1r/1ppp1ppp/p1n2n2/4p3/B3P3/5N2/PPPP1PPP/RNBQ1RK1 b kq - 3 5",
+        );
+    }
+
+    #[test]
+    fn ai_captures_en_passant_through_apply_move() {
+        // Taking the checking pawn en passant is the only legal move
+        let mut game = game_from("7k/8/p7/Ppp5/K7/7r/8/8 w - b6 0 2");
+        assert_ai_played(&mut game, "axb6", "7k/8/pP6/2p5/K7/7r/8/8 b - - 0 2");
+    }
+
+    #[test]
+    fn ai_promotes_through_apply_move() {
+        // Promoting is the only legal move, and the AI takes a queen
+        let mut game = game_from("8/P7/8/3k4/8/5n1p/8/7K w - - 4 40");
+        assert_ai_played(&mut game, "a8=Q+", "Q7/8/8/3k4/8/5n1p/8/7K b - - 0 40");
+    }
 }
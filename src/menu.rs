@@ -0,0 +1,127 @@
+use std::time::Duration;
+
+use itsjustchess::chess_core::{ClockMode, Difficulty, TimeControl, Variant};
+
+use crate::AiColor;
+
+// The player setups offered, in the order clicking cycles through them:
+// which side the AI plays, if any
+const PLAYER_CHOICES: [Option<AiColor>; 4] = [
+    Some(AiColor::Black),
+    Some(AiColor::White),
+    None,
+    Some(AiColor::Both),
+];
+
+const VARIANT_CHOICES: [Variant; 4] = [
+    Variant::Standard,
+    Variant::KingOfTheHill,
+    Variant::ThreeCheck,
+    Variant::Antichess,
+];
+
+// The time controls offered besides no clock, as (minutes, increment seconds)
+const CLOCK_CHOICES: [(u64, u64); 6] = [(1, 0), (3, 2), (5, 3), (10, 0), (15, 10), (30, 0)];
+
+/// The rows of the main menu: the choices for the next game, then its
+/// buttons.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum MenuItem {
+    Players,
+    Difficulty,
+    Variant,
+    Clock,
+    Play,
+    Settings,
+    Close, // Back to the game underneath, or close the tab or quit if there is none
+    Positions,
+    Trainer,
+    Stats,
+    Database,
+}
+
+/// The settings for the next game, chosen on the main menu. Each row steps
+/// through its choices when clicked, starting from the current game's.
+pub struct NewGameMenu {
+    pub ai_color: Option<AiColor>,
+    pub difficulty: Option<Difficulty>, // None keeps the plain --ai-depth search
+    pub variant: Variant,
+    pub time_control: Option<TimeControl>,
+}
+
+impl NewGameMenu {
+    /// Moves `item` on to its next choice. The buttons have none.
+    pub fn cycle(&mut self, item: MenuItem) {
+        match item {
+            MenuItem::Players => {
+                let current = PLAYER_CHOICES.iter().position(|&c| c == self.ai_color);
+                self.ai_color =
+                    PLAYER_CHOICES[current.map_or(0, |i| (i + 1) % PLAYER_CHOICES.len())];
+            }
+            MenuItem::Difficulty => {
+                self.difficulty = match self.difficulty {
+                    None => Difficulty::new(Difficulty::MIN),
+                    Some(difficulty) => Difficulty::new(difficulty.level() + 1),
+                };
+            }
+            MenuItem::Variant => {
+                let current = VARIANT_CHOICES.iter().position(|&v| v == self.variant);
+                self.variant =
+                    VARIANT_CHOICES[current.map_or(0, |i| (i + 1) % VARIANT_CHOICES.len())];
+            }
+            MenuItem::Clock => {
+                let choices = CLOCK_CHOICES.map(|(minutes, increment)| TimeControl {
+                    base: Duration::from_secs(minutes * 60),
+                    increment: Duration::from_secs(increment),
+                    mode: ClockMode::Increment,
+                });
+                // A time control from the command line that isn't one of the
+                // choices goes on to the first
+                self.time_control = match self.time_control {
+                    None => Some(choices[0]),
+                    Some(current) => choices
+                        .iter()
+                        .position(|&c| c == current)
+                        .map_or(Some(choices[0]), |i| choices.get(i + 1).copied()),
+                };
+            }
+            MenuItem::Play
+            | MenuItem::Settings
+            | MenuItem::Close
+            | MenuItem::Positions
+            | MenuItem::Trainer
+            | MenuItem::Stats
+            | MenuItem::Database => {}
+        }
+    }
+
+    /// The text shown on `item`'s row. `ai_depth` is the search depth used
+    /// when no difficulty is chosen. Close is labelled by the caller, which
+    /// knows what closing the menu leads to.
+    pub fn label(&self, item: MenuItem, ai_depth: u32) -> String {
+        match item {
+            MenuItem::Players => match self.ai_color {
+                Some(AiColor::Black) => "White against the AI".to_string(),
+                Some(AiColor::White) => "Black against the AI".to_string(),
+                Some(AiColor::Both) => "AI against AI".to_string(),
+                None => "Two players".to_string(),
+            },
+            MenuItem::Difficulty => match self.difficulty {
+                Some(difficulty) => format!("AI level {difficulty} of {}", Difficulty::MAX),
+                None => format!("AI depth {ai_depth}"),
+            },
+            MenuItem::Variant => self.variant.to_string(),
+            MenuItem::Clock => match self.time_control {
+                Some(time_control) => format!("Clock {time_control}"),
+                None => "No clock".to_string(),
+            },
+            MenuItem::Play => "Play".to_string(),
+            MenuItem::Settings => "Settings".to_string(),
+            MenuItem::Close => String::new(),
+            MenuItem::Positions => "Positions".to_string(),
+            MenuItem::Trainer => "Coordinate trainer".to_string(),
+            MenuItem::Stats => "Statistics".to_string(),
+            MenuItem::Database => "Games database".to_string(),
+        }
+    }
+}
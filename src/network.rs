@@ -0,0 +1,265 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+// How long a client waits between attempts to (re)connect to the host
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+// Largest frame we accept; anything bigger means the peer is confused
+const MAX_FRAME_LEN: u32 = 64 * 1024;
+
+/// A message exchanged between two game instances.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Message {
+    /// A move in UCI notation, e.g. "e2e4" or "e7e8q"
+    Move(String),
+    /// Start a new game from the standard position
+    NewGame,
+    /// Start a new game from the standard position with the colours swapped
+    Rematch,
+    /// The host's view of the game: starting FEN and the UCI moves since.
+    /// Sent whenever a connection is (re)established.
+    Sync { fen: String, moves: Vec<String> },
+    /// Asks to take moves back until only this many half-moves remain
+    TakebackOffer(usize),
+    /// Agrees to the takeback offer for the same number of half-moves
+    TakebackAccept(usize),
+    TakebackDecline,
+    /// Stops both clocks and the game until a Resume
+    Pause,
+    Resume,
+    /// Asks to adjourn the game, saving it to be finished another time
+    AdjournOffer,
+    /// Agrees to adjourn, with the clocks as they stand (White's, then
+    /// Black's) so that both sides save the same times
+    AdjournAccept(Option<(Duration, Duration)>),
+    AdjournDecline,
+}
+
+impl Message {
+    fn encode(&self) -> String {
+        match self {
+            Message::Move(uci) => format!("MOVE {uci}"),
+            Message::NewGame => "NEWGAME".to_string(),
+            Message::Rematch => "REMATCH".to_string(),
+            Message::Sync { fen, moves } => format!("SYNC {fen}|{}", moves.join(" ")),
+            Message::TakebackOffer(ply) => format!("TAKEBACK {ply}"),
+            Message::TakebackAccept(ply) => format!("TAKEBACK_ACCEPT {ply}"),
+            Message::TakebackDecline => "TAKEBACK_DECLINE".to_string(),
+            Message::Pause => "PAUSE".to_string(),
+            Message::Resume => "RESUME".to_string(),
+            Message::AdjournOffer => "ADJOURN".to_string(),
+            Message::AdjournAccept(Some((white, black))) => {
+                format!("ADJOURN_ACCEPT {} {}", white.as_millis(), black.as_millis())
+            }
+            Message::AdjournAccept(None) => "ADJOURN_ACCEPT".to_string(),
+            Message::AdjournDecline => "ADJOURN_DECLINE".to_string(),
+        }
+    }
+
+    fn decode(text: &str) -> Option<Message> {
+        let (kind, body) = text.split_once(' ').unwrap_or((text, ""));
+        match kind {
+            "MOVE" => Some(Message::Move(body.to_string())),
+            "NEWGAME" => Some(Message::NewGame),
+            "REMATCH" => Some(Message::Rematch),
+            "SYNC" => {
+                let (fen, moves) = body.split_once('|')?;
+                Some(Message::Sync {
+                    fen: fen.to_string(),
+                    moves: moves.split_whitespace().map(str::to_string).collect(),
+                })
+            }
+            "TAKEBACK" => Some(Message::TakebackOffer(body.parse().ok()?)),
+            "TAKEBACK_ACCEPT" => Some(Message::TakebackAccept(body.parse().ok()?)),
+            "TAKEBACK_DECLINE" => Some(Message::TakebackDecline),
+            "PAUSE" => Some(Message::Pause),
+            "RESUME" => Some(Message::Resume),
+            "ADJOURN" => Some(Message::AdjournOffer),
+            "ADJOURN_ACCEPT" if body.is_empty() => Some(Message::AdjournAccept(None)),
+            "ADJOURN_ACCEPT" => {
+                let (white, black) = body.split_once(' ')?;
+                let millis = |s: &str| s.parse().ok().map(Duration::from_millis);
+                let remaining = (millis(white)?, millis(black)?);
+                Some(Message::AdjournAccept(Some(remaining)))
+            }
+            "ADJOURN_DECLINE" => Some(Message::AdjournDecline),
+            _ => None,
+        }
+    }
+}
+
+/// Something that happened on the connection, for the game loop to handle.
+#[derive(Debug)]
+pub enum NetEvent {
+    Connected,
+    Disconnected,
+    Received(Message),
+}
+
+/// A link to the other player's instance. Connecting, reconnecting and all
+/// socket I/O happen on background threads; the game sends with
+/// [`Connection::send`] and polls for events once per frame.
+pub struct Connection {
+    outgoing: Sender<Message>,
+    events: Receiver<NetEvent>,
+    connected: bool,
+}
+
+impl Connection {
+    /// Listens on `addr` and serves one opponent at a time, accepting a new
+    /// connection whenever the current one drops.
+    pub fn host(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        log::info!("Waiting for an opponent on {}", listener.local_addr()?);
+        Ok(Self::spawn(move || loop {
+            match listener.accept() {
+                Ok((stream, _)) => return stream,
+                Err(e) => log::warn!("Failed to accept connection: {e}"),
+            }
+        }))
+    }
+
+    /// Connects to a host at `addr`, retrying until it answers and again
+    /// whenever the connection drops.
+    pub fn connect(addr: &str) -> Self {
+        let addr = addr.to_string();
+        Self::spawn(move || loop {
+            match TcpStream::connect(&addr) {
+                Ok(stream) => return stream,
+                Err(e) => {
+                    log::warn!("Failed to connect to {addr}: {e}; retrying");
+                    thread::sleep(RECONNECT_DELAY);
+                }
+            }
+        })
+    }
+
+    // Runs the connection loop on a background thread: open a stream with
+    // `open`, pump messages both ways until it fails, then start over.
+    fn spawn(mut open: impl FnMut() -> TcpStream + Send + 'static) -> Self {
+        let (outgoing, outgoing_rx) = mpsc::channel::<Message>();
+        let (events_tx, events) = mpsc::channel();
+
+        thread::spawn(move || loop {
+            let stream = open();
+            let _ = stream.set_nodelay(true);
+            let reader = match stream.try_clone() {
+                Ok(reader) => reader,
+                Err(e) => {
+                    log::warn!("Connection error: {e}");
+                    continue;
+                }
+            };
+
+            match stream.peer_addr() {
+                Ok(peer) => log::info!("Connected to {peer}"),
+                Err(_) => log::info!("Connected"),
+            }
+
+            // Anything queued while disconnected is stale; the sync replaces it
+            while outgoing_rx.try_recv().is_ok() {}
+            if events_tx.send(NetEvent::Connected).is_err() {
+                return; // The game has gone away
+            }
+
+            let closed = Arc::new(AtomicBool::new(false));
+            let reader_closed = Arc::clone(&closed);
+            let reader_events = events_tx.clone();
+            thread::spawn(move || {
+                read_messages(reader, &reader_events);
+                reader_closed.store(true, Ordering::SeqCst);
+            });
+
+            let mut writer = stream;
+            let mut game_closed = false;
+            while !closed.load(Ordering::SeqCst) {
+                match outgoing_rx.recv_timeout(Duration::from_millis(100)) {
+                    Ok(message) => {
+                        let text = message.encode();
+                        log::debug!("Sent {text}");
+                        if write_frame(&mut writer, &text).is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => {
+                        game_closed = true;
+                        break;
+                    }
+                }
+            }
+
+            // Shutting down also unblocks the reader thread
+            let _ = writer.shutdown(std::net::Shutdown::Both);
+            log::info!("Disconnected");
+            if game_closed || events_tx.send(NetEvent::Disconnected).is_err() {
+                return;
+            }
+        });
+
+        Connection {
+            outgoing,
+            events,
+            connected: false,
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    pub fn send(&self, message: Message) {
+        // Dropped silently if the connection thread has died
+        let _ = self.outgoing.send(message);
+    }
+
+    /// The next pending event, if any.
+    pub fn poll(&mut self) -> Option<NetEvent> {
+        let event = self.events.try_recv().ok()?;
+        match event {
+            NetEvent::Connected => self.connected = true,
+            NetEvent::Disconnected => self.connected = false,
+            NetEvent::Received(_) => {}
+        }
+        Some(event)
+    }
+}
+
+// Reads frames until the stream closes or sends garbage.
+fn read_messages(mut stream: TcpStream, events: &Sender<NetEvent>) {
+    while let Ok(text) = read_frame(&mut stream) {
+        log::debug!("Received {text}");
+        match Message::decode(&text) {
+            Some(message) => {
+                if events.send(NetEvent::Received(message)).is_err() {
+                    return;
+                }
+            }
+            None => log::warn!("Ignoring unknown message: {text}"),
+        }
+    }
+}
+
+// Frames are a 4-byte big-endian length followed by that many bytes of UTF-8.
+fn write_frame(stream: &mut TcpStream, text: &str) -> io::Result<()> {
+    stream.write_all(&(text.len() as u32).to_be_bytes())?;
+    stream.write_all(text.as_bytes())?;
+    stream.flush()
+}
+
+fn read_frame(stream: &mut TcpStream) -> io::Result<String> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len);
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too large"));
+    }
+    let mut buf = vec![0; len as usize];
+    stream.read_exact(&mut buf)?;
+    String::from_utf8(buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
@@ -1,37 +1,88 @@
-use ggez::graphics::{Canvas, Color, DrawMode, DrawParam, MeshBuilder};
-use ggez::{Context, GameResult};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
-pub struct Pieces;
+use ggez::graphics::{Canvas, Color, DrawMode, DrawParam, Image, MeshBuilder};
+use ggez::{Context, GameError, GameResult};
+
+use itsjustchess::chess_core::{PieceColor, PieceType};
+
+// Where named piece sets live, relative to the working directory
+const PIECE_SET_DIR: &str = "assets/pieces";
+
+/// Draws pieces, either from a loaded set of images or, by default, with the
+/// built-in vector shapes.
+pub struct Pieces {
+    sprites: HashMap<(PieceColor, PieceType), Image>,
+}
 
 impl Pieces {
     pub fn new() -> Self {
-        Pieces
+        Pieces {
+            sprites: HashMap::new(),
+        }
+    }
+
+    /// Loads a piece set: either a directory path, or the name of a directory
+    /// under `assets/pieces`. The directory holds one image per piece named
+    /// like `wK.png`, `bN.png` (colour letter, then piece letter); images of
+    /// the archbishop and chancellor are optional.
+    pub fn load_set(ctx: &Context, name: &str) -> GameResult<Self> {
+        let dir = if Path::new(name).is_dir() {
+            PathBuf::from(name)
+        } else {
+            Path::new(PIECE_SET_DIR).join(name)
+        };
+
+        let mut sprites = HashMap::new();
+        for color in [PieceColor::White, PieceColor::Black] {
+            for piece_type in PieceType::ALL {
+                let path = dir.join(sprite_file_name(color, piece_type));
+                // Few sets have fairy pieces, which are drawn as shapes instead
+                if piece_type.is_fairy() && !path.exists() {
+                    continue;
+                }
+                let bytes = std::fs::read(&path).map_err(|e| {
+                    GameError::ResourceLoadError(format!("{}: {e}", path.display()))
+                })?;
+                sprites.insert((color, piece_type), Image::from_bytes(ctx, &bytes)?);
+            }
+        }
+        Ok(Pieces { sprites })
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn draw_piece(
         &self,
         ctx: &mut Context,
         canvas: &mut Canvas,
-        piece_color: crate::PieceColor,
-        piece_type: crate::PieceType,
+        piece_color: PieceColor,
+        piece_type: PieceType,
         x: f32,
         y: f32,
         tile_size: f32, 
     ) -> GameResult<()> {
+        if let Some(image) = self.sprites.get(&(piece_color, piece_type)) {
+            let scale = [
+                tile_size / image.width() as f32,
+                tile_size / image.height() as f32,
+            ];
+            canvas.draw(image, DrawParam::default().dest([x, y]).scale(scale));
+            return Ok(());
+        }
+
         let mut mb = MeshBuilder::new();
         // Scaling factors based on tile_size
-        let tile_size = tile_size;
         let grid_square = tile_size / 10.0;
         let piece_x_offset = tile_size * 0.2;
         let piece_y_offset = tile_size * 0.15;
         let piece_color = match piece_color {
-            crate::PieceColor::White => Color::from_rgb(240, 240, 240),
-            crate::PieceColor::Black => Color::from_rgb(50, 50, 50),
+            PieceColor::White => Color::from_rgb(240, 240, 240),
+            PieceColor::Black => Color::from_rgb(50, 50, 50),
         };
         
         // Each piece will be drawn on a 6x8 grid
         match piece_type {
-            crate::PieceType::Pawn => {
+            PieceType::Pawn => {
                 // Head and body
                 mb.rectangle(
                     DrawMode::fill(),
@@ -85,7 +136,7 @@ impl Pieces {
                     piece_color,
                 )?;
             }
-            crate::PieceType::Knight => {
+            PieceType::Knight => {
                 mb.rectangle(
                     DrawMode::fill(),
                     ggez::graphics::Rect::new(
@@ -138,7 +189,7 @@ impl Pieces {
                     piece_color,
                 )?;
             }
-            crate::PieceType::Bishop => {
+            PieceType::Bishop => {
                 // Body
                 mb.rectangle(
                     DrawMode::fill(),
@@ -202,7 +253,7 @@ impl Pieces {
                     piece_color,
                 )?;
             }
-            crate::PieceType::Rook => {
+            PieceType::Rook => {
                 // Body
                 mb.rectangle(
                     DrawMode::fill(),
@@ -266,7 +317,7 @@ impl Pieces {
                     piece_color,
                 )?;
             }
-            crate::PieceType::Queen => {
+            PieceType::Queen => {
                 // Head and crown. Queen of my heart
                 mb.rectangle(
                     DrawMode::fill(),
@@ -361,7 +412,7 @@ impl Pieces {
                     piece_color,
                 )?;
             }
-            crate::PieceType::King => {
+            PieceType::King => {
                 // Cross
                 mb.rectangle(
                     DrawMode::fill(),
@@ -467,6 +518,144 @@ impl Pieces {
                     piece_color,
                 )?;
             }
+            PieceType::Archbishop => {
+                // A bishop's mitre over a knight's muzzle
+                mb.rectangle(
+                    DrawMode::fill(),
+                    ggez::graphics::Rect::new(
+                        x + piece_x_offset + grid_square * 2.0,
+                        y + piece_y_offset + grid_square * 2.0,
+                        grid_square * 2.0, // width
+                        grid_square * 5.0, // height 
+                    ),
+                    piece_color,
+                )?;
+                mb.rectangle(
+                    DrawMode::fill(),
+                    ggez::graphics::Rect::new(
+                        x + piece_x_offset + grid_square * 3.0,
+                        y + piece_y_offset + grid_square,
+                        grid_square * 0.5, // width
+                        grid_square * 1.0, // height 
+                    ),
+                    piece_color,
+                )?;
+                mb.rectangle(
+                    DrawMode::fill(),
+                    ggez::graphics::Rect::new(
+                        x + piece_x_offset + grid_square * 2.5,
+                        y + piece_y_offset + grid_square * 1.5,
+                        grid_square * 1.5, // width
+                        grid_square * 1.0, // height 
+                    ),
+                    piece_color,
+                )?;
+                mb.rectangle(
+                    DrawMode::fill(),
+                    ggez::graphics::Rect::new(
+                        x + piece_x_offset + grid_square * 2.0,
+                        y + piece_y_offset + grid_square * 3.0,
+                        grid_square * 3.5, // width
+                        grid_square * 1.5, // height 
+                    ),
+                    piece_color,
+                )?;
+                // Base
+                mb.rectangle(
+                    DrawMode::fill(),
+                    ggez::graphics::Rect::new(
+                        x + piece_x_offset,
+                        y + piece_y_offset + grid_square * 7.0,
+                        grid_square * 6.0, // width
+                        grid_square * 1.0, // height 
+                    ),
+                    piece_color,
+                )?;
+                mb.rectangle(
+                    DrawMode::fill(),
+                    ggez::graphics::Rect::new(
+                        x + piece_x_offset + grid_square,
+                        y + piece_y_offset + grid_square * 6.5,
+                        grid_square * 4.0, // width
+                        grid_square * 1.0, // height 
+                    ),
+                    piece_color,
+                )?;
+            }
+            PieceType::Chancellor => {
+                // A rook's battlements over a knight's muzzle
+                mb.rectangle(
+                    DrawMode::fill(),
+                    ggez::graphics::Rect::new(
+                        x + piece_x_offset + grid_square * 1.5,
+                        y + piece_y_offset + grid_square * 2.0,
+                        grid_square * 3.0, // width
+                        grid_square * 6.0, // height 
+                    ),
+                    piece_color,
+                )?;
+                mb.rectangle(
+                    DrawMode::fill(),
+                    ggez::graphics::Rect::new(
+                        x + piece_x_offset + grid_square * 1.0,
+                        y + piece_y_offset + grid_square * 1.0,
+                        grid_square * 1.0, // width
+                        grid_square * 1.0, // height 
+                    ),
+                    piece_color,
+                )?;
+                mb.rectangle(
+                    DrawMode::fill(),
+                    ggez::graphics::Rect::new(
+                        x + piece_x_offset + grid_square * 2.5,
+                        y + piece_y_offset + grid_square * 1.0,
+                        grid_square * 1.0, // width
+                        grid_square * 1.0, // height 
+                    ),
+                    piece_color,
+                )?;
+                mb.rectangle(
+                    DrawMode::fill(),
+                    ggez::graphics::Rect::new(
+                        x + piece_x_offset + grid_square * 4.0,
+                        y + piece_y_offset + grid_square * 1.0,
+                        grid_square * 1.0, // width
+                        grid_square * 1.0, // height 
+                    ),
+                    piece_color,
+                )?;
+                mb.rectangle(
+                    DrawMode::fill(),
+                    ggez::graphics::Rect::new(
+                        x + piece_x_offset + grid_square * 4.5,
+                        y + piece_y_offset + grid_square * 3.0,
+                        grid_square * 1.5, // width
+                        grid_square * 1.5, // height 
+                    ),
+                    piece_color,
+                )?;
+                // Base
+                mb.rectangle(
+                    DrawMode::fill(),
+                    ggez::graphics::Rect::new(
+                        x + piece_x_offset,
+                        y + piece_y_offset + grid_square * 7.0,
+                        grid_square * 6.0, // width
+                        grid_square * 1.0, // height 
+                    ),
+                    piece_color,
+                )?;
+                mb.rectangle(
+                    DrawMode::fill(),
+                    ggez::graphics::Rect::new(
+                        x + piece_x_offset + grid_square,
+                        y + piece_y_offset + grid_square * 6.5,
+                        grid_square * 4.0, // width
+                        grid_square * 1.0, // height 
+                    ),
+                    piece_color,
+                )?;
+            }
         }
 
         let mesh_data = mb.build();
@@ -475,4 +664,13 @@ impl Pieces {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+// File name of a piece image, e.g. "wK.png" for the white king.
+fn sprite_file_name(color: PieceColor, piece_type: PieceType) -> String {
+    let color = match color {
+        PieceColor::White => 'w',
+        PieceColor::Black => 'b',
+    };
+    format!("{color}{}.png", piece_type.letter())
+}
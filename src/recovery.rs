@@ -0,0 +1,49 @@
+use std::io;
+use std::time::Duration;
+
+use itsjustchess::chess_core::{parse_pgn, PgnGame};
+
+use crate::settings::config_path;
+
+// The game in progress, kept in the config directory beside the settings
+const RECOVERY_FILE: &str = "recovery.pgn";
+
+/// How often a game with a clock is saved between moves, so the times lost
+/// to a crash are no more than this.
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Writes `pgn`, a game saved as for adjourning, as the game to recover. It
+/// goes to a temporary file first, so a crash part way through leaves the
+/// last save as it was.
+pub fn save(pgn: &str) -> io::Result<()> {
+    let Some(path) = config_path(RECOVERY_FILE) else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let temporary = path.with_extension("pgn.tmp");
+    std::fs::write(&temporary, pgn)?;
+    std::fs::rename(&temporary, &path)
+}
+
+/// The unfinished game saved by the last run, if it didn't get to finish it.
+pub fn load() -> Option<PgnGame> {
+    let pgn = std::fs::read_to_string(config_path(RECOVERY_FILE)?).ok()?;
+    match parse_pgn(&pgn) {
+        Ok(game) if !game.moves.is_empty() => Some(game),
+        Ok(_) => None,
+        Err(err) => {
+            log::warn!("Ignoring the unreadable recovery file: {err}");
+            clear();
+            None
+        }
+    }
+}
+
+/// Forgets the saved game, once it is over or given up.
+pub fn clear() {
+    if let Some(path) = config_path(RECOVERY_FILE) {
+        let _ = std::fs::remove_file(path);
+    }
+}
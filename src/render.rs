@@ -0,0 +1,188 @@
+use ggez::graphics::{Canvas, Color, DrawMode, DrawParam, Mesh, Rect};
+use ggez::{Context, GameError, GameResult};
+
+use itsjustchess::chess_core::{ChessBoard, Piece};
+
+use crate::accessibility::BoardTheme;
+use crate::pieces::Pieces;
+
+/// What a frontend is asked to draw of a position, whichever it is: the
+/// pieces and the squares to pick out. Squares are (row, col) on the board,
+/// with row 0 the top rank and column 0 the a-file.
+pub struct BoardView<'a> {
+    pub board: &'a ChessBoard,
+    pub flipped: bool, // Black at the bottom
+    pub last_move: Option<((usize, usize), (usize, usize))>,
+    pub highlighted: Vec<(usize, usize)>, // The piece picked up, and the like
+    pub targets: Vec<(usize, usize)>,     // Where the piece picked up can go
+    pub cursor: Option<(usize, usize)>,   // For frontends moved about with keys
+}
+
+/// How a square is filled, most important first.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Shade {
+    Highlighted,
+    Target,
+    LastMove,
+    Plain,
+}
+
+impl BoardView<'_> {
+    pub fn shade(&self, square: (usize, usize)) -> Shade {
+        if self.highlighted.contains(&square) {
+            Shade::Highlighted
+        } else if self.targets.contains(&square) {
+            Shade::Target
+        } else if self
+            .last_move
+            .is_some_and(|(from, to)| from == square || to == square)
+        {
+            Shade::LastMove
+        } else {
+            Shade::Plain
+        }
+    }
+
+    /// Where `square` is shown, as a (row, col) counted from the top left,
+    /// which is turned round when flipped. Turning it round again puts it
+    /// back, so this also takes a place on the screen to its square.
+    pub fn screen_square(&self, (row, col): (usize, usize)) -> (usize, usize) {
+        if self.flipped {
+            (self.board.ranks - 1 - row, self.board.files - 1 - col)
+        } else {
+            (row, col)
+        }
+    }
+}
+
+/// The colour `theme` gives a square filled as `shade`.
+pub fn square_color(theme: &BoardTheme, shade: Shade, is_light: bool) -> (u8, u8, u8) {
+    let pick = |(light, dark)| if is_light { light } else { dark };
+    match shade {
+        Shade::Highlighted => theme.selected,
+        Shade::Target => {
+            let (r, g, b, _) = theme.possible_move;
+            (r, g, b)
+        }
+        Shade::LastMove => pick(theme.last_move),
+        Shade::Plain => pick(theme.square),
+    }
+}
+
+/// What the player asked for, in terms any frontend can give.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Input {
+    /// Move the cursor by (rows, cols) as seen on the screen
+    Cursor(isize, isize),
+    /// Pick up or put down a piece where the cursor is
+    Choose,
+    /// Put the piece picked up back, or back out of a choice
+    Cancel,
+    /// Any other key, by its character
+    Key(char),
+    /// The screen was resized or spoiled and has to be drawn afresh
+    Redraw,
+    Quit,
+}
+
+/// A way of showing the game and hearing from the player, so frontends can
+/// be added without touching the rules or the AI, which live in chess_core.
+/// The window's is GgezRenderer and the terminal's TerminalRenderer.
+pub trait Renderer {
+    type Error;
+
+    /// Draws the squares, shaded as `view` says.
+    fn draw_board(&mut self, view: &BoardView) -> Result<(), Self::Error>;
+
+    /// Draws `piece` on `square`, over the square drawn by draw_board.
+    fn draw_piece(
+        &mut self,
+        view: &BoardView,
+        square: (usize, usize),
+        piece: Piece,
+    ) -> Result<(), Self::Error>;
+
+    /// Shows what has been drawn since the last time.
+    fn present(&mut self) -> Result<(), Self::Error>;
+
+    /// The next thing the player asked for, if any, waiting a moment for it
+    /// where input has to be asked for.
+    fn poll_input(&mut self) -> Result<Option<Input>, Self::Error>;
+}
+
+/// Draws every piece of `view`'s board.
+pub fn draw_pieces<R: Renderer>(renderer: &mut R, view: &BoardView) -> Result<(), R::Error> {
+    for row in 0..view.board.ranks {
+        for col in 0..view.board.files {
+            if let Some(piece) = view.board.squares[row][col].occupant {
+                renderer.draw_piece(view, (row, col), piece)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Draws `view` whole: the squares, then the pieces.
+pub fn draw_position<R: Renderer>(renderer: &mut R, view: &BoardView) -> Result<(), R::Error> {
+    renderer.draw_board(view)?;
+    draw_pieces(renderer, view)
+}
+
+/// Draws on the window's canvas, in board space (see Layout), with the
+/// loaded piece set.
+pub struct GgezRenderer<'a> {
+    pub ctx: &'a mut Context,
+    pub canvas: &'a mut Canvas,
+    pub pieces: &'a Pieces,
+    pub theme: &'a BoardTheme,
+    pub tile_size: f32,
+}
+
+impl GgezRenderer<'_> {
+    fn square_rect(&self, view: &BoardView, square: (usize, usize)) -> Rect {
+        let (row, col) = view.screen_square(square);
+        let tile = self.tile_size;
+        Rect::new(col as f32 * tile, row as f32 * tile, tile, tile)
+    }
+}
+
+impl Renderer for GgezRenderer<'_> {
+    type Error = GameError;
+
+    fn draw_board(&mut self, view: &BoardView) -> GameResult {
+        for row in 0..view.board.ranks {
+            for col in 0..view.board.files {
+                let is_light = view.board.is_light((row, col));
+                let color = square_color(self.theme, view.shade((row, col)), is_light);
+                let rect = self.square_rect(view, (row, col));
+                let mesh =
+                    Mesh::new_rectangle(self.ctx, DrawMode::fill(), rect, Color::from(color))?;
+                self.canvas.draw(&mesh, DrawParam::default());
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_piece(&mut self, view: &BoardView, square: (usize, usize), piece: Piece) -> GameResult {
+        let rect = self.square_rect(view, square);
+        self.pieces.draw_piece(
+            self.ctx,
+            self.canvas,
+            piece.color,
+            piece.piece_type,
+            rect.x,
+            rect.y,
+            self.tile_size,
+        )
+    }
+
+    // App finishes the frame, once the menus and toasts are drawn over the board
+    fn present(&mut self) -> GameResult {
+        Ok(())
+    }
+
+    // ggez hands input to the EventHandler callbacks instead
+    fn poll_input(&mut self) -> GameResult<Option<Input>> {
+        Ok(None)
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use itsjustchess::chess_core::{parse_pgn_lines, split_pgn_games, GameState, Move, PieceColor};
+
+use crate::settings::config_path;
+
+// The imported repertoire as it was given, and when each of its lines is
+// next due, kept in the config directory beside the settings
+const REPERTOIRE_FILE: &str = "repertoire.pgn";
+const SCHEDULE_FILE: &str = "repertoire.conf";
+
+/// One line through the repertoire, from its game's start to the end of a
+/// variation, with its place in the spaced-repetition schedule.
+struct Line {
+    start: GameState,
+    moves: Vec<Move>,
+    level: u32, // Times in a row it was played without a mistake
+    due: u64,   // The day it is next drilled, in days since the epoch
+}
+
+impl Line {
+    // The line's moves in UCI, which the schedule knows it by
+    fn key(&self) -> String {
+        let moves: Vec<String> = self
+            .moves
+            .iter()
+            .map(|mv| mv.to_uci_on(&self.start.board))
+            .collect();
+        moves.join(" ")
+    }
+}
+
+/// An opening repertoire, read from a PGN whose variations are the replies
+/// to be ready for, with the player's side being the one that branches less.
+pub struct Repertoire {
+    pub color: PieceColor,
+    lines: Vec<Line>,
+}
+
+impl Repertoire {
+    /// Reads the repertoire in `pgn`: every line of every game in it.
+    pub fn from_pgn(pgn: &str) -> Result<Self, String> {
+        let mut lines = Vec::new();
+        for game in split_pgn_games(pgn) {
+            let (start, moves) = parse_pgn_lines(game)?;
+            lines.extend(moves.into_iter().map(|moves| Line {
+                start: start.clone(),
+                moves,
+                level: 0,
+                due: 0,
+            }));
+        }
+        if lines.is_empty() {
+            return Err("No moves in the repertoire".to_string());
+        }
+
+        // The opponent's side is the one with several moves to answer
+        let mut replies: HashMap<u64, (PieceColor, Vec<Move>)> = HashMap::new();
+        for line in &lines {
+            let mut position = line.start.clone();
+            for &mv in &line.moves {
+                let entry = replies
+                    .entry(position.zobrist_key())
+                    .or_insert((position.turn, Vec::new()));
+                if !entry.1.contains(&mv) {
+                    entry.1.push(mv);
+                }
+                position.make_move(mv);
+            }
+        }
+        let branching = |color| {
+            replies
+                .values()
+                .filter(|(turn, moves)| *turn == color && moves.len() > 1)
+                .count()
+        };
+        let color = if branching(PieceColor::Black) >= branching(PieceColor::White) {
+            PieceColor::White
+        } else {
+            PieceColor::Black
+        };
+        Ok(Repertoire { color, lines })
+    }
+
+    /// The repertoire saved by the last import, with its schedule; `None` if
+    /// there is none or it can't be read.
+    pub fn load() -> Option<Self> {
+        let pgn = std::fs::read_to_string(config_path(REPERTOIRE_FILE)?).ok()?;
+        let mut repertoire = match Repertoire::from_pgn(&pgn) {
+            Ok(repertoire) => repertoire,
+            Err(err) => {
+                log::warn!("Failed to read the saved repertoire: {err}");
+                return None;
+            }
+        };
+        let schedule = config_path(SCHEDULE_FILE)
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        // Each line of the schedule is the level, the day due and the moves,
+        // e.g. "2 19876 e2e4 c7c5 g1f3"
+        let mut due: HashMap<&str, (u32, u64)> = HashMap::new();
+        for entry in schedule.lines() {
+            let mut fields = entry.splitn(3, ' ');
+            let level = fields.next().and_then(|level| level.parse().ok());
+            let day = fields.next().and_then(|day| day.parse().ok());
+            match (level, day, fields.next()) {
+                (Some(level), Some(day), Some(moves)) => {
+                    due.insert(moves, (level, day));
+                }
+                _ => log::warn!("Ignoring malformed repertoire schedule line: {entry}"),
+            }
+        }
+        for line in &mut repertoire.lines {
+            if let Some(&(level, day)) = due.get(line.key().as_str()) {
+                line.level = level;
+                line.due = day;
+            }
+        }
+        Some(repertoire)
+    }
+
+    /// Saves `pgn` as the repertoire to drill, in place of any before it,
+    /// returning how many lines it has. Lines it shares with the old one
+    /// keep their place in the schedule.
+    pub fn import(pgn: &str) -> Result<usize, String> {
+        let lines = Repertoire::from_pgn(pgn)?.lines.len();
+        let path = config_path(REPERTOIRE_FILE)
+            .ok_or_else(|| "No config directory to keep the repertoire in".to_string())?;
+        match path.parent() {
+            Some(dir) => std::fs::create_dir_all(dir),
+            None => Ok(()),
+        }
+        .and_then(|_| std::fs::write(&path, pgn))
+        .map_err(|err| format!("Failed to save the repertoire: {err}"))?;
+        Ok(lines)
+    }
+
+    fn save_schedule(&self) {
+        let Some(path) = config_path(SCHEDULE_FILE) else {
+            return;
+        };
+        let mut text = String::new();
+        for line in self.lines.iter().filter(|line| line.due > 0) {
+            text.push_str(&format!("{} {} {}\n", line.level, line.due, line.key()));
+        }
+        if let Err(e) = std::fs::write(&path, text) {
+            log::warn!(
+                "Failed to save the repertoire schedule to {}: {e}",
+                path.display()
+            );
+        }
+    }
+
+    /// How many lines are due to be drilled today.
+    pub fn due(&self) -> usize {
+        let today = today();
+        self.lines.iter().filter(|line| line.due <= today).count()
+    }
+
+    // The line to drill next: the most overdue, and of those the least
+    // well known
+    fn next_line(&self) -> usize {
+        (0..self.lines.len())
+            .min_by_key(|&i| (self.lines[i].due, self.lines[i].level))
+            .unwrap_or(0)
+    }
+
+    // Moves line `index` along the schedule: a line played without a
+    // mistake comes back after twice as long as the last time, starting at
+    // a day; a missed one starts over and comes back today
+    fn record(&mut self, index: usize, missed: bool) {
+        let line = &mut self.lines[index];
+        if missed {
+            line.level = 0;
+            line.due = today();
+        } else {
+            line.due = today() + (1 << line.level.min(10));
+            line.level += 1;
+        }
+        self.save_schedule();
+    }
+}
+
+// Days since the epoch (UTC)
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / 86_400)
+        .unwrap_or(0)
+}
+
+/// What the drill made of a move.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum DrillEvent {
+    /// The player left the line; `expected` is the move the repertoire has,
+    /// and `known` whether the move played is in another of its lines
+    Deviation { expected: Move, known: bool },
+    /// The line was played to its end, then put back in the schedule for
+    /// `days` from now
+    Finished { missed: bool, days: u64 },
+}
+
+/// The opening drill: the lines of a repertoire in the order they are due,
+/// with the opponent's moves played from the line and the player's checked
+/// against it.
+pub struct RepertoireDrill {
+    pub repertoire: Repertoire,
+    line: usize,
+    missed: bool, // Whether the player has left the line yet
+}
+
+impl RepertoireDrill {
+    /// The drill of `repertoire`, and the position its first line starts
+    /// from.
+    pub fn start(repertoire: Repertoire) -> (Self, GameState) {
+        let line = repertoire.next_line();
+        let start = repertoire.lines[line].start.clone();
+        let drill = RepertoireDrill {
+            repertoire,
+            line,
+            missed: false,
+        };
+        (drill, start)
+    }
+
+    /// Moves on to the line due next, returning the position it starts from.
+    pub fn next(&mut self) -> GameState {
+        self.line = self.repertoire.next_line();
+        self.missed = false;
+        self.repertoire.lines[self.line].start.clone()
+    }
+
+    /// The opponent's move at `ply` of the line, if it has one there.
+    pub fn reply(&self, ply: usize) -> Option<Move> {
+        let moves = &self.repertoire.lines[self.line].moves;
+        moves.get(ply).copied().filter(|_| self.is_opponents(ply))
+    }
+
+    fn is_opponents(&self, ply: usize) -> bool {
+        let line = &self.repertoire.lines[self.line];
+        let white_first = line.start.turn == PieceColor::White;
+        let white = ply.is_multiple_of(2) == white_first;
+        white != (self.repertoire.color == PieceColor::White)
+    }
+
+    /// Checks `mv`, played as move `ply` of the game, against the line. A
+    /// deviation is marked against the line, unless the move is one the
+    /// repertoire has elsewhere.
+    pub fn judge(&mut self, ply: usize, mv: Move) -> Option<DrillEvent> {
+        let moves = &self.repertoire.lines[self.line].moves;
+        let expected = *moves.get(ply)?;
+        if mv == expected {
+            if ply + 1 < moves.len() {
+                return None;
+            }
+            let missed = self.missed;
+            self.repertoire.record(self.line, missed);
+            let days = self.repertoire.lines[self.line].due - today();
+            return Some(DrillEvent::Finished { missed, days });
+        }
+        if self.is_opponents(ply) {
+            return None;
+        }
+        let known = self.repertoire.lines.iter().any(|line| {
+            line.moves.len() > ply && line.moves[..ply] == moves[..ply] && line.moves[ply] == mv
+        });
+        self.missed |= !known;
+        Some(DrillEvent::Deviation { expected, known })
+    }
+
+    /// A line for the status bar.
+    pub fn describe(&self) -> String {
+        format!(
+            "Repertoire drill: line {} of {}, {} due today{}",
+            self.line + 1,
+            self.repertoire.lines.len(),
+            self.repertoire.due(),
+            if self.missed { " (missed)" } else { "" }
+        )
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use itsjustchess::chess_core::{MoveQuality, PieceColor};
+
+use crate::database::PlayerGame;
+
+// How deep each of the player's moves is checked for blunders; shallower
+// than the review at the board, as a report may go through thousands of games
+const REPORT_DEPTH: u32 = 3;
+
+// The most blunder positions a report lists
+const BLUNDER_LIMIT: usize = 50;
+
+/// How a player did with one opening and one colour.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct OpeningScore {
+    pub eco: String,
+    pub opening: String,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    pub moves: u32, // Full moves over all the games, for the average length
+}
+
+impl OpeningScore {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Points per game, a win counting 1 and a draw a half, as a percentage.
+    pub fn score(&self) -> f32 {
+        (self.wins as f32 + self.draws as f32 / 2.0) * 100.0 / self.games().max(1) as f32
+    }
+
+    /// The average length of the games in full moves.
+    pub fn average_moves(&self) -> f32 {
+        self.moves as f32 / self.games().max(1) as f32
+    }
+}
+
+/// A position the player blundered in, with the move they played there and
+/// the move the engine preferred.
+#[derive(Clone, PartialEq, Debug)]
+pub struct BlunderPosition {
+    pub fen: String,
+    pub played: String, // In SAN, as are the best moves
+    pub best: String,
+    pub count: u32,
+}
+
+/// A player's results with each opening, as White and as Black, and the
+/// positions they blundered in most often, from their games in the database.
+#[derive(Clone, Default, Debug)]
+pub struct Report {
+    pub openings: Vec<(PieceColor, OpeningScore)>,
+    pub blunders: Vec<BlunderPosition>,
+}
+
+impl Report {
+    /// Goes through `games`, checking each of the player's moves with the
+    /// engine, which is most of the work.
+    pub fn build(games: &[PlayerGame]) -> Self {
+        let mut openings: HashMap<(PieceColor, String), OpeningScore> = HashMap::new();
+        let mut blunders: HashMap<(u64, String), BlunderPosition> = HashMap::new();
+        for (index, game) in games.iter().enumerate() {
+            log::info!("Reporting on game {} of {}", index + 1, games.len());
+            let score = openings
+                .entry((game.color, game.eco.clone()))
+                .or_insert_with(|| OpeningScore {
+                    eco: game.eco.clone(),
+                    opening: game.opening.clone(),
+                    ..OpeningScore::default()
+                });
+            let outcome = match (game.result.as_str(), game.color) {
+                ("1-0", PieceColor::White) | ("0-1", PieceColor::Black) => Some(&mut score.wins),
+                ("1-0", PieceColor::Black) | ("0-1", PieceColor::White) => Some(&mut score.losses),
+                ("1/2-1/2", _) => Some(&mut score.draws),
+                // Unfinished games count for the blunders but not the score
+                _ => None,
+            };
+            if let Some(count) = outcome {
+                *count += 1;
+                score.moves += game.game.moves.len().div_ceil(2) as u32;
+            }
+
+            let mut position = game.game.start.clone();
+            for &mv in &game.game.moves {
+                if position.turn == game.color {
+                    let review = position.review_move(mv, REPORT_DEPTH);
+                    if let Some(review) = review.filter(|r| r.quality == MoveQuality::Blunder) {
+                        let played = mv.to_san(&position);
+                        let blunder = blunders
+                            .entry((position.zobrist_key(), played.clone()))
+                            .or_insert_with(|| BlunderPosition {
+                                fen: position.to_fen(),
+                                played,
+                                best: review.best.line[0].to_san(&position),
+                                count: 0,
+                            });
+                        blunder.count += 1;
+                    }
+                }
+                position.make_move(mv);
+            }
+        }
+
+        let mut openings: Vec<_> = openings
+            .into_iter()
+            .map(|((color, _), score)| (color, score))
+            .filter(|(_, score)| score.games() > 0)
+            .collect();
+        openings.sort_by_key(|(color, score)| {
+            (
+                *color == PieceColor::Black,
+                std::cmp::Reverse(score.games()),
+                score.eco.clone(),
+            )
+        });
+        let mut blunders: Vec<_> = blunders.into_values().collect();
+        blunders.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.fen.cmp(&b.fen)));
+        blunders.truncate(BLUNDER_LIMIT);
+        Report { openings, blunders }
+    }
+
+    /// The report as CSV for a spreadsheet: the openings, then after a blank
+    /// line the blunder positions, each under its own header row.
+    pub fn to_csv(&self) -> String {
+        let mut csv =
+            String::from("Colour,ECO,Opening,Games,Wins,Draws,Losses,Score %,Average moves\n");
+        for (color, score) in &self.openings {
+            let color = match color {
+                PieceColor::White => "White",
+                PieceColor::Black => "Black",
+            };
+            let _ = writeln!(
+                csv,
+                "{color},{},{},{},{},{},{},{:.1},{:.1}",
+                csv_field(&score.eco),
+                csv_field(&score.opening),
+                score.games(),
+                score.wins,
+                score.draws,
+                score.losses,
+                score.score(),
+                score.average_moves()
+            );
+        }
+        csv.push_str("\nFEN,Played,Best,Times\n");
+        for blunder in &self.blunders {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{}",
+                csv_field(&blunder.fen),
+                csv_field(&blunder.played),
+                csv_field(&blunder.best),
+                blunder.count
+            );
+        }
+        csv
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_csv())
+    }
+}
+
+// A CSV field, quoted if it has a comma, quote or line break in it, as
+// opening names often have commas.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
@@ -0,0 +1,114 @@
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use itsjustchess::chess_core::{GameState, Move, MoveQuality, MoveReview, PieceColor};
+
+use crate::evals::EvalCache;
+
+// How deep each move of a reviewed game is searched
+const REVIEW_DEPTH: u32 = 4;
+
+/// Checks every move of a game against the engine on a background thread,
+/// first move first, taking moves reviewed before from the session's
+/// evaluations.
+pub struct Reviewer {
+    results: Receiver<MoveReview>,
+    reviews: Vec<MoveReview>,
+    total: usize,
+    first_mover: PieceColor,
+}
+
+impl Reviewer {
+    pub fn new(initial: &GameState, moves: &[Move], evals: Arc<Mutex<EvalCache>>) -> Self {
+        let (results_tx, results) = mpsc::channel();
+        let mut position = initial.clone();
+        let to_review = moves.to_vec();
+        thread::spawn(move || {
+            for mv in to_review {
+                let cached = evals
+                    .lock()
+                    .ok()
+                    .and_then(|cache| cache.review(&position, mv, REVIEW_DEPTH).cloned());
+                let review = match cached {
+                    Some(review) => review,
+                    None => {
+                        let Some(review) = position.review_move(mv, REVIEW_DEPTH) else {
+                            return;
+                        };
+                        if let Ok(mut cache) = evals.lock() {
+                            cache.store_review(&position, mv, &review);
+                        }
+                        review
+                    }
+                };
+                // Stop once the review has been closed
+                if results_tx.send(review).is_err() {
+                    return;
+                }
+                position.make_move(mv);
+            }
+        });
+
+        Reviewer {
+            results,
+            reviews: Vec::new(),
+            total: moves.len(),
+            first_mover: initial.turn,
+        }
+    }
+
+    /// Collects newly reviewed moves. Returns whether there were any.
+    pub fn poll(&mut self) -> bool {
+        let before = self.reviews.len();
+        self.reviews.extend(self.results.try_iter());
+        self.reviews.len() > before
+    }
+
+    /// The review of the move that made half-move `ply` (counting from 1), if
+    /// it has been reached.
+    pub fn get(&self, ply: usize) -> Option<&MoveReview> {
+        self.reviews.get(ply.checked_sub(1)?)
+    }
+
+    /// How many moves have been reviewed, out of how many.
+    pub fn progress(&self) -> (usize, usize) {
+        (self.reviews.len(), self.total)
+    }
+
+    /// The share of `color`'s moves that were best or good, in percent, once
+    /// the whole game has been reviewed.
+    pub fn accuracy(&self, color: PieceColor) -> Option<f32> {
+        if self.reviews.len() < self.total {
+            return None;
+        }
+        let skip = if color == self.first_mover { 0 } else { 1 };
+        let qualities: Vec<MoveQuality> = self
+            .reviews
+            .iter()
+            .skip(skip)
+            .step_by(2)
+            .map(|review| review.quality)
+            .collect();
+        let good = qualities
+            .iter()
+            .filter(|&&q| q == MoveQuality::Best || q == MoveQuality::Good)
+            .count();
+        (!qualities.is_empty()).then(|| 100.0 * good as f32 / qualities.len() as f32)
+    }
+
+    /// How many inaccuracies, mistakes and blunders `color` made.
+    pub fn error_counts(&self, color: PieceColor) -> [usize; 3] {
+        let skip = if color == self.first_mover { 0 } else { 1 };
+        let mut counts = [0; 3];
+        for review in self.reviews.iter().skip(skip).step_by(2) {
+            match review.quality {
+                MoveQuality::Inaccuracy => counts[0] += 1,
+                MoveQuality::Mistake => counts[1] += 1,
+                MoveQuality::Blunder => counts[2] += 1,
+                MoveQuality::Best | MoveQuality::Good => {}
+            }
+        }
+        counts
+    }
+}
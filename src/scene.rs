@@ -0,0 +1,1668 @@
+use std::rc::Rc;
+use std::time::Duration;
+
+use ggez::conf::FullscreenType;
+use ggez::event::winit_event::TouchPhase;
+use ggez::event::{EventHandler, MouseButton};
+use ggez::graphics::{Canvas, Color, DrawMode, DrawParam, Mesh, PxScale, Rect, Text};
+use ggez::input::keyboard::{KeyCode, KeyInput, KeyMods};
+use ggez::{Context, GameError, GameResult};
+
+use itsjustchess::chess_core::{Endgame, GameState, Notation, PgnGame, POSITION_LIBRARY};
+
+use crate::accessibility::BoardTheme;
+use crate::database::{Database, GameQuery, GameSummary};
+use crate::menu::{MenuItem, NewGameMenu};
+use crate::network::Message;
+use crate::recovery;
+use crate::touch::{Gesture, TouchInput};
+use crate::trainer::CoordinateTrainer;
+use crate::ui::{self, Align, Button, Label};
+use crate::ChessGame;
+
+/// One screen of the app. Scenes stack up: the top one takes the input and
+/// is drawn over the game board, which is always shown underneath.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Scene {
+    MainMenu,
+    Game,
+    Settings,
+    GameOver,
+    CoordinateTrainer,
+    Positions,
+    FenInput,
+    Stats,
+    Recovery,
+    Export,
+    Database,
+}
+
+// The main menu, row by row: the choices for the next game, then the buttons
+const MAIN_MENU_ROWS: [&[MenuItem]; 7] = [
+    &[MenuItem::Players],
+    &[MenuItem::Difficulty],
+    &[MenuItem::Variant],
+    &[MenuItem::Clock],
+    &[MenuItem::Play, MenuItem::Settings, MenuItem::Close],
+    &[MenuItem::Positions, MenuItem::Trainer],
+    &[MenuItem::Stats, MenuItem::Database],
+];
+
+/// The buttons of the positions scene: one for each position in the
+/// library, by its index there, one for each basic endgame to train, then
+/// drilling the opening repertoire, time-scramble practice, typing in a FEN
+/// and going back.
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum PositionItem {
+    Library(usize),
+    Endgame(Endgame),
+    EnterFen,
+    Repertoire,
+    Scramble,
+    Back,
+}
+
+const POSITION_ROWS: [&[PositionItem]; 9] = [
+    &[PositionItem::Library(0), PositionItem::Library(1)],
+    &[PositionItem::Library(2), PositionItem::Library(3)],
+    &[PositionItem::Library(4), PositionItem::Library(5)],
+    &[PositionItem::Library(6), PositionItem::Library(7)],
+    &[PositionItem::Library(8), PositionItem::Library(9)],
+    &[PositionItem::Library(10), PositionItem::Library(11)],
+    &[
+        PositionItem::Endgame(Endgame::Queen),
+        PositionItem::Endgame(Endgame::Rook),
+        PositionItem::Endgame(Endgame::Pawn),
+    ],
+    &[PositionItem::Repertoire, PositionItem::Scramble],
+    &[PositionItem::EnterFen, PositionItem::Back],
+];
+
+/// The rows of the settings scene.
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum SettingsItem {
+    PossibleMoves,
+    Threats,
+    Sound,
+    Volume,
+    MoveDelay,
+    HighContrast,
+    PieceLetters,
+    AutoFlip,
+    ConfirmMoves,
+    Announce,
+    Back,
+}
+
+const SETTINGS_ROWS: [&[SettingsItem]; 8] = [
+    &[SettingsItem::PossibleMoves, SettingsItem::Threats],
+    &[SettingsItem::Sound],
+    &[SettingsItem::Volume],
+    &[SettingsItem::MoveDelay],
+    &[SettingsItem::HighContrast, SettingsItem::PieceLetters],
+    &[SettingsItem::AutoFlip, SettingsItem::ConfirmMoves],
+    &[SettingsItem::Announce],
+    &[SettingsItem::Back],
+];
+
+// The choices offered for the volume and the AI move delay (in milliseconds)
+const VOLUME_CHOICES: [f32; 5] = [0.0, 0.25, 0.5, 0.75, 1.0];
+const MOVE_DELAY_CHOICES: [u64; 5] = [0, 150, 300, 600, 1000];
+
+// The buttons on the end-of-game banner. A network game has no main menu,
+// and a watched game goes on to the next by itself.
+const GAME_OVER_BUTTONS: [&str; 3] = ["New game", "Review", "Main menu"];
+const AI_GAME_OVER_BUTTONS: [&str; 3] = ["Rematch", "Review", "Main menu"];
+const NETWORK_GAME_OVER_BUTTONS: [&str; 3] = ["Rematch", "New game", "Review"];
+const SPECTATOR_GAME_OVER_BUTTONS: [&str; 1] = ["Review"];
+
+// The most lines of figures the stats scene has room for
+const STATS_LINES: usize = 8;
+
+// The buttons shown when a round of the coordinate trainer is over
+const TRAINER_BUTTONS: [&str; 2] = ["Again", "Back"];
+
+// The buttons under the FEN being typed in, and the longest FEN taken
+const FEN_INPUT_BUTTONS: [&str; 3] = ["Load", "Paste", "Cancel"];
+const MAX_FEN_LENGTH: usize = 100;
+
+// The most games open at once, one for each of Ctrl+1 to Ctrl+9
+const MAX_TABS: usize = 9;
+
+// The choices for the game left unfinished by the last run
+const RECOVERY_BUTTONS: [&str; 2] = ["Resume", "Discard"];
+
+// The buttons of the dialog for copying the move list
+const EXPORT_BUTTONS: [&str; 3] = ["Notation", "Copy", "Cancel"];
+
+// The buttons under the games database's results, how many results it
+// shows at once, how much of each it shows and the longest search taken
+const DATABASE_BUTTONS: [&str; 3] = ["Search", "This position", "Back"];
+const DATABASE_ROWS: usize = 7;
+const DATABASE_ROW_CHARS: usize = 48;
+const MAX_QUERY_LENGTH: usize = 200;
+
+/// One open game and the scenes stacked over it.
+struct Tab {
+    game: ChessGame,
+    scenes: Vec<Scene>,
+    was_over: bool,                     // Whether the game was over at the last update
+    trainer: Option<CoordinateTrainer>, // Set while the coordinate trainer is open
+    fen_input: String,                  // The FEN being typed in
+    fen_error: Option<String>,          // Why the last FEN typed in couldn't be loaded
+    search: String,                     // The games database search being typed in
+    found: Vec<GameSummary>,            // The games the last search found
+    found_scroll: usize,                // The first of them in view
+    search_message: String,             // How many games it found, or why it failed
+}
+
+impl Tab {
+    fn new(game: ChessGame, first: Scene) -> Self {
+        Tab {
+            game,
+            scenes: vec![first],
+            was_over: false,
+            trainer: None,
+            fen_input: String::new(),
+            fen_error: None,
+            search: String::new(),
+            found: Vec::new(),
+            found_scroll: 0,
+            search_message: String::new(),
+        }
+    }
+
+    fn top(&self) -> Scene {
+        self.scenes.last().copied().unwrap_or(Scene::Game)
+    }
+}
+
+/// The event handler: keeps the open games in tabs, passes events to the
+/// top scene of the current one and draws each frame. Games in the other
+/// tabs are paused.
+pub struct App {
+    tabs: Vec<Tab>,
+    current: usize,
+    menu: NewGameMenu, // The main menu's current choices
+    touch: TouchInput,
+    fullscreen: bool,
+    recovered: Option<PgnGame>, // The unfinished game offered for resuming
+    export_notation: Notation,  // The notation the move list is copied in
+}
+
+impl App {
+    /// Starts on the main menu, or straight in the game if `show_menu` is
+    /// false.
+    pub fn new(game: ChessGame, show_menu: bool) -> Self {
+        let menu = game.menu_choices();
+        let first = if show_menu {
+            Scene::MainMenu
+        } else {
+            Scene::Game
+        };
+        App {
+            tabs: vec![Tab::new(game, first)],
+            current: 0,
+            menu,
+            touch: TouchInput::default(),
+            fullscreen: false,
+            recovered: None,
+            export_notation: Notation::San,
+        }
+    }
+
+    /// Asks whether to resume `game`, the game the last run didn't get to
+    /// finish, before anything else.
+    pub fn offer_recovery(&mut self, game: PgnGame) {
+        self.recovered = Some(game);
+        self.push(Scene::Recovery);
+    }
+
+    // Resumes the recovered game in place of the main menu, or forgets it
+    fn answer_recovery(&mut self, ctx: &mut Context, resume: bool) {
+        match self.recovered.take() {
+            Some(recovered) if resume => {
+                self.game_mut().resume_recovered(ctx, recovered);
+                let tab = self.tab_mut();
+                tab.scenes = vec![Scene::Game];
+                tab.game.needs_redraw = true;
+            }
+            _ => {
+                recovery::clear();
+                self.pop();
+            }
+        }
+    }
+
+    fn recovery_click(&mut self, ctx: &mut Context, x: f32, y: f32) {
+        let button = (0..RECOVERY_BUTTONS.len()).find(|&i| {
+            self.game()
+                .dialog_button_rect(i, RECOVERY_BUTTONS.len())
+                .contains([x, y])
+        });
+        if let Some(i) = button {
+            self.answer_recovery(ctx, RECOVERY_BUTTONS[i] == "Resume");
+        }
+    }
+
+    // Asks which notation to copy the move list in, if there are any moves
+    fn open_export(&mut self) {
+        if self.game().has_moves() {
+            self.push(Scene::Export);
+        } else {
+            self.game().toasts.info("No moves to copy yet");
+        }
+    }
+
+    fn next_export_notation(&mut self) {
+        self.export_notation = self.export_notation.next();
+        self.game_mut().needs_redraw = true;
+    }
+
+    fn copy_moves(&mut self) {
+        self.game().copy_moves(self.export_notation);
+        self.pop();
+    }
+
+    fn export_click(&mut self, x: f32, y: f32) {
+        let button = (0..EXPORT_BUTTONS.len()).find(|&i| {
+            self.game()
+                .dialog_button_rect(i, EXPORT_BUTTONS.len())
+                .contains([x, y])
+        });
+        match button.map(|i| EXPORT_BUTTONS[i]) {
+            Some("Notation") => self.next_export_notation(),
+            Some("Copy") => self.copy_moves(),
+            Some(_) => self.pop(),
+            None => {}
+        }
+    }
+
+    fn tab(&self) -> &Tab {
+        &self.tabs[self.current]
+    }
+
+    fn tab_mut(&mut self) -> &mut Tab {
+        &mut self.tabs[self.current]
+    }
+
+    fn game(&self) -> &ChessGame {
+        &self.tab().game
+    }
+
+    fn game_mut(&mut self) -> &mut ChessGame {
+        &mut self.tab_mut().game
+    }
+
+    fn top(&self) -> Scene {
+        self.tab().top()
+    }
+
+    fn push(&mut self, scene: Scene) {
+        let tab = self.tab_mut();
+        tab.scenes.push(scene);
+        tab.game.needs_redraw = true;
+    }
+
+    fn pop(&mut self) {
+        let tab = self.tab_mut();
+        if tab.scenes.len() > 1 && tab.scenes.pop() == Some(Scene::CoordinateTrainer) {
+            tab.trainer = None;
+        }
+        tab.game.resume();
+    }
+
+    // Opens a tab with a fresh board and the main menu over it.
+    fn open_tab(&mut self, ctx: &mut Context) -> GameResult {
+        if self.tabs.len() >= MAX_TABS {
+            self.game()
+                .toasts
+                .info(format!("At most {MAX_TABS} games can be open at once"));
+            return Ok(());
+        }
+        let game = self.game().new_tab()?;
+        self.menu = game.menu_choices();
+        self.tabs.push(Tab::new(game, Scene::MainMenu));
+        self.switch_tab(ctx, self.tabs.len() - 1)
+    }
+
+    // Closes the current tab and goes to the one before it. The last tab
+    // stays open.
+    fn close_tab(&mut self, ctx: &mut Context) -> GameResult {
+        if self.tabs.len() == 1 {
+            self.game().toasts.info("The last game can't be closed");
+            return Ok(());
+        }
+        let closing = self.current;
+        self.tabs.remove(closing);
+        self.current = usize::MAX; // No tab is in front until switch_tab
+        self.switch_tab(ctx, closing.saturating_sub(1))
+    }
+
+    // Brings tab `index` to the front, fitting its board to the window, which
+    // may have been resized since the tab was last shown.
+    fn switch_tab(&mut self, ctx: &mut Context, index: usize) -> GameResult {
+        if index >= self.tabs.len() || index == self.current {
+            return Ok(());
+        }
+        self.current = index;
+        let (width, height) = ctx.gfx.drawable_size();
+        self.game_mut().layout.fit(width, height);
+        self.game_mut().resume();
+        Ok(())
+    }
+
+    // Switches between the window and the whole screen. The resize that
+    // follows fits the board to the new size.
+    fn toggle_fullscreen(&mut self, ctx: &mut Context) -> GameResult {
+        self.fullscreen = !self.fullscreen;
+        ctx.gfx.set_fullscreen(if self.fullscreen {
+            FullscreenType::Desktop
+        } else {
+            FullscreenType::Windowed
+        })
+    }
+
+    // The tabs along the top of the move list, then the "+" for a new one.
+    fn tab_rects(&self) -> Vec<Rect> {
+        let bar = self.game().layout.tab_bar_rect();
+        let width = bar.w / (MAX_TABS + 1) as f32;
+        (0..=self.tabs.len())
+            .map(|i| Rect::new(bar.x + i as f32 * width, bar.y, width, bar.h))
+            .collect()
+    }
+
+    fn draw_tabs(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        for (i, rect) in self.tab_rects().into_iter().enumerate() {
+            let (label, color) = if i == self.tabs.len() {
+                ("+".to_string(), ui::SIDEBAR)
+            } else if i == self.current {
+                ((i + 1).to_string(), ui::PRIMARY)
+            } else {
+                ((i + 1).to_string(), ui::SECONDARY)
+            };
+            let button = Button {
+                label: &label,
+                rect: ui::inset(rect, 1.0),
+                color,
+            };
+            button.draw(ctx, canvas, rect.h * 0.6)?;
+        }
+        Ok(())
+    }
+
+    // Switches to a clicked tab or opens a new one; false if the click was
+    // somewhere else.
+    fn tab_click(&mut self, ctx: &mut Context, x: f32, y: f32) -> GameResult<bool> {
+        let Some(index) = self.tab_rects().iter().position(|r| r.contains([x, y])) else {
+            return Ok(false);
+        };
+        if index == self.tabs.len() {
+            self.open_tab(ctx)?;
+        } else {
+            self.switch_tab(ctx, index)?;
+        }
+        Ok(true)
+    }
+
+    // Opens the main menu over the game, with the game's settings chosen.
+    fn open_main_menu(&mut self) {
+        if self.game().network.is_some() || self.game().spectator.is_some() {
+            self.game()
+                .toasts
+                .info("The main menu is only available when playing locally");
+            return;
+        }
+        self.menu = self.game().menu_choices();
+        self.push(Scene::MainMenu);
+    }
+
+    // What the main menu's Close button does: go back to the game under the
+    // menu, close a tab that hasn't started a game yet, or quit.
+    fn close_main_menu(&mut self, ctx: &mut Context) -> GameResult {
+        if self.tab().scenes.contains(&Scene::Game) {
+            self.pop();
+        } else if self.tabs.len() > 1 {
+            self.close_tab(ctx)?;
+        } else {
+            ctx.request_quit();
+        }
+        Ok(())
+    }
+
+    fn close_label(&self) -> &'static str {
+        if self.tab().scenes.contains(&Scene::Game) {
+            "Back"
+        } else if self.tabs.len() > 1 {
+            "Close tab"
+        } else {
+            "Quit"
+        }
+    }
+
+    fn play(&mut self, ctx: &mut Context) -> GameResult {
+        let tab = &mut self.tabs[self.current];
+        tab.game.start_game(ctx, &self.menu)?;
+        tab.scenes = vec![Scene::Game];
+        tab.was_over = false;
+        Ok(())
+    }
+
+    // Rects for a panel of buttons in the middle of the board, one row per
+    // entry of `rows` with that row's buttons side by side. The panel itself
+    // comes first, followed by the buttons in order.
+    fn panel_rects<T: Copy>(&self, rows: &[&[T]]) -> (Rect, Vec<(T, Rect)>) {
+        let tile = self.game().layout.tile_size;
+        let layout = &self.game().layout;
+        let height = tile * 0.55;
+        let gap = tile * 0.15;
+        let title_height = tile * 1.0;
+
+        let panel_height = title_height + rows.len() as f32 * (height + gap) + gap;
+        let panel = Rect::new(
+            tile,
+            (layout.board_height() - panel_height) / 2.0,
+            layout.board_width() - tile * 2.0,
+            panel_height,
+        );
+
+        let mut rects = Vec::new();
+        for (row, items) in rows.iter().enumerate() {
+            let y = panel.y + title_height + row as f32 * (height + gap);
+            let area = Rect::new(panel.x + gap, y, panel.w - 2.0 * gap, height);
+            let cells = ui::columns(area, items.len(), gap);
+            rects.extend(items.iter().copied().zip(cells));
+        }
+        (panel, rects)
+    }
+
+    // Dims the board and draws a panel headed `title` with a button for each
+    // of `buttons`. Highlighted buttons are drawn in green.
+    fn draw_panel(
+        &self,
+        ctx: &mut Context,
+        canvas: &mut Canvas,
+        title: &str,
+        panel: Rect,
+        buttons: Vec<(String, Rect, bool)>,
+    ) -> GameResult {
+        let layout = &self.game().layout;
+        let tile = layout.tile_size;
+        ui::fill(
+            ctx,
+            canvas,
+            Rect::new(0.0, 0.0, layout.board_width(), layout.board_height()),
+            ui::SHADE,
+        )?;
+        ui::fill(ctx, canvas, panel, ui::PANEL)?;
+        Label::new(title, tile * 0.45).align(Align::Top).draw(
+            ctx,
+            canvas,
+            Rect::new(panel.x, panel.y + tile * 0.3, panel.w, panel.h),
+        )?;
+
+        for (label, rect, highlight) in buttons {
+            Button::new(&label, rect, highlight).draw(ctx, canvas, rect.h * 0.55)?;
+        }
+        Ok(())
+    }
+
+    fn draw_main_menu(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let (panel, rects) = self.panel_rects(&MAIN_MENU_ROWS);
+        let buttons = rects
+            .into_iter()
+            .map(|(item, rect)| {
+                let label = match item {
+                    MenuItem::Close => self.close_label().to_string(),
+                    _ => self.menu.label(item, self.game().ai_depth),
+                };
+                (label, rect, item == MenuItem::Play)
+            })
+            .collect();
+        self.draw_panel(ctx, canvas, "It's just chess", panel, buttons)
+    }
+
+    fn main_menu_click(&mut self, ctx: &mut Context, x: f32, y: f32) -> GameResult {
+        let (_, rects) = self.panel_rects(&MAIN_MENU_ROWS);
+        let Some((item, _)) = rects.into_iter().find(|(_, rect)| rect.contains([x, y])) else {
+            return Ok(());
+        };
+        match item {
+            MenuItem::Play => self.play(ctx)?,
+            MenuItem::Settings => self.push(Scene::Settings),
+            MenuItem::Close => self.close_main_menu(ctx)?,
+            MenuItem::Positions => self.push(Scene::Positions),
+            MenuItem::Stats => self.push(Scene::Stats),
+            MenuItem::Database => self.push(Scene::Database),
+            MenuItem::Trainer => {
+                let trainer = CoordinateTrainer::new(&self.game().state.board);
+                self.tab_mut().trainer = Some(trainer);
+                self.push(Scene::CoordinateTrainer);
+            }
+            _ => self.menu.cycle(item),
+        }
+        self.game_mut().needs_redraw = true;
+        Ok(())
+    }
+
+    fn settings_label(&self, item: SettingsItem) -> String {
+        let game = self.game();
+        let on_off = |on: bool| if on { "on" } else { "off" };
+        match item {
+            SettingsItem::PossibleMoves => {
+                format!("Show possible moves: {}", on_off(game.show_possible_moves))
+            }
+            SettingsItem::Threats => format!("Show threats: {}", on_off(game.show_threats)),
+            SettingsItem::Sound => format!("Sound: {}", on_off(!game.sounds.borrow().is_muted())),
+            SettingsItem::Volume => {
+                let volume = game.settings.borrow().volume.unwrap_or(1.0);
+                format!("Volume: {:.0}%", volume * 100.0)
+            }
+            SettingsItem::MoveDelay => {
+                format!("AI move delay: {} ms", game.ai_move_delay.as_millis())
+            }
+            SettingsItem::HighContrast => format!("High contrast: {}", on_off(game.high_contrast)),
+            SettingsItem::PieceLetters => format!("Piece letters: {}", on_off(game.piece_letters)),
+            SettingsItem::AutoFlip => format!("Auto-flip: {}", on_off(game.auto_flip)),
+            SettingsItem::ConfirmMoves => format!("Confirm moves: {}", on_off(game.confirm_moves)),
+            SettingsItem::Announce => format!("Announce moves: {}", game.announcer.mode()),
+            SettingsItem::Back => "Back".to_string(),
+        }
+    }
+
+    fn draw_settings(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let (panel, rects) = self.panel_rects(&SETTINGS_ROWS);
+        let buttons = rects
+            .into_iter()
+            .map(|(item, rect)| (self.settings_label(item), rect, false))
+            .collect();
+        self.draw_panel(ctx, canvas, "Settings", panel, buttons)
+    }
+
+    // Each setting steps to its next value when clicked and is saved straight
+    // away, as with the keyboard shortcuts. It applies to every open game.
+    fn settings_click(&mut self, x: f32, y: f32) {
+        let (_, rects) = self.panel_rects(&SETTINGS_ROWS);
+        let Some((item, _)) = rects.into_iter().find(|(_, rect)| rect.contains([x, y])) else {
+            return;
+        };
+        if item == SettingsItem::Back {
+            self.pop();
+            return;
+        }
+        // Settings and sounds are shared by every tab
+        let shared = Rc::clone(&self.game().settings);
+        let mut settings = shared.borrow_mut();
+        match item {
+            SettingsItem::PossibleMoves => {
+                let show = !self.game().show_possible_moves;
+                settings.show_possible_moves = Some(show);
+                for tab in &mut self.tabs {
+                    tab.game.show_possible_moves = show;
+                }
+            }
+            SettingsItem::Threats => {
+                let show = !self.game().show_threats;
+                settings.show_threats = Some(show);
+                for tab in &mut self.tabs {
+                    tab.game.show_threats = show;
+                }
+            }
+            SettingsItem::Sound => {
+                settings.muted = Some(self.game().sounds.borrow_mut().toggle_mute());
+            }
+            SettingsItem::Volume => {
+                let current = settings.volume.unwrap_or(1.0);
+                let volume = VOLUME_CHOICES
+                    .into_iter()
+                    .find(|&v| v > current + 0.01)
+                    .unwrap_or(VOLUME_CHOICES[0]);
+                self.game().sounds.borrow_mut().set_volume(volume);
+                settings.volume = Some(volume);
+            }
+            SettingsItem::MoveDelay => {
+                let current = self.game().ai_move_delay.as_millis() as u64;
+                let delay = MOVE_DELAY_CHOICES
+                    .into_iter()
+                    .find(|&d| d > current)
+                    .unwrap_or(MOVE_DELAY_CHOICES[0]);
+                settings.ai_move_delay = Some(delay);
+                for tab in &mut self.tabs {
+                    tab.game.ai_move_delay = Duration::from_millis(delay);
+                }
+            }
+            SettingsItem::HighContrast => {
+                let high_contrast = !self.game().high_contrast;
+                settings.high_contrast = Some(high_contrast);
+                for tab in &mut self.tabs {
+                    tab.game.high_contrast = high_contrast;
+                }
+            }
+            SettingsItem::PieceLetters => {
+                let piece_letters = !self.game().piece_letters;
+                settings.piece_letters = Some(piece_letters);
+                for tab in &mut self.tabs {
+                    tab.game.piece_letters = piece_letters;
+                }
+            }
+            SettingsItem::AutoFlip => {
+                let auto_flip = !self.game().auto_flip;
+                settings.auto_flip = Some(auto_flip);
+                for tab in &mut self.tabs {
+                    tab.game.auto_flip = auto_flip;
+                }
+            }
+            SettingsItem::ConfirmMoves => {
+                let confirm_moves = !self.game().confirm_moves;
+                settings.confirm_moves = Some(confirm_moves);
+                for tab in &mut self.tabs {
+                    tab.game.confirm_moves = confirm_moves;
+                }
+            }
+            SettingsItem::Announce => {
+                let announce = self.game().announcer.mode().next();
+                settings.announce = Some(announce);
+                for tab in &mut self.tabs {
+                    tab.game.announcer.set_mode(announce);
+                }
+            }
+            SettingsItem::Back => {}
+        }
+        settings.save();
+        self.game_mut().needs_redraw = true;
+    }
+
+    // The coordinate trainer draws its own empty board, without the labels
+    // that would give the answers away, and keeps score in the move list's
+    // place. The round's result comes up in a banner.
+    fn draw_trainer(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let Some(trainer) = &self.tab().trainer else {
+            return Ok(());
+        };
+        let game = self.game();
+        let tile = game.layout.tile_size;
+        let board = trainer.board();
+        let theme = BoardTheme::get(game.high_contrast);
+        let flash = trainer.flash();
+        for row in 0..board.ranks {
+            for col in 0..board.files {
+                let color = match flash {
+                    Some((square, true)) if square == (row, col) => Color::from_rgb(90, 190, 90),
+                    Some((square, false)) if square == (row, col) => Color::from_rgb(210, 70, 70),
+                    _ => BoardTheme::shade(theme.square, board.is_light((row, col))),
+                };
+                let rect = Rect::new(col as f32 * tile, row as f32 * tile, tile, tile);
+                let mesh = Mesh::new_rectangle(ctx, DrawMode::fill(), rect, color)?;
+                canvas.draw(&mesh, DrawParam::default());
+            }
+        }
+
+        if trainer.is_finished() {
+            let message = format!(
+                "Time's up: {} found, {} missed (best {})",
+                trainer.score(),
+                trainer.misses(),
+                trainer.best_score()
+            );
+            game.draw_dialog(ctx, canvas, &message, &TRAINER_BUTTONS)?;
+        } else {
+            let mut target = Text::new(trainer.target_name());
+            target.set_scale(PxScale::from(tile * 2.0));
+            let size = target.measure(ctx)?;
+            canvas.draw(
+                &target,
+                DrawParam::default()
+                    .dest([
+                        (game.layout.board_width() - size.x) / 2.0,
+                        (game.layout.board_height() - size.y) / 2.0,
+                    ])
+                    .color(Color::from_rgba(255, 255, 255, 170)),
+            );
+        }
+
+        let panel = game.layout.history_panel_rect();
+        let background =
+            Mesh::new_rectangle(ctx, DrawMode::fill(), panel, Color::from_rgb(40, 40, 40))?;
+        canvas.draw(&background, DrawParam::default());
+        let lines = [
+            format!("Find {}", trainer.target_name()),
+            format!("Time: {}s", trainer.time_left().as_secs_f32().ceil()),
+            format!("Score: {}", trainer.score()),
+            format!("Missed: {}", trainer.misses()),
+            format!("Streak: {}", trainer.streak()),
+            format!("Best streak: {}", trainer.best_streak()),
+            "Esc to leave".to_string(),
+        ];
+        for (i, line) in lines.into_iter().enumerate() {
+            let mut text = Text::new(line);
+            text.set_scale(PxScale::from(tile * 0.3));
+            canvas.draw(
+                &text,
+                DrawParam::default()
+                    .dest([
+                        panel.x + tile * 0.2,
+                        panel.y + tile * (0.3 + 0.5 * i as f32),
+                    ])
+                    .color(Color::WHITE),
+            );
+        }
+        Ok(())
+    }
+
+    // Clicks on the board are guesses; once the round is over they go to
+    // the banner's buttons instead.
+    fn trainer_click(&mut self, x: f32, y: f32) {
+        let game = self.game();
+        let square = game.coords_to_square(x, y);
+        let button = (0..TRAINER_BUTTONS.len())
+            .find(|&i| {
+                game.dialog_button_rect(i, TRAINER_BUTTONS.len())
+                    .contains([x, y])
+            })
+            .map(|i| TRAINER_BUTTONS[i]);
+        let Some(trainer) = &mut self.tab_mut().trainer else {
+            return;
+        };
+        if !trainer.is_finished() {
+            if let Some(square) = square {
+                trainer.click(square);
+            }
+            return;
+        }
+        match button {
+            Some("Again") => trainer.restart(),
+            Some(_) => self.pop(),
+            None => {}
+        }
+    }
+
+    fn draw_positions(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let (panel, rects) = self.panel_rects(&POSITION_ROWS);
+        let buttons = rects
+            .into_iter()
+            .map(|(item, rect)| {
+                let label = match item {
+                    PositionItem::Library(i) => POSITION_LIBRARY[i].name.to_string(),
+                    PositionItem::Endgame(endgame) => format!("Train {endgame}"),
+                    PositionItem::EnterFen => "Enter FEN".to_string(),
+                    PositionItem::Repertoire => "Drill repertoire".to_string(),
+                    PositionItem::Scramble => "Time scramble".to_string(),
+                    PositionItem::Back => "Back".to_string(),
+                };
+                (label, rect, false)
+            })
+            .collect();
+        self.draw_panel(ctx, canvas, "Start from a position", panel, buttons)
+    }
+
+    fn positions_click(&mut self, ctx: &mut Context, x: f32, y: f32) -> GameResult {
+        let (_, rects) = self.panel_rects(&POSITION_ROWS);
+        let Some((item, _)) = rects.into_iter().find(|(_, rect)| rect.contains([x, y])) else {
+            return Ok(());
+        };
+        match item {
+            PositionItem::Library(i) => {
+                let position = &POSITION_LIBRARY[i];
+                match self.load_position(ctx, position.fen)? {
+                    Ok(()) => {
+                        let about = format!("{}: {}", position.name, position.about);
+                        self.game().toasts.info(about);
+                    }
+                    Err(err) => {
+                        let error = format!("Failed to load {}: {err}", position.name);
+                        self.game().toasts.error(error);
+                    }
+                }
+            }
+            PositionItem::Endgame(endgame) => {
+                let tab = &mut self.tabs[self.current];
+                if tab.scenes.contains(&Scene::MainMenu) {
+                    tab.game.start_game(ctx, &self.menu)?;
+                }
+                tab.game.start_endgame_trainer(endgame);
+                tab.scenes = vec![Scene::Game];
+                tab.was_over = false;
+            }
+            PositionItem::EnterFen => self.open_fen_input(),
+            PositionItem::Repertoire => {
+                let tab = &mut self.tabs[self.current];
+                if tab.scenes.contains(&Scene::MainMenu) {
+                    tab.game.start_game(ctx, &self.menu)?;
+                }
+                tab.game.start_repertoire_drill();
+                if tab.game.repertoire_drill.is_some() {
+                    tab.scenes = vec![Scene::Game];
+                    tab.was_over = false;
+                }
+            }
+            PositionItem::Scramble => {
+                let tab = &mut self.tabs[self.current];
+                if tab.scenes.contains(&Scene::MainMenu) {
+                    tab.game.start_game(ctx, &self.menu)?;
+                }
+                tab.game.start_time_scramble(ctx);
+                tab.scenes = vec![Scene::Game];
+                tab.was_over = false;
+            }
+            PositionItem::Back => self.pop(),
+        }
+        self.game_mut().needs_redraw = true;
+        Ok(())
+    }
+
+    // The figures shown in the stats scene, a line each: the rating, the
+    // results, the accuracy and the favourite openings, then the results at
+    // each AI level for as many levels as there is room.
+    fn stats_lines(&self) -> Vec<String> {
+        let stats = self.game().stats.borrow();
+        let mut lines = Vec::new();
+        lines.push(match stats.rating() {
+            Some((rating, games)) => format!("Rating {rating} from {games} games against the AI"),
+            None => "Play the AI at a level for a rating".to_string(),
+        });
+        let tally = stats.tally();
+        lines.push(match tally.games() {
+            0 => "No games finished yet".to_string(),
+            games => format!(
+                "{games} games: {} won, {} lost, {} drawn",
+                tally.wins, tally.losses, tally.draws
+            ),
+        });
+        lines.push(match stats.average_accuracy() {
+            Some((accuracy, games)) => {
+                format!("Average accuracy {accuracy:.0}% over {games} reviewed games")
+            }
+            None => "Review a finished game for its accuracy".to_string(),
+        });
+        for (opening, games) in stats.top_openings(2) {
+            lines.push(format!("{opening} ({games})"));
+        }
+        for (difficulty, tally) in stats.tally_by_difficulty() {
+            lines.push(format!(
+                "Level {difficulty}: {} won, {} lost, {} drawn",
+                tally.wins, tally.losses, tally.draws
+            ));
+        }
+        lines.truncate(STATS_LINES);
+        lines
+    }
+
+    // A panel with a row for each line of figures and a Back button under
+    // them.
+    fn stats_rects(&self, lines: usize) -> (Rect, Vec<(Option<usize>, Rect)>) {
+        let rows: Vec<[Option<usize>; 1]> = (0..lines)
+            .map(Some)
+            .chain([None])
+            .map(|row| [row])
+            .collect();
+        let rows: Vec<&[Option<usize>]> = rows.iter().map(|row| &row[..]).collect();
+        self.panel_rects(&rows)
+    }
+
+    fn draw_stats(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let lines = self.stats_lines();
+        let (panel, rects) = self.stats_rects(lines.len());
+        let back = rects
+            .iter()
+            .filter(|(row, _)| row.is_none())
+            .map(|&(_, rect)| ("Back".to_string(), rect, false))
+            .collect();
+        self.draw_panel(ctx, canvas, "Statistics", panel, back)?;
+
+        for (row, rect) in rects {
+            let Some(line) = row.map(|i| &lines[i]) else {
+                continue;
+            };
+            let mut text = Text::new(line.as_str());
+            text.set_scale(PxScale::from(rect.h * 0.5));
+            text.set_bounds([rect.w, rect.h]);
+            let height = text.measure(ctx)?.y;
+            canvas.draw(
+                &text,
+                DrawParam::default()
+                    .dest([rect.x, rect.y + (rect.h - height) / 2.0])
+                    .color(Color::WHITE),
+            );
+        }
+        Ok(())
+    }
+
+    fn stats_click(&mut self, x: f32, y: f32) {
+        let (_, rects) = self.stats_rects(self.stats_lines().len());
+        let back = rects
+            .iter()
+            .any(|(row, rect)| row.is_none() && rect.contains([x, y]));
+        if back {
+            self.pop();
+            self.game_mut().needs_redraw = true;
+        }
+    }
+
+    fn open_fen_input(&mut self) {
+        let tab = self.tab_mut();
+        tab.fen_input.clear();
+        tab.fen_error = None;
+        self.push(Scene::FenInput);
+    }
+
+    // Plays from `fen`. From the main menu this starts a game with the
+    // menu's choices; over a game it starts that game again from `fen`.
+    // Either way the game then comes back to the front. A FEN that can't be
+    // loaded is reported back and changes nothing.
+    fn load_position(&mut self, ctx: &mut Context, fen: &str) -> GameResult<Result<(), String>> {
+        let tab = &mut self.tabs[self.current];
+        if tab.scenes.contains(&Scene::MainMenu) {
+            let mut check = GameState::with_variant(self.menu.variant);
+            if let Err(err) = check.from_fen(fen) {
+                return Ok(Err(err.to_string()));
+            }
+            tab.game.start_game(ctx, &self.menu)?;
+        }
+        if let Err(err) = tab.game.load_fen(fen) {
+            return Ok(Err(err));
+        }
+        tab.scenes = vec![Scene::Game];
+        tab.was_over = false;
+        Ok(Ok(()))
+    }
+
+    fn submit_fen_input(&mut self, ctx: &mut Context) -> GameResult {
+        let fen = self.tab().fen_input.trim().to_string();
+        if let Err(err) = self.load_position(ctx, &fen)? {
+            self.tab_mut().fen_error = Some(err);
+        }
+        self.game_mut().needs_redraw = true;
+        Ok(())
+    }
+
+    fn paste_fen_input(&mut self) {
+        match arboard::Clipboard::new().and_then(|mut cb| cb.get_text()) {
+            Ok(text) => {
+                let tab = self.tab_mut();
+                let room = MAX_FEN_LENGTH.saturating_sub(tab.fen_input.chars().count());
+                let pasted = text.trim().chars().filter(|c| !c.is_control());
+                tab.fen_input.extend(pasted.take(room));
+            }
+            Err(e) => self
+                .game()
+                .toasts
+                .error(format!("Failed to read the clipboard: {e}")),
+        }
+        self.game_mut().needs_redraw = true;
+    }
+
+    // The FEN goes in a box between the banner's message and its buttons.
+    // Only as much of the end of a long FEN is shown as fits. Why the last
+    // one couldn't be loaded takes the message's place, in smaller print as
+    // it can be long.
+    fn draw_fen_input(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let game = self.game();
+        let tab = self.tab();
+        let tile = game.layout.tile_size;
+        let (board_width, board_height) = (game.layout.board_width(), game.layout.board_height());
+        match &tab.fen_error {
+            None => game.draw_dialog(ctx, canvas, "Type or paste a FEN", &FEN_INPUT_BUTTONS)?,
+            Some(error) => {
+                game.draw_dialog(ctx, canvas, "", &FEN_INPUT_BUTTONS)?;
+                let mut text = Text::new(error.as_str());
+                text.set_scale(PxScale::from(tile * 0.25));
+                text.set_bounds([board_width - tile * 2.5, tile * 0.6]);
+                canvas.draw(
+                    &text,
+                    DrawParam::default()
+                        .dest([tile * 1.25, board_height / 2.0 - tile * 1.05])
+                        .color(Color::from_rgb(240, 130, 110)),
+                );
+            }
+        }
+
+        let field = Rect::new(
+            tile * 1.25,
+            board_height / 2.0 - tile * 0.4,
+            board_width - tile * 2.5,
+            tile * 0.55,
+        );
+        let background =
+            Mesh::new_rectangle(ctx, DrawMode::fill(), field, Color::from_rgb(20, 20, 20))?;
+        canvas.draw(&background, DrawParam::default());
+
+        let padding = tile * 0.1;
+        let mut shown = tab.fen_input.as_str();
+        let text = loop {
+            let mut text = Text::new(format!("{shown}_"));
+            text.set_scale(PxScale::from(field.h * 0.55));
+            if shown.is_empty() || text.measure(ctx)?.x <= field.w - 2.0 * padding {
+                break text;
+            }
+            shown = &shown[shown.chars().next().map_or(0, char::len_utf8)..];
+        };
+        let height = text.measure(ctx)?.y;
+        canvas.draw(
+            &text,
+            DrawParam::default()
+                .dest([field.x + padding, field.y + (field.h - height) / 2.0])
+                .color(Color::WHITE),
+        );
+        Ok(())
+    }
+
+    fn fen_input_click(&mut self, ctx: &mut Context, x: f32, y: f32) -> GameResult {
+        let button = (0..FEN_INPUT_BUTTONS.len())
+            .find(|&i| {
+                self.game()
+                    .dialog_button_rect(i, FEN_INPUT_BUTTONS.len())
+                    .contains([x, y])
+            })
+            .map(|i| FEN_INPUT_BUTTONS[i]);
+        match button {
+            Some("Load") => self.submit_fen_input(ctx)?,
+            Some("Paste") => self.paste_fen_input(),
+            Some(_) => self.pop(),
+            None => {}
+        }
+        Ok(())
+    }
+
+    // Looks for the games matching the search typed in.
+    fn search_database(&mut self) {
+        let query = GameQuery::parse(&self.tab().search);
+        let found = query.and_then(|query| Database::open()?.search(&query));
+        let tab = self.tab_mut();
+        tab.found_scroll = 0;
+        match found {
+            Ok(found) => {
+                tab.search_message = match found.len() {
+                    0 => "No games found".to_string(),
+                    1 => "1 game found".to_string(),
+                    games => format!("{games} games found"),
+                };
+                tab.found = found;
+            }
+            Err(err) => {
+                tab.search_message = err;
+                tab.found.clear();
+            }
+        }
+        tab.game.needs_redraw = true;
+    }
+
+    // Searches for the games reaching the position on the board.
+    fn search_position(&mut self) {
+        let fen = self.game().displayed_state().to_fen();
+        self.tab_mut().search = format!("fen:{fen}");
+        self.search_database();
+    }
+
+    // Opens the `index`th game found to step through.
+    fn open_found(&mut self, ctx: &mut Context, index: usize) {
+        let Some(id) = self.tab().found.get(index).map(|summary| summary.id) else {
+            return;
+        };
+        match Database::open().and_then(|database| database.game(id)) {
+            Ok(pgn) => {
+                let tab = self.tab_mut();
+                tab.game.open_replay(ctx, pgn);
+                tab.scenes = vec![Scene::Game];
+                tab.was_over = tab.game.status.is_over();
+            }
+            Err(err) => self
+                .game()
+                .toasts
+                .error(format!("Failed to open the game: {err}")),
+        }
+    }
+
+    // The games database scene fills the board: the search box under the
+    // title, then a line saying what the search found, a row for each game
+    // in view and the buttons. Rects for the whole panel, the search box and
+    // the rows come first, the buttons last.
+    fn database_rects(&self) -> (Rect, Rect, Vec<Rect>, Vec<Rect>) {
+        let tile = self.game().layout.tile_size;
+        let layout = &self.game().layout;
+        let gap = tile * 0.15;
+        let panel = Rect::new(
+            tile * 0.5,
+            tile * 0.5,
+            layout.board_width() - tile,
+            layout.board_height() - tile,
+        );
+        let inner = panel.w - 2.0 * gap;
+        let field = Rect::new(panel.x + gap, panel.y + tile * 0.9, inner, tile * 0.5);
+        let first_row = field.y + field.h + tile * 0.5;
+        let row_height = tile * 0.6;
+        let rows = (0..DATABASE_ROWS)
+            .map(|i| {
+                Rect::new(
+                    field.x,
+                    first_row + i as f32 * row_height,
+                    inner,
+                    row_height,
+                )
+            })
+            .collect();
+        let buttons_area = Rect::new(
+            field.x,
+            panel.y + panel.h - gap - tile * 0.55,
+            inner,
+            tile * 0.55,
+        );
+        let buttons = ui::columns(buttons_area, DATABASE_BUTTONS.len(), gap);
+        (panel, field, rows, buttons)
+    }
+
+    fn draw_database(&self, ctx: &mut Context, canvas: &mut Canvas) -> GameResult {
+        let (panel, field, rows, buttons) = self.database_rects();
+        let buttons = DATABASE_BUTTONS
+            .iter()
+            .zip(buttons)
+            .map(|(label, rect)| (label.to_string(), rect, *label == "Search"))
+            .collect();
+        self.draw_panel(ctx, canvas, "Games database", panel, buttons)?;
+
+        let tab = self.tab();
+        let tile = self.game().layout.tile_size;
+        ui::fill(ctx, canvas, field, Color::from_rgb(20, 20, 20))?;
+        let (search, color) = match tab.search.as_str() {
+            "" => (
+                "player:Carlsen opening:B90 material:KRvKR result:1-0".to_string(),
+                ui::MUTED,
+            ),
+            search => (format!("{search}_"), Color::WHITE),
+        };
+        // Only as much of the end of a long search as fits is shown
+        let shown: String = search
+            .chars()
+            .skip(search.chars().count().saturating_sub(DATABASE_ROW_CHARS))
+            .collect();
+        Label::new(shown, field.h * 0.5)
+            .color(color)
+            .align(Align::Centre)
+            .draw(ctx, canvas, field)?;
+        Label::new(tab.search_message.as_str(), tile * 0.22)
+            .color(ui::MUTED)
+            .draw(
+                ctx,
+                canvas,
+                Rect::new(
+                    field.x,
+                    field.y + field.h + tile * 0.12,
+                    field.w,
+                    tile * 0.3,
+                ),
+            )?;
+
+        for (summary, rect) in tab.found.iter().skip(tab.found_scroll).zip(rows) {
+            let players = format!(
+                "{} - {}  {}  {}",
+                summary.white, summary.black, summary.result, summary.date
+            );
+            let about = match summary.opening.as_str() {
+                "" => summary.event.clone(),
+                opening => format!("{opening}, {}", summary.event),
+            };
+            let cut = |text: String| text.chars().take(DATABASE_ROW_CHARS).collect::<String>();
+            Label::new(cut(players), rect.h * 0.4).draw(ctx, canvas, rect)?;
+            Label::new(cut(about), rect.h * 0.3).color(ui::MUTED).draw(
+                ctx,
+                canvas,
+                Rect::new(rect.x, rect.y + rect.h * 0.45, rect.w, rect.h),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn database_click(&mut self, ctx: &mut Context, x: f32, y: f32) {
+        let (_, _, rows, buttons) = self.database_rects();
+        if let Some(row) = rows.iter().position(|rect| rect.contains([x, y])) {
+            self.open_found(ctx, self.tab().found_scroll + row);
+        }
+        match buttons.iter().position(|rect| rect.contains([x, y])) {
+            Some(0) => self.search_database(),
+            Some(1) => self.search_position(),
+            Some(_) => self.pop(),
+            None => {}
+        }
+        self.game_mut().needs_redraw = true;
+    }
+
+    // Plays a gesture out as the mouse clicks it stands for.
+    fn gesture(&mut self, ctx: &mut Context, gesture: Gesture) -> GameResult {
+        match gesture {
+            Gesture::Tap(x, y) => {
+                self.mouse_button_down_event(ctx, MouseButton::Left, x, y)?;
+                self.mouse_button_up_event(ctx, MouseButton::Left, x, y)
+            }
+            Gesture::LongPress(x, y) => self.mouse_button_down_event(ctx, MouseButton::Right, x, y),
+            Gesture::LongPressEnd(x, y) => {
+                self.mouse_button_up_event(ctx, MouseButton::Right, x, y)
+            }
+        }
+    }
+
+    fn game_over_buttons(&self) -> &'static [&'static str] {
+        if self.game().spectator.is_some() {
+            &SPECTATOR_GAME_OVER_BUTTONS
+        } else if self.game().network.is_some() {
+            &NETWORK_GAME_OVER_BUTTONS
+        } else if self.game().series_opponent().is_some() {
+            &AI_GAME_OVER_BUTTONS
+        } else {
+            &GAME_OVER_BUTTONS
+        }
+    }
+
+    fn game_over_click(&mut self, x: f32, y: f32) {
+        let buttons = self.game_over_buttons();
+        let Some(&label) = buttons.iter().enumerate().find_map(|(i, label)| {
+            let rect = self.game().dialog_button_rect(i, buttons.len());
+            rect.contains([x, y]).then_some(label)
+        }) else {
+            return;
+        };
+        match label {
+            "New game" => {
+                let game = self.game_mut();
+                game.new_game();
+                game.send_to_opponent(Message::NewGame);
+                self.pop();
+            }
+            "Rematch" => {
+                let game = self.game_mut();
+                game.rematch();
+                game.send_to_opponent(Message::Rematch);
+                self.pop();
+            }
+            "Main menu" => self.open_main_menu(),
+            "Review" => {
+                self.game_mut().start_review();
+                self.pop();
+            }
+            _ => self.pop(),
+        }
+    }
+}
+
+impl EventHandler<GameError> for App {
+    fn update(&mut self, ctx: &mut Context) -> Result<(), GameError> {
+        if let Some(gesture) = self.touch.update() {
+            self.gesture(ctx, gesture)?;
+        }
+        if self.game().toasts.update() {
+            self.game_mut().needs_redraw = true;
+        }
+
+        // The game stands still while a menu is open
+        match self.top() {
+            Scene::Game | Scene::GameOver => self.game_mut().update(ctx)?,
+            Scene::MainMenu
+            | Scene::Settings
+            | Scene::Positions
+            | Scene::FenInput
+            | Scene::Stats
+            | Scene::Recovery
+            | Scene::Export
+            | Scene::Database => return Ok(()),
+            // Redrawn every frame for its timer
+            Scene::CoordinateTrainer => {
+                self.game_mut().needs_redraw = true;
+                return Ok(());
+            }
+        }
+
+        // Show the result when the game ends, and take it down if the remote
+        // player starts a new game
+        let over = self.game().status.is_over();
+        let was_over = self.tab().was_over;
+        if over && !was_over && self.top() == Scene::Game {
+            self.push(Scene::GameOver);
+        } else if !over && self.top() == Scene::GameOver {
+            self.pop();
+        }
+        self.tab_mut().was_over = over;
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> Result<(), GameError> {
+        if !self.game().needs_redraw {
+            return Ok(());
+        }
+
+        self.game_mut().save_requested_image(ctx);
+        let mut canvas = Canvas::from_frame(ctx, Color::from_rgb(34, 139, 34));
+
+        // Draw in board space; see Layout
+        canvas.set_screen_coordinates(self.game().layout.view_rect());
+
+        if self.top() == Scene::CoordinateTrainer {
+            self.draw_trainer(ctx, &mut canvas)?;
+        } else {
+            self.game_mut().draw(ctx, &mut canvas)?;
+        }
+        self.draw_tabs(ctx, &mut canvas)?;
+        match self.top() {
+            Scene::Game | Scene::CoordinateTrainer => {}
+            Scene::MainMenu => self.draw_main_menu(ctx, &mut canvas)?,
+            Scene::Settings => self.draw_settings(ctx, &mut canvas)?,
+            Scene::Positions => self.draw_positions(ctx, &mut canvas)?,
+            Scene::FenInput => self.draw_fen_input(ctx, &mut canvas)?,
+            Scene::Stats => self.draw_stats(ctx, &mut canvas)?,
+            Scene::Database => self.draw_database(ctx, &mut canvas)?,
+            Scene::Recovery => self.game().draw_dialog(
+                ctx,
+                &mut canvas,
+                "Resume the unfinished game from last time?",
+                &RECOVERY_BUTTONS,
+            )?,
+            Scene::Export => self.game().draw_dialog(
+                ctx,
+                &mut canvas,
+                &format!("Copy the moves in {}", self.export_notation),
+                &EXPORT_BUTTONS,
+            )?,
+            Scene::GameOver => {
+                let description = self.game().status.description();
+                let buttons = self.game_over_buttons();
+                self.game()
+                    .draw_dialog(ctx, &mut canvas, &description, buttons)?;
+            }
+        }
+
+        // Toasts go over everything, in the bottom corner of the board
+        let layout = &self.game().layout;
+        let board = Rect::new(0.0, 0.0, layout.board_width(), layout.board_height());
+        let scale = layout.tile_size * 0.25;
+        self.game().toasts.draw(ctx, &mut canvas, board, scale)?;
+
+        canvas.finish(ctx)?;
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        input: KeyInput,
+        repeat: bool,
+    ) -> Result<(), GameError> {
+        let Some(key) = input.keycode else {
+            return Ok(());
+        };
+        let ctrl = input.mods.contains(KeyMods::CTRL);
+
+        if key == KeyCode::F11 {
+            return self.toggle_fullscreen(ctx);
+        }
+
+        // Tabs work from any scene: Ctrl+T opens one, Ctrl+W closes it,
+        // Ctrl+Tab goes to the next and Ctrl+1 to Ctrl+9 pick one
+        if ctrl {
+            let digit = [
+                KeyCode::Key1,
+                KeyCode::Key2,
+                KeyCode::Key3,
+                KeyCode::Key4,
+                KeyCode::Key5,
+                KeyCode::Key6,
+                KeyCode::Key7,
+                KeyCode::Key8,
+                KeyCode::Key9,
+            ]
+            .iter()
+            .position(|&k| k == key);
+            match key {
+                KeyCode::T => return self.open_tab(ctx),
+                KeyCode::W => return self.close_tab(ctx),
+                KeyCode::Tab => return self.switch_tab(ctx, (self.current + 1) % self.tabs.len()),
+                _ if digit.is_some() => return self.switch_tab(ctx, digit.unwrap_or(0)),
+                _ => {}
+            }
+        }
+
+        match self.top() {
+            // Ctrl+N opens the main menu, Ctrl+L asks for a FEN to play from
+            // and Ctrl+M copies the moves, unless a position is being set up
+            Scene::Game => {
+                if key == KeyCode::N && ctrl && self.game().editor.is_none() {
+                    self.open_main_menu();
+                    return Ok(());
+                }
+                if key == KeyCode::L && ctrl && self.game().editor.is_none() {
+                    self.open_fen_input();
+                    return Ok(());
+                }
+                if key == KeyCode::M && ctrl && self.game().editor.is_none() {
+                    self.open_export();
+                    return Ok(());
+                }
+                self.game_mut().key_down_event(ctx, input, repeat)?;
+            }
+            // Enter plays, and Escape or Ctrl+N goes back to the game
+            Scene::MainMenu => {
+                let in_game = self.tab().scenes.contains(&Scene::Game);
+                match key {
+                    KeyCode::Return => self.play(ctx)?,
+                    KeyCode::Escape if in_game => self.pop(),
+                    KeyCode::N if ctrl && in_game => self.pop(),
+                    _ => {}
+                }
+            }
+            Scene::Settings | Scene::Positions | Scene::Stats => {
+                if key == KeyCode::Escape {
+                    self.pop();
+                }
+            }
+            // Enter loads the FEN, Ctrl+V pastes and Escape gives up
+            Scene::FenInput => {
+                match key {
+                    KeyCode::Return | KeyCode::NumpadEnter => self.submit_fen_input(ctx)?,
+                    KeyCode::V if ctrl => self.paste_fen_input(),
+                    KeyCode::Back => {
+                        self.tab_mut().fen_input.pop();
+                    }
+                    KeyCode::Escape => self.pop(),
+                    _ => {}
+                }
+                self.game_mut().needs_redraw = true;
+            }
+            // Enter searches, Up and Down scroll through what it found and
+            // Escape goes back
+            Scene::Database => {
+                let tab = self.tab_mut();
+                match key {
+                    KeyCode::Return | KeyCode::NumpadEnter => self.search_database(),
+                    KeyCode::Escape => self.pop(),
+                    KeyCode::Back => {
+                        tab.search.pop();
+                    }
+                    KeyCode::Up => tab.found_scroll = tab.found_scroll.saturating_sub(1),
+                    KeyCode::Down => {
+                        let last = tab.found.len().saturating_sub(DATABASE_ROWS);
+                        tab.found_scroll = (tab.found_scroll + 1).min(last);
+                    }
+                    _ => {}
+                }
+                self.game_mut().needs_redraw = true;
+            }
+            // Escape leaves the result to look back through the game
+            Scene::GameOver => {
+                if key == KeyCode::Escape {
+                    self.pop();
+                }
+            }
+            // Enter resumes the unfinished game and Escape discards it
+            Scene::Recovery => match key {
+                KeyCode::Return | KeyCode::NumpadEnter => self.answer_recovery(ctx, true),
+                KeyCode::Escape => self.answer_recovery(ctx, false),
+                _ => {}
+            },
+            // Space or Right moves on to the next notation, Enter copies and
+            // Escape gives up
+            Scene::Export => match key {
+                KeyCode::Space | KeyCode::Right => self.next_export_notation(),
+                KeyCode::Return | KeyCode::NumpadEnter => self.copy_moves(),
+                KeyCode::Escape => self.pop(),
+                _ => {}
+            },
+            // Escape leaves the trainer and Enter starts another round
+            Scene::CoordinateTrainer => match key {
+                KeyCode::Escape => self.pop(),
+                KeyCode::Return => {
+                    if let Some(trainer) = &mut self.tab_mut().trainer {
+                        if trainer.is_finished() {
+                            trainer.restart();
+                        }
+                    }
+                }
+                _ => {}
+            },
+        }
+        Ok(())
+    }
+
+    // Key releases always reach the game, so a peek under the blindfold
+    // ends even if a menu opened in the meantime
+    fn key_up_event(&mut self, ctx: &mut Context, input: KeyInput) -> Result<(), GameError> {
+        self.game_mut().key_up_event(ctx, input)
+    }
+
+    fn mouse_button_down_event(
+        &mut self,
+        ctx: &mut Context,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+    ) -> Result<(), GameError> {
+        let (board_x, board_y) = self.game().layout.to_board_space(x, y);
+        if button == MouseButton::Left && self.tab_click(ctx, board_x, board_y)? {
+            return Ok(());
+        }
+
+        if self.top() == Scene::Game {
+            return self.game_mut().mouse_button_down_event(ctx, button, x, y);
+        }
+        if button != MouseButton::Left {
+            return Ok(());
+        }
+        let (x, y) = (board_x, board_y);
+        match self.top() {
+            Scene::MainMenu => self.main_menu_click(ctx, x, y)?,
+            Scene::Settings => self.settings_click(x, y),
+            Scene::GameOver => self.game_over_click(x, y),
+            Scene::CoordinateTrainer => self.trainer_click(x, y),
+            Scene::Positions => self.positions_click(ctx, x, y)?,
+            Scene::Stats => self.stats_click(x, y),
+            Scene::FenInput => self.fen_input_click(ctx, x, y)?,
+            Scene::Recovery => self.recovery_click(ctx, x, y),
+            Scene::Export => self.export_click(x, y),
+            Scene::Database => self.database_click(ctx, x, y),
+            Scene::Game => {}
+        }
+        Ok(())
+    }
+
+    fn mouse_motion_event(
+        &mut self,
+        ctx: &mut Context,
+        x: f32,
+        y: f32,
+        dx: f32,
+        dy: f32,
+    ) -> Result<(), GameError> {
+        if self.top() == Scene::Game {
+            self.game_mut().mouse_motion_event(ctx, x, y, dx, dy)?;
+        }
+        Ok(())
+    }
+
+    fn mouse_button_up_event(
+        &mut self,
+        ctx: &mut Context,
+        button: MouseButton,
+        x: f32,
+        y: f32,
+    ) -> Result<(), GameError> {
+        if self.top() == Scene::Game {
+            self.game_mut().mouse_button_up_event(ctx, button, x, y)?;
+        }
+        Ok(())
+    }
+
+    // Touches are turned into clicks: a tap is a left click, and a long press
+    // a right click, for annotations. See TouchInput.
+    fn touch_event(
+        &mut self,
+        ctx: &mut Context,
+        phase: TouchPhase,
+        x: f64,
+        y: f64,
+    ) -> Result<(), GameError> {
+        match self.touch.touch(phase, x, y) {
+            Some(gesture) => self.gesture(ctx, gesture),
+            None => Ok(()),
+        }
+    }
+
+    fn text_input_event(&mut self, ctx: &mut Context, character: char) -> Result<(), GameError> {
+        match self.top() {
+            Scene::Game => self.game_mut().text_input_event(ctx, character)?,
+            Scene::FenInput => {
+                let tab = self.tab_mut();
+                if !character.is_control() && tab.fen_input.chars().count() < MAX_FEN_LENGTH {
+                    tab.fen_input.push(character);
+                    tab.game.needs_redraw = true;
+                }
+            }
+            Scene::Database => {
+                let tab = self.tab_mut();
+                if !character.is_control() && tab.search.chars().count() < MAX_QUERY_LENGTH {
+                    tab.search.push(character);
+                    tab.game.needs_redraw = true;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // Only the tab in front is fitted to the new size; the others are
+    // fitted when they are switched to. Sizes are in physical pixels, so
+    // moving the window to a screen with another scale factor comes here
+    // too, and the board keeps its size on the screen.
+    fn resize_event(
+        &mut self,
+        _ctx: &mut Context,
+        width: f32,
+        height: f32,
+    ) -> Result<(), GameError> {
+        let game = self.game_mut();
+        game.layout.fit(width, height);
+        game.needs_redraw = true;
+        Ok(())
+    }
+
+    fn mouse_wheel_event(&mut self, ctx: &mut Context, x: f32, y: f32) -> Result<(), GameError> {
+        match self.top() {
+            Scene::Game => self.game_mut().mouse_wheel_event(ctx, x, y)?,
+            Scene::Database => {
+                let tab = self.tab_mut();
+                let last = tab.found.len().saturating_sub(DATABASE_ROWS);
+                tab.found_scroll = if y > 0.0 {
+                    tab.found_scroll.saturating_sub(1)
+                } else {
+                    (tab.found_scroll + 1).min(last)
+                };
+                tab.game.needs_redraw = true;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // The tabs share one set of evaluations, saved as the window closes if
+    // they are kept between sessions
+    fn quit_event(&mut self, _ctx: &mut Context) -> Result<bool, GameError> {
+        if let Ok(evals) = self.game().evals.lock() {
+            evals.save();
+        }
+        Ok(false)
+    }
+}
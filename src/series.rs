@@ -0,0 +1,35 @@
+use crate::stats::Outcome;
+
+/// The score over a run of games against the same opponent, counted from
+/// the local player's side. It carries over rematches, and with
+/// --keep-series the score against the AI is kept in the stats file from
+/// one session to the next.
+#[derive(Default, Copy, Clone, PartialEq, Debug)]
+pub struct Series {
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+}
+
+impl Series {
+    pub fn record(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Win => self.wins += 1,
+            Outcome::Loss => self.losses += 1,
+            Outcome::Draw => self.draws += 1,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Series::default()
+    }
+
+    /// The score for the status bar, e.g. "You 2 – 1 AI (1 drawn)".
+    pub fn describe(&self, opponent: &str) -> String {
+        let mut text = format!("You {} – {} {opponent}", self.wins, self.losses);
+        if self.draws > 0 {
+            text.push_str(&format!(" ({} drawn)", self.draws));
+        }
+        text
+    }
+}
@@ -0,0 +1,340 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use rand::Rng;
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+use crate::settings::config_path;
+
+// How long a submitted move waits for the game to play it. The game only
+// looks for moves while it is on screen, not while a menu is open.
+const MOVE_TIMEOUT: Duration = Duration::from_secs(2);
+
+// How often an event stream checks for pings and the client leaving
+const EVENT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// The longest request body read, which is plenty for a move
+const MAX_BODY_LEN: usize = 1024;
+
+// Where the session's token is written for programs that play moves, in the
+// config directory. Web pages can't read it, so they can't play moves.
+const TOKEN_FILE: &str = "server.token";
+
+/// A move submitted to the server, waiting for the game to play it.
+pub struct MoveRequest {
+    pub uci: String,
+    reply: Sender<Result<(), String>>,
+}
+
+impl MoveRequest {
+    /// Tells the client the move was played, or why it wasn't.
+    pub fn answer(self, result: Result<(), String>) {
+        // The client may have given up waiting
+        let _ = self.reply.send(result);
+    }
+}
+
+// The game as the server last heard of it, and the event streams open
+#[derive(Default)]
+struct Shared {
+    fen: String,
+    pgn: String,
+    subscribers: Vec<Sender<String>>,
+}
+
+/// An HTTP server that lets other programs, such as home automation or a
+/// streaming overlay, follow and play the game:
+///
+/// - `GET /fen` and `GET /pgn` return the game so far.
+/// - `POST /move` with a move in UCI notation as the body plays it, for
+///   whoever could type a move at the board, and returns the new FEN. It
+///   needs the token made for the session, written to `server.token` in
+///   the config directory, in an `Authorization: Bearer` header.
+/// - `GET /events`, opened as a WebSocket, sends a JSON message for every
+///   move played and every time the position is replaced, starting with
+///   the current one.
+///
+/// Any web page may read the game, for overlays served from elsewhere, but
+/// only programs that can read the token may play moves.
+///
+/// Each connection is handled on its own thread. The game polls for moves
+/// once per frame and tells the server whenever the position changes.
+pub struct Server {
+    shared: Arc<Mutex<Shared>>,
+    moves: Receiver<MoveRequest>,
+}
+
+impl Server {
+    pub fn serve(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let token = format!("{:032x}", rand::rng().random::<u128>());
+        let token_path = config_path(TOKEN_FILE);
+        let written = token_path.as_ref().map(|path| {
+            path.parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|_| std::fs::write(path, &token))
+        });
+        match (written, &token_path) {
+            (Some(Ok(())), Some(path)) => log::info!(
+                "Serving the game on http://{}, with the token for moves in {}",
+                listener.local_addr()?,
+                path.display()
+            ),
+            // Without the file the token has to be shown some other way
+            _ => log::warn!(
+                "Serving the game on http://{}; moves need the token {token}",
+                listener.local_addr()?
+            ),
+        }
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let token = Arc::new(token);
+        let (moves_tx, moves) = mpsc::channel();
+
+        let listener_shared = Arc::clone(&shared);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        log::warn!("Failed to accept connection: {e}");
+                        continue;
+                    }
+                };
+                let shared = Arc::clone(&listener_shared);
+                let moves_tx = moves_tx.clone();
+                let token = Arc::clone(&token);
+                thread::spawn(move || {
+                    if let Err(e) = handle_connection(stream, &shared, &moves_tx, &token) {
+                        log::debug!("Server connection failed: {e}");
+                    }
+                });
+            }
+        });
+
+        Ok(Server { shared, moves })
+    }
+
+    /// The next move submitted, if any.
+    pub fn poll(&self) -> Option<MoveRequest> {
+        self.moves.try_recv().ok()
+    }
+
+    /// Records the position after `uci` (`san`) was played and tells the
+    /// subscribers.
+    pub fn publish_move(&self, uci: &str, san: &str, fen: String, pgn: String) {
+        // None of these need escaping in JSON
+        let event = format!(r#"{{"type":"move","uci":"{uci}","san":"{san}","fen":"{fen}"}}"#);
+        self.publish(fen, pgn, event);
+    }
+
+    /// Records a position that replaced the last one, such as a new game or
+    /// a takeback, and tells the subscribers.
+    pub fn publish_position(&self, fen: String, pgn: String) {
+        let event = position_event(&fen);
+        self.publish(fen, pgn, event);
+    }
+
+    fn publish(&self, fen: String, pgn: String, event: String) {
+        let mut shared = lock(&self.shared);
+        shared.fen = fen;
+        shared.pgn = pgn;
+        shared
+            .subscribers
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+fn position_event(fen: &str) -> String {
+    format!(r#"{{"type":"position","fen":"{fen}"}}"#)
+}
+
+// A thread that panicked holding the lock can't have left the game half
+// updated, so carry on with it
+fn lock(shared: &Mutex<Shared>) -> MutexGuard<'_, Shared> {
+    shared.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Answers one request, or for /events keeps the connection open as a
+// WebSocket until the client leaves.
+fn handle_connection(
+    mut stream: TcpStream,
+    shared: &Mutex<Shared>,
+    moves: &Sender<MoveRequest>,
+    token: &str,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut words = request_line.split_whitespace();
+    let method = words.next().unwrap_or_default();
+    let path = words.next().unwrap_or_default();
+
+    let mut content_length = 0;
+    let mut websocket_key = None;
+    let mut authorization = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => content_length = value.trim().parse().unwrap_or(0),
+            "sec-websocket-key" => websocket_key = Some(value.trim().to_string()),
+            "authorization" => authorization = Some(value.trim().to_string()),
+            _ => {}
+        }
+    }
+
+    match (method, path) {
+        ("GET", "/fen") => {
+            let fen = lock(shared).fen.clone();
+            respond_to_any_origin(&mut stream, "200 OK", "text/plain", &fen)
+        }
+        ("GET", "/pgn") => {
+            let pgn = lock(shared).pgn.clone();
+            respond_to_any_origin(&mut stream, "200 OK", "application/x-chess-pgn", &pgn)
+        }
+        ("POST", "/move") => {
+            let bearer = authorization
+                .as_deref()
+                .and_then(|value| value.strip_prefix("Bearer "));
+            if !bearer.is_some_and(|bearer| tokens_match(bearer.trim(), token)) {
+                return respond(
+                    &mut stream,
+                    "401 Unauthorized",
+                    "text/plain",
+                    "Moves need the token in server.token as an Authorization: Bearer header",
+                );
+            }
+            if content_length > MAX_BODY_LEN {
+                return respond(&mut stream, "413 Payload Too Large", "text/plain", "");
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body)?;
+            let uci = String::from_utf8_lossy(&body).trim().to_ascii_lowercase();
+            let (reply, answer) = mpsc::channel();
+            let _ = moves.send(MoveRequest { uci, reply });
+            match answer.recv_timeout(MOVE_TIMEOUT) {
+                Ok(Ok(())) => {
+                    let fen = lock(shared).fen.clone();
+                    respond(&mut stream, "200 OK", "text/plain", &fen)
+                }
+                Ok(Err(reason)) => respond(&mut stream, "409 Conflict", "text/plain", &reason),
+                Err(_) => respond(
+                    &mut stream,
+                    "503 Service Unavailable",
+                    "text/plain",
+                    "The game isn't taking moves while a menu is open",
+                ),
+            }
+        }
+        ("GET", "/events") => match websocket_key {
+            Some(key) => stream_events(stream, &key, shared),
+            None => respond(
+                &mut stream,
+                "426 Upgrade Required",
+                "text/plain",
+                "Open /events as a WebSocket",
+            ),
+        },
+        _ => respond(&mut stream, "404 Not Found", "text/plain", "Not found"),
+    }
+}
+
+// Compares every byte whatever the first difference, so the time taken
+// doesn't give away how much of a guessed token was right
+fn tokens_match(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> io::Result<()> {
+    write_response(stream, status, content_type, "", body)
+}
+
+// Browser overlays are served from elsewhere, so any origin may read the
+// game, though not change it
+fn respond_to_any_origin(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> io::Result<()> {
+    let headers = "Access-Control-Allow-Origin: *\r\n";
+    write_response(stream, status, content_type, headers, body)
+}
+
+fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    headers: &str,
+    body: &str,
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: {content_type}; charset=utf-8\r\n\
+         Content-Length: {}\r\n\
+         {headers}\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+// Completes the WebSocket handshake, then sends the current position and
+// every event after it until the client leaves.
+fn stream_events(mut stream: TcpStream, key: &str, shared: &Mutex<Shared>) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        derive_accept_key(key.as_bytes())
+    )?;
+    stream.set_read_timeout(Some(EVENT_POLL_INTERVAL))?;
+
+    let (events_tx, events) = mpsc::channel();
+    let current = {
+        let mut shared = lock(shared);
+        shared.subscribers.push(events_tx);
+        position_event(&shared.fen)
+    };
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+    if socket.send(Message::text(current)).is_err() {
+        return Ok(());
+    }
+    loop {
+        // Reading answers pings, and notices the client closing or vanishing
+        match socket.read() {
+            Ok(message) if message.is_close() => return Ok(()),
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e))
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(_) => return Ok(()),
+        }
+        for event in events.try_iter() {
+            if socket.send(Message::text(event)).is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
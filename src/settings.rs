@@ -0,0 +1,133 @@
+use std::path::PathBuf;
+
+use itsjustchess::chess_core::Difficulty;
+
+use crate::accessibility::Announce;
+
+const SETTINGS_FILE: &str = "settings.conf";
+
+/// Preferences remembered between launches, stored as `key = value` lines in
+/// the platform's config directory. Anything missing is left as `None` so
+/// the built-in default applies; command-line flags override all of them.
+#[derive(Default, Debug)]
+pub struct Settings {
+    pub board_size: Option<f32>,
+    pub piece_set: Option<String>,
+    pub show_possible_moves: Option<bool>,
+    pub muted: Option<bool>,
+    pub volume: Option<f32>,
+    pub ai_depth: Option<u32>,
+    pub difficulty: Option<Difficulty>,
+    pub ai_move_delay: Option<u64>,
+    pub high_contrast: Option<bool>,
+    pub piece_letters: Option<bool>,
+    pub announce: Option<Announce>,
+    pub auto_flip: Option<bool>,
+    pub confirm_moves: Option<bool>,
+    pub show_threats: Option<bool>,
+    pub analysis_depth: Option<u32>,
+    pub analysis_lines: Option<usize>,
+}
+
+impl Settings {
+    /// Reads the settings file. A missing file gives empty settings, and
+    /// lines that don't parse are skipped.
+    pub fn load() -> Self {
+        let mut settings = Settings::default();
+        let text = match config_path(SETTINGS_FILE).and_then(|p| std::fs::read_to_string(p).ok()) {
+            Some(text) => text,
+            None => return settings,
+        };
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                log::warn!("Ignoring malformed settings line: {line}");
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "board_size" => settings.board_size = value.parse().ok(),
+                "piece_set" => settings.piece_set = Some(value.to_string()),
+                "show_possible_moves" => settings.show_possible_moves = value.parse().ok(),
+                "muted" => settings.muted = value.parse().ok(),
+                "volume" => settings.volume = value.parse().ok(),
+                "ai_depth" => settings.ai_depth = value.parse().ok(),
+                "difficulty" => settings.difficulty = value.parse().ok(),
+                "ai_move_delay" => settings.ai_move_delay = value.parse().ok(),
+                "high_contrast" => settings.high_contrast = value.parse().ok(),
+                "piece_letters" => settings.piece_letters = value.parse().ok(),
+                "announce" => settings.announce = value.parse().ok(),
+                "auto_flip" => settings.auto_flip = value.parse().ok(),
+                "confirm_moves" => settings.confirm_moves = value.parse().ok(),
+                "show_threats" => settings.show_threats = value.parse().ok(),
+                "analysis_depth" => settings.analysis_depth = value.parse().ok(),
+                "analysis_lines" => settings.analysis_lines = value.parse().ok(),
+                other => log::warn!("Ignoring unknown setting: {other}"),
+            }
+        }
+        settings
+    }
+
+    /// Writes the settings file, creating the config directory if needed.
+    pub fn save(&self) {
+        let Some(path) = config_path(SETTINGS_FILE) else {
+            return;
+        };
+        let mut text = String::from("# itsjustchess settings\n");
+        let mut write = |key: &str, value: Option<String>| {
+            if let Some(value) = value {
+                text.push_str(&format!("{key} = {value}\n"));
+            }
+        };
+        write("board_size", self.board_size.map(|v| v.to_string()));
+        write("piece_set", self.piece_set.clone());
+        write(
+            "show_possible_moves",
+            self.show_possible_moves.map(|v| v.to_string()),
+        );
+        write("muted", self.muted.map(|v| v.to_string()));
+        write("volume", self.volume.map(|v| v.to_string()));
+        write("ai_depth", self.ai_depth.map(|v| v.to_string()));
+        write("difficulty", self.difficulty.map(|v| v.to_string()));
+        write("ai_move_delay", self.ai_move_delay.map(|v| v.to_string()));
+        write("high_contrast", self.high_contrast.map(|v| v.to_string()));
+        write("piece_letters", self.piece_letters.map(|v| v.to_string()));
+        write("announce", self.announce.map(|v| v.to_string()));
+        write("auto_flip", self.auto_flip.map(|v| v.to_string()));
+        write("confirm_moves", self.confirm_moves.map(|v| v.to_string()));
+        write("show_threats", self.show_threats.map(|v| v.to_string()));
+        write("analysis_depth", self.analysis_depth.map(|v| v.to_string()));
+        write("analysis_lines", self.analysis_lines.map(|v| v.to_string()));
+
+        let result = match path.parent() {
+            Some(dir) => std::fs::create_dir_all(dir),
+            None => Ok(()),
+        }
+        .and_then(|_| std::fs::write(&path, text));
+        if let Err(e) = result {
+            log::warn!("Failed to save settings to {}: {e}", path.display());
+        }
+    }
+}
+
+/// Where `file` is kept in the config directory, e.g.
+/// ~/.config/itsjustchess/settings.conf on Linux, ~/Library/Application
+/// Support/itsjustchess/settings.conf on macOS and
+/// %APPDATA%\itsjustchess\settings.conf on Windows.
+pub fn config_path(file: &str) -> Option<PathBuf> {
+    let env_dir = |name: &str| std::env::var_os(name).filter(|v| !v.is_empty());
+    let config_dir = if cfg!(windows) {
+        env_dir("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env_dir("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env_dir("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    Some(config_dir?.join("itsjustchess").join(file))
+}
@@ -0,0 +1,204 @@
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use itsjustchess::chess_core::{parse_pgn, GameState, Move};
+
+// The newline-delimited JSON feed of the game being shown on Lichess TV
+const LICHESS_TV_URL: &str = "https://lichess.org/api/tv/feed";
+
+// How often a watched PGN file is checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// How long to wait before reopening a feed that dropped
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Where a followed game comes from.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Source {
+    /// Whatever game Lichess TV is showing, moving on to the next when it ends
+    LichessTv,
+    /// A PGN file, read again whenever it changes (the last game in it is shown)
+    PgnFile(PathBuf),
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lichess" | "lichess-tv" => Ok(Source::LichessTv),
+            _ => Ok(Source::PgnFile(PathBuf::from(s))),
+        }
+    }
+}
+
+/// Something that happened in the followed game, for the game loop to show.
+pub enum SpectatorEvent {
+    /// The game being followed, from its starting position. It replaces the
+    /// one on the board unless it only adds moves to it.
+    Game {
+        white: String,
+        black: String,
+        start: Box<GameState>,
+        moves: Vec<Move>,
+    },
+    /// A move just played and the FEN of the position it led to, which is
+    /// shown instead if the move doesn't follow on from the board.
+    Move { mv: Option<Move>, fen: String },
+}
+
+/// A game being watched rather than played. The source is read on a
+/// background thread; the game polls for events once per frame.
+pub struct Spectator {
+    events: Receiver<SpectatorEvent>,
+    pub players: Option<(String, String)>, // White and Black, once known
+}
+
+impl Spectator {
+    pub fn watch(source: Source) -> Self {
+        let (events_tx, events) = mpsc::channel();
+        thread::spawn(move || match source {
+            Source::LichessTv => follow_lichess_tv(&events_tx),
+            Source::PgnFile(path) => watch_pgn_file(&path, &events_tx),
+        });
+        Spectator {
+            events,
+            players: None,
+        }
+    }
+
+    /// The next pending event, if any.
+    pub fn poll(&self) -> Option<SpectatorEvent> {
+        self.events.try_recv().ok()
+    }
+}
+
+// Rereads `path` whenever its modification time changes and sends the last
+// game in it if that differs from what was sent before. A file that doesn't
+// parse is usually still being written, so it is reported and tried again
+// on the next change.
+fn watch_pgn_file(path: &Path, events: &Sender<SpectatorEvent>) {
+    let mut last_modified: Option<SystemTime> = None;
+    let mut last_sent: Option<(String, Vec<String>)> = None;
+    loop {
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        if modified.is_some() && modified != last_modified {
+            last_modified = modified;
+            match fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|text| parse_pgn(&text))
+            {
+                Ok(game) => {
+                    let key = (
+                        game.start.to_fen(),
+                        game.moves
+                            .iter()
+                            .map(|mv| mv.to_uci_on(&game.start.board))
+                            .collect(),
+                    );
+                    if last_sent.as_ref() != Some(&key) {
+                        last_sent = Some(key);
+                        let tag = |name: &str| {
+                            game.tags
+                                .iter()
+                                .find(|(n, _)| n == name)
+                                .map_or("?".to_string(), |(_, v)| v.clone())
+                        };
+                        let event = SpectatorEvent::Game {
+                            white: tag("White"),
+                            black: tag("Black"),
+                            start: Box::new(game.start),
+                            moves: game.moves,
+                        };
+                        if events.send(event).is_err() {
+                            return; // The game has gone away
+                        }
+                    }
+                }
+                Err(err) => log::warn!("Failed to read {}: {err}", path.display()),
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+// Streams the Lichess TV feed, reconnecting whenever it drops.
+fn follow_lichess_tv(events: &Sender<SpectatorEvent>) {
+    loop {
+        match ureq::get(LICHESS_TV_URL).call() {
+            Ok(response) => {
+                log::info!("Watching Lichess TV");
+                for line in BufReader::new(response.into_reader()).lines() {
+                    let Ok(line) = line else {
+                        break;
+                    };
+                    if let Some(event) = parse_tv_line(&line) {
+                        if events.send(event).is_err() {
+                            return;
+                        }
+                    }
+                }
+                log::warn!("Lichess TV feed closed; reconnecting");
+            }
+            Err(e) => log::warn!("Failed to reach Lichess TV: {e}; retrying"),
+        }
+        thread::sleep(RECONNECT_DELAY);
+    }
+}
+
+// Reads one line of the feed: "featured" when a new game comes on, with its
+// players and current position, then "fen" after each move.
+fn parse_tv_line(line: &str) -> Option<SpectatorEvent> {
+    let fen = complete_fen(json_strings(line, "fen").first()?);
+    match *json_strings(line, "t").first()? {
+        "featured" => {
+            let mut start = GameState::new();
+            if let Err(err) = start.from_fen(&fen) {
+                log::warn!("Ignoring Lichess TV game: {err}");
+                return None;
+            }
+            // Players are listed White first
+            let names = json_strings(line, "name");
+            Some(SpectatorEvent::Game {
+                white: names.first().unwrap_or(&"?").to_string(),
+                black: names.get(1).unwrap_or(&"?").to_string(),
+                start: Box::new(start),
+                moves: Vec::new(),
+            })
+        }
+        "fen" => Some(SpectatorEvent::Move {
+            mv: json_strings(line, "lm")
+                .first()
+                .and_then(|uci| Move::from_uci(uci)),
+            fen,
+        }),
+        _ => None,
+    }
+}
+
+// Every string value of `key` in a line of JSON, in order. The feed's values
+// are plain enough (names, FENs, moves) not to need a full parser.
+fn json_strings<'a>(json: &'a str, key: &str) -> Vec<&'a str> {
+    let pattern = format!("\"{key}\":\"");
+    json.match_indices(&pattern)
+        .filter_map(|(i, _)| {
+            let value = &json[i + pattern.len()..];
+            value.find('"').map(|end| &value[..end])
+        })
+        .collect()
+}
+
+// Lichess sends only the placement and side to move; fill in the rest
+fn complete_fen(fen: &str) -> String {
+    let mut fields: Vec<&str> = fen.split_whitespace().collect();
+    let defaults = ["w", "-", "-", "0", "1"];
+    for default in &defaults[fields.len().saturating_sub(1).min(defaults.len())..] {
+        fields.push(default);
+    }
+    fields.join(" ")
+}
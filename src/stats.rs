@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+
+use itsjustchess::chess_core::{Difficulty, GameStatus, PieceColor};
+
+use crate::series::Series;
+use crate::settings::config_path;
+
+const STATS_FILE: &str = "stats.conf";
+
+// The rating a player starts from, and how far one game can move it
+const START_RATING: f64 = 1200.0;
+const RATING_K: f64 = 32.0;
+
+/// How a finished game went for the local player.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Outcome {
+    Win,
+    Loss,
+    Draw,
+}
+
+impl Outcome {
+    /// The outcome of a finished game in which the local player had
+    /// `player`, or `None` if it isn't finished.
+    pub fn of(status: GameStatus, player: PieceColor) -> Option<Self> {
+        match status.winner() {
+            Some(winner) if winner == player => Some(Outcome::Win),
+            Some(_) => Some(Outcome::Loss),
+            None if status.is_over() => Some(Outcome::Draw),
+            None => None,
+        }
+    }
+
+    // Points for the rating: 1 for a win, a half for a draw
+    fn score(self) -> f64 {
+        match self {
+            Outcome::Win => 1.0,
+            Outcome::Loss => 0.0,
+            Outcome::Draw => 0.5,
+        }
+    }
+}
+
+/// Who a recorded game was played against.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Opponent {
+    /// The AI, at a difficulty level or (`None`) a plain --ai-depth search
+    Ai(Option<Difficulty>),
+    Remote,
+}
+
+/// One finished game, as kept in the stats file.
+#[derive(Clone, PartialEq, Debug)]
+pub struct GameRecord {
+    pub date: String, // In the PGN YYYY.MM.DD format
+    pub opponent: Opponent,
+    pub color: PieceColor,
+    pub outcome: Outcome,
+    pub opening: Option<String>, // ECO code and name, e.g. "C50 Italian Game"
+    /// The share of the player's moves the review found best or good, in
+    /// percent, once the game has been reviewed
+    pub accuracy: Option<f32>,
+}
+
+impl GameRecord {
+    // One `game = ...` line of the stats file, fields separated by '|' and
+    // "-" for those missing, e.g.
+    // "2024.05.01 | ai 3 | white | win | C50 Italian Game | 87.5".
+    fn encode(&self) -> String {
+        let opponent = match self.opponent {
+            Opponent::Ai(Some(difficulty)) => format!("ai {difficulty}"),
+            Opponent::Ai(None) => "ai".to_string(),
+            Opponent::Remote => "remote".to_string(),
+        };
+        let color = match self.color {
+            PieceColor::White => "white",
+            PieceColor::Black => "black",
+        };
+        let outcome = match self.outcome {
+            Outcome::Win => "win",
+            Outcome::Loss => "loss",
+            Outcome::Draw => "draw",
+        };
+        let accuracy = self.accuracy.map(|a| format!("{a:.1}"));
+        format!(
+            "{} | {opponent} | {color} | {outcome} | {} | {}",
+            self.date,
+            self.opening.as_deref().unwrap_or("-"),
+            accuracy.as_deref().unwrap_or("-"),
+        )
+    }
+
+    fn decode(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split('|').map(str::trim).collect();
+        let [date, opponent, color, outcome, opening, accuracy] = fields[..] else {
+            return None;
+        };
+        let opponent = match opponent.split_once(' ') {
+            Some(("ai", level)) => Opponent::Ai(Some(level.parse().ok()?)),
+            None if opponent == "ai" => Opponent::Ai(None),
+            None if opponent == "remote" => Opponent::Remote,
+            _ => return None,
+        };
+        let color = match color {
+            "white" => PieceColor::White,
+            "black" => PieceColor::Black,
+            _ => return None,
+        };
+        let outcome = match outcome {
+            "win" => Outcome::Win,
+            "loss" => Outcome::Loss,
+            "draw" => Outcome::Draw,
+            _ => return None,
+        };
+        Some(GameRecord {
+            date: date.to_string(),
+            opponent,
+            color,
+            outcome,
+            opening: (opening != "-").then(|| opening.to_string()),
+            accuracy: accuracy.parse().ok(),
+        })
+    }
+}
+
+/// Wins, losses and draws.
+#[derive(Default, Copy, Clone, PartialEq, Debug)]
+pub struct Tally {
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+}
+
+impl Tally {
+    fn add(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Win => self.wins += 1,
+            Outcome::Loss => self.losses += 1,
+            Outcome::Draw => self.draws += 1,
+        }
+    }
+
+    pub fn games(&self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+}
+
+/// The local player's record: every game finished against the AI or a
+/// remote player, and the series score against the AI kept with
+/// --keep-series. Stored as `key = value` lines in the stats file next to
+/// the settings file.
+#[derive(Default, Debug)]
+pub struct Stats {
+    pub series: Series,
+    pub games: Vec<GameRecord>,
+}
+
+impl Stats {
+    /// Reads the stats file. A missing file gives empty stats, and lines
+    /// that don't parse are skipped.
+    pub fn load() -> Self {
+        let mut stats = Stats::default();
+        let Some(text) = config_path(STATS_FILE).and_then(|p| std::fs::read_to_string(p).ok())
+        else {
+            return stats;
+        };
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                log::warn!("Ignoring malformed stats line: {line}");
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "ai_wins" => stats.series.wins = value.parse().unwrap_or(0),
+                "ai_losses" => stats.series.losses = value.parse().unwrap_or(0),
+                "ai_draws" => stats.series.draws = value.parse().unwrap_or(0),
+                "game" => match GameRecord::decode(value) {
+                    Some(game) => stats.games.push(game),
+                    None => log::warn!("Ignoring malformed game in stats: {value}"),
+                },
+                other => log::warn!("Ignoring unknown stat: {other}"),
+            }
+        }
+        stats
+    }
+
+    /// Writes the stats file, creating the config directory if needed.
+    pub fn save(&self) {
+        let Some(path) = config_path(STATS_FILE) else {
+            return;
+        };
+        let mut text = String::from("# itsjustchess stats\n");
+        text.push_str(&format!("ai_wins = {}\n", self.series.wins));
+        text.push_str(&format!("ai_losses = {}\n", self.series.losses));
+        text.push_str(&format!("ai_draws = {}\n", self.series.draws));
+        for game in &self.games {
+            text.push_str(&format!("game = {}\n", game.encode()));
+        }
+
+        let result = match path.parent() {
+            Some(dir) => std::fs::create_dir_all(dir),
+            None => Ok(()),
+        }
+        .and_then(|_| std::fs::write(&path, text));
+        if let Err(e) = result {
+            log::warn!("Failed to save stats to {}: {e}", path.display());
+        }
+    }
+
+    /// Adds a finished game and saves, returning its index for filling in
+    /// the accuracy once the game is reviewed.
+    pub fn record(&mut self, game: GameRecord) -> usize {
+        self.games.push(game);
+        self.save();
+        self.games.len() - 1
+    }
+
+    /// Fills in the accuracy of game `index` and saves.
+    pub fn set_accuracy(&mut self, index: usize, accuracy: f32) {
+        if let Some(game) = self.games.get_mut(index) {
+            game.accuracy = Some(accuracy);
+            self.save();
+        }
+    }
+
+    pub fn tally(&self) -> Tally {
+        let mut tally = Tally::default();
+        for game in &self.games {
+            tally.add(game.outcome);
+        }
+        tally
+    }
+
+    /// The results against the AI at each difficulty level played, easiest
+    /// first.
+    pub fn tally_by_difficulty(&self) -> Vec<(Difficulty, Tally)> {
+        let mut tallies: Vec<(Difficulty, Tally)> = Vec::new();
+        for game in &self.games {
+            let Opponent::Ai(Some(difficulty)) = game.opponent else {
+                continue;
+            };
+            match tallies.iter_mut().find(|(d, _)| *d == difficulty) {
+                Some((_, tally)) => tally.add(game.outcome),
+                None => {
+                    let mut tally = Tally::default();
+                    tally.add(game.outcome);
+                    tallies.push((difficulty, tally));
+                }
+            }
+        }
+        tallies.sort_by_key(|(difficulty, _)| difficulty.level());
+        tallies
+    }
+
+    /// The average accuracy over the reviewed games, and how many there are.
+    pub fn average_accuracy(&self) -> Option<(f32, usize)> {
+        let accuracies: Vec<f32> = self.games.iter().filter_map(|g| g.accuracy).collect();
+        if accuracies.is_empty() {
+            return None;
+        }
+        let average = accuracies.iter().sum::<f32>() / accuracies.len() as f32;
+        Some((average, accuracies.len()))
+    }
+
+    /// The `count` openings played most often, with how often, most played
+    /// first.
+    pub fn top_openings(&self, count: usize) -> Vec<(&str, usize)> {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for opening in self.games.iter().filter_map(|g| g.opening.as_deref()) {
+            *counts.entry(opening).or_default() += 1;
+        }
+        let mut openings: Vec<(&str, usize)> = counts.into_iter().collect();
+        openings.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        openings.truncate(count);
+        openings
+    }
+
+    /// An Elo-like rating from the games against the AI at a difficulty
+    /// level, taken in the order played with each level rated as a player
+    /// of fixed strength, and how many games it is from. `None` before the
+    /// first such game.
+    pub fn rating(&self) -> Option<(i32, usize)> {
+        let mut rating = START_RATING;
+        let mut rated = 0;
+        for game in &self.games {
+            let Opponent::Ai(Some(difficulty)) = game.opponent else {
+                continue;
+            };
+            let expected = 1.0 / (1.0 + 10f64.powf((level_rating(difficulty) - rating) / 400.0));
+            rating += RATING_K * (game.outcome.score() - expected);
+            rated += 1;
+        }
+        (rated > 0).then(|| (rating.round() as i32, rated))
+    }
+}
+
+// The strength the AI is rated at for each difficulty level: 600 at level
+// 1, up 200 a level to 2000 at full strength.
+fn level_rating(difficulty: Difficulty) -> f64 {
+    400.0 + 200.0 * f64::from(difficulty.level())
+}
@@ -0,0 +1,35 @@
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+/// Moves written to stdin by another program, in UCI notation and separated
+/// by spaces or newlines, e.g. "e2e4 e7e5". Stdin is read on a background
+/// thread; the game polls for moves once per frame.
+pub struct StdinMoves {
+    moves: Receiver<String>,
+}
+
+impl StdinMoves {
+    pub fn read() -> Self {
+        let (moves_tx, moves) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                for uci in line.split_whitespace() {
+                    if moves_tx.send(uci.to_ascii_lowercase()).is_err() {
+                        return; // The game has gone away
+                    }
+                }
+            }
+            log::info!("Stdin closed; no more moves will be read from it");
+        });
+        StdinMoves { moves }
+    }
+
+    /// The next move read, if any.
+    pub fn poll(&self) -> Option<String> {
+        self.moves.try_recv().ok()
+    }
+}
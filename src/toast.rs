@@ -0,0 +1,108 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use ggez::graphics::{Canvas, Color, Rect};
+use ggez::{Context, GameResult};
+
+use crate::ui::{self, Label};
+
+// How long a toast stays up
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+
+// How many toasts can be up at once; the oldest go to make room
+const MAX_TOASTS: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ToastKind {
+    Info,
+    Error,
+}
+
+struct Toast {
+    text: String,
+    kind: ToastKind,
+    shown: Instant,
+}
+
+/// Short messages ("Draw offered", "Invalid FEN: ...") shown in the corner
+/// of the board for a few seconds, newest at the bottom. Each is printed
+/// to the terminal as well. Anything holding a game can raise one, even
+/// without mutable access.
+#[derive(Default)]
+pub struct Toasts {
+    toasts: RefCell<VecDeque<Toast>>,
+    changed: Cell<bool>, // Since the last call to update
+}
+
+impl Toasts {
+    pub fn info(&self, text: impl Into<String>) {
+        self.push(ToastKind::Info, text.into());
+    }
+
+    pub fn error(&self, text: impl Into<String>) {
+        self.push(ToastKind::Error, text.into());
+    }
+
+    fn push(&self, kind: ToastKind, text: String) {
+        match kind {
+            ToastKind::Info => println!("{text}"),
+            ToastKind::Error => eprintln!("{text}"),
+        }
+        let mut toasts = self.toasts.borrow_mut();
+        if toasts.len() == MAX_TOASTS {
+            toasts.pop_front();
+        }
+        toasts.push_back(Toast {
+            text,
+            kind,
+            shown: Instant::now(),
+        });
+        self.changed.set(true);
+    }
+
+    /// Takes down the toasts that have been up long enough. Returns whether
+    /// any came or went since the last call, so the screen needs redrawing.
+    pub fn update(&self) -> bool {
+        let mut toasts = self.toasts.borrow_mut();
+        while toasts
+            .front()
+            .is_some_and(|toast| toast.shown.elapsed() >= TOAST_DURATION)
+        {
+            toasts.pop_front();
+            self.changed.set(true);
+        }
+        self.changed.replace(false)
+    }
+
+    /// Draws the toasts stacked up from the bottom-right corner of `area`,
+    /// each `scale` pixels high.
+    pub fn draw(
+        &self,
+        ctx: &mut Context,
+        canvas: &mut Canvas,
+        area: Rect,
+        scale: f32,
+    ) -> GameResult {
+        let padding = scale * 0.4;
+        let mut bottom = area.y + area.h - padding;
+        for toast in self.toasts.borrow().iter().rev() {
+            let label = Label::new(toast.text.as_str(), scale).wrap();
+            let size = label.measure(ctx, area.w * 0.6)?;
+            let rect = Rect::new(
+                area.x + area.w - size.x - 3.0 * padding,
+                bottom - size.y - 2.0 * padding,
+                size.x + 2.0 * padding,
+                size.y + 2.0 * padding,
+            );
+            let color = match toast.kind {
+                ToastKind::Info => ui::PANEL,
+                ToastKind::Error => Color::from_rgba(150, 40, 35, 230),
+            };
+            ui::fill(ctx, canvas, rect, color)?;
+            label.draw(ctx, canvas, ui::inset(rect, padding))?;
+            bottom = rect.y - padding;
+        }
+        Ok(())
+    }
+}
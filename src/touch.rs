@@ -0,0 +1,100 @@
+use std::time::{Duration, Instant};
+
+use ggez::event::winit_event::TouchPhase;
+
+// How long a finger has to stay down for a long press
+const LONG_PRESS_TIME: Duration = Duration::from_millis(500);
+
+// How far, in window pixels, a finger can wander and still be tapping
+const TAP_SLOP: f64 = 12.0;
+
+/// What a touch on the screen amounts to. Positions are in window
+/// coordinates, like those of mouse events.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Gesture {
+    /// A short touch, which acts as a left click where it began
+    Tap(f32, f32),
+    /// A finger held still, which acts as pressing the right button where
+    /// it is: the start of an annotation, or erasing in the editor
+    LongPress(f32, f32),
+    /// The finger from a long press lifted, which acts as letting go of the
+    /// right button where it lifted, so dragging after a long press draws
+    /// an arrow
+    LongPressEnd(f32, f32),
+}
+
+// The finger currently down, if only one is
+struct Press {
+    start: (f64, f64),
+    at: Instant,
+    long: bool,      // Held long enough to become a long press
+    cancelled: bool, // Dragged away or joined by another finger
+}
+
+/// Turns touch events into taps and long presses. Anything with two or more
+/// fingers, like a pinch, or a finger dragged across the screen is ignored
+/// entirely, so zooming or scrolling by habit never moves a piece.
+#[derive(Default)]
+pub struct TouchInput {
+    fingers: u32, // Fingers on the screen
+    press: Option<Press>,
+}
+
+impl TouchInput {
+    /// Takes one touch event, returning the gesture it completes, if any.
+    pub fn touch(&mut self, phase: TouchPhase, x: f64, y: f64) -> Option<Gesture> {
+        match phase {
+            TouchPhase::Started => {
+                self.fingers += 1;
+                if self.fingers == 1 {
+                    self.press = Some(Press {
+                        start: (x, y),
+                        at: Instant::now(),
+                        long: false,
+                        cancelled: false,
+                    });
+                } else if let Some(press) = &mut self.press {
+                    press.cancelled = true;
+                }
+                None
+            }
+            TouchPhase::Moved => {
+                if let Some(press) = &mut self.press {
+                    let moved = (x - press.start.0).hypot(y - press.start.1);
+                    if moved > TAP_SLOP && !press.long {
+                        press.cancelled = true;
+                    }
+                }
+                None
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                self.fingers = self.fingers.saturating_sub(1);
+                if self.fingers > 0 {
+                    return None;
+                }
+                let press = self.press.take()?;
+                if press.cancelled || phase == TouchPhase::Cancelled {
+                    None
+                } else if press.long {
+                    Some(Gesture::LongPressEnd(x as f32, y as f32))
+                } else {
+                    Some(Gesture::Tap(press.start.0 as f32, press.start.1 as f32))
+                }
+            }
+        }
+    }
+
+    /// Checks the finger being held, returning a long press once it has been
+    /// down long enough. Called every frame.
+    pub fn update(&mut self) -> Option<Gesture> {
+        let press = self.press.as_mut()?;
+        if press.long || press.cancelled || press.at.elapsed() < LONG_PRESS_TIME {
+            return None;
+        }
+        press.long = true;
+        Some(Gesture::LongPress(
+            press.start.0 as f32,
+            press.start.1 as f32,
+        ))
+    }
+}
@@ -0,0 +1,135 @@
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use itsjustchess::chess_core::ChessBoard;
+
+// How long each round of the coordinate trainer lasts
+const ROUND_TIME: Duration = Duration::from_secs(30);
+
+// How long a clicked square stays marked right or wrong
+const FLASH_TIME: Duration = Duration::from_millis(400);
+
+/// The coordinate trainer: square names come up one at a time and are found
+/// by clicking them on an empty board, as many as possible before the round
+/// runs out of time.
+pub struct CoordinateTrainer {
+    board: ChessBoard, // Only its size matters: the squares to find are on it
+    target: (usize, usize),
+    started: Instant,
+    score: u32,
+    misses: u32,
+    streak: u32,
+    best_score: u32,  // Over every round since the trainer was opened
+    best_streak: u32, // Likewise
+    last_click: Option<((usize, usize), bool, Instant)>, // Square, whether it was right, when
+}
+
+impl CoordinateTrainer {
+    /// A trainer for the squares of a board the size of `board`.
+    pub fn new(board: &ChessBoard) -> Self {
+        let board =
+            ChessBoard::with_size(board.files, board.ranks).unwrap_or_else(ChessBoard::empty);
+        CoordinateTrainer {
+            board,
+            target: random_square(&board, None),
+            started: Instant::now(),
+            score: 0,
+            misses: 0,
+            streak: 0,
+            best_score: 0,
+            best_streak: 0,
+            last_click: None,
+        }
+    }
+
+    /// Starts another round, keeping the best score and streak.
+    pub fn restart(&mut self) {
+        *self = CoordinateTrainer {
+            best_score: self.best_score,
+            best_streak: self.best_streak,
+            ..CoordinateTrainer::new(&self.board)
+        };
+    }
+
+    /// The empty board the squares are found on.
+    pub fn board(&self) -> &ChessBoard {
+        &self.board
+    }
+
+    /// The name of the square to find, e.g. "e4".
+    pub fn target_name(&self) -> String {
+        self.board.square_name(self.target)
+    }
+
+    pub fn time_left(&self) -> Duration {
+        ROUND_TIME.saturating_sub(self.started.elapsed())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.time_left().is_zero()
+    }
+
+    /// Scores a click on `square` and moves on to a new square if it was the
+    /// right one. Clicks after the round is over are ignored.
+    pub fn click(&mut self, square: (usize, usize)) {
+        if self.is_finished() {
+            return;
+        }
+        let correct = square == self.target;
+        if correct {
+            self.score += 1;
+            self.streak += 1;
+            self.best_score = self.best_score.max(self.score);
+            self.best_streak = self.best_streak.max(self.streak);
+            self.target = random_square(&self.board, Some(self.target));
+        } else {
+            self.misses += 1;
+            self.streak = 0;
+        }
+        self.last_click = Some((square, correct, Instant::now()));
+    }
+
+    /// The square clicked last and whether it was right, while it is still
+    /// marked on the board.
+    pub fn flash(&self) -> Option<((usize, usize), bool)> {
+        self.last_click
+            .filter(|&(_, _, at)| at.elapsed() < FLASH_TIME)
+            .map(|(square, correct, _)| (square, correct))
+    }
+
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+
+    pub fn misses(&self) -> u32 {
+        self.misses
+    }
+
+    pub fn streak(&self) -> u32 {
+        self.streak
+    }
+
+    pub fn best_score(&self) -> u32 {
+        self.best_score
+    }
+
+    pub fn best_streak(&self) -> u32 {
+        self.best_streak
+    }
+}
+
+// A square of `board` picked at random, never the same as `previous` so
+// that a new name always comes up.
+fn random_square(board: &ChessBoard, previous: Option<(usize, usize)>) -> (usize, usize) {
+    let mut rng = rand::rng();
+    loop {
+        let square = (
+            rng.random_range(0..board.ranks),
+            rng.random_range(0..board.files),
+        );
+        if Some(square) != previous {
+            return square;
+        }
+    }
+}
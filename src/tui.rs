@@ -0,0 +1,566 @@
+use std::io::{self, Stdout, Write};
+use std::time::{Duration, Instant};
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use itsjustchess::chess_core::{
+    format_clock, ChessBoard, ChessClock, Game, GameState, GameStatus, Move, OpeningBook, Piece,
+    PieceColor, PieceType, MAX_RANKS,
+};
+
+use crate::accessibility::BoardTheme;
+use crate::headless::Engine;
+use crate::render::{self, square_color, BoardView, Input, Renderer};
+use crate::AiColor;
+
+// How long to wait for a key before redrawing, for the clocks
+const FRAME_INTERVAL: Duration = Duration::from_millis(100);
+
+// The screen rows of the top rank (or the first, flipped), and of the
+// message and key help under the tallest board
+const BOARD_TOP: u16 = 2;
+const MESSAGE_ROW: u16 = BOARD_TOP + MAX_RANKS as u16 + 2;
+const HELP_ROW: u16 = MESSAGE_ROW + 2;
+
+// The column of the a-file (or the h-file, flipped), after the rank numbers.
+// Each square is three columns wide.
+const BOARD_LEFT: u16 = 2;
+const SQUARE_WIDTH: u16 = 3;
+
+// Where the clocks and the move list go, to the right of the board
+const PANEL_COLUMN: u16 = 32;
+const MOVE_LIST_TOP: u16 = BOARD_TOP + 3;
+
+const HELP: [&str; 5] = [
+    "arrows/hjkl  move the cursor",
+    "Enter/Space  pick up, put down",
+    "Esc  put the piece back",
+    "u  take back   f  flip",
+    "n  new game    q  quit",
+];
+
+/// Settings for a game played in the terminal with --frontend tui.
+pub struct TuiConfig {
+    pub start: GameState,
+    pub ai_color: Option<AiColor>,
+    pub engine: Engine,
+    pub book: OpeningBook,
+    pub clock: Option<ChessClock>,
+    pub high_contrast: bool,
+    pub seed: u64, // For the AI's random choices
+}
+
+/// Draws in the terminal with Unicode pieces, and reads the keys.
+pub struct TerminalRenderer {
+    out: Stdout,
+    theme: &'static BoardTheme,
+}
+
+impl TerminalRenderer {
+    fn new(high_contrast: bool) -> Self {
+        TerminalRenderer {
+            out: io::stdout(),
+            theme: BoardTheme::get(high_contrast),
+        }
+    }
+
+    // Writes `text` at (`x`, `y`) and clears the rest of the line, picking
+    // it out as the selected square is if `highlight`
+    fn line(&mut self, x: u16, y: u16, text: &str, highlight: bool) -> io::Result<()> {
+        queue!(self.out, MoveTo(x, y))?;
+        if highlight {
+            let (r, g, b) = self.theme.selected;
+            queue!(
+                self.out,
+                SetBackgroundColor(Color::Rgb { r, g, b }),
+                SetForegroundColor(Color::Black)
+            )?;
+        }
+        queue!(
+            self.out,
+            Print(text),
+            ResetColor,
+            Clear(ClearType::UntilNewLine)
+        )
+    }
+
+    // Fills `square` with `symbol` in `color`, in brackets if the cursor is
+    // on it
+    fn cell(
+        &mut self,
+        view: &BoardView,
+        square: (usize, usize),
+        symbol: char,
+        color: Color,
+    ) -> io::Result<()> {
+        let (row, col) = view.screen_square(square);
+        let is_light = view.board.is_light(square);
+        let (r, g, b) = square_color(self.theme, view.shade(square), is_light);
+        let (open, close) = if view.cursor == Some(square) {
+            ('[', ']')
+        } else {
+            (' ', ' ')
+        };
+        queue!(
+            self.out,
+            MoveTo(
+                BOARD_LEFT + col as u16 * SQUARE_WIDTH,
+                BOARD_TOP + row as u16
+            ),
+            SetBackgroundColor(Color::Rgb { r, g, b }),
+            SetForegroundColor(color),
+            Print(format!("{open}{symbol}{close}")),
+            ResetColor
+        )
+    }
+}
+
+impl Renderer for TerminalRenderer {
+    type Error = io::Error;
+
+    // The squares, with the rank numbers beside them and the files below
+    fn draw_board(&mut self, view: &BoardView) -> io::Result<()> {
+        let (files, ranks) = (view.board.files, view.board.ranks);
+        for screen_row in 0..ranks {
+            let (row, _) = view.screen_square((screen_row, 0));
+            let y = BOARD_TOP + screen_row as u16;
+            self.line(0, y, &format!("{} ", ranks - row), false)?;
+            for col in 0..files {
+                self.cell(view, (row, col), ' ', Color::Black)?;
+            }
+        }
+        let letters: String = (0..files)
+            .map(|screen_col| {
+                let (_, col) = view.screen_square((0, screen_col));
+                format!(" {} ", (b'a' + col as u8) as char)
+            })
+            .collect();
+        let y = BOARD_TOP + ranks as u16;
+        self.line(BOARD_LEFT, y, &letters, false)?;
+        self.line(0, y + 1, "", false)
+    }
+
+    // The solid symbols, coloured, show up on any square
+    fn draw_piece(
+        &mut self,
+        view: &BoardView,
+        square: (usize, usize),
+        piece: Piece,
+    ) -> io::Result<()> {
+        let symbol = Piece {
+            color: PieceColor::Black,
+            ..piece
+        }
+        .symbol();
+        let color = match piece.color {
+            PieceColor::White => Color::White,
+            PieceColor::Black => Color::Black,
+        };
+        self.cell(view, square, symbol, color)
+    }
+
+    fn present(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    fn poll_input(&mut self) -> io::Result<Option<Input>> {
+        if !event::poll(FRAME_INTERVAL)? {
+            return Ok(None);
+        }
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            Event::Resize(..) => return Ok(Some(Input::Redraw)),
+            _ => return Ok(None),
+        };
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return Ok(Some(Input::Quit));
+        }
+        Ok(match key.code {
+            KeyCode::Up | KeyCode::Char('k') => Some(Input::Cursor(-1, 0)),
+            KeyCode::Down | KeyCode::Char('j') => Some(Input::Cursor(1, 0)),
+            KeyCode::Left | KeyCode::Char('h') => Some(Input::Cursor(0, -1)),
+            KeyCode::Right | KeyCode::Char('l') => Some(Input::Cursor(0, 1)),
+            KeyCode::Enter | KeyCode::Char(' ') => Some(Input::Choose),
+            KeyCode::Esc => Some(Input::Cancel),
+            KeyCode::Char(c) => Some(Input::Key(c.to_ascii_lowercase())),
+            _ => None,
+        })
+    }
+}
+
+/// A game in the terminal instead of the window, e.g. over SSH: the board
+/// in Unicode pieces, moved about with the cursor keys, beside the clocks
+/// and the move list. The rules, the AI and the clocks are the same ones
+/// the window uses.
+struct Tui {
+    config: TuiConfig,
+    renderer: TerminalRenderer,
+    rng: StdRng,
+    game: Game,
+    sans: Vec<String>,                // The moves of the game in SAN
+    cursor: (usize, usize),           // The square under the cursor, as (row, col) on the board
+    selected: Option<(usize, usize)>, // The piece picked up to move
+    promotion: Option<Move>,          // A promotion waiting for its piece
+    flipped: bool,                    // Black at the bottom
+    timeout: Option<GameStatus>,      // Set when a flag falls, which Game doesn't know about
+    last_tick: Instant,
+    message: Option<String>, // Why the last move was refused
+}
+
+/// Plays in the terminal until the player quits, then puts the terminal
+/// back as it was.
+pub fn run(config: TuiConfig) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(stdout, EnterAlternateScreen, Hide)?;
+    let result = Tui::new(config).run();
+    let restored = execute!(stdout, Show, LeaveAlternateScreen);
+    terminal::disable_raw_mode()?;
+    result.and(restored)
+}
+
+impl Tui {
+    fn new(config: TuiConfig) -> Self {
+        let game = Game::from_position(config.start.clone());
+        // The cursor starts on the king's pawn of whoever moves first
+        let board = game.board();
+        let cursor = (board.pawn_row(game.turn()), board.king_file());
+        let flipped = config.ai_color == Some(AiColor::White);
+        Tui {
+            renderer: TerminalRenderer::new(config.high_contrast),
+            rng: StdRng::seed_from_u64(config.seed),
+            config,
+            game,
+            sans: Vec::new(),
+            cursor,
+            selected: None,
+            promotion: None,
+            flipped,
+            timeout: None,
+            last_tick: Instant::now(),
+            message: None,
+        }
+    }
+
+    // The AI moves between frames, so the keys still work while two AIs
+    // play each other
+    fn run(&mut self) -> io::Result<()> {
+        queue!(self.renderer.out, Clear(ClearType::All))?;
+        loop {
+            self.tick_clock();
+            self.draw()?;
+            if self.ai_to_move() {
+                self.play_ai_move();
+            }
+            match self.renderer.poll_input()? {
+                Some(Input::Quit) => return Ok(()),
+                Some(Input::Key('q')) if self.promotion.is_none() => return Ok(()),
+                Some(Input::Redraw) => queue!(self.renderer.out, Clear(ClearType::All))?,
+                Some(input) => self.input(input),
+                None => {}
+            }
+        }
+    }
+
+    fn status(&self) -> GameStatus {
+        self.timeout.unwrap_or_else(|| self.game.status())
+    }
+
+    fn ai_to_move(&self) -> bool {
+        !self.status().is_over()
+            && self
+                .config
+                .ai_color
+                .is_some_and(|ai_color| ai_color.plays(self.game.turn()))
+    }
+
+    // Runs down the clock of the side to move by the time since the last tick
+    fn tick_clock(&mut self) {
+        let now = Instant::now();
+        let elapsed = now - self.last_tick;
+        self.last_tick = now;
+        if self.status().is_over() {
+            return;
+        }
+        let turn = self.game.turn();
+        if let Some(clock) = &mut self.config.clock {
+            if clock.tick(turn, elapsed) {
+                self.timeout = Some(GameStatus::Timeout {
+                    winner: turn.opposite(),
+                });
+            }
+        }
+    }
+
+    fn play_ai_move(&mut self) {
+        let mv =
+            self.config
+                .engine
+                .choose_move(self.game.position(), &self.config.book, &mut self.rng);
+        // Charge the thinking time to the AI before the turn passes
+        self.tick_clock();
+        if let Some(mv) = mv.filter(|_| !self.status().is_over()) {
+            self.play(mv);
+        }
+    }
+
+    // Plays `mv` for the side to move, or says why it can't be
+    fn play(&mut self, mv: Move) {
+        let mover = self.game.turn();
+        let san = mv.to_san(self.game.position());
+        match self.game.make_move(mv) {
+            Ok(()) => {
+                log::info!(
+                    "{mover:?} played {san} ({})",
+                    mv.to_uci_on(self.game.board())
+                );
+                if let Some(clock) = &mut self.config.clock {
+                    clock.end_move(mover);
+                }
+                self.sans.push(san);
+                self.message = None;
+            }
+            Err(e) => self.message = Some(e.to_string()),
+        }
+        self.selected = None;
+        self.promotion = None;
+    }
+
+    fn input(&mut self, input: Input) {
+        // While choosing a promotion piece, its letter picks it and Escape
+        // cancels
+        if let Some(mv) = self.promotion {
+            match input {
+                Input::Key(c) => {
+                    let choice = PieceType::from_letter(c).filter(|piece_type| {
+                        self.game.position().promotion_pieces().contains(piece_type)
+                    });
+                    if let Some(piece_type) = choice {
+                        self.play(Move {
+                            promotion: Some(piece_type),
+                            ..mv
+                        });
+                    }
+                }
+                Input::Cancel => self.promotion = None,
+                _ => {}
+            }
+            return;
+        }
+
+        match input {
+            Input::Cursor(rows, cols) => self.move_cursor(rows, cols),
+            Input::Choose => self.choose_square(),
+            Input::Cancel => self.selected = None,
+            Input::Key('u') => self.take_back(),
+            Input::Key('f') => self.flipped = !self.flipped,
+            Input::Key('n') => self.new_game(),
+            _ => {}
+        }
+    }
+
+    // Moves the cursor by (`rows`, `cols`) as seen on the screen, stopping at
+    // the edge of the board
+    fn move_cursor(&mut self, rows: isize, cols: isize) {
+        let view = self.board_view(self.game.board());
+        let (row, col) = view.screen_square(self.cursor);
+        let step = |i: usize, by: isize, size: usize| i.saturating_add_signed(by).min(size - 1);
+        self.cursor = view.screen_square((
+            step(row, rows, view.board.ranks),
+            step(col, cols, view.board.files),
+        ));
+    }
+
+    // Picks up the piece under the cursor, or puts the one picked up down
+    // there if it can go there
+    fn choose_square(&mut self) {
+        if self.status().is_over() || self.ai_to_move() {
+            return;
+        }
+        let square = self.cursor;
+        if let Some(from) = self.selected {
+            let moves: Vec<Move> = self
+                .game
+                .legal_moves()
+                .into_iter()
+                .filter(|mv| mv.from == from && mv.to == square)
+                .collect();
+            match moves.first() {
+                Some(&mv) if moves.len() > 1 => {
+                    self.promotion = Some(Move {
+                        promotion: None,
+                        ..mv
+                    });
+                    return;
+                }
+                Some(&mv) => {
+                    self.play(mv);
+                    return;
+                }
+                None => {}
+            }
+        }
+        let own_piece = self.game.board().squares[square.0][square.1]
+            .occupant
+            .is_some_and(|piece| piece.color == self.game.turn());
+        self.selected = own_piece.then_some(square);
+    }
+
+    // Takes back the last move, and against the AI its reply as well, so it
+    // is the player's turn again. The clocks keep the time already used.
+    fn take_back(&mut self) {
+        if self.timeout.is_some() {
+            return;
+        }
+        let ai_color = self.config.ai_color;
+        loop {
+            if self.game.undo().is_none() {
+                break;
+            }
+            self.sans.pop();
+            let ai_moves = ai_color.is_some_and(|ai_color| ai_color.plays(self.game.turn()));
+            if !ai_moves || ai_color == Some(AiColor::Both) {
+                break;
+            }
+        }
+        self.selected = None;
+        self.promotion = None;
+        self.message = None;
+    }
+
+    fn new_game(&mut self) {
+        self.game = Game::from_position(self.config.start.clone());
+        self.sans.clear();
+        if let Some(clock) = &mut self.config.clock {
+            clock.reset();
+        }
+        self.selected = None;
+        self.promotion = None;
+        self.timeout = None;
+        self.message = None;
+        self.last_tick = Instant::now();
+    }
+
+    // The position with the last move, the piece picked up and where it can
+    // go marked, and the cursor
+    fn board_view<'a>(&self, board: &'a ChessBoard) -> BoardView<'a> {
+        let targets = match self.selected {
+            Some(from) => self
+                .game
+                .legal_moves()
+                .iter()
+                .filter(|mv| mv.from == from)
+                .map(|mv| mv.to)
+                .collect(),
+            None => Vec::new(),
+        };
+        BoardView {
+            board,
+            flipped: self.flipped,
+            last_move: self.game.moves().last().map(|mv| (mv.from, mv.to)),
+            highlighted: self.selected.into_iter().collect(),
+            targets,
+            cursor: Some(self.cursor),
+        }
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        let (_, height) = terminal::size()?;
+        let status = self.status_line();
+        self.renderer.line(0, 0, &status, false)?;
+        let board = *self.game.board();
+        let view = self.board_view(&board);
+        render::draw_position(&mut self.renderer, &view)?;
+        let message = self.message.clone().unwrap_or_default();
+        self.renderer.line(0, MESSAGE_ROW, &message, false)?;
+        for (i, help) in HELP.iter().enumerate() {
+            self.renderer.line(0, HELP_ROW + i as u16, help, false)?;
+        }
+        self.draw_clocks()?;
+        self.draw_move_list(height)?;
+        self.renderer.present()
+    }
+
+    fn status_line(&self) -> String {
+        let status = self.status();
+        let turn = self.game.turn();
+        if status.is_over() {
+            format!("{}. Press n for a new game.", status.description())
+        } else if self.ai_to_move() {
+            format!("{turn:?} is thinking...")
+        } else if self.promotion.is_some() {
+            let letters: Vec<String> = self
+                .game
+                .position()
+                .promotion_pieces()
+                .iter()
+                .map(|piece_type| piece_type.letter().to_ascii_lowercase().to_string())
+                .collect();
+            // e.g. "q, r, b or n"
+            let (last, rest) = letters.split_last().expect("a pawn can always promote");
+            format!("Promote to {} or {last} (Esc cancels)", rest.join(", "))
+        } else if self.game.position().is_king_in_check(turn) {
+            format!("{turn:?} to move, in check")
+        } else {
+            format!("{turn:?} to move")
+        }
+    }
+
+    // Both clocks, with the side to move's picked out
+    fn draw_clocks(&mut self) -> io::Result<()> {
+        let Some(clock) = &self.config.clock else {
+            return Ok(());
+        };
+        let running = (!self.status().is_over()).then_some(self.game.turn());
+        let labels = [PieceColor::White, PieceColor::Black].map(|color| {
+            let mut label = format!(" {color:?}  {}", format_clock(clock.remaining(color)));
+            if let Some(mode) = clock.time_control(color).mode_label() {
+                label += &format!("  {mode}");
+            }
+            (label + " ", running == Some(color))
+        });
+        for (i, (label, highlight)) in labels.iter().enumerate() {
+            self.renderer
+                .line(PANEL_COLUMN, BOARD_TOP + i as u16, label, *highlight)?;
+        }
+        Ok(())
+    }
+
+    // The moves so far, a line per move number, ending with the latest that
+    // fit above the bottom of the terminal
+    fn draw_move_list(&mut self, height: u16) -> io::Result<()> {
+        let start = self.game.start_position();
+        let mut number = start.fullmove_number;
+        let mut white_to_move = start.turn == PieceColor::White;
+        let mut lines: Vec<String> = Vec::new();
+        for san in &self.sans {
+            if white_to_move {
+                lines.push(format!("{number:>3}. {san:<8}"));
+            } else {
+                if lines.is_empty() {
+                    lines.push(format!("{number:>3}. {:<8}", "..."));
+                }
+                if let Some(line) = lines.last_mut() {
+                    line.push_str(san);
+                }
+                number += 1;
+            }
+            white_to_move = !white_to_move;
+        }
+
+        let rows = usize::from(height.saturating_sub(MOVE_LIST_TOP + 1));
+        let shown = &lines[lines.len().saturating_sub(rows)..];
+        for i in 0..rows {
+            let text = shown.get(i).map_or("", String::as_str);
+            self.renderer
+                .line(PANEL_COLUMN, MOVE_LIST_TOP + i as u16, text, false)?;
+        }
+        Ok(())
+    }
+}
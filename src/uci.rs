@@ -0,0 +1,232 @@
+use std::io::{self, BufRead};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use itsjustchess::chess_core::{Analysis, GameState, Move, PieceColor, PieceType, Variant};
+
+// How deep a search goes when `go` doesn't limit it, which in practice means
+// until it is stopped
+const MAX_DEPTH: u32 = 100;
+
+// When `go` gives only the clocks, each move gets this share of the time
+// left (or of the moves to go, if fewer), plus half the increment
+const MOVES_TO_GO: u64 = 30;
+
+/// Speaks the Universal Chess Interface on stdin and stdout (--uci), so the
+/// built-in AI can be plugged into other GUIs. Returns once told to quit or
+/// when stdin closes.
+pub fn run(variant: Variant) {
+    let mut engine = UciEngine {
+        variant,
+        position: GameState::with_variant(variant),
+        stop: Arc::new(AtomicBool::new(false)),
+        search: None,
+    };
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if !engine.handle(&line) {
+            break;
+        }
+    }
+    engine.stop_search();
+}
+
+struct UciEngine {
+    variant: Variant,
+    position: GameState,
+    stop: Arc<AtomicBool>, // Ends the search running in `search`
+    search: Option<JoinHandle<()>>,
+}
+
+impl UciEngine {
+    // Carries out one command from the GUI. Returns false on `quit`.
+    fn handle(&mut self, line: &str) -> bool {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.first().copied() {
+            Some("uci") => {
+                println!("id name itsjustchess {}", env!("CARGO_PKG_VERSION"));
+                println!("id author the itsjustchess authors");
+                println!("uciok");
+            }
+            Some("isready") => println!("readyok"),
+            Some("ucinewgame") => {
+                self.stop_search();
+                self.position = GameState::with_variant(self.variant);
+            }
+            Some("position") => {
+                self.stop_search();
+                match parse_position(self.variant, &words[1..]) {
+                    Ok(position) => self.position = position,
+                    Err(err) => println!("info string {err}"),
+                }
+            }
+            Some("go") => {
+                self.stop_search();
+                self.go(&words[1..]);
+            }
+            Some("stop") => self.stop_search(),
+            Some("quit") => return false,
+            // The protocol says to ignore anything not understood
+            _ => {}
+        }
+        true
+    }
+
+    // Starts searching the current position on a background thread, which
+    // reports each depth as it completes and the best move once the limits
+    // of `go` are reached or the search is stopped.
+    fn go(&mut self, args: &[&str]) {
+        let limits = SearchLimits::parse(args, self.position.turn);
+        self.stop = Arc::new(AtomicBool::new(false));
+        if let Some(time) = limits.time {
+            let stop = Arc::clone(&self.stop);
+            thread::spawn(move || {
+                thread::sleep(time);
+                stop.store(true, Ordering::Relaxed);
+            });
+        }
+
+        let position = self.position.clone();
+        let stop = Arc::clone(&self.stop);
+        self.search = Some(thread::spawn(move || {
+            let mut best: Option<Analysis> = None;
+            position.analyse(limits.depth, &stop, |analysis| {
+                println!("{}", info_line(&position, &analysis));
+                best = Some(analysis);
+            });
+            // Stopped before the first depth finished, any legal move will do
+            let mv = best
+                .and_then(|analysis| analysis.line.first().copied())
+                .or_else(|| {
+                    position
+                        .generate_valid_moves(position.turn)
+                        .first()
+                        .copied()
+                });
+            match mv {
+                Some(mv) => println!("bestmove {}", uci_move(&position, mv)),
+                None => println!("bestmove 0000"),
+            }
+        }));
+    }
+
+    // Ends the running search, if any, once it has sent its best move.
+    fn stop_search(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(search) = self.search.take() {
+            let _ = search.join();
+        }
+    }
+}
+
+// How long and how deep to search, from the arguments to `go`
+struct SearchLimits {
+    depth: u32,
+    time: Option<Duration>,
+}
+
+impl SearchLimits {
+    fn parse(args: &[&str], turn: PieceColor) -> Self {
+        let value = |name: &str| -> Option<u64> {
+            let at = args.iter().position(|&word| word == name)?;
+            args.get(at + 1)?.parse().ok()
+        };
+        let (clock, increment) = match turn {
+            PieceColor::White => (value("wtime"), value("winc")),
+            PieceColor::Black => (value("btime"), value("binc")),
+        };
+        let time = match (value("movetime"), clock) {
+            (Some(movetime), _) => Some(movetime),
+            (None, Some(clock)) => {
+                let moves_to_go = value("movestogo")
+                    .unwrap_or(MOVES_TO_GO)
+                    .clamp(1, MOVES_TO_GO);
+                // Never more than half of what is left, so as not to lose on time
+                Some((clock / moves_to_go + increment.unwrap_or(0) / 2).min(clock / 2))
+            }
+            (None, None) => None,
+        };
+        let depth = value("depth").map_or(MAX_DEPTH, |depth| (depth as u32).clamp(1, MAX_DEPTH));
+        SearchLimits {
+            depth,
+            time: time.map(Duration::from_millis),
+        }
+    }
+}
+
+// Sets up the position from `position`'s arguments: "startpos" or
+// "fen <FEN>", then optionally "moves" and the moves played from there.
+fn parse_position(variant: Variant, args: &[&str]) -> Result<GameState, String> {
+    let mut state = GameState::with_variant(variant);
+    let moves_at = args
+        .iter()
+        .position(|&word| word == "moves")
+        .unwrap_or(args.len());
+    match args.first().copied() {
+        Some("startpos") => {}
+        Some("fen") => state
+            .from_fen(&args[1..moves_at].join(" "))
+            .map_err(|err| err.to_string())?,
+        _ => return Err("Invalid position: expected startpos or fen".to_string()),
+    }
+    for uci in args.iter().skip(moves_at + 1) {
+        match Move::from_uci_on(uci, &state.board) {
+            Some(mv) if state.is_legal_move(mv) => {
+                state.make_move(mv);
+            }
+            _ => return Err(format!("Invalid position: illegal move {uci}")),
+        }
+    }
+    Ok(state)
+}
+
+// An `info` line for a completed depth, with the score from the side to
+// move's point of view as the protocol wants
+fn info_line(position: &GameState, analysis: &Analysis) -> String {
+    let sign = match position.turn {
+        PieceColor::White => 1,
+        PieceColor::Black => -1,
+    };
+    let score = match analysis.mate_in() {
+        Some(moves) => format!("mate {}", sign * moves),
+        None => format!("cp {}", sign * analysis.score),
+    };
+    let stats = &analysis.stats;
+    let mut line = position.clone();
+    let pv: Vec<String> = analysis
+        .line
+        .iter()
+        .map(|&mv| {
+            let uci = uci_move(&line, mv);
+            line.make_move(mv);
+            uci
+        })
+        .collect();
+    format!(
+        "info depth {} score {score} nodes {} nps {} time {} pv {}",
+        analysis.depth,
+        stats.nodes,
+        stats.nodes_per_second(),
+        stats.elapsed.as_millis(),
+        pv.join(" ")
+    )
+}
+
+// `mv` in UCI notation. The search leaves promotions to a queen implicit,
+// but the protocol needs them spelt out.
+fn uci_move(position: &GameState, mv: Move) -> String {
+    let promotes = mv.is_pawn_move(position) && position.board.is_back_rank(mv.to.0);
+    if promotes && mv.promotion.is_none() {
+        Move {
+            promotion: Some(PieceType::Queen),
+            ..mv
+        }
+        .to_uci_on(&position.board)
+    } else {
+        mv.to_uci_on(&position.board)
+    }
+}
@@ -0,0 +1,259 @@
+use std::ops::Range;
+
+use ggez::glam::Vec2;
+use ggez::graphics::{Canvas, Color, DrawMode, DrawParam, Mesh, PxScale, Rect, Text};
+use ggez::{Context, GameResult};
+
+/// Dims the board behind menus and dialogs.
+pub const SHADE: Color = rgba(0, 0, 0, 120);
+
+/// The background of menus, dialogs and overlays drawn over the board.
+pub const PANEL: Color = rgba(40, 40, 40, 230);
+
+/// The background of the panels beside the board.
+pub const SIDEBAR: Color = rgba(40, 40, 40, 255);
+
+/// A button for the usual choice, or one that is switched on.
+pub const PRIMARY: Color = rgba(118, 150, 86, 255);
+
+/// Any other button.
+pub const SECONDARY: Color = rgba(70, 70, 70, 255);
+
+/// Marks the selected row of a list.
+pub const HIGHLIGHT: Color = rgba(237, 202, 142, 255);
+
+/// Text that matters less than the text around it, such as move numbers.
+pub const MUTED: Color = rgba(161, 159, 151, 255);
+
+const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+    Color::new(
+        r as f32 / 255.0,
+        g as f32 / 255.0,
+        b as f32 / 255.0,
+        a as f32 / 255.0,
+    )
+}
+
+/// Fills `rect` with `color`.
+pub fn fill(ctx: &mut Context, canvas: &mut Canvas, rect: Rect, color: Color) -> GameResult {
+    let mesh = Mesh::new_rectangle(ctx, DrawMode::fill(), rect, color)?;
+    canvas.draw(&mesh, DrawParam::default());
+    Ok(())
+}
+
+/// A filled circle of `radius` around `centre`.
+pub fn dot(
+    ctx: &mut Context,
+    canvas: &mut Canvas,
+    centre: [f32; 2],
+    radius: f32,
+    color: Color,
+) -> GameResult {
+    let mesh = Mesh::new_circle(ctx, DrawMode::fill(), centre, radius, 0.5, color)?;
+    canvas.draw(&mesh, DrawParam::default());
+    Ok(())
+}
+
+/// A circle `width` thick whose outer edge is `radius` from `centre`.
+pub fn ring(
+    ctx: &mut Context,
+    canvas: &mut Canvas,
+    centre: [f32; 2],
+    radius: f32,
+    width: f32,
+    color: Color,
+) -> GameResult {
+    let mesh = Mesh::new_circle(
+        ctx,
+        DrawMode::stroke(width),
+        centre,
+        radius - width / 2.0,
+        0.5,
+        color,
+    )?;
+    canvas.draw(&mesh, DrawParam::default());
+    Ok(())
+}
+
+/// `rect` shrunk by `by` on every side.
+pub fn inset(rect: Rect, by: f32) -> Rect {
+    Rect::new(
+        rect.x + by,
+        rect.y + by,
+        (rect.w - 2.0 * by).max(0.0),
+        (rect.h - 2.0 * by).max(0.0),
+    )
+}
+
+/// Splits `area` into `count` equal cells side by side, `gap` apart.
+pub fn columns(area: Rect, count: usize, gap: f32) -> Vec<Rect> {
+    let width = (area.w - gap * count.saturating_sub(1) as f32) / count.max(1) as f32;
+    (0..count)
+        .map(|i| Rect::new(area.x + i as f32 * (width + gap), area.y, width, area.h))
+        .collect()
+}
+
+/// Where a label sits in the rect it is drawn in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Align {
+    TopLeft,
+    /// Centred horizontally against the top edge
+    Top,
+    Centre,
+}
+
+/// A piece of text and how to draw it.
+pub struct Label {
+    text: String,
+    scale: f32,
+    color: Color,
+    align: Align,
+    wrap: bool,
+}
+
+impl Label {
+    /// White text `scale` pixels high, in the top-left corner of its rect.
+    pub fn new(text: impl Into<String>, scale: f32) -> Self {
+        Label {
+            text: text.into(),
+            scale,
+            color: Color::WHITE,
+            align: Align::TopLeft,
+            wrap: false,
+        }
+    }
+
+    pub fn color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn align(mut self, align: Align) -> Self {
+        self.align = align;
+        self
+    }
+
+    /// Breaks lines at word boundaries to fit the width of the rect.
+    pub fn wrap(mut self) -> Self {
+        self.wrap = true;
+        self
+    }
+
+    /// The size of the text if drawn no more than `width` wide.
+    pub fn measure(&self, ctx: &mut Context, width: f32) -> GameResult<Vec2> {
+        self.text([width, f32::INFINITY])
+            .measure(ctx)
+            .map(Vec2::from)
+    }
+
+    pub fn draw(&self, ctx: &mut Context, canvas: &mut Canvas, rect: Rect) -> GameResult {
+        let text = self.text([rect.w, rect.h]);
+        let size = text.measure(ctx)?;
+        let (x, y) = match self.align {
+            Align::TopLeft => (rect.x, rect.y),
+            Align::Top => (rect.x + (rect.w - size.x) / 2.0, rect.y),
+            Align::Centre => (
+                rect.x + (rect.w - size.x) / 2.0,
+                rect.y + (rect.h - size.y) / 2.0,
+            ),
+        };
+        canvas.draw(&text, DrawParam::default().dest([x, y]).color(self.color));
+        Ok(())
+    }
+
+    fn text(&self, bounds: [f32; 2]) -> Text {
+        let mut text = Text::new(self.text.as_str());
+        text.set_scale(PxScale::from(self.scale));
+        if self.wrap {
+            text.set_bounds(bounds);
+            text.set_wrap(true);
+        }
+        text
+    }
+}
+
+/// A filled rect with a label in the middle, for clicking.
+pub struct Button<'a> {
+    pub label: &'a str,
+    pub rect: Rect,
+    pub color: Color,
+}
+
+impl<'a> Button<'a> {
+    pub fn new(label: &'a str, rect: Rect, primary: bool) -> Self {
+        Button {
+            label,
+            rect,
+            color: if primary { PRIMARY } else { SECONDARY },
+        }
+    }
+
+    /// Draws the button with its label `scale` pixels high.
+    pub fn draw(&self, ctx: &mut Context, canvas: &mut Canvas, scale: f32) -> GameResult {
+        fill(ctx, canvas, self.rect, self.color)?;
+        Label::new(self.label, scale)
+            .align(Align::Centre)
+            .draw(ctx, canvas, self.rect)
+    }
+}
+
+/// A list of `len` rows of equal height shown in `area`, scrolled down by
+/// `offset` rows. It only does the arithmetic: the owner keeps the offset
+/// and draws the rows.
+#[derive(Clone, Copy, Debug)]
+pub struct ScrollList {
+    pub area: Rect,
+    pub row_height: f32,
+    pub len: usize,
+    pub offset: usize,
+}
+
+impl ScrollList {
+    /// How many rows fit, always at least one.
+    pub fn visible_rows(&self) -> usize {
+        ((self.area.h / self.row_height) as usize).max(1)
+    }
+
+    /// The rows currently in view.
+    pub fn rows(&self) -> Range<usize> {
+        self.offset..self.len.min(self.offset + self.visible_rows())
+    }
+
+    /// Where `row` is drawn at the current offset.
+    pub fn row_rect(&self, row: usize) -> Rect {
+        Rect::new(
+            self.area.x,
+            self.area.y + (row as f32 - self.offset as f32) * self.row_height,
+            self.area.w,
+            self.row_height,
+        )
+    }
+
+    /// The row under `y`, if there is one there.
+    pub fn row_at(&self, y: f32) -> Option<usize> {
+        if y < self.area.y || y >= self.area.y + self.area.h {
+            return None;
+        }
+        let row = self.offset + ((y - self.area.y) / self.row_height) as usize;
+        (row < self.len).then_some(row)
+    }
+
+    /// The offset after scrolling `rows` down (up if negative), kept so the
+    /// last row stays at the bottom.
+    pub fn scrolled(&self, rows: isize) -> usize {
+        let max_offset = self.len.saturating_sub(self.visible_rows());
+        self.offset.saturating_add_signed(rows).min(max_offset)
+    }
+
+    /// The offset after scrolling just far enough to bring `row` into view.
+    pub fn scrolled_to(&self, row: usize) -> usize {
+        let visible = self.visible_rows();
+        if row < self.offset {
+            row
+        } else if row >= self.offset + visible {
+            row + 1 - visible
+        } else {
+            self.offset
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Bindings for the browser version in `web/`, built for
+//! `wasm32-unknown-unknown` with wasm-bindgen. The page draws the board on a
+//! canvas and hands clicks and taps to [`WebGame::click`], which turns them
+//! into moves the same way the desktop board does.
+
+use wasm_bindgen::prelude::*;
+
+use crate::chess_core::{Difficulty, Game, Move, OpeningBook, PieceColor};
+
+// The browser version only plays from the standard position, on its 8x8 board
+const BOARD_SIZE: usize = 8;
+
+/// A game played in the browser. Squares are passed to and from JavaScript
+/// as `row * 8 + col`, with row 0 the eighth rank and column 0 the a-file.
+#[wasm_bindgen]
+pub struct WebGame {
+    game: Game,
+    book: OpeningBook,
+    selected: Option<(usize, usize)>,
+    targets: Vec<(usize, usize)>, // Where the selected piece can go
+}
+
+impl Default for WebGame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl WebGame {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WebGame {
+        WebGame {
+            game: Game::new(),
+            book: OpeningBook::builtin(),
+            selected: None,
+            targets: Vec::new(),
+        }
+    }
+
+    /// Starts again from the standard position.
+    pub fn reset(&mut self) {
+        self.game = Game::new();
+        self.clear_selection();
+    }
+
+    pub fn fen(&self) -> String {
+        self.game.fen()
+    }
+
+    /// "white" or "black".
+    pub fn turn(&self) -> String {
+        match self.game.turn() {
+            PieceColor::White => "white".to_string(),
+            PieceColor::Black => "black".to_string(),
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.game.status().is_over()
+    }
+
+    /// How the game stands, e.g. "Game in progress" or "White wins by
+    /// checkmate".
+    pub fn status(&self) -> String {
+        self.game.status().description()
+    }
+
+    /// The FEN letter of the piece on `square` ("K" for a white king, "p"
+    /// for a black pawn), or an empty string.
+    pub fn piece_at(&self, square: usize) -> String {
+        let (row, col) = (square / BOARD_SIZE, square % BOARD_SIZE);
+        let Some(piece) = self.game.board().squares.get(row).and_then(|r| r.get(col)) else {
+            return String::new();
+        };
+        let Some(piece) = piece.occupant else {
+            return String::new();
+        };
+        let letter = piece.piece_type.letter();
+        match piece.color {
+            PieceColor::White => letter.to_string(),
+            PieceColor::Black => letter.to_ascii_lowercase().to_string(),
+        }
+    }
+
+    pub fn selected(&self) -> Option<u32> {
+        self.selected.map(square_number)
+    }
+
+    /// The squares the selected piece can move to.
+    pub fn targets(&self) -> Vec<u32> {
+        self.targets.iter().copied().map(square_number).collect()
+    }
+
+    /// The squares the last move left and landed on, or nothing at the start.
+    pub fn last_move(&self) -> Vec<u32> {
+        match self.game.moves().last() {
+            Some(mv) => vec![square_number(mv.from), square_number(mv.to)],
+            None => Vec::new(),
+        }
+    }
+
+    /// The moves so far in standard algebraic notation.
+    pub fn san_moves(&self) -> Vec<String> {
+        self.game.san_moves()
+    }
+
+    /// Handles a click or tap at (`x`, `y`) on a board `board_px` wide drawn
+    /// from White's side: picks up one of the side to move's pieces, plays
+    /// it to one of its target squares (pawns promote to a queen) or drops
+    /// the selection. Returns whether the board needs drawing again.
+    pub fn click(&mut self, x: f64, y: f64, board_px: f64) -> bool {
+        if self.is_over() {
+            return false;
+        }
+        let tile = board_px / BOARD_SIZE as f64;
+        let on_board = x >= 0.0 && y >= 0.0 && x < board_px && y < board_px;
+        let Some(square) = on_board.then(|| ((y / tile) as usize, (x / tile) as usize)) else {
+            self.clear_selection();
+            return true;
+        };
+
+        match self.selected {
+            Some(selected) if selected == square => self.clear_selection(),
+            Some(selected) if self.targets.contains(&square) => {
+                let _ = self.game.make_move(Move::new(selected, square));
+                self.clear_selection();
+            }
+            Some(_) => self.clear_selection(),
+            None => {
+                let (row, col) = square;
+                let own_piece = self.game.board().squares[row][col]
+                    .occupant
+                    .is_some_and(|p| p.color == self.game.turn());
+                if !own_piece {
+                    return false;
+                }
+                self.selected = Some(square);
+                self.targets = self
+                    .game
+                    .legal_moves()
+                    .into_iter()
+                    .filter(|mv| mv.from == square)
+                    .map(|mv| mv.to)
+                    .collect();
+                self.targets.dedup(); // Promotions come once for each piece
+            }
+        }
+        true
+    }
+
+    /// Plays a move for the side to move as the AI at `level` (1 to 8) would,
+    /// returning it in UCI, or nothing if the game is over.
+    pub fn play_ai(&mut self, level: u8) -> Option<String> {
+        if self.is_over() {
+            return None;
+        }
+        let difficulty = Difficulty::new(level.clamp(Difficulty::MIN, Difficulty::MAX))?;
+        let mv = self.game.position().choose_move_at(
+            difficulty,
+            Some(&self.book),
+            true,
+            &mut rand::rng(),
+        )?;
+        self.game.make_move(mv).ok()?;
+        self.clear_selection();
+        Some(mv.to_uci_on(self.game.board()))
+    }
+
+    /// Takes back the last move.
+    pub fn undo(&mut self) {
+        self.game.undo();
+        self.clear_selection();
+    }
+}
+
+impl WebGame {
+    fn clear_selection(&mut self) {
+        self.selected = None;
+        self.targets.clear();
+    }
+}
+
+fn square_number((row, col): (usize, usize)) -> u32 {
+    (row * BOARD_SIZE + col) as u32
+}
@@ -0,0 +1,109 @@
+// The browser frontend: draws the board on a canvas and passes clicks and
+// taps to the game compiled from src/web.rs. Build pkg/ as in the README.
+import init, { WebGame } from "./pkg/itsjustchess.js";
+
+const LIGHT = "rgb(161, 159, 151)";
+const DARK = "rgb(118, 150, 86)";
+const TARGET = ["rgb(207, 203, 192)", "rgb(180, 220, 180)"];
+const LAST_MOVE = ["rgb(205, 210, 106)", "rgb(170, 162, 58)"];
+const SELECTED = "rgb(237, 202, 142)";
+
+const GLYPHS = {
+  K: "♔", Q: "♕", R: "♖", B: "♗", N: "♘", P: "♙",
+  k: "♚", q: "♛", r: "♜", b: "♝", n: "♞", p: "♟",
+};
+
+const AI_DELAY_MS = 300;
+
+await init();
+const game = new WebGame();
+const canvas = document.getElementById("board");
+const context = canvas.getContext("2d");
+let boardPx = 0;
+
+// Fits the board to the window, sharp on high-DPI screens.
+function resize() {
+  boardPx = Math.floor(Math.min(window.innerWidth, window.innerHeight * 0.8) * 0.96);
+  const scale = window.devicePixelRatio || 1;
+  canvas.style.width = `${boardPx}px`;
+  canvas.style.height = `${boardPx}px`;
+  canvas.width = boardPx * scale;
+  canvas.height = boardPx * scale;
+  context.setTransform(scale, 0, 0, scale, 0, 0);
+  draw();
+}
+
+function draw() {
+  const tile = boardPx / 8;
+  const selected = game.selected();
+  const targets = Array.from(game.targets());
+  const lastMove = Array.from(game.last_move());
+
+  for (let square = 0; square < 64; square++) {
+    const row = Math.floor(square / 8);
+    const col = square % 8;
+    const light = (row + col) % 2 === 0 ? 0 : 1;
+    let color = light === 0 ? LIGHT : DARK;
+    if (lastMove.includes(square)) color = LAST_MOVE[light];
+    if (targets.includes(square)) color = TARGET[light];
+    if (square === selected) color = SELECTED;
+    context.fillStyle = color;
+    context.fillRect(col * tile, row * tile, tile, tile);
+
+    const piece = game.piece_at(square);
+    if (piece) {
+      context.fillStyle = "black";
+      context.font = `${Math.floor(tile * 0.8)}px serif`;
+      context.textAlign = "center";
+      context.textBaseline = "middle";
+      context.fillText(GLYPHS[piece], (col + 0.5) * tile, (row + 0.55) * tile);
+    }
+  }
+
+  document.getElementById("status").textContent = game.is_over()
+    ? game.status()
+    : `${game.turn() === "white" ? "White" : "Black"} to move`;
+  document.getElementById("moves").textContent = Array.from(game.san_moves())
+    .map((san, i) => (i % 2 === 0 ? `${i / 2 + 1}. ${san}` : san))
+    .join(" ");
+}
+
+function aiToMove() {
+  return document.getElementById("ai").checked && game.turn() === "black" && !game.is_over();
+}
+
+function playAi() {
+  if (!aiToMove()) return;
+  setTimeout(() => {
+    const level = Number(document.getElementById("level").value) || 3;
+    game.play_ai(level);
+    draw();
+  }, AI_DELAY_MS);
+}
+
+// Pointer events cover the mouse, touch and pens alike
+canvas.addEventListener("pointerdown", (event) => {
+  event.preventDefault();
+  if (aiToMove()) return;
+  const rect = canvas.getBoundingClientRect();
+  if (game.click(event.clientX - rect.left, event.clientY - rect.top, boardPx)) {
+    draw();
+    playAi();
+  }
+});
+
+document.getElementById("new-game").addEventListener("click", () => {
+  game.reset();
+  draw();
+});
+
+document.getElementById("undo").addEventListener("click", () => {
+  game.undo();
+  // Take back the AI's reply as well, so it is the player's turn again
+  if (aiToMove()) game.undo();
+  draw();
+});
+
+document.getElementById("ai").addEventListener("change", playAi);
+window.addEventListener("resize", resize);
+resize();